@@ -9,7 +9,7 @@
 // Usage:
 //   cargo run --example relay_server
 
-use rtmp::{RtmpServer, RtmpClient, ServerConfig, ClientConfig, Result, Error};
+use rtmp::{RtmpServer, RtmpClient, ServerConfig, ClientConfig, RegistryEvent, Result, Error};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
@@ -20,8 +20,8 @@ pub struct RelayServer {
     /// Local RTMP server
     server: RtmpServer,
     
-    /// Upstream connections
-    upstreams: Arc<RwLock<HashMap<String, Arc<RtmpClient>>>>,
+    /// Upstream server URLs by name
+    upstreams: Arc<RwLock<HashMap<String, String>>>,
     
     /// Active relay tasks
     relay_tasks: Arc<RwLock<HashMap<String, tokio::task::JoinHandle<()>>>>,
@@ -38,35 +38,23 @@ impl RelayServer {
     }
     
     /// Add an upstream server
+    ///
+    /// Each relayed stream opens its own connection, since a client plays
+    /// one stream at a time.
     pub async fn add_upstream(&self, name: String, url: String) -> Result<()> {
         info!("Adding upstream: {} -> {}", name, url);
         
-        // Create client connection
-        let client_config = ClientConfig::builder()
-            .auto_reconnect(true)
-            .build()?;
-        
-        let mut client = RtmpClient::with_config(client_config);
+        let mut upstreams = self.upstreams.write().await;
+        upstreams.insert(name, url);
         
-        // Connect to upstream
-        match client.connect(&url).await {
-            Ok(_) => {
-                info!("Connected to upstream: {}", name);
-                
-                // Store upstream
-                let mut upstreams = self.upstreams.write().await;
-                upstreams.insert(name.clone(), Arc::new(client));
-                
-                Ok(())
-            }
-            Err(e) => {
-                error!("Failed to connect to upstream {}: {}", name, e);
-                Err(e)
-            }
-        }
+        Ok(())
     }
     
     /// Start relaying a stream from upstream
+    ///
+    /// The stream is played from the upstream server and everything it
+    /// sends is fed into a local publisher of the same name, which local
+    /// viewers play like any other stream.
     pub async fn start_relay(
         &self,
         stream_name: String,
@@ -74,28 +62,38 @@ impl RelayServer {
     ) -> Result<()> {
         info!("Starting relay: stream={}, upstream={}", stream_name, upstream_name);
         
-        // Get upstream client
-        let upstreams = self.upstreams.read().await;
-        let upstream = upstreams.get(&upstream_name)
+        // Get upstream URL
+        let url = self.upstreams.read().await.get(&upstream_name).cloned()
             .ok_or_else(|| Error::invalid_state(format!("Upstream not found: {}", upstream_name)))?;
         
-        let upstream_clone = upstream.clone();
-        let stream_name_clone = stream_name.clone();
+        // Play the stream from upstream
+        let client_config = ClientConfig::builder()
+            .auto_reconnect(true)
+            .build()?;
+        let mut client = RtmpClient::with_config(client_config);
+        client.connect(&url).await?;
+        let mut media = client.media_receiver().await;
+        client.play(&stream_name, -2.0, -1.0, true).await?;
+        
+        // Publish it locally
+        let registry = self.server.context().publishers();
+        registry.register(stream_name.clone(), format!("relay-{}", stream_name), 1, None).await?;
+        let publisher = registry.get(&stream_name).await
+            .ok_or_else(|| Error::invalid_state(format!("Relayed stream vanished: {}", stream_name)))?
+            .publisher;
         
-        // Spawn relay task
+        // Spawn relay task, which owns the upstream connection
+        let stream_name_clone = stream_name.clone();
         let task = tokio::spawn(async move {
             info!("Relay task started for stream: {}", stream_name_clone);
             
-            // In a real implementation, this would:
-            // 1. Call upstream.play() to start receiving data
-            // 2. Register handlers to receive video/audio packets
-            // 3. Republish those packets to local server's stream
-            
-            // Placeholder implementation
-            warn!("Relay task for {} is a placeholder. Full implementation needed.", stream_name_clone);
+            while let Some(packet) = media.recv().await {
+                if let Err(e) = publisher.ingest(packet).await {
+                    warn!("Dropping relayed packet for {}: {}", stream_name_clone, e);
+                }
+            }
             
-            // Keep task alive
-            tokio::time::sleep(tokio::time::Duration::from_secs(3600)).await;
+            let _ = client.disconnect().await;
         });
         
         // Store task
@@ -112,6 +110,13 @@ impl RelayServer {
         let mut relay_tasks = self.relay_tasks.write().await;
         if let Some(task) = relay_tasks.remove(stream_name) {
             task.abort();
+            
+            // Tell local viewers the stream ended
+            let registry = self.server.context().publishers();
+            if let Some(info) = registry.get(stream_name).await {
+                registry.unregister(stream_name).await?;
+                info.publisher.end().await;
+            }
             info!("Relay task stopped: {}", stream_name);
         } else {
             warn!("No active relay for stream: {}", stream_name);
@@ -120,6 +125,22 @@ impl RelayServer {
         Ok(())
     }
     
+    /// Log streams appearing and disappearing locally
+    ///
+    /// This is where relays would be started or stopped as local streams
+    /// come and go, instead of polling the registry.
+    pub fn watch_streams(&self) -> tokio::task::JoinHandle<()> {
+        let mut events = self.server.context().publishers().subscribe();
+        tokio::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                match event {
+                    RegistryEvent::Published(name) => info!("Stream published: {}", name),
+                    RegistryEvent::Unpublished(name) => info!("Stream unpublished: {}", name),
+                }
+            }
+        })
+    }
+    
     /// Get upstream server names
     pub async fn list_upstreams(&self) -> Vec<String> {
         let upstreams = self.upstreams.read().await;
@@ -143,7 +164,7 @@ impl RelayServer {
             task.abort();
         }
         
-        // Disconnect from upstreams
+        // Forget upstreams
         let mut upstreams = self.upstreams.write().await;
         upstreams.clear();
         
@@ -198,6 +219,9 @@ async fn main() -> Result<()> {
     // since RelayServer doesn't implement Clone. One approach is to use Arc<RelayServer>
     // or separate shutdown signaling mechanism.
     
+    // Follow local streams
+    relay.watch_streams();
+    
     // Run relay server
     info!("Relay server ready");
     info!("Listening on port 1936");
@@ -98,12 +98,26 @@ async fn main() -> Result<()> {
         "play" => {
             // Start playing
             info!("Playing stream: {}", stream_name);
+            let mut media = client.media_receiver().await;
             client.play(stream_name, 0.0, -1.0, true).await?;
             info!("Playing started");
             
-            // Wait for Ctrl+C
+            // Report received packets until Ctrl+C
             info!("Receiving stream data. Press Ctrl+C to stop");
-            tokio::signal::ctrl_c().await?;
+            loop {
+                tokio::select! {
+                    packet = media.recv() => {
+                        let Some(packet) = packet else { break };
+                        let kind = match packet.message_type() {
+                            rtmp::MSG_TYPE_AUDIO => "audio",
+                            rtmp::MSG_TYPE_VIDEO => "video",
+                            _ => "data",
+                        };
+                        info!("Received {} packet: {} bytes at {}ms", kind, packet.payload.len(), packet.timestamp());
+                    }
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
             info!("Stopping playback");
         }
         _ => {
@@ -80,7 +80,8 @@ impl Amf0Value {
         }
     }
 
-    /// Extract object reference
+    /// Extract object reference; a typed object's class name is dropped,
+    /// use `class_name` to read it
     pub fn as_object(&self) -> Option<&HashMap<String, Amf0Value>> {
         match self {
             Amf0Value::Object(obj) | Amf0Value::EcmaArray(obj) => Some(obj),
@@ -89,6 +90,14 @@ impl Amf0Value {
         }
     }
 
+    /// Class name of a typed object
+    pub fn class_name(&self) -> Option<&str> {
+        match self {
+            Amf0Value::TypedObject(class_name, _) => Some(class_name.as_str()),
+            _ => None,
+        }
+    }
+
     /// Extract array reference
     pub fn as_array(&self) -> Option<&Vec<Amf0Value>> {
         match self {
@@ -119,9 +128,14 @@ pub fn decode_amf0_number(bytes: &[u8]) -> Result<(f64, usize)> {
 }
 
 pub fn encode_amf0_string(value: &str) -> Vec<u8> {
-    let mut buffer = ByteBuffer::with_capacity(3 + value.len());
-    buffer.write_u8(markers::STRING).unwrap();
-    buffer.write_u16_be(value.len() as u16).unwrap();
+    let mut buffer = ByteBuffer::with_capacity(5 + value.len());
+    if value.len() > u16::MAX as usize {
+        buffer.write_u8(markers::LONG_STRING).unwrap();
+        buffer.write_u32_be(value.len() as u32).unwrap();
+    } else {
+        buffer.write_u8(markers::STRING).unwrap();
+        buffer.write_u16_be(value.len() as u16).unwrap();
+    }
     buffer.write_bytes(value.as_bytes()).unwrap();
     buffer.to_vec()
 }
\ No newline at end of file
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use crate::{ByteBuffer, Error, Result};
+use crate::amf::amf0::Amf0Value;
+use crate::amf::decoder::DEFAULT_MAX_DEPTH;
+
+/// AMF3 data types
+#[derive(Debug, Clone, PartialEq)]
+pub enum Amf3Value {
+    Undefined,                                      // 0x00
+    Null,                                           // 0x01
+    Boolean(bool),                                  // 0x02 / 0x03
+    Integer(i32),                                   // 0x04 (29-bit signed)
+    Double(f64),                                    // 0x05
+    String(String),                                 // 0x06
+    XmlDocument(String),                            // 0x07
+    Date(f64),                                      // 0x08
+    Array {                                         // 0x09
+        assoc: HashMap<String, Amf3Value>,
+        dense: Vec<Amf3Value>,
+    },
+    Object {                                        // 0x0A
+        class_name: String,
+        properties: HashMap<String, Amf3Value>,
+    },
+    Xml(String),                                    // 0x0B
+    ByteArray(Vec<u8>),                             // 0x0C
+}
+
+// AMF3 type markers
+pub mod amf3_markers {
+    pub const UNDEFINED: u8 = 0x00;
+    pub const NULL: u8 = 0x01;
+    pub const FALSE: u8 = 0x02;
+    pub const TRUE: u8 = 0x03;
+    pub const INTEGER: u8 = 0x04;       // U29 variable length integer
+    pub const DOUBLE: u8 = 0x05;
+    pub const STRING: u8 = 0x06;        // Referenceable
+    pub const XML_DOCUMENT: u8 = 0x07;  // Referenceable
+    pub const DATE: u8 = 0x08;          // Referenceable
+    pub const ARRAY: u8 = 0x09;         // Referenceable
+    pub const OBJECT: u8 = 0x0A;        // Referenceable, with traits
+    pub const XML: u8 = 0x0B;           // Referenceable
+    pub const BYTE_ARRAY: u8 = 0x0C;    // Referenceable
+
+    // Not used by RTMP command exchanges
+    pub const VECTOR_INT: u8 = 0x0D;
+    pub const VECTOR_UINT: u8 = 0x0E;
+    pub const VECTOR_DOUBLE: u8 = 0x0F;
+    pub const VECTOR_OBJECT: u8 = 0x10;
+    pub const DICTIONARY: u8 = 0x11;
+}
+
+impl Amf3Value {
+    /// Convert to the equivalent AMF0 value so command handlers can treat
+    /// AMF3 payloads the same way as AMF0 ones
+    pub fn to_amf0(&self) -> Amf0Value {
+        match self {
+            Amf3Value::Undefined => Amf0Value::Undefined,
+            Amf3Value::Null => Amf0Value::Null,
+            Amf3Value::Boolean(b) => Amf0Value::Boolean(*b),
+            Amf3Value::Integer(i) => Amf0Value::Number(*i as f64),
+            Amf3Value::Double(n) => Amf0Value::Number(*n),
+            Amf3Value::String(s) => Amf0Value::String(s.clone()),
+            Amf3Value::XmlDocument(xml) | Amf3Value::Xml(xml) => Amf0Value::XmlDocument(xml.clone()),
+            Amf3Value::Date(ms) => Amf0Value::Date(*ms, 0),
+            Amf3Value::Array { assoc, dense } => {
+                if assoc.is_empty() {
+                    Amf0Value::Array(dense.iter().map(|v| v.to_amf0()).collect())
+                } else {
+                    // Mixed arrays become ECMA arrays with the dense part keyed by index
+                    let mut map: HashMap<String, Amf0Value> = assoc.iter()
+                        .map(|(k, v)| (k.clone(), v.to_amf0()))
+                        .collect();
+                    for (i, v) in dense.iter().enumerate() {
+                        map.insert(i.to_string(), v.to_amf0());
+                    }
+                    Amf0Value::EcmaArray(map)
+                }
+            }
+            Amf3Value::Object { class_name, properties } => {
+                let obj = properties.iter()
+                    .map(|(k, v)| (k.clone(), v.to_amf0()))
+                    .collect();
+                if class_name.is_empty() {
+                    Amf0Value::Object(obj)
+                } else {
+                    Amf0Value::TypedObject(class_name.clone(), obj)
+                }
+            }
+            Amf3Value::ByteArray(_) => Amf0Value::Unsupported,
+        }
+    }
+}
+
+/// Object traits (class definition) referenced by AMF3 objects
+#[derive(Debug, Clone)]
+struct Amf3Traits {
+    class_name: String,
+    dynamic: bool,
+    sealed_names: Vec<String>,
+}
+
+pub struct Amf3Decoder<'a> {
+    buffer: &'a mut ByteBuffer,
+    string_refs: Vec<String>,
+    object_refs: Vec<Amf3Value>,
+    trait_refs: Vec<Amf3Traits>,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<'a> Amf3Decoder<'a> {
+    pub fn new(buffer: &'a mut ByteBuffer) -> Self {
+        Amf3Decoder {
+            buffer,
+            string_refs: Vec::new(),
+            object_refs: Vec::new(),
+            trait_refs: Vec::new(),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Limit how deeply objects and arrays may nest
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Check if decoder has remaining data to decode
+    pub fn has_remaining(&self) -> bool {
+        self.buffer.remaining() > 0
+    }
+
+    pub fn decode(&mut self) -> Result<Amf3Value> {
+        let marker = self.buffer.read_u8()?;
+        match marker {
+            amf3_markers::UNDEFINED => Ok(Amf3Value::Undefined),
+            amf3_markers::NULL => Ok(Amf3Value::Null),
+            amf3_markers::FALSE => Ok(Amf3Value::Boolean(false)),
+            amf3_markers::TRUE => Ok(Amf3Value::Boolean(true)),
+            amf3_markers::INTEGER => self.decode_integer(),
+            amf3_markers::DOUBLE => Ok(Amf3Value::Double(self.buffer.read_f64_be()?)),
+            amf3_markers::STRING => Ok(Amf3Value::String(self.read_string()?)),
+            amf3_markers::XML_DOCUMENT => self.decode_xml(true),
+            amf3_markers::DATE => self.decode_date(),
+            amf3_markers::ARRAY => self.decode_nested(Self::decode_array),
+            amf3_markers::OBJECT => self.decode_nested(Self::decode_object),
+            amf3_markers::XML => self.decode_xml(false),
+            amf3_markers::BYTE_ARRAY => self.decode_byte_array(),
+            _ => Err(Error::amf_decode(format!("Unsupported AMF3 marker: 0x{:02x}", marker))),
+        }
+    }
+
+    /// Decode a container one nesting level deeper
+    fn decode_nested(&mut self, decode: fn(&mut Self) -> Result<Amf3Value>) -> Result<Amf3Value> {
+        if self.depth >= self.max_depth {
+            return Err(Error::amf_decode("Max AMF depth exceeded"));
+        }
+
+        self.depth += 1;
+        let value = decode(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// Read a U29 variable length integer (1-4 bytes)
+    fn read_u29(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..3 {
+            let byte = self.buffer.read_u8()?;
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        // Fourth byte contributes all 8 bits
+        let byte = self.buffer.read_u8()?;
+        Ok((value << 8) | byte as u32)
+    }
+
+    fn decode_integer(&mut self) -> Result<Amf3Value> {
+        let value = self.read_u29()?;
+
+        // Sign extend the 29-bit value
+        let value = if value & 0x1000_0000 != 0 {
+            value as i32 - 0x2000_0000
+        } else {
+            value as i32
+        };
+
+        Ok(Amf3Value::Integer(value))
+    }
+
+    /// Read a string, either inline or from the string reference table
+    fn read_string(&mut self) -> Result<String> {
+        let header = self.read_u29()?;
+
+        if header & 1 == 0 {
+            let index = (header >> 1) as usize;
+            return self.string_refs.get(index)
+                .cloned()
+                .ok_or_else(|| Error::amf_decode(format!("Invalid AMF3 string reference: {}", index)));
+        }
+
+        let len = (header >> 1) as usize;
+        let bytes = self.buffer.read_bytes(len)?;
+        let string = String::from_utf8(bytes)
+            .map_err(|e| Error::amf_decode(format!("Invalid UTF-8 in AMF3 string: {}", e)))?;
+
+        // Empty strings are never sent by reference
+        if !string.is_empty() {
+            self.string_refs.push(string.clone());
+        }
+
+        Ok(string)
+    }
+
+    /// Read the header of a referenceable value. Returns the remaining header
+    /// bits, plus the referenced value when the header is a reference.
+    fn read_object_header(&mut self) -> Result<(u32, Option<Amf3Value>)> {
+        let header = self.read_u29()?;
+
+        if header & 1 == 0 {
+            let index = (header >> 1) as usize;
+            let value = self.object_refs.get(index)
+                .cloned()
+                .ok_or_else(|| Error::amf_decode(format!("Invalid AMF3 object reference: {}", index)))?;
+            return Ok((header, Some(value)));
+        }
+
+        Ok((header >> 1, None))
+    }
+
+    fn decode_xml(&mut self, document: bool) -> Result<Amf3Value> {
+        let (len, reference) = self.read_object_header()?;
+        if let Some(value) = reference {
+            return Ok(value);
+        }
+
+        let bytes = self.buffer.read_bytes(len as usize)?;
+        let xml = String::from_utf8(bytes)
+            .map_err(|e| Error::amf_decode(format!("Invalid UTF-8 in AMF3 XML: {}", e)))?;
+
+        let value = if document {
+            Amf3Value::XmlDocument(xml)
+        } else {
+            Amf3Value::Xml(xml)
+        };
+        self.object_refs.push(value.clone());
+        Ok(value)
+    }
+
+    fn decode_date(&mut self) -> Result<Amf3Value> {
+        let (_, reference) = self.read_object_header()?;
+        if let Some(value) = reference {
+            return Ok(value);
+        }
+
+        let value = Amf3Value::Date(self.buffer.read_f64_be()?);
+        self.object_refs.push(value.clone());
+        Ok(value)
+    }
+
+    fn decode_byte_array(&mut self) -> Result<Amf3Value> {
+        let (len, reference) = self.read_object_header()?;
+        if let Some(value) = reference {
+            return Ok(value);
+        }
+
+        let value = Amf3Value::ByteArray(self.buffer.read_bytes(len as usize)?);
+        self.object_refs.push(value.clone());
+        Ok(value)
+    }
+
+    fn decode_array(&mut self) -> Result<Amf3Value> {
+        let (dense_count, reference) = self.read_object_header()?;
+        if let Some(value) = reference {
+            return Ok(value);
+        }
+
+        // Reserve the reference slot before decoding children
+        let index = self.object_refs.len();
+        self.object_refs.push(Amf3Value::Null);
+
+        // Associative part, terminated by an empty key
+        let mut assoc = HashMap::new();
+        loop {
+            let key = self.read_string()?;
+            if key.is_empty() {
+                break;
+            }
+            let value = self.decode()?;
+            assoc.insert(key, value);
+        }
+
+        // Dense part
+        let mut dense = Vec::new();
+        for _ in 0..dense_count {
+            dense.push(self.decode()?);
+        }
+
+        let value = Amf3Value::Array { assoc, dense };
+        self.object_refs[index] = value.clone();
+        Ok(value)
+    }
+
+    fn decode_object(&mut self) -> Result<Amf3Value> {
+        let (header, reference) = self.read_object_header()?;
+        if let Some(value) = reference {
+            return Ok(value);
+        }
+
+        let traits = if header & 1 == 0 {
+            // Traits reference
+            let index = (header >> 1) as usize;
+            self.trait_refs.get(index)
+                .cloned()
+                .ok_or_else(|| Error::amf_decode(format!("Invalid AMF3 traits reference: {}", index)))?
+        } else if header & 2 != 0 {
+            return Err(Error::amf_decode("Externalizable AMF3 objects are not supported"));
+        } else {
+            let dynamic = header & 4 != 0;
+            let sealed_count = header >> 3;
+            let class_name = self.read_string()?;
+
+            // The count comes off the wire, so it does not size the allocation
+            let mut sealed_names = Vec::new();
+            for _ in 0..sealed_count {
+                sealed_names.push(self.read_string()?);
+            }
+
+            let traits = Amf3Traits { class_name, dynamic, sealed_names };
+            self.trait_refs.push(traits.clone());
+            traits
+        };
+
+        // Reserve the reference slot before decoding children
+        let index = self.object_refs.len();
+        self.object_refs.push(Amf3Value::Null);
+
+        let mut properties = HashMap::new();
+        for name in &traits.sealed_names {
+            let value = self.decode()?;
+            properties.insert(name.clone(), value);
+        }
+
+        if traits.dynamic {
+            loop {
+                let key = self.read_string()?;
+                if key.is_empty() {
+                    break;
+                }
+                let value = self.decode()?;
+                properties.insert(key, value);
+            }
+        }
+
+        let value = Amf3Value::Object {
+            class_name: traits.class_name,
+            properties,
+        };
+        self.object_refs[index] = value.clone();
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(bytes: &[u8]) -> Vec<Amf3Value> {
+        let mut buffer = ByteBuffer::new(bytes.to_vec());
+        let mut decoder = Amf3Decoder::new(&mut buffer);
+        let mut values = Vec::new();
+        while decoder.has_remaining() {
+            values.push(decoder.decode().unwrap());
+        }
+        values
+    }
+
+    #[test]
+    fn test_decode_integers() {
+        let values = decode_all(&[
+            0x04, 0x05,                   // 5
+            0x04, 0x81, 0x00,             // 128
+            0x04, 0xFF, 0xFF, 0xFF, 0xFF, // -1
+        ]);
+        assert_eq!(values, vec![
+            Amf3Value::Integer(5),
+            Amf3Value::Integer(128),
+            Amf3Value::Integer(-1),
+        ]);
+    }
+
+    #[test]
+    fn test_decode_string_reference() {
+        // "live" inline, then a reference to string 0
+        let values = decode_all(&[0x06, 0x09, b'l', b'i', b'v', b'e', 0x06, 0x00]);
+        assert_eq!(values[0], Amf3Value::String("live".to_string()));
+        assert_eq!(values[1], Amf3Value::String("live".to_string()));
+    }
+
+    #[test]
+    fn test_decode_dynamic_object() {
+        // Anonymous dynamic object { app: "live", objectEncoding: 3 }
+        let mut bytes = vec![0x0A, 0x0B, 0x01];
+        bytes.extend_from_slice(&[0x07, b'a', b'p', b'p', 0x06, 0x09, b'l', b'i', b'v', b'e']);
+        bytes.push(0x1D);
+        bytes.extend_from_slice(b"objectEncoding");
+        bytes.extend_from_slice(&[0x04, 0x03]);
+        bytes.push(0x01);
+
+        let value = decode_all(&bytes).remove(0);
+        let amf0 = value.to_amf0();
+        assert_eq!(amf0.get_property("app").and_then(|v| v.as_string()), Some("live"));
+        assert_eq!(amf0.get_property("objectEncoding").and_then(|v| v.as_number()), Some(3.0));
+    }
+
+    #[test]
+    fn test_decode_dense_array() {
+        let values = decode_all(&[0x09, 0x05, 0x01, 0x03, 0x05, 0x3F, 0xF0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(values[0].to_amf0(), Amf0Value::Array(vec![
+            Amf0Value::Boolean(true),
+            Amf0Value::Number(1.0),
+        ]));
+    }
+
+    #[test]
+    fn test_deep_nesting_is_bounded() {
+        // Dense arrays of one element, each holding the next
+        let nested = |levels: usize| {
+            let mut bytes = Vec::new();
+            for _ in 0..levels {
+                bytes.extend_from_slice(&[0x09, 0x03, 0x01]);
+            }
+            bytes.push(0x01);
+            bytes
+        };
+
+        let mut buffer = ByteBuffer::new(nested(100));
+        assert!(matches!(Amf3Decoder::new(&mut buffer).decode(), Err(Error::AmfDecode(_))));
+
+        let mut buffer = ByteBuffer::new(nested(DEFAULT_MAX_DEPTH));
+        assert!(Amf3Decoder::new(&mut buffer).decode().is_ok());
+    }
+
+    #[test]
+    fn test_huge_sealed_count_fails_without_allocating() {
+        // Traits claiming 2^25 sealed members, then nothing
+        let mut buffer = ByteBuffer::new(vec![0x0A, 0xFF, 0xFF, 0xFF, 0xFB, 0x01]);
+        assert!(Amf3Decoder::new(&mut buffer).decode().is_err());
+    }
+}
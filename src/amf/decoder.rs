@@ -1,10 +1,18 @@
 use std::collections::HashMap;
 use crate::amf::amf0::{markers, Amf0Value};
+use crate::amf::amf3::Amf3Decoder;
 use crate::{ByteBuffer, Error};
 use crate::Result;
+
+/// Nesting of objects and arrays allowed by default
+pub const DEFAULT_MAX_DEPTH: usize = 32;
+
 pub struct Amf0Decoder<'a> {
     buffer: &'a mut ByteBuffer,
     references: Vec<Amf0Value>,
+    strict: bool,
+    depth: usize,
+    max_depth: usize,
 }
 
 impl<'a> Amf0Decoder<'a> {
@@ -12,9 +20,30 @@ impl<'a> Amf0Decoder<'a> {
         Amf0Decoder {
             buffer,
             references: Vec::new(),
+            strict: false,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
+    /// Limit how deeply objects and arrays may nest
+    ///
+    /// Each level recurses, so unbounded input could overflow the stack.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Hold ECMA arrays to their count
+    ///
+    /// By default the count is only a hint, as many encoders write 0. In
+    /// strict mode an array ends once `count` pairs are read, and an end
+    /// marker arriving before that is an error.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
     /// Check if decoder has remaining data to decode
     pub fn has_remaining(&self) -> bool {
         self.buffer.remaining() > 0
@@ -26,20 +55,34 @@ impl<'a> Amf0Decoder<'a> {
             markers::NUMBER => self.decode_number(),
             markers::BOOLEAN => self.decode_boolean(),
             markers::STRING => self.decode_string(),
-            markers::OBJECT => self.decode_object(),
+            markers::OBJECT => self.decode_nested(Self::decode_object),
             markers::NULL => Ok(Amf0Value::Null),
             markers::UNDEFINED => Ok(Amf0Value::Undefined),
-            markers::ECMA_ARRAY => self.decode_ecma_array(),
-            markers::STRICT_ARRAY => self.decode_strict_array(),
+            markers::ECMA_ARRAY => self.decode_nested(Self::decode_ecma_array),
+            markers::STRICT_ARRAY => self.decode_nested(Self::decode_strict_array),
             markers::DATE => self.decode_date(),
             markers::LONG_STRING => self.decode_long_string(),
             markers::UNSUPPORTED => Ok(Amf0Value::Unsupported),
             markers::XML_DOCUMENT => self.decode_xml_document(),
-            markers::TYPED_OBJECT => self.decode_typed_object(),
+            markers::TYPED_OBJECT => self.decode_nested(Self::decode_typed_object),
+            markers::REFERENCE => self.decode_reference(),
+            markers::AVMPLUS_OBJECT => self.decode_avmplus(),
             _ => Err(Error::protocol(format!("Unknown AMF0 marker: 0x{:02x}", marker))),
         }
     }
 
+    /// Decode a container one nesting level deeper
+    fn decode_nested(&mut self, decode: fn(&mut Self) -> Result<Amf0Value>) -> Result<Amf0Value> {
+        if self.depth >= self.max_depth {
+            return Err(Error::amf_decode("Max AMF depth exceeded"));
+        }
+
+        self.depth += 1;
+        let value = decode(self);
+        self.depth -= 1;
+        value
+    }
+
     fn decode_number(&mut self) -> Result<Amf0Value> {
         let value = self.buffer.read_f64_be()?;
         Ok(Amf0Value::Number(value))
@@ -59,6 +102,7 @@ impl<'a> Amf0Decoder<'a> {
     }
 
     fn decode_object(&mut self) -> Result<Amf0Value> {
+        let index = self.reserve_reference();
         let mut object = HashMap::new();
         loop {
             let name_len = self.buffer.read_u16_be()? as usize;
@@ -71,33 +115,52 @@ impl<'a> Amf0Decoder<'a> {
             let value = self.decode()?;
             object.insert(name, value);
         }
-        Ok(Amf0Value::Object(object))
+        Ok(self.set_reference(index, Amf0Value::Object(object)))
     }
 
     fn decode_ecma_array(&mut self) -> Result<Amf0Value> {
-        let _count = self.buffer.read_u32_be()?; // Array count (not used)
+        let count = self.buffer.read_u32_be()? as usize;
+        let index = self.reserve_reference();
         let mut array = HashMap::new();
+        let mut pairs = 0;
         loop {
+            if self.strict && pairs == count {
+                // The end marker is optional once the count is reached
+                if self.buffer.peek_bytes(3).is_ok_and(|end| end == [0x00, 0x00, markers::OBJECT_END]) {
+                    self.buffer.read_bytes(3)?;
+                }
+                break;
+            }
+
             let name_len = self.buffer.read_u16_be()? as usize;
             if name_len == 0 {
                 self.buffer.read_u8()?; // Array end marker
+                if self.strict {
+                    return Err(Error::protocol(format!(
+                        "ECMA array ended after {} of {} pairs", pairs, count
+                    )));
+                }
                 break;
             }
+            pairs += 1;
             let name = String::from_utf8(self.buffer.read_bytes(name_len)?)
                 .map_err(|e| Error::protocol(format!("Invalid UTF-8 in property name: {}", e)))?;
             let value = self.decode()?;
             array.insert(name, value);
         }
-        Ok(Amf0Value::EcmaArray(array))
+        Ok(self.set_reference(index, Amf0Value::EcmaArray(array)))
     }
 
     fn decode_strict_array(&mut self) -> Result<Amf0Value> {
         let count = self.buffer.read_u32_be()? as usize;
-        let mut array = Vec::with_capacity(count);
+        let index = self.reserve_reference();
+        // Every element takes at least one byte, so a count beyond the
+        // remaining input is bogus and must not drive the allocation
+        let mut array = Vec::with_capacity(count.min(self.buffer.remaining()));
         for _ in 0..count {
             array.push(self.decode()?);
         }
-        Ok(Amf0Value::Array(array))
+        Ok(self.set_reference(index, Amf0Value::Array(array)))
     }
 
     fn decode_date(&mut self) -> Result<Amf0Value> {
@@ -127,6 +190,7 @@ impl<'a> Amf0Decoder<'a> {
         let class_name = String::from_utf8(self.buffer.read_bytes(class_name_len)?)
             .map_err(|e| Error::protocol(format!("Invalid UTF-8 in class name: {}", e)))?;
 
+        let index = self.reserve_reference();
         let mut object = HashMap::new();
         loop {
             let name_len = self.buffer.read_u16_be()? as usize;
@@ -139,6 +203,219 @@ impl<'a> Amf0Decoder<'a> {
             let value = self.decode()?;
             object.insert(name, value);
         }
-        Ok(Amf0Value::TypedObject(class_name, object))
+        Ok(self.set_reference(index, Amf0Value::TypedObject(class_name, object)))
+    }
+
+    /// Decode a reference to a previously decoded complex value
+    fn decode_reference(&mut self) -> Result<Amf0Value> {
+        let index = self.buffer.read_u16_be()? as usize;
+        self.references.get(index)
+            .cloned()
+            .ok_or_else(|| Error::protocol(format!("Invalid AMF0 reference: {}", index)))
     }
-}
\ No newline at end of file
+
+    /// Reserve a reference slot before decoding a complex value's members,
+    /// so nested values get indices in the order they appear on the wire
+    fn reserve_reference(&mut self) -> usize {
+        self.references.push(Amf0Value::Undefined);
+        self.references.len() - 1
+    }
+
+    fn set_reference(&mut self, index: usize, value: Amf0Value) -> Amf0Value {
+        self.references[index] = value.clone();
+        value
+    }
+
+    /// Decode an AMF3 value embedded in the AMF0 stream.
+    /// Each AVMPLUS marker starts a fresh set of AMF3 reference tables.
+    fn decode_avmplus(&mut self) -> Result<Amf0Value> {
+        let value = Amf3Decoder::new(self.buffer)
+            .with_max_depth(self.max_depth - self.depth)
+            .decode()?;
+        Ok(value.to_amf0())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_avmplus_ecma_array_in_command_object() {
+        // Command object { app: "live", params: <AMF3 array { codec: "h264" }, [1]> }
+        let bytes: Vec<u8> = vec![
+            0x03,
+            0x00, 0x03, b'a', b'p', b'p',
+            0x02, 0x00, 0x04, b'l', b'i', b'v', b'e',
+            0x00, 0x06, b'p', b'a', b'r', b'a', b'm', b's',
+            0x11, 0x09, 0x03,
+            0x0B, b'c', b'o', b'd', b'e', b'c',
+            0x06, 0x09, b'h', b'2', b'6', b'4',
+            0x01,
+            0x04, 0x01,
+            0x00, 0x00, 0x09,
+        ];
+        let mut buffer = ByteBuffer::new(bytes);
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+
+        let value = decoder.decode().unwrap();
+        assert!(!decoder.has_remaining());
+        assert_eq!(value.get_property("app").and_then(|v| v.as_string()), Some("live"));
+
+        let params = value.get_property("params").unwrap();
+        assert_eq!(params.get_property("codec").and_then(|v| v.as_string()), Some("h264"));
+        assert_eq!(params.get_property("0").and_then(|v| v.as_number()), Some(1.0));
+    }
+
+    #[test]
+    fn test_decode_references_to_same_object() {
+        // Object { name: "a" } followed by two references to it
+        let bytes: Vec<u8> = vec![
+            0x03,
+            0x00, 0x04, b'n', b'a', b'm', b'e',
+            0x02, 0x00, 0x01, b'a',
+            0x00, 0x00, 0x09,
+            0x07, 0x00, 0x00,
+            0x07, 0x00, 0x00,
+        ];
+        let mut buffer = ByteBuffer::new(bytes);
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+
+        let original = decoder.decode().unwrap();
+        let first = decoder.decode().unwrap();
+        let second = decoder.decode().unwrap();
+
+        assert_eq!(first, original);
+        assert_eq!(second, original);
+        assert_eq!(second.get_property("name").and_then(|v| v.as_string()), Some("a"));
+    }
+
+    #[test]
+    fn test_strict_array_round_trip_with_nested_object() {
+        let mut settings = HashMap::new();
+        settings.insert("codec".to_string(), Amf0Value::String("h264".to_string()));
+        let mut object = HashMap::new();
+        object.insert("width".to_string(), Amf0Value::Number(1280.0));
+        object.insert("settings".to_string(), Amf0Value::EcmaArray(settings));
+
+        let array = Amf0Value::Array(vec![
+            Amf0Value::Number(1.5),
+            Amf0Value::String("live".to_string()),
+            Amf0Value::Object(object),
+        ]);
+
+        let mut encoder = crate::amf::encoder::Amf0Encoder::new();
+        encoder.encode(&array).unwrap();
+        let mut bytes = encoder.get_bytes();
+        // A reference to the array, then to the object inside it
+        bytes.extend_from_slice(&[0x07, 0x00, 0x00, 0x07, 0x00, 0x01]);
+
+        let mut buffer = ByteBuffer::new(bytes);
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+
+        let decoded = decoder.decode().unwrap();
+        assert_eq!(decoded, array);
+        assert_eq!(decoder.decode().unwrap(), array);
+
+        let Amf0Value::Array(items) = &array else { unreachable!() };
+        assert_eq!(decoder.decode().unwrap(), items[2]);
+        assert!(!decoder.has_remaining());
+    }
+
+    #[test]
+    fn test_long_string_round_trip() {
+        let token = "abc".repeat(70000 / 3 + 1)[..70000].to_string();
+
+        let mut encoder = crate::amf::encoder::Amf0Encoder::new();
+        encoder.encode(&Amf0Value::String(token.clone())).unwrap();
+        encoder.encode(&Amf0Value::String("short".to_string())).unwrap();
+        let bytes = encoder.get_bytes();
+        assert_eq!(bytes[0], markers::LONG_STRING);
+        assert_eq!(&bytes[1..5], &70000u32.to_be_bytes());
+
+        let mut buffer = ByteBuffer::new(bytes);
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+        assert_eq!(decoder.decode().unwrap().as_string(), Some(token.as_str()));
+        assert_eq!(decoder.decode().unwrap(), Amf0Value::String("short".to_string()));
+        assert!(!decoder.has_remaining());
+    }
+
+    /// `onMetaData`-style ECMA array { duration: 10 } followed by a string
+    fn ecma_array_then_string(count: u32) -> Vec<u8> {
+        let mut bytes = vec![markers::ECMA_ARRAY];
+        bytes.extend_from_slice(&count.to_be_bytes());
+        bytes.extend_from_slice(&[0x00, 0x08]);
+        bytes.extend_from_slice(b"duration");
+        bytes.push(markers::NUMBER);
+        bytes.extend_from_slice(&10.0f64.to_be_bytes());
+        bytes.extend_from_slice(&[0x00, 0x00, markers::OBJECT_END]);
+        bytes.extend_from_slice(&[markers::STRING, 0x00, 0x02, b'o', b'k']);
+        bytes
+    }
+
+    #[test]
+    fn test_strict_ecma_array_with_matching_count() {
+        let mut metadata = HashMap::new();
+        metadata.insert("duration".to_string(), Amf0Value::Number(10.0));
+        let mut encoder = crate::amf::encoder::Amf0Encoder::new();
+        encoder.encode(&Amf0Value::EcmaArray(metadata.clone())).unwrap();
+        assert!(ecma_array_then_string(1).starts_with(&encoder.get_bytes()));
+
+        let mut buffer = ByteBuffer::new(ecma_array_then_string(1));
+        let mut decoder = Amf0Decoder::new(&mut buffer).with_strict(true);
+
+        assert_eq!(decoder.decode().unwrap(), Amf0Value::EcmaArray(metadata));
+        assert_eq!(decoder.decode().unwrap(), Amf0Value::String("ok".to_string()));
+        assert!(!decoder.has_remaining());
+    }
+
+    #[test]
+    fn test_strict_ecma_array_with_mismatched_count() {
+        let mut buffer = ByteBuffer::new(ecma_array_then_string(2));
+        let mut decoder = Amf0Decoder::new(&mut buffer).with_strict(true);
+        assert!(decoder.decode().is_err());
+
+        // Lenient decoding treats the count as a hint
+        let mut buffer = ByteBuffer::new(ecma_array_then_string(2));
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+        let value = decoder.decode().unwrap();
+        assert_eq!(value.get_property("duration").and_then(|v| v.as_number()), Some(10.0));
+        assert_eq!(decoder.decode().unwrap(), Amf0Value::String("ok".to_string()));
+    }
+
+    /// `levels` objects, each the value of the one around it
+    fn nested_objects(levels: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        for _ in 0..levels {
+            data.extend_from_slice(&[markers::OBJECT, 0x00, 0x01, b'a']);
+        }
+        data.push(markers::NULL);
+        for _ in 0..levels {
+            data.extend_from_slice(&[0x00, 0x00, markers::OBJECT_END]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_deep_nesting_is_bounded() {
+        let mut buffer = ByteBuffer::new(nested_objects(100));
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+        assert!(matches!(decoder.decode(), Err(Error::AmfDecode(_))));
+
+        let mut buffer = ByteBuffer::new(nested_objects(DEFAULT_MAX_DEPTH));
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+        assert!(decoder.decode().is_ok());
+
+        let mut buffer = ByteBuffer::new(nested_objects(100));
+        let mut decoder = Amf0Decoder::new(&mut buffer).with_max_depth(100);
+        assert!(decoder.decode().is_ok());
+    }
+
+    #[test]
+    fn test_decode_invalid_reference() {
+        let mut buffer = ByteBuffer::new(vec![0x07, 0x00, 0x03]);
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+
+        assert!(decoder.decode().is_err());
+    }
+}
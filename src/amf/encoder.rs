@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use crate::amf::amf0::{markers, Amf0Value};
 use crate::ByteBuffer;
-use crate::Result;
+use crate::{Error, Result};
 
 pub struct Amf0Encoder {
     buffer: ByteBuffer,
@@ -38,7 +38,12 @@ impl Amf0Encoder {
         Ok(())
     }
 
+    /// Strings too long for a u16 length go out as a LongString
     fn encode_string(&mut self, value: &str) -> Result<()> {
+        if value.len() > u16::MAX as usize {
+            return self.encode_long_string(value);
+        }
+
         self.buffer.write_u8(markers::STRING)?;
         let bytes = value.as_bytes();
         self.buffer.write_u16_be(bytes.len() as u16)?;
@@ -54,10 +59,7 @@ impl Amf0Encoder {
 
     fn encode_object(&mut self, obj: &HashMap<String, Amf0Value>) -> Result<()> {
         self.buffer.write_u8(markers::OBJECT)?;
-        for (key, value) in obj {
-            self.write_string_no_marker(key)?;
-            self.encode(value)?;
-        }
+        self.encode_properties(obj)?;
         // Object end marker
         self.buffer.write_u16_be(0)?;
         self.buffer.write_u8(markers::OBJECT_END)?;
@@ -77,17 +79,14 @@ impl Amf0Encoder {
     fn encode_ecma_array(&mut self, obj: &HashMap<String, Amf0Value>) -> Result<()> {
         self.buffer.write_u8(markers::ECMA_ARRAY)?;
         self.buffer.write_u32_be(obj.len() as u32)?;
-        for (key, value) in obj {
-            self.write_string_no_marker(key)?;
-            self.encode(value)?;
-        }
+        self.encode_properties(obj)?;
         // Array end marker
         self.buffer.write_u16_be(0)?;
         self.buffer.write_u8(markers::OBJECT_END)?;
         Ok(())
     }
 
-    fn encode_array(&mut self, arr: &Vec<Amf0Value>) -> Result<()> {
+    fn encode_array(&mut self, arr: &[Amf0Value]) -> Result<()> {
         self.buffer.write_u8(markers::STRICT_ARRAY)?;
         self.buffer.write_u32_be(arr.len() as u32)?;
         for value in arr {
@@ -130,19 +129,32 @@ impl Amf0Encoder {
         self.buffer.write_u16_be(bytes.len() as u16)?;
         self.buffer.write_bytes(bytes)?;
 
-        for (key, value) in obj {
-            self.write_string_no_marker(key)?;
-            self.encode(value)?;
-        }
+        self.encode_properties(obj)?;
         // Object end marker
         self.buffer.write_u16_be(0)?;
         self.buffer.write_u8(markers::OBJECT_END)?;
         Ok(())
     }
 
+    /// Write key/value pairs sorted by key, so equal maps encode to equal bytes
+    fn encode_properties(&mut self, obj: &HashMap<String, Amf0Value>) -> Result<()> {
+        let mut entries: Vec<_> = obj.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+
+        for (key, value) in entries {
+            self.write_string_no_marker(key)?;
+            self.encode(value)?;
+        }
+        Ok(())
+    }
+
     /// Helper to write string without type marker (for object keys)
     fn write_string_no_marker(&mut self, value: &str) -> Result<()> {
         let bytes = value.as_bytes();
+        if bytes.len() > u16::MAX as usize {
+            return Err(Error::amf_encode(format!("Key of {} bytes exceeds 65535", bytes.len())));
+        }
+
         self.buffer.write_u16_be(bytes.len() as u16)?;
         self.buffer.write_bytes(bytes)?;
         Ok(())
@@ -151,4 +163,33 @@ impl Amf0Encoder {
     pub fn get_bytes(&self) -> Vec<u8> {
         self.buffer.to_vec()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Amf0Value {
+        let mut obj = HashMap::new();
+        for (i, key) in ["width", "height", "framerate", "videocodecid", "audiocodecid", "encoder"].iter().enumerate() {
+            obj.insert(key.to_string(), Amf0Value::Number(i as f64));
+        }
+        obj.insert("nested".to_string(), Amf0Value::EcmaArray(obj.clone()));
+        Amf0Value::Object(obj)
+    }
+
+    #[test]
+    fn test_object_encoding_is_deterministic() {
+        // Each map gets its own hasher seed, so iteration orders differ
+        let mut first = Amf0Encoder::new();
+        first.encode(&metadata()).unwrap();
+        let mut second = Amf0Encoder::new();
+        second.encode(&metadata()).unwrap();
+        assert_eq!(first.get_bytes(), second.get_bytes());
+
+        // Keys go out sorted: "audiocodecid" first
+        let bytes = first.get_bytes();
+        assert_eq!(&bytes[1..3], &12u16.to_be_bytes());
+        assert_eq!(&bytes[3..15], b"audiocodecid");
+    }
+}
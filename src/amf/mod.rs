@@ -1,7 +1,9 @@
 mod amf0;
+mod amf3;
 mod decoder;
 mod encoder;
 
 pub use amf0::*;
+pub use amf3::*;
 pub use decoder::*;
 pub use encoder::*;
\ No newline at end of file
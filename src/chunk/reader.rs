@@ -1,4 +1,4 @@
-use crate::{ByteBuffer, Error, Result, DEFAULT_CHUNK_SIZE};
+use crate::{ByteBuffer, Error, Result, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_MESSAGE_LENGTH};
 use crate::protocol::{RtmpPacket, RtmpHeader};
 use crate::chunk::stream::{ChunkStream, ChunkStreamContext};
 use std::collections::HashMap;
@@ -11,6 +11,9 @@ pub struct ChunkReader {
     /// Current chunk size for reading
     chunk_size_in: usize,
 
+    /// Largest message length accepted in a header
+    max_message_length: u32,
+
     /// Buffer for reading
     read_buffer: Vec<u8>,
 }
@@ -21,6 +24,7 @@ impl ChunkReader {
         ChunkReader {
             chunk_streams: HashMap::new(),
             chunk_size_in: DEFAULT_CHUNK_SIZE as usize,
+            max_message_length: DEFAULT_MAX_MESSAGE_LENGTH,
             read_buffer: Vec::with_capacity(4096),
         }
     }
@@ -30,34 +34,94 @@ impl ChunkReader {
         self.chunk_size_in = size;
     }
 
-    /// Read next chunk from stream
+    /// Set the largest message length a peer may declare
+    pub fn set_max_message_length(&mut self, length: u32) {
+        self.max_message_length = length;
+    }
+
+    /// Drop the message being assembled on a chunk stream (Abort message)
+    pub fn abort_message(&mut self, cs_id: u32) {
+        if let Some(context) = self.chunk_streams.get_mut(&cs_id) {
+            context.abort_message();
+        }
+    }
+
+    /// Reject declared lengths above the limit before anything is allocated
+    fn check_message_length(&self, message_length: u32) -> Result<()> {
+        if message_length > self.max_message_length {
+            return Err(Error::chunk(format!(
+                "Message length {} exceeds maximum {}",
+                message_length, self.max_message_length
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read chunks until the next message is complete
+    ///
+    /// Returns `Ok(None)` once the peer closes the stream between messages.
+    /// Closing partway through a message is an error.
     pub async fn read_chunk<R: AsyncRead + Unpin>(
         &mut self,
         reader: &mut R
     ) -> Result<Option<RtmpPacket>> {
-        // Read basic header (1-3 bytes)
-        let mut basic_header = [0u8; 1];
-        reader.read_exact(&mut basic_header).await
-            .map_err(|e| Error::chunk(format!("Failed to read basic header: {}", e)))?;
+        loop {
+            // Read the first basic header byte, where a clean close shows up
+            let mut basic_header = [0u8; 1];
+            let read = reader.read(&mut basic_header).await
+                .map_err(|e| Error::chunk(format!("Failed to read basic header: {}", e)))?;
 
-        let (fmt, cs_id) = self.parse_basic_header(basic_header[0], reader).await?;
+            if read == 0 {
+                if self.chunk_streams.values().any(|context| context.is_assembling()) {
+                    return Err(Error::chunk("Connection closed in the middle of a message"));
+                }
+                return Ok(None);
+            }
+
+            if let Some(packet) = self.read_chunk_after(basic_header[0], reader).await? {
+                return Ok(Some(packet));
+            }
+        }
+    }
+
+    /// Read the rest of a chunk whose first byte is `first_byte`
+    async fn read_chunk_after<R: AsyncRead + Unpin>(
+        &mut self,
+        first_byte: u8,
+        reader: &mut R
+    ) -> Result<Option<RtmpPacket>> {
+        // Rest of the basic header (1-3 bytes)
+        let (fmt, cs_id) = self.parse_basic_header(first_byte, reader).await?;
 
         // Get previous header for delta calculations (if exists)
         let prev_header = self.chunk_streams.get(&cs_id).and_then(|ctx| ctx.prev_header.clone());
 
         // Read message header based on fmt
-        let header = self.read_message_header(fmt, cs_id, prev_header, reader).await?;
+        let (header, extended) = self.read_message_header(fmt, cs_id, prev_header, reader).await?;
 
-        // Get or create chunk stream context
+        // Get or create chunk stream context. Assembly state lives here, so
+        // chunks of messages on different chunk streams may interleave.
         let context = self.chunk_streams.entry(cs_id)
             .or_insert_with(ChunkStreamContext::new);
 
+        // Type 3 chunks repeat the extended timestamp of the header they continue
+        if fmt == 3 {
+            if context.extended_timestamp {
+                let mut ext_bytes = [0u8; 4];
+                reader.read_exact(&mut ext_bytes).await
+                    .map_err(|e| Error::chunk(format!("Failed to read extended timestamp: {}", e)))?;
+            }
+        } else {
+            context.extended_timestamp = extended;
+        }
+
         // Start new message if not continuing
         if !context.is_assembling() {
             context.start_message(header.clone());
         }
 
-        // Calculate chunk data size
+        // Calculate chunk data size; a zero-length message has no data and
+        // completes with this chunk
         let chunk_data_size = if context.bytes_remaining > self.chunk_size_in {
             self.chunk_size_in
         } else {
@@ -103,13 +167,15 @@ impl ChunkReader {
     }
 
     /// Read message header based on format type
+    ///
+    /// Also returns whether the header carried an extended timestamp.
     async fn read_message_header<R: AsyncRead + Unpin>(
         &mut self,
         fmt: u8,
         cs_id: u32,
         prev_header: Option<RtmpHeader>,
         reader: &mut R
-    ) -> Result<RtmpHeader> {
+    ) -> Result<(RtmpHeader, bool)> {
         match fmt {
             0 => {
                 // Type 0: Full header (11 bytes)
@@ -117,9 +183,11 @@ impl ChunkReader {
                 reader.read_exact(&mut header_bytes).await
                     .map_err(|e| Error::chunk(format!("Failed to read type 0 header: {}", e)))?;
 
-                let timestamp = u32::from_be_bytes([0, header_bytes[0], header_bytes[1], header_bytes[2]]);
-                let message_length = u32::from_be_bytes([0, header_bytes[3], header_bytes[4], header_bytes[5]]);
-                let message_type = header_bytes[6];
+                let mut header = ByteBuffer::new(header_bytes.to_vec());
+                let timestamp = header.read_u24_be()?;
+                let message_length = header.read_u24_be()?;
+                self.check_message_length(message_length)?;
+                let message_type = header.read_u8()?;
                 let message_stream_id = u32::from_le_bytes([
                     header_bytes[7], header_bytes[8], header_bytes[9], header_bytes[10]
                 ]);
@@ -134,13 +202,13 @@ impl ChunkReader {
                     timestamp
                 };
 
-                Ok(RtmpHeader::new(
+                Ok((RtmpHeader::new(
                     final_timestamp,
                     message_length,
                     message_type,
                     message_stream_id,
                     cs_id,
-                ))
+                ), timestamp == 0xFFFFFF))
             }
             1 => {
                 // Type 1: Same stream ID (7 bytes)
@@ -148,9 +216,11 @@ impl ChunkReader {
                 reader.read_exact(&mut header_bytes).await
                     .map_err(|e| Error::chunk(format!("Failed to read type 1 header: {}", e)))?;
 
-                let timestamp_delta = u32::from_be_bytes([0, header_bytes[0], header_bytes[1], header_bytes[2]]);
-                let message_length = u32::from_be_bytes([0, header_bytes[3], header_bytes[4], header_bytes[5]]);
-                let message_type = header_bytes[6];
+                let mut header = ByteBuffer::new(header_bytes.to_vec());
+                let timestamp_delta = header.read_u24_be()?;
+                let message_length = header.read_u24_be()?;
+                self.check_message_length(message_length)?;
+                let message_type = header.read_u8()?;
 
                 // Check for extended timestamp
                 let final_timestamp_delta = if timestamp_delta == 0xFFFFFF {
@@ -166,13 +236,13 @@ impl ChunkReader {
                 let prev = prev_header.ok_or_else(|| Error::chunk("Type 1 header requires previous header"))?;
                 let timestamp = prev.timestamp.wrapping_add(final_timestamp_delta);
 
-                Ok(RtmpHeader::new(
+                Ok((RtmpHeader::new(
                     timestamp,
                     message_length,
                     message_type,
                     prev.message_stream_id, // Reuse stream ID
                     cs_id,
-                ))
+                ), timestamp_delta == 0xFFFFFF))
             }
             2 => {
                 // Type 2: Same length and stream ID (3 bytes)
@@ -180,7 +250,7 @@ impl ChunkReader {
                 reader.read_exact(&mut header_bytes).await
                     .map_err(|e| Error::chunk(format!("Failed to read type 2 header: {}", e)))?;
 
-                let timestamp_delta = u32::from_be_bytes([0, header_bytes[0], header_bytes[1], header_bytes[2]]);
+                let timestamp_delta = ByteBuffer::new(header_bytes.to_vec()).read_u24_be()?;
 
                 // Check for extended timestamp
                 let final_timestamp_delta = if timestamp_delta == 0xFFFFFF {
@@ -196,19 +266,220 @@ impl ChunkReader {
                 let prev = prev_header.ok_or_else(|| Error::chunk("Type 2 header requires previous header"))?;
                 let timestamp = prev.timestamp.wrapping_add(final_timestamp_delta);
 
-                Ok(RtmpHeader::new(
+                Ok((RtmpHeader::new(
                     timestamp,
                     prev.message_length,   // Reuse
                     prev.message_type,     // Reuse
                     prev.message_stream_id, // Reuse
                     cs_id,
-                ))
+                ), timestamp_delta == 0xFFFFFF))
             }
             3 => {
-                // Type 3: No header - reuse everything from previous
-                prev_header.ok_or_else(|| Error::chunk("Type 3 header requires previous header"))
+                // Type 3: No header - reuse everything from previous. Any
+                // extended timestamp is read by the caller, which tracks it.
+                let prev = prev_header.ok_or_else(|| Error::chunk("Type 3 header requires previous header"))?;
+                Ok((prev, false))
             }
             _ => Err(Error::chunk(format!("Invalid chunk format: {}", fmt)))
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ChunkWriter;
+    use crate::protocol::make_video_packet;
+    use crate::MSG_TYPE_VIDEO;
+
+    #[tokio::test]
+    async fn test_type3_chunks_carry_extended_timestamp() {
+        let timestamp = 0x0123_4567u32;
+        let payload: Vec<u8> = (0..300).map(|i| i as u8).collect();
+
+        // Type 0 header with extended timestamp, then two type 3 continuations
+        // that each repeat the 4 byte extended field, as ffmpeg writes them
+        let mut bytes = vec![0x06, 0xFF, 0xFF, 0xFF, 0x00, 0x01, 0x2C, MSG_TYPE_VIDEO, 1, 0, 0, 0];
+        bytes.extend_from_slice(&timestamp.to_be_bytes());
+        bytes.extend_from_slice(&payload[..128]);
+        for chunk in payload[128..].chunks(128) {
+            bytes.push(0xC6);
+            bytes.extend_from_slice(&timestamp.to_be_bytes());
+            bytes.extend_from_slice(chunk);
+        }
+
+        let mut reader = ChunkReader::new();
+        let mut input = std::io::Cursor::new(bytes);
+        let mut packet = None;
+        while packet.is_none() {
+            packet = reader.read_chunk(&mut input).await.unwrap();
+        }
+
+        let packet = packet.unwrap();
+        assert_eq!(packet.timestamp(), timestamp);
+        assert_eq!(packet.payload, payload);
+        assert_eq!(input.position() as usize, input.get_ref().len());
+    }
+
+    #[tokio::test]
+    async fn test_extended_timestamp_round_trip() {
+        let mut writer = ChunkWriter::new();
+        let mut reader = ChunkReader::new();
+
+        let first = make_video_packet(vec![0x17; 500], 0x0100_0000, 1);
+        let second = make_video_packet(vec![0x27; 500], 0x0100_0000, 1);
+        let mut output = Vec::new();
+        writer.write_packet(&first, &mut output).await.unwrap();
+        writer.write_packet(&second, &mut output).await.unwrap();
+
+        let mut input = std::io::Cursor::new(output);
+        let mut packets = Vec::new();
+        while packets.len() < 2 {
+            if let Some(packet) = reader.read_chunk(&mut input).await.unwrap() {
+                packets.push(packet);
+            }
+        }
+
+        assert_eq!(packets[0].payload, first.payload);
+        assert_eq!(packets[1].payload, second.payload);
+        assert!(packets.iter().all(|p| p.timestamp() == 0x0100_0000));
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_chunk_streams_reassemble() {
+        let mut writer = ChunkWriter::new();
+        let audio: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let video: Vec<u8> = (0..300).map(|i| (i as u8).wrapping_mul(7)).collect();
+
+        // Message A on cs_id 6, message B on cs_id 4
+        let mut a = make_video_packet(video.clone(), 1000, 1);
+        a.header.chunk_stream_id = 6;
+        let mut b = crate::protocol::make_audio_packet(audio.clone(), 1000, 1);
+        b.header.chunk_stream_id = 4;
+
+        let a_bytes = writer.create_chunks(&a).unwrap();
+        let b_bytes = writer.create_chunks(&b).unwrap();
+
+        // Both are a 12 byte type 0 chunk of 128 bytes, then type 3 chunks
+        // of 128 and 44 bytes; alternate them A, B, A, B, A, B
+        let split = |bytes: &[u8]| {
+            vec![bytes[..140].to_vec(), bytes[140..269].to_vec(), bytes[269..].to_vec()]
+        };
+        let mut interleaved = Vec::new();
+        for (chunk_a, chunk_b) in split(&a_bytes).into_iter().zip(split(&b_bytes)) {
+            interleaved.extend(chunk_a);
+            interleaved.extend(chunk_b);
+        }
+
+        let mut reader = ChunkReader::new();
+        let mut input = std::io::Cursor::new(interleaved);
+        let mut packets = Vec::new();
+        while packets.len() < 2 {
+            if let Some(packet) = reader.read_chunk(&mut input).await.unwrap() {
+                packets.push(packet);
+            }
+        }
+
+        assert_eq!(packets[0].header.chunk_stream_id, 6);
+        assert_eq!(packets[0].payload, video);
+        assert!(packets[0].is_video());
+        assert_eq!(packets[1].header.chunk_stream_id, 4);
+        assert_eq!(packets[1].payload, audio);
+        assert!(packets[1].is_audio());
+    }
+
+    #[tokio::test]
+    async fn test_close_between_messages_is_clean() {
+        let mut writer = ChunkWriter::new();
+        let bytes = writer.create_chunks(&make_video_packet(vec![0x17; 300], 0, 1)).unwrap();
+
+        let mut reader = ChunkReader::new();
+        let mut input = std::io::Cursor::new(bytes);
+        assert!(reader.read_chunk(&mut input).await.unwrap().is_some());
+        assert!(reader.read_chunk(&mut input).await.unwrap().is_none());
+
+        // Nothing was ever sent
+        let mut reader = ChunkReader::new();
+        assert!(reader.read_chunk(&mut std::io::Cursor::new(Vec::new())).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_close_mid_message_is_error() {
+        let mut writer = ChunkWriter::new();
+        let bytes = writer.create_chunks(&make_video_packet(vec![0x17; 300], 0, 1)).unwrap();
+
+        // Cut off at the boundary after the first chunk
+        let mut reader = ChunkReader::new();
+        let result = reader.read_chunk(&mut std::io::Cursor::new(bytes[..140].to_vec())).await;
+        assert!(matches!(result, Err(Error::Chunk(_))));
+
+        // Cut off inside a chunk
+        let mut reader = ChunkReader::new();
+        let result = reader.read_chunk(&mut std::io::Cursor::new(bytes[..200].to_vec())).await;
+        assert!(matches!(result, Err(Error::Chunk(_))));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_length_rejected() {
+        let mut reader = ChunkReader::new();
+        reader.set_max_message_length(1024);
+
+        // Type 0 header declaring a 16MB - 1 video message
+        let bytes = vec![0x06, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, MSG_TYPE_VIDEO, 1, 0, 0, 0];
+        let result = reader.read_chunk(&mut std::io::Cursor::new(bytes)).await;
+        assert!(matches!(result, Err(Error::Chunk(_))));
+
+        // The default limit still admits ordinary messages
+        let mut reader = ChunkReader::new();
+        let mut writer = ChunkWriter::new();
+        let bytes = writer.create_chunks(&make_video_packet(vec![0x17; 100], 0, 1)).unwrap();
+        let packet = reader.read_chunk(&mut std::io::Cursor::new(bytes)).await.unwrap();
+        assert_eq!(packet.unwrap().payload.len(), 100);
+    }
+
+    #[test]
+    fn test_declared_length_is_not_preallocated() {
+        let mut context = ChunkStreamContext::new();
+        let mut header = make_video_packet(vec![0x27; 4], 0, 1).header;
+        header.message_length = DEFAULT_MAX_MESSAGE_LENGTH;
+
+        // A header whose body never arrives costs nothing up front
+        context.start_message(header);
+        assert_eq!(context.message_buffer.capacity(), 0);
+
+        context.add_chunk_data(vec![0x27; 128]).unwrap();
+        assert_eq!(context.message_buffer.len(), 128);
+    }
+
+    #[test]
+    fn test_assembled_length_mismatch_rejected() {
+        let mut context = ChunkStreamContext::new();
+        let header = make_video_packet(vec![0x27; 4], 0, 1).header;
+
+        // More bytes than the header declared, as a delta bug could produce
+        context.start_message(header);
+        let result = context.add_chunk_data(vec![0x27; 6]);
+        assert!(matches!(result, Err(Error::Chunk(_))));
+        assert!(!context.is_assembling());
+
+        // The chunk stream still assembles the next message
+        context.start_message(header);
+        let packet = context.add_chunk_data(vec![0x27; 4]).unwrap().unwrap();
+        assert_eq!(packet.payload.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_zero_length_message_completes_immediately() {
+        // Type 0 header on a fresh chunk stream declaring an empty data message
+        let bytes = vec![0x05, 0x00, 0x00, 0x0A, 0x00, 0x00, 0x00, crate::MSG_TYPE_DATA_AMF0, 1, 0, 0, 0];
+
+        let mut reader = ChunkReader::new();
+        let mut input = std::io::Cursor::new(bytes);
+        let packet = reader.read_chunk(&mut input).await.unwrap().unwrap();
+        assert!(packet.payload.is_empty());
+        assert_eq!(packet.header.chunk_stream_id, 5);
+        assert_eq!(packet.timestamp(), 10);
+
+        // Nothing is left half assembled, so the close is clean
+        assert!(reader.read_chunk(&mut input).await.unwrap().is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use log::warn;
 use crate::{Error, Result};
 use crate::protocol::{RtmpPacket, RtmpHeader};
 
@@ -17,6 +18,10 @@ pub struct ChunkStreamContext {
 
     /// Timestamp delta accumulator
     pub timestamp_delta: u32,
+
+    /// Whether the last message header carried an extended timestamp,
+    /// which type 3 chunks on this stream then repeat
+    pub extended_timestamp: bool,
 }
 
 impl ChunkStreamContext {
@@ -28,6 +33,7 @@ impl ChunkStreamContext {
             bytes_remaining: 0,
             current_header: None,
             timestamp_delta: 0,
+            extended_timestamp: false,
         }
     }
 
@@ -46,6 +52,21 @@ impl ChunkStreamContext {
 
             // Message complete
             if let Some(header) = self.current_header.take() {
+                // Assembly must yield exactly the declared length
+                let assembled = self.message_buffer.len();
+                if assembled != header.message_length as usize {
+                    warn!(
+                        chunk_stream = header.chunk_stream_id, stream_id = header.message_stream_id;
+                        "Assembled {} bytes for a {} byte message", assembled, header.message_length
+                    );
+                    self.message_buffer.clear();
+                    self.prev_header = Some(header);
+                    return Err(Error::chunk(format!(
+                        "Assembled {} bytes but message length is {}",
+                        assembled, header.message_length
+                    )));
+                }
+
                 let packet = RtmpPacket::new(
                     header.clone(),
                     self.message_buffer.clone()
@@ -66,13 +87,21 @@ impl ChunkStreamContext {
         Ok(None)
     }
 
+    /// Discard the partially assembled message, keeping `prev_header` so
+    /// later chunks can still be decoded against it
+    pub fn abort_message(&mut self) {
+        self.current_header = None;
+        self.bytes_remaining = 0;
+        self.message_buffer.clear();
+    }
+
     /// Start new message
     pub fn start_message(&mut self, header: RtmpHeader) {
         self.current_header = Some(header.clone());
         self.prev_header = Some(header.clone()); // Update for next chunk
         self.bytes_remaining = header.message_length as usize;
+        // Grown as chunks arrive, so declared lengths alone allocate nothing
         self.message_buffer.clear();
-        self.message_buffer.reserve(header.message_length as usize);
     }
 }
 
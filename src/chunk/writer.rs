@@ -25,14 +25,13 @@ impl ChunkWriter {
         self.chunk_size_out = size;
     }
 
-    /// Write packet as chunks
+    /// Write packet as chunks, returning the number of bytes written
     pub async fn write_packet<W: AsyncWrite + Unpin>(
         &mut self,
         packet: &RtmpPacket,
         writer: &mut W
-    ) -> Result<()> {
-        let cs_id = packet.header.chunk_stream_id;
-        let chunks = self.create_chunks(packet)?;
+    ) -> Result<usize> {
+        let chunks = self.serialize(packet)?;
 
         writer.write_all(&chunks).await
             .map_err(|e| Error::chunk(format!("Failed to write chunks: {}", e)))?;
@@ -40,10 +39,20 @@ impl ChunkWriter {
         writer.flush().await
             .map_err(|e| Error::chunk(format!("Failed to flush: {}", e)))?;
 
-        // Store header for delta encoding
-        self.prev_headers.insert(cs_id, packet.header.clone());
+        Ok(chunks.len())
+    }
+
+    /// Chunk a packet for sending, without writing it anywhere
+    ///
+    /// Later headers are delta encoded against this packet, so the bytes
+    /// must be sent before those of any packet serialized after it.
+    pub fn serialize(&mut self, packet: &RtmpPacket) -> Result<Vec<u8>> {
+        let chunks = self.create_chunks(packet)?;
+
+        // Store header for delta encoding, with the length actually sent
+        self.prev_headers.insert(packet.header.chunk_stream_id, wire_header(packet));
 
-        Ok(())
+        Ok(chunks)
     }
 
     /// Create chunks from packet
@@ -52,16 +61,23 @@ impl ChunkWriter {
         let cs_id = packet.header.chunk_stream_id;
 
         // Determine chunk format type
-        let (fmt, header_bytes) = self.get_header_bytes(packet)?;
+        let header = wire_header(packet);
+        let (fmt, header_bytes) = self.get_header_bytes(&header)?;
 
-        // Calculate number of chunks needed
+        // A zero-length message is a single chunk of header alone
         let payload_len = packet.payload.len();
-        let num_chunks = (payload_len + self.chunk_size_out - 1) / self.chunk_size_out;
 
         // Write first chunk with full header
         result.extend_from_slice(&self.encode_basic_header(fmt, cs_id));
         result.extend_from_slice(&header_bytes);
 
+        // Continuation chunks repeat an extended timestamp field
+        let extended_timestamp = if fmt != 3 && header_bytes.starts_with(&[0xFF, 0xFF, 0xFF]) {
+            Some(&header_bytes[header_bytes.len() - 4..])
+        } else {
+            None
+        };
+
         // Write first chunk data
         let first_chunk_size = payload_len.min(self.chunk_size_out);
         result.extend_from_slice(&packet.payload[0..first_chunk_size]);
@@ -71,6 +87,9 @@ impl ChunkWriter {
         while offset < payload_len {
             // Type 3 header (no message header)
             result.extend_from_slice(&self.encode_basic_header(3, cs_id));
+            if let Some(extended) = extended_timestamp {
+                result.extend_from_slice(extended);
+            }
 
             // Chunk data
             let chunk_end = (offset + self.chunk_size_out).min(payload_len);
@@ -83,33 +102,38 @@ impl ChunkWriter {
     }
 
     /// Get header bytes and format type
-    fn get_header_bytes(&self, packet: &RtmpPacket) -> Result<(u8, Vec<u8>)> {
-        let cs_id = packet.header.chunk_stream_id;
+    fn get_header_bytes(&self, header: &RtmpHeader) -> Result<(u8, Vec<u8>)> {
+        let cs_id = header.chunk_stream_id;
+
+        // Check if we have previous header. Deltas are unsigned, so a timestamp
+        // going backwards (seek, B-frame reordering) needs a full type 0 header.
+        if let Some(prev) = self.prev_headers.get(&cs_id)
+            .filter(|prev| header.timestamp >= prev.timestamp)
+        {
+            let delta = header.timestamp.wrapping_sub(prev.timestamp);
 
-        // Check if we have previous header
-        if let Some(prev) = self.prev_headers.get(&cs_id) {
             // Can we use type 1, 2, or 3?
-            if prev.message_stream_id == packet.header.message_stream_id &&
-                prev.message_type == packet.header.message_type &&
-                prev.message_length == packet.header.message_length {
-                // Type 3: No header needed (continuation)
-                if packet.header.timestamp == prev.timestamp {
+            if prev.message_stream_id == header.message_stream_id &&
+                prev.message_type == header.message_type &&
+                prev.message_length == header.message_length {
+                // Type 3: No header needed (continuation). A previous header
+                // may have used an extended timestamp that type 3 would have
+                // to repeat, so only use it for timestamps that never need one.
+                if delta == 0 && prev.timestamp < 0xFFFFFF {
                     return Ok((3, vec![]));
                 }
                 // Type 2: Timestamp delta only
-                let delta = packet.header.timestamp - prev.timestamp;
                 return Ok((2, self.encode_type2_header(delta)));
             }
 
-            if prev.message_stream_id == packet.header.message_stream_id {
+            if prev.message_stream_id == header.message_stream_id {
                 // Type 1: Same stream ID
-                let delta = packet.header.timestamp - prev.timestamp;
-                return Ok((1, self.encode_type1_header(delta, packet)?));
+                return Ok((1, self.encode_type1_header(delta, header)?));
             }
         }
 
         // Type 0: Full header
-        Ok((0, self.encode_type0_header(packet)?))
+        Ok((0, self.encode_type0_header(header)?))
     }
 
     /// Encode basic header
@@ -135,64 +159,42 @@ impl ChunkWriter {
     }
 
     /// Encode type 0 header (11 bytes + optional extended timestamp)
-    fn encode_type0_header(&self, packet: &RtmpPacket) -> Result<Vec<u8>> {
+    fn encode_type0_header(&self, header: &RtmpHeader) -> Result<Vec<u8>> {
         let mut buffer = ByteBuffer::with_capacity(15);
 
         // Timestamp (3 bytes) or 0xFFFFFF for extended
-        if packet.header.timestamp >= 0xFFFFFF {
-            buffer.write_u8(0xFF)?;
-            buffer.write_u8(0xFF)?;
-            buffer.write_u8(0xFF)?;
-        } else {
-            buffer.write_u8((packet.header.timestamp >> 16) as u8)?;
-            buffer.write_u8((packet.header.timestamp >> 8) as u8)?;
-            buffer.write_u8(packet.header.timestamp as u8)?;
-        }
+        buffer.write_u24_be(header.timestamp.min(0xFFFFFF))?;
 
         // Message length (3 bytes)
-        let len = packet.payload.len() as u32;
-        buffer.write_u8((len >> 16) as u8)?;
-        buffer.write_u8((len >> 8) as u8)?;
-        buffer.write_u8(len as u8)?;
+        buffer.write_u24_be(header.message_length)?;
 
         // Message type (1 byte)
-        buffer.write_u8(packet.header.message_type)?;
+        buffer.write_u8(header.message_type)?;
 
         // Message stream ID (4 bytes, little endian)
-        let stream_id = packet.header.message_stream_id.to_le_bytes();
+        let stream_id = header.message_stream_id.to_le_bytes();
         buffer.write_bytes(&stream_id)?;
 
         // Extended timestamp if needed
-        if packet.header.timestamp >= 0xFFFFFF {
-            buffer.write_u32_be(packet.header.timestamp)?;
+        if header.timestamp >= 0xFFFFFF {
+            buffer.write_u32_be(header.timestamp)?;
         }
 
         Ok(buffer.to_vec())
     }
 
     /// Encode type 1 header (7 bytes + optional extended timestamp)
-    fn encode_type1_header(&self, timestamp_delta: u32, packet: &RtmpPacket) -> Result<Vec<u8>> {
+    fn encode_type1_header(&self, timestamp_delta: u32, header: &RtmpHeader) -> Result<Vec<u8>> {
         let mut buffer = ByteBuffer::with_capacity(11);
 
         // Timestamp delta (3 bytes)
-        if timestamp_delta >= 0xFFFFFF {
-            buffer.write_u8(0xFF)?;
-            buffer.write_u8(0xFF)?;
-            buffer.write_u8(0xFF)?;
-        } else {
-            buffer.write_u8((timestamp_delta >> 16) as u8)?;
-            buffer.write_u8((timestamp_delta >> 8) as u8)?;
-            buffer.write_u8(timestamp_delta as u8)?;
-        }
+        buffer.write_u24_be(timestamp_delta.min(0xFFFFFF))?;
 
         // Message length (3 bytes)
-        let len = packet.payload.len() as u32;
-        buffer.write_u8((len >> 16) as u8)?;
-        buffer.write_u8((len >> 8) as u8)?;
-        buffer.write_u8(len as u8)?;
+        buffer.write_u24_be(header.message_length)?;
 
         // Message type (1 byte)
-        buffer.write_u8(packet.header.message_type)?;
+        buffer.write_u8(header.message_type)?;
 
         // Extended timestamp if needed
         if timestamp_delta >= 0xFFFFFF {
@@ -207,17 +209,152 @@ impl ChunkWriter {
         let mut buffer = ByteBuffer::with_capacity(7);
 
         // Timestamp delta (3 bytes)
+        buffer.write_u24_be(timestamp_delta.min(0xFFFFFF)).unwrap();
         if timestamp_delta >= 0xFFFFFF {
-            buffer.write_u8(0xFF).unwrap();
-            buffer.write_u8(0xFF).unwrap();
-            buffer.write_u8(0xFF).unwrap();
             buffer.write_u32_be(timestamp_delta).unwrap();
-        } else {
-            buffer.write_u8((timestamp_delta >> 16) as u8).unwrap();
-            buffer.write_u8((timestamp_delta >> 8) as u8).unwrap();
-            buffer.write_u8(timestamp_delta as u8).unwrap();
         }
 
         buffer.to_vec()
     }
-}
\ No newline at end of file
+}
+
+/// Packet header with `message_length` taken from the payload
+///
+/// Readers size type 2 and 3 messages from the previous header, so the
+/// length compared and stored here must be the one actually sent.
+fn wire_header(packet: &RtmpPacket) -> RtmpHeader {
+    let mut header = packet.header;
+    header.message_length = packet.payload.len() as u32;
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::make_video_packet;
+
+    #[tokio::test]
+    async fn test_descending_timestamp_uses_type0_header() {
+        let mut writer = ChunkWriter::new();
+        let mut output = Vec::new();
+
+        let first = make_video_packet(vec![0x27, 0x01], 2000, 1);
+        writer.write_packet(&first, &mut output).await.unwrap();
+
+        // Same chunk stream, earlier timestamp
+        let second = make_video_packet(vec![0x27, 0x01, 0x02], 1000, 1);
+        let chunks = writer.create_chunks(&second).unwrap();
+
+        assert_eq!(chunks[0] >> 6, 0);
+        let timestamp = u32::from_be_bytes([0, chunks[1], chunks[2], chunks[3]]);
+        assert_eq!(timestamp, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_publish_sequence_uses_delta_headers() {
+        use crate::protocol::make_audio_packet;
+        use crate::RtmpData;
+
+        let metadata = RtmpData::with_stream_metadata(1280.0, 720.0, "avc1", "mp4a", 30.0).encode().unwrap();
+        let mut packets = vec![
+            RtmpPacket::new(RtmpHeader::data(0, metadata.len() as u32, 1), metadata),
+            make_video_packet(vec![0x17, 0x00, 0x00, 0x00, 0x00, 0x01, 0x64, 0x00, 0x1F, 0xFF], 0, 1),
+            make_audio_packet(vec![0xAF, 0x00, 0x12, 0x10], 0, 1),
+        ];
+        for i in 0..10 {
+            packets.push(make_audio_packet(vec![0xAF, 0x01, 0x21, 0x10, 0x04, i as u8], i * 23, 1));
+            packets.push(make_video_packet(vec![0x27; 20 + i as usize], i * 33, 1));
+        }
+
+        let mut writer = ChunkWriter::new();
+        let mut output = Vec::new();
+        let mut seen = std::collections::HashSet::new();
+        for packet in &packets {
+            let fmt = writer.create_chunks(packet).unwrap()[0] >> 6;
+            let cs_id = packet.header.chunk_stream_id;
+
+            // A full header only opens each chunk stream
+            let expected = if seen.insert(cs_id) {
+                0
+            } else if packet.is_audio() && packet.timestamp() > 0 {
+                // Same length and type as the previous audio frame
+                2
+            } else {
+                // New length (first audio frame after the sequence header, or video)
+                1
+            };
+            assert_eq!(fmt, expected, "cs_id {} at {}ms", cs_id, packet.timestamp());
+
+            writer.write_packet(packet, &mut output).await.unwrap();
+        }
+
+        // The deltas decode back to the original timestamps
+        let mut reader = crate::chunk::ChunkReader::new();
+        let mut input = std::io::Cursor::new(output);
+        for packet in &packets {
+            let decoded = reader.read_chunk(&mut input).await.unwrap().unwrap();
+            assert_eq!(decoded.header.chunk_stream_id, packet.header.chunk_stream_id);
+            assert_eq!(decoded.timestamp(), packet.timestamp());
+            assert_eq!(decoded.payload, packet.payload);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_message_length_is_corrected() {
+        let mut writer = ChunkWriter::new();
+        let mut output = Vec::new();
+
+        let first = make_video_packet(vec![0x27, 0x01], 1000, 1);
+        writer.write_packet(&first, &mut output).await.unwrap();
+
+        // Header still claims the first packet's length
+        let mut second = make_video_packet(vec![0x27, 0x01, 0x02, 0x03], 1040, 1);
+        second.header.message_length = 2;
+        writer.write_packet(&second, &mut output).await.unwrap();
+
+        // Length changed, so the second message needs a type 1 header
+        let second_start = output.len() - (1 + 7 + second.payload.len());
+        assert_eq!(output[second_start] >> 6, 1);
+
+        let mut reader = crate::chunk::ChunkReader::new();
+        let mut input = std::io::Cursor::new(output);
+        let decoded_first = reader.read_chunk(&mut input).await.unwrap().unwrap();
+        let decoded_second = reader.read_chunk(&mut input).await.unwrap().unwrap();
+
+        assert_eq!(decoded_first.payload, first.payload);
+        assert_eq!(decoded_second.payload, second.payload);
+        assert_eq!(decoded_second.header.message_length, 4);
+        assert_eq!(decoded_second.timestamp(), 1040);
+    }
+
+    #[tokio::test]
+    async fn test_zero_length_message_round_trip() {
+        let empty = RtmpPacket::new(RtmpHeader::data(500, 0, 1), Vec::new());
+        let after = make_video_packet(vec![0x27, 0x01], 540, 1);
+
+        let mut writer = ChunkWriter::new();
+        let mut output = Vec::new();
+        writer.write_packet(&empty, &mut output).await.unwrap();
+
+        // Basic header and type 0 message header, no data
+        assert_eq!(output.len(), 1 + 11);
+        assert_eq!(output[0] >> 6, 0);
+        assert_eq!(&output[4..7], &[0, 0, 0]);
+
+        // Again on the same chunk stream, then a message that follows it
+        writer.write_packet(&empty, &mut output).await.unwrap();
+        writer.write_packet(&after, &mut output).await.unwrap();
+
+        let mut reader = crate::chunk::ChunkReader::new();
+        let mut input = std::io::Cursor::new(output);
+        for _ in 0..2 {
+            let decoded = reader.read_chunk(&mut input).await.unwrap().unwrap();
+            assert!(decoded.payload.is_empty());
+            assert_eq!(decoded.header.message_length, 0);
+            assert_eq!(decoded.timestamp(), 500);
+        }
+        let decoded = reader.read_chunk(&mut input).await.unwrap().unwrap();
+        assert_eq!(decoded.payload, after.payload);
+        assert!(reader.read_chunk(&mut input).await.unwrap().is_none());
+    }
+}
@@ -1,17 +1,49 @@
 use std::collections::HashMap;
+use log::warn;
 use crate::{Error, Result};
-use crate::connection::{Connection, ConnectionContext};
+use crate::amf::Amf0Value;
+use crate::connection::{Connection, ConnectionContext, ConnectionState};
 use crate::handshake::{C0C1, S0S1S2, C2};
-use crate::protocol::{RtmpCommand, RtmpPacket, RtmpData};
+use crate::protocol::{RtmpCommand, RtmpPacket, RtmpData, MSG_TYPE_AUDIO, MSG_TYPE_VIDEO, MSG_TYPE_DATA_AMF0, MSG_TYPE_DATA_AMF3};
 use crate::message::MessageDispatcher;
-use tokio::net::TcpStream;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::stream::{is_aac_sequence_header, is_avc_sequence_header};
+use crate::transport::{Connector, TcpConnector};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
+use tokio::sync::{Mutex, Notify, RwLock, mpsc, oneshot};
+use tokio::task::JoinHandle;
 use url::Url;
 use crate::client::config::ClientConfig;
+use crate::client::handlers::{FcStatusHandler, IgnoreHandler, MediaHandler, MediaSink, PendingTransactions, ResponseHandler};
 use crate::client::state::ClientState;
 
+/// Received packets a media receiver may hold before delivery waits
+pub const MEDIA_QUEUE_SIZE: usize = 256;
+
+/// Last publish or play request, re-issued after a reconnect
+#[derive(Debug, Clone)]
+enum StreamRequest {
+    Publish {
+        stream_name: String,
+        publish_type: String,
+    },
+    Play {
+        stream_name: String,
+        start: f64,
+        duration: f64,
+        reset: bool,
+    },
+}
+
+/// Headers a publisher has sent, re-sent after a reconnect so the
+/// server can decode the resumed stream
+#[derive(Debug, Clone, Default)]
+struct StreamHeaders {
+    metadata: Option<Vec<u8>>,
+    audio_config: Option<Vec<u8>>,
+    video_config: Option<Vec<u8>>,
+}
+
 pub struct RtmpClient {
     /// Client configuration
     config: Arc<ClientConfig>,
@@ -19,8 +51,8 @@ pub struct RtmpClient {
     /// Client state
     state: Arc<RwLock<ClientState>>,
 
-    /// Connection
-    connection: Option<Arc<Connection>>,
+    /// Connection, replaced on reconnect
+    connection: Arc<RwLock<Option<Arc<Connection>>>>,
 
     /// Server URL
     url: Option<Url>,
@@ -36,6 +68,24 @@ pub struct RtmpClient {
 
     /// Transaction ID counter
     transaction_id: Arc<RwLock<f64>>,
+
+    /// Commands awaiting a `_result` or `_error` response
+    pending: PendingTransactions,
+
+    /// Last publish or play request
+    request: Arc<RwLock<Option<StreamRequest>>>,
+
+    /// Headers sent while publishing
+    headers: Arc<RwLock<StreamHeaders>>,
+
+    /// Receives played media, once `media_receiver` is called
+    media: MediaSink,
+
+    /// Signalled when a connection's processing task ends
+    session_closed: Arc<Notify>,
+
+    /// Reconnect supervisor, when auto-reconnect is enabled
+    supervisor: Option<JoinHandle<()>>,
 }
 
 impl RtmpClient {
@@ -49,17 +99,87 @@ impl RtmpClient {
         RtmpClient {
             config: Arc::new(config),
             state: Arc::new(RwLock::new(ClientState::Disconnected)),
-            connection: None,
+            connection: Arc::new(RwLock::new(None)),
             url: None,
             app: None,
             stream_name: None,
             stream_id: Arc::new(RwLock::new(None)),
             transaction_id: Arc::new(RwLock::new(1.0)),
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            request: Arc::new(RwLock::new(None)),
+            headers: Arc::new(RwLock::new(StreamHeaders::default())),
+            media: Arc::new(RwLock::new(None)),
+            session_closed: Arc::new(Notify::new()),
+            supervisor: None,
         }
     }
 
     /// Connect to RTMP server
+    ///
+    /// With `auto_reconnect` set, a lost connection is re-established in the
+    /// background and the last publish or play is re-issued.
     pub async fn connect(&mut self, url: &str) -> Result<()> {
+        self.establish(url).await?;
+
+        if self.config.auto_reconnect && self.supervisor.is_none() {
+            let client = self.share();
+            let url = url.to_string();
+            self.supervisor = Some(tokio::spawn(supervise(client, url)));
+        }
+
+        Ok(())
+    }
+
+    /// Connect over an already open transport, such as an in-memory stream
+    ///
+    /// `url` only supplies the app and tcUrl sent in `connect`; no socket is
+    /// opened and the connection is not re-established if lost.
+    pub async fn connect_stream<S>(&mut self, stream: S, url: &str) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (_, app) = self.prepare_connect(url).await?;
+        self.start_session(stream, &app, url).await
+    }
+
+    /// Connect through `connector` instead of TCP, such as to a Unix socket
+    ///
+    /// The connector is given the URL's host and port. The connection is
+    /// not re-established if lost.
+    pub async fn connect_with<C: Connector>(&mut self, connector: &C, url: &str) -> Result<()> {
+        let (parsed_url, app) = self.prepare_connect(url).await?;
+        let (host, port) = url_host_port(&parsed_url)?;
+
+        let stream = connector.connect(host, port).await?;
+        self.start_session(stream, &app, url).await
+    }
+
+    /// Open the transport, handshake and send `connect`
+    async fn establish(&mut self, url: &str) -> Result<()> {
+        let (parsed_url, app) = self.prepare_connect(url).await?;
+        let (host, port) = url_host_port(&parsed_url)?;
+
+        // Connect TCP
+        let stream = TcpConnector.connect(host, port).await?;
+
+        // Wrap in TLS for rtmps
+        if parsed_url.scheme() == "rtmps" {
+            #[cfg(feature = "tls")]
+            {
+                let host = host.to_string();
+                let stream = crate::client::tls::connect_tls(stream, &host, &self.config).await?;
+                return self.start_session(stream, &app, url).await;
+            }
+
+            #[cfg(not(feature = "tls"))]
+            return Err(Error::config("rtmps requires the `tls` feature"));
+        }
+
+        self.start_session(stream, &app, url).await
+    }
+
+    /// Parse `url`, remember it and its app, and enter `Connecting`
+    async fn prepare_connect(&mut self, url: &str) -> Result<(Url, String)> {
         // Parse URL
         let parsed_url = Url::parse(url)
             .map_err(|e| Error::config(format!("Invalid URL: {}", e)))?;
@@ -70,13 +190,8 @@ impl RtmpClient {
             scheme => return Err(Error::config(format!("Unsupported scheme: {}", scheme))),
         }
 
-        // Extract components
-        let host = parsed_url.host_str()
-            .ok_or_else(|| Error::config("Missing host in URL"))?;
-        let port = parsed_url.port().unwrap_or(1935);
-        let path = parsed_url.path().trim_start_matches('/');
-
         // Parse app and stream name
+        let path = parsed_url.path().trim_start_matches('/');
         let parts: Vec<&str> = path.split('/').collect();
         let app = parts.get(0).map(|s| s.to_string())
             .unwrap_or_else(|| "live".to_string());
@@ -91,14 +206,14 @@ impl RtmpClient {
             *state = ClientState::Connecting;
         }
 
-        // Connect TCP
-        let addr = format!("{}:{}", host, port);
-        let stream = TcpStream::connect(&addr).await
-            .map_err(|e| Error::connection(format!("Failed to connect to {}: {}", addr, e)))?;
-
-        // Set TCP options
-        stream.set_nodelay(true)?;
+        Ok((parsed_url, app))
+    }
 
+    /// Perform the RTMP handshake over `stream` and start the connection
+    async fn start_session<S>(&mut self, stream: S, app: &str, url: &str) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
         // Perform client handshake
         let stream = self.client_handshake(stream).await?;
 
@@ -109,27 +224,46 @@ impl RtmpClient {
             packet_tx,
         ));
 
-        let dispatcher = Arc::new(MessageDispatcher::new());
-        // Register client handlers...
+        // Status notifications are not surfaced yet, but must not tear the
+        // connection down
+        let mut dispatcher = MessageDispatcher::new();
+        dispatcher.set_default_handler(Arc::new(IgnoreHandler));
+        let response_handler = Arc::new(ResponseHandler { pending: self.pending.clone() });
+        dispatcher.register_command("_result".to_string(), response_handler.clone()).await;
+        dispatcher.register_command("_error".to_string(), response_handler).await;
+        dispatcher.register_command("onStatus".to_string(), Arc::new(IgnoreHandler)).await;
+        let fc_status_handler = Arc::new(FcStatusHandler { url: url.to_string() });
+        dispatcher.register_command("onFCPublish".to_string(), fc_status_handler.clone()).await;
+        dispatcher.register_command("onFCUnpublish".to_string(), fc_status_handler).await;
+
+        // Played media goes to the application's receiver
+        let media_handler = Arc::new(MediaHandler { sink: self.media.clone() });
+        for message_type in [MSG_TYPE_AUDIO, MSG_TYPE_VIDEO, MSG_TYPE_DATA_AMF0, MSG_TYPE_DATA_AMF3] {
+            dispatcher.register_handler(message_type, media_handler.clone()).await;
+        }
+        let dispatcher = Arc::new(dispatcher);
 
         let connection = Arc::new(Connection::new(
             "client".to_string(),
             conn_context,
             dispatcher,
+            packet_rx,
         ));
 
-        self.connection = Some(connection.clone());
+        *self.connection.write().await = Some(connection.clone());
 
         // Start connection processing
         let connection_clone = connection.clone();
+        let session_closed = self.session_closed.clone();
         tokio::spawn(async move {
             if let Err(e) = connection_clone.process_client(stream).await {
                 eprintln!("Client connection error: {}", e);
             }
+            session_closed.notify_one();
         });
 
         // Send connect command
-        self.send_connect(&app, url).await?;
+        self.send_connect(app, url).await?;
 
         // Update state
         {
@@ -141,38 +275,41 @@ impl RtmpClient {
     }
 
     /// Perform client handshake
-    async fn client_handshake(&self, stream: TcpStream) -> Result<TcpStream> {
-        let (mut reader, mut writer) = stream.into_split();
-        
+    async fn client_handshake<S>(&self, mut stream: S) -> Result<S>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
         // Send C0+C1
         let c0c1 = C0C1::create_client();
-        writer.write_all(&c0c1.encode()).await?;
-        writer.flush().await?;
+        stream.write_all(&c0c1.encode()).await?;
+        stream.flush().await?;
 
         // Read S0+S1+S2
         let mut s0s1s2_buf = vec![0u8; 3073];
-        reader.read_exact(&mut s0s1s2_buf).await?;
+        stream.read_exact(&mut s0s1s2_buf).await?;
         let s0s1s2 = S0S1S2::parse(&s0s1s2_buf)?;
 
         // Send C2
         let c2 = C2::create_from_s1(&s0s1s2);
-        writer.write_all(&c2.encode()).await?;
-        writer.flush().await?;
+        stream.write_all(&c2.encode()).await?;
+        stream.flush().await?;
 
-        // Reunite stream
-        let stream = reader.reunite(writer)
-            .map_err(|e| Error::io(format!("Failed to reunite stream: {}", e)))?;
         Ok(stream)
     }
 
+    /// Current connection
+    async fn connection(&self) -> Result<Arc<Connection>> {
+        self.connection.read().await.clone()
+            .ok_or_else(|| Error::invalid_state("Not connected"))
+    }
+
     /// Send connect command
     async fn send_connect(&self, app: &str, tc_url: &str) -> Result<()> {
         let mut tid = self.transaction_id.write().await;
         let connect_cmd = RtmpCommand::connect(app, tc_url);
         *tid += 1.0;
 
-        let connection = self.connection.as_ref()
-            .ok_or_else(|| Error::invalid_state("Not connected"))?;
+        let connection = self.connection().await?;
 
         let bytes = connect_cmd.encode()?;
         let header = crate::protocol::RtmpHeader::command(0, bytes.len() as u32, 0);
@@ -184,22 +321,52 @@ impl RtmpClient {
 
     /// Create stream for publishing/playing
     pub async fn create_stream(&self) -> Result<u32> {
-        let connection = self.connection.as_ref()
-            .ok_or_else(|| Error::invalid_state("Not connected"))?;
+        let connection = self.connection().await?;
 
-        let mut tid = self.transaction_id.write().await;
-        let cmd = RtmpCommand::create_stream(*tid);
-        *tid += 1.0;
+        let transaction_id = {
+            let mut tid = self.transaction_id.write().await;
+            let current = *tid;
+            *tid += 1.0;
+            current
+        };
+
+        // Register for the response before sending so it cannot be missed
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().await.insert(transaction_id as u64, tx);
 
+        let cmd = RtmpCommand::create_stream(transaction_id);
         let bytes = cmd.encode()?;
         let header = crate::protocol::RtmpHeader::command(0, bytes.len() as u32, 0);
         let packet = RtmpPacket::new(header, bytes);
 
-        connection.send_packet(packet).await?;
+        if let Err(e) = connection.send_packet(packet).await {
+            self.pending.lock().await.remove(&(transaction_id as u64));
+            return Err(e);
+        }
+
+        let response = match tokio::time::timeout(self.config.command_timeout, rx).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => return Err(Error::connection("Connection closed before createStream response")),
+            Err(_) => {
+                self.pending.lock().await.remove(&(transaction_id as u64));
+                return Err(Error::timeout("Timed out waiting for createStream response"));
+            }
+        };
+
+        if response.name == "_error" {
+            // The error object carries the reason, e.g. too many streams
+            let description = response.arguments.first()
+                .and_then(|v| v.as_object())
+                .and_then(|info| info.get("description"))
+                .and_then(|v| v.as_string())
+                .unwrap_or("no description");
+            return Err(Error::stream(format!("Server rejected createStream: {}", description)));
+        }
 
-        // Wait for response (simplified - real impl would use futures)
-        // For now, return placeholder
-        let stream_id = 1;
+        let stream_id = response.arguments.first()
+            .and_then(|v| v.as_number())
+            .ok_or_else(|| Error::protocol("Missing stream ID in createStream response"))?
+            as u32;
 
         let mut sid = self.stream_id.write().await;
         *sid = Some(stream_id);
@@ -207,7 +374,28 @@ impl RtmpClient {
         Ok(stream_id)
     }
 
+    /// Send an FMLE stream command such as `FCPublish`, without waiting
+    /// for its reply
+    async fn send_stream_command(&self, name: &str, stream_name: &str) -> Result<()> {
+        let transaction_id = {
+            let mut tid = self.transaction_id.write().await;
+            let current = *tid;
+            *tid += 1.0;
+            current
+        };
+
+        let mut cmd = RtmpCommand::new(name.to_string(), transaction_id);
+        cmd.command_object = Some(Amf0Value::Null);
+        cmd.arguments.push(Amf0Value::String(stream_name.to_string()));
+
+        let bytes = cmd.encode()?;
+        let header = crate::protocol::RtmpHeader::command(0, bytes.len() as u32, 0);
+        self.connection().await?.send_packet(RtmpPacket::new(header, bytes)).await
+    }
+
     /// Publish stream
+    ///
+    /// With `fmle_publish` set, `releaseStream` and `FCPublish` go first.
     pub async fn publish(&mut self, stream_name: &str, publish_type: &str) -> Result<()> {
         // Ensure connected
         let state = *self.state.read().await;
@@ -215,6 +403,12 @@ impl RtmpClient {
             return Err(Error::invalid_state("Must be connected to publish"));
         }
 
+        // FMLE announces the stream before creating it
+        if self.config.fmle_publish {
+            self.send_stream_command("releaseStream", stream_name).await?;
+            self.send_stream_command("FCPublish", stream_name).await?;
+        }
+
         // Create stream if needed
         if self.stream_id.read().await.is_none() {
             self.create_stream().await?;
@@ -229,13 +423,16 @@ impl RtmpClient {
         let header = crate::protocol::RtmpHeader::command(0, bytes.len() as u32, stream_id);
         let packet = RtmpPacket::new(header, bytes);
 
-        let connection = self.connection.as_ref()
-            .ok_or_else(|| Error::invalid_state("Not connected"))?;
+        let connection = self.connection().await?;
 
         connection.send_packet(packet).await?;
 
         // Update state
         self.stream_name = Some(stream_name.to_string());
+        *self.request.write().await = Some(StreamRequest::Publish {
+            stream_name: stream_name.to_string(),
+            publish_type: publish_type.to_string(),
+        });
         let mut state = self.state.write().await;
         *state = ClientState::Publishing;
 
@@ -264,19 +461,34 @@ impl RtmpClient {
         let header = crate::protocol::RtmpHeader::command(0, bytes.len() as u32, stream_id);
         let packet = RtmpPacket::new(header, bytes);
 
-        let connection = self.connection.as_ref()
-            .ok_or_else(|| Error::invalid_state("Not connected"))?;
+        let connection = self.connection().await?;
 
         connection.send_packet(packet).await?;
 
         // Update state
         self.stream_name = Some(stream_name.to_string());
+        *self.request.write().await = Some(StreamRequest::Play {
+            stream_name: stream_name.to_string(),
+            start,
+            duration,
+            reset,
+        });
         let mut state = self.state.write().await;
         *state = ClientState::Playing;
 
         Ok(())
     }
 
+    /// Receive the audio, video and data messages of played streams
+    ///
+    /// Call before `play` so nothing is missed; a later call replaces the
+    /// earlier receiver. The receiver keeps working across reconnects.
+    pub async fn media_receiver(&self) -> mpsc::Receiver<RtmpPacket> {
+        let (tx, rx) = mpsc::channel(MEDIA_QUEUE_SIZE);
+        *self.media.write().await = Some(tx);
+        rx
+    }
+
     /// Send audio data
     pub async fn send_audio(&self, data: Vec<u8>, timestamp: u32) -> Result<()> {
         let state = *self.state.read().await;
@@ -287,10 +499,13 @@ impl RtmpClient {
         let stream_id = self.stream_id.read().await
             .ok_or_else(|| Error::invalid_state("No stream ID"))?;
 
+        if is_aac_sequence_header(&data) {
+            self.headers.write().await.audio_config = Some(data.clone());
+        }
+
         let packet = crate::protocol::make_audio_packet(data, timestamp, stream_id);
 
-        let connection = self.connection.as_ref()
-            .ok_or_else(|| Error::invalid_state("Not connected"))?;
+        let connection = self.connection().await?;
 
         connection.send_packet(packet).await
     }
@@ -305,10 +520,13 @@ impl RtmpClient {
         let stream_id = self.stream_id.read().await
             .ok_or_else(|| Error::invalid_state("No stream ID"))?;
 
+        if is_avc_sequence_header(&data) {
+            self.headers.write().await.video_config = Some(data.clone());
+        }
+
         let packet = crate::protocol::make_video_packet(data, timestamp, stream_id);
 
-        let connection = self.connection.as_ref()
-            .ok_or_else(|| Error::invalid_state("Not connected"))?;
+        let connection = self.connection().await?;
 
         connection.send_packet(packet).await
     }
@@ -325,23 +543,39 @@ impl RtmpClient {
 
         let data_msg = RtmpData::on_metadata(metadata);
         let bytes = data_msg.encode()?;
+        self.headers.write().await.metadata = Some(bytes.clone());
+
         let header = crate::protocol::RtmpHeader::data(0, bytes.len() as u32, stream_id);
         let packet = RtmpPacket::new(header, bytes);
 
-        let connection = self.connection.as_ref()
-            .ok_or_else(|| Error::invalid_state("Not connected"))?;
+        let connection = self.connection().await?;
 
         connection.send_packet(packet).await
     }
 
     /// Disconnect from server
     pub async fn disconnect(&mut self) -> Result<()> {
-        if let Some(connection) = &self.connection {
+        // Stop the supervisor first so closing is not mistaken for a drop
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.abort();
+        }
+
+        // Queued ahead of the close, which writes what is queued
+        let publishing = *self.state.read().await == ClientState::Publishing;
+        if self.config.fmle_publish
+            && publishing
+            && let Some(stream_name) = self.stream_name.clone()
+        {
+            self.send_stream_command("FCUnpublish", &stream_name).await?;
+        }
+
+        if let Some(connection) = self.connection.write().await.take() {
             connection.close().await?;
         }
 
-        self.connection = None;
-        self.stream_id = Arc::new(RwLock::new(None));
+        *self.stream_id.write().await = None;
+        *self.request.write().await = None;
+        *self.headers.write().await = StreamHeaders::default();
         self.stream_name = None;
 
         let mut state = self.state.write().await;
@@ -354,4 +588,452 @@ impl RtmpClient {
     pub async fn state(&self) -> ClientState {
         *self.state.read().await
     }
-}
\ No newline at end of file
+
+    /// Another client over the same shared state, driven by the supervisor
+    fn share(&self) -> RtmpClient {
+        RtmpClient {
+            config: self.config.clone(),
+            state: self.state.clone(),
+            connection: self.connection.clone(),
+            url: self.url.clone(),
+            app: self.app.clone(),
+            stream_name: self.stream_name.clone(),
+            stream_id: self.stream_id.clone(),
+            transaction_id: self.transaction_id.clone(),
+            pending: self.pending.clone(),
+            request: self.request.clone(),
+            headers: self.headers.clone(),
+            media: self.media.clone(),
+            session_closed: self.session_closed.clone(),
+            supervisor: None,
+        }
+    }
+
+    /// Retry with exponential backoff; false once attempts are exhausted
+    async fn reconnect(&mut self, url: &str) -> bool {
+        for attempt in 0..self.config.max_reconnect_attempts {
+            tokio::time::sleep(self.config.reconnect_backoff(attempt)).await;
+
+            match self.resume(url).await {
+                Ok(()) => return true,
+                Err(e) => {
+                    warn!(url:% = url; "Reconnect attempt {} failed: {}", attempt + 1, e);
+                    // Tear down a half-established session before retrying
+                    if let Some(connection) = self.connection.write().await.take() {
+                        let _ = connection.close().await;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Connect again and re-issue the last publish or play
+    async fn resume(&mut self, url: &str) -> Result<()> {
+        // Stream IDs do not survive the connection
+        *self.stream_id.write().await = None;
+
+        self.establish(url).await?;
+
+        let request = self.request.read().await.clone();
+        match request {
+            Some(StreamRequest::Publish { stream_name, publish_type }) => {
+                self.publish(&stream_name, &publish_type).await?;
+                self.resend_headers().await?;
+            }
+            Some(StreamRequest::Play { stream_name, start, duration, reset }) => {
+                self.play(&stream_name, start, duration, reset).await?;
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Re-send metadata and codec configs ahead of resumed media
+    async fn resend_headers(&self) -> Result<()> {
+        let headers = self.headers.read().await.clone();
+
+        if let Some(metadata) = headers.metadata {
+            let stream_id = self.stream_id.read().await
+                .ok_or_else(|| Error::invalid_state("No stream ID"))?;
+            let header = crate::protocol::RtmpHeader::data(0, metadata.len() as u32, stream_id);
+            self.connection().await?.send_packet(RtmpPacket::new(header, metadata)).await?;
+        }
+        if let Some(config) = headers.audio_config {
+            self.send_audio(config, 0).await?;
+        }
+        if let Some(config) = headers.video_config {
+            self.send_video(config, 0).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for RtmpClient {
+    fn drop(&mut self) {
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.abort();
+        }
+    }
+}
+
+/// Host and port an RTMP URL points at, 1935 unless given
+fn url_host_port(url: &Url) -> Result<(&str, u16)> {
+    let host = url.host_str()
+        .ok_or_else(|| Error::config("Missing host in URL"))?;
+    Ok((host, url.port().unwrap_or(1935)))
+}
+
+/// Watch for the connection dropping and reconnect until attempts run out
+async fn supervise(mut client: RtmpClient, url: String) {
+    loop {
+        client.session_closed.notified().await;
+
+        // Ignore wakeups from sessions that were already replaced
+        if let Some(connection) = client.connection.read().await.clone()
+            && connection.state().await != ConnectionState::Closed
+        {
+            continue;
+        }
+
+        // Fail sends fast while reconnecting
+        *client.state.write().await = ClientState::Connecting;
+
+        if !client.reconnect(&url).await {
+            *client.state.write().await = ClientState::Error;
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{ChunkReader, ChunkWriter};
+    use crate::handshake::generate_s0s1s2;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    /// Accept one client, complete the handshake and answer createStream
+    /// with the given stream ID (or never answer when `None`)
+    async fn mock_server(listener: TcpListener, stream_id: Option<f64>) {
+        let (socket, _) = listener.accept().await.unwrap();
+        serve_mock_session(socket, stream_id).await;
+    }
+
+    async fn serve_mock_session<S>(socket: S, stream_id: Option<f64>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        serve_session_until(socket, stream_id, |_| false).await;
+    }
+
+    /// Serve a session until the peer leaves or `stop` returns true for a
+    /// received packet, then drop the socket
+    async fn serve_session_until<S, F>(socket: S, stream_id: Option<f64>, stop: F)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        F: FnMut(&RtmpPacket) -> bool,
+    {
+        let answer = |command: &RtmpCommand| {
+            stream_id.map(|id| RtmpCommand::result(command.transaction_id, Amf0Value::Number(id)))
+        };
+        serve_session_with(socket, answer, stop).await;
+    }
+
+    /// Serve a session, answering createStream with whatever `answer`
+    /// returns (nothing when `None`)
+    async fn serve_session_with<S, A, F>(mut socket: S, mut answer: A, mut stop: F)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        A: FnMut(&RtmpCommand) -> Option<RtmpCommand>,
+        F: FnMut(&RtmpPacket) -> bool,
+    {
+        let mut c0c1 = vec![0u8; 1537];
+        socket.read_exact(&mut c0c1).await.unwrap();
+        let s0s1s2 = generate_s0s1s2(&C0C1::parse(&c0c1).unwrap()).unwrap();
+        socket.write_all(&s0s1s2).await.unwrap();
+        socket.flush().await.unwrap();
+        let mut c2 = vec![0u8; 1536];
+        socket.read_exact(&mut c2).await.unwrap();
+
+        let mut reader = ChunkReader::new();
+        let mut writer = ChunkWriter::new();
+        loop {
+            let Ok(Some(packet)) = reader.read_chunk(&mut socket).await else {
+                return;
+            };
+            if stop(&packet) {
+                return;
+            }
+            if packet.message_type() != crate::MSG_TYPE_COMMAND_AMF0 {
+                continue;
+            }
+            let command = RtmpCommand::decode(&packet.payload).unwrap();
+            if command.name != "createStream" {
+                continue;
+            }
+
+            if let Some(response) = answer(&command) {
+                let bytes = response.encode().unwrap();
+                let header = crate::protocol::RtmpHeader::command(0, bytes.len() as u32, 0);
+                writer.write_packet(&RtmpPacket::new(header, bytes), &mut socket).await.unwrap();
+                socket.flush().await.unwrap();
+            }
+        }
+    }
+
+    /// Command name, or the media kind for audio and video
+    fn packet_label(packet: &RtmpPacket) -> String {
+        match packet.message_type() {
+            crate::MSG_TYPE_AUDIO => "audio".to_string(),
+            crate::MSG_TYPE_VIDEO => "video".to_string(),
+            crate::MSG_TYPE_DATA_AMF0 => "data".to_string(),
+            crate::MSG_TYPE_COMMAND_AMF0 => RtmpCommand::decode(&packet.payload).unwrap().name,
+            other => format!("type {}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_stream_uses_server_stream_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(mock_server(listener, Some(7.0)));
+
+        let mut client = RtmpClient::new();
+        client.connect(&format!("rtmp://127.0.0.1:{}/live", port)).await.unwrap();
+
+        assert_eq!(client.create_stream().await.unwrap(), 7);
+        assert_eq!(*client.stream_id.read().await, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_create_stream_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(mock_server(listener, None));
+
+        let config = ClientConfig::builder()
+            .command_timeout(Duration::from_millis(200))
+            .build()
+            .unwrap();
+        let mut client = RtmpClient::with_config(config);
+        client.connect(&format!("rtmp://127.0.0.1:{}/live", port)).await.unwrap();
+
+        assert!(matches!(client.create_stream().await, Err(Error::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_stream_error_is_surfaced() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let answer = |command: &RtmpCommand| {
+                let mut error = HashMap::new();
+                error.insert("level".to_string(), Amf0Value::String("error".to_string()));
+                error.insert("code".to_string(), Amf0Value::String("NetConnection.Call.Failed".to_string()));
+                error.insert("description".to_string(), Amf0Value::String("Stream limit reached".to_string()));
+                Some(RtmpCommand::error(command.transaction_id, Amf0Value::Object(error)))
+            };
+            serve_session_with(socket, answer, |_| false).await;
+        });
+
+        let mut client = RtmpClient::new();
+        client.connect(&format!("rtmp://127.0.0.1:{}/live", port)).await.unwrap();
+
+        let Err(Error::Stream(message)) = client.create_stream().await else {
+            panic!("createStream should fail with a stream error");
+        };
+        assert!(message.contains("Stream limit reached"));
+        assert_eq!(*client.stream_id.read().await, None);
+    }
+
+    #[cfg(not(feature = "tls"))]
+    #[tokio::test]
+    async fn test_rtmps_requires_tls_feature() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut client = RtmpClient::new();
+        let result = client.connect(&format!("rtmps://127.0.0.1:{}/live", port)).await;
+        assert!(matches!(result, Err(Error::Configuration(_))));
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_rtmps_connect_with_self_signed_cert() {
+        use tokio_rustls::TlsAcceptor;
+        use tokio_rustls::rustls::ServerConfig as TlsServerConfig;
+        use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert_path = std::env::temp_dir().join(format!("rtmp-test-root-{}.pem", std::process::id()));
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+
+        let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+        let tls_config = TlsServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                vec![CertificateDer::from(certified.cert.der().to_vec())],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der())),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let tls_stream = acceptor.accept(socket).await.unwrap();
+            serve_mock_session(tls_stream, Some(7.0)).await;
+        });
+
+        let config = ClientConfig::builder()
+            .tls_root_cert(&cert_path)
+            .build()
+            .unwrap();
+        let mut client = RtmpClient::with_config(config);
+        client.connect(&format!("rtmps://localhost:{}/live", port)).await.unwrap();
+
+        assert_eq!(client.create_stream().await.unwrap(), 7);
+        let _ = std::fs::remove_file(&cert_path);
+    }
+
+    #[tokio::test]
+    async fn test_fmle_publish_sends_fc_commands_first() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve_session_until(socket, Some(1.0), |packet| {
+                let _ = events_tx.send(packet_label(packet));
+                false
+            }).await;
+        });
+
+        let config = ClientConfig::builder().fmle_publish(true).build().unwrap();
+        let mut client = RtmpClient::with_config(config);
+        client.connect(&format!("rtmp://127.0.0.1:{}/live", port)).await.unwrap();
+        client.publish("stream", "live").await.unwrap();
+        client.disconnect().await.unwrap();
+
+        let mut commands = Vec::new();
+        while let Ok(Some(label)) = tokio::time::timeout(Duration::from_secs(2), events_rx.recv()).await {
+            if !label.starts_with("type") {
+                commands.push(label);
+            }
+        }
+        assert_eq!(commands, ["connect", "releaseStream", "FCPublish", "createStream", "publish", "FCUnpublish"]);
+    }
+
+    fn reconnect_config(attempts: usize) -> ClientConfig {
+        ClientConfig::builder()
+            .auto_reconnect(true)
+            .max_reconnect_attempts(attempts)
+            .reconnect_delay(Duration::from_millis(10))
+            .max_reconnect_delay(Duration::from_millis(40))
+            .command_timeout(Duration::from_millis(500))
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_auto_reconnect_resumes_publishing() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            // First session dies as soon as media arrives
+            let (socket, _) = listener.accept().await.unwrap();
+            serve_session_until(socket, Some(1.0), |packet| packet.message_type() == crate::MSG_TYPE_VIDEO).await;
+
+            let (socket, _) = listener.accept().await.unwrap();
+            serve_session_until(socket, Some(2.0), |packet| {
+                let _ = events_tx.send(packet_label(packet));
+                false
+            }).await;
+        });
+
+        let mut client = RtmpClient::with_config(reconnect_config(3));
+        client.connect(&format!("rtmp://127.0.0.1:{}/live", port)).await.unwrap();
+        client.publish("stream", "live").await.unwrap();
+        client.send_video(vec![0x17, 0x00, 0x00, 0x00, 0x00, 0x01], 0).await.unwrap();
+
+        // The second session sees the publish re-issued and the config re-sent
+        let mut seen = Vec::new();
+        while !seen.iter().any(|label| label == "video") {
+            let label = tokio::time::timeout(Duration::from_secs(2), events_rx.recv())
+                .await
+                .expect("client did not reconnect")
+                .unwrap();
+            seen.push(label);
+        }
+        let commands: Vec<&str> = seen.iter()
+            .map(String::as_str)
+            .filter(|label| matches!(*label, "connect" | "createStream" | "publish" | "video"))
+            .collect();
+        assert_eq!(commands, ["connect", "createStream", "publish", "video"]);
+
+        assert_eq!(client.state().await, ClientState::Publishing);
+        assert_eq!(*client.stream_id.read().await, Some(2));
+        client.send_video(vec![0x27, 0x01, 0x00, 0x00, 0x00], 40).await.unwrap();
+        let label = tokio::time::timeout(Duration::from_secs(2), events_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(label, "video");
+    }
+
+    #[tokio::test]
+    async fn test_auto_reconnect_gives_up_after_max_attempts() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let counter = attempts.clone();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            serve_session_until(socket, Some(1.0), |packet| packet_label(packet) == "publish").await;
+
+            // Server is "down": accept and hang up before the handshake
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                drop(socket);
+            }
+        });
+
+        let mut client = RtmpClient::with_config(reconnect_config(2));
+        client.connect(&format!("rtmp://127.0.0.1:{}/live", port)).await.unwrap();
+        client.publish("stream", "live").await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while client.state().await != ClientState::Error {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("client kept reconnecting");
+
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_stops_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(mock_server(listener, Some(1.0)));
+
+        let mut client = RtmpClient::with_config(reconnect_config(3));
+        client.connect(&format!("rtmp://127.0.0.1:{}/live", port)).await.unwrap();
+        client.disconnect().await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(client.state().await, ClientState::Disconnected);
+        assert!(client.connection.read().await.is_none());
+    }
+}
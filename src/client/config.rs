@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::time::Duration;
 use crate::{Error, Result};
 
@@ -12,6 +13,9 @@ pub struct ClientConfig {
     /// Write timeout
     pub write_timeout: Duration,
 
+    /// Timeout waiting for a command response
+    pub command_timeout: Duration,
+
     /// Chunk size
     pub chunk_size: u32,
 
@@ -24,9 +28,12 @@ pub struct ClientConfig {
     /// Maximum reconnect attempts
     pub max_reconnect_attempts: usize,
 
-    /// Reconnect delay
+    /// Delay before the first reconnect attempt, doubled on each retry
     pub reconnect_delay: Duration,
 
+    /// Upper bound for the reconnect delay
+    pub max_reconnect_delay: Duration,
+
     /// Enable audio
     pub enable_audio: bool,
 
@@ -35,6 +42,14 @@ pub struct ClientConfig {
 
     /// Buffer time in milliseconds
     pub buffer_time: u32,
+
+    /// Send `releaseStream` and `FCPublish` before publishing, and
+    /// `FCUnpublish` on disconnect, as FMLE does
+    pub fmle_publish: bool,
+
+    /// Extra PEM root certificates trusted for rtmps, on top of the
+    /// bundled web PKI roots
+    pub tls_root_cert_path: Option<PathBuf>,
 }
 
 impl Default for ClientConfig {
@@ -43,14 +58,18 @@ impl Default for ClientConfig {
             connect_timeout: Duration::from_secs(10),
             read_timeout: Duration::from_secs(30),
             write_timeout: Duration::from_secs(30),
+            command_timeout: Duration::from_secs(10),
             chunk_size: 4096,
             window_ack_size: 2500000,
             auto_reconnect: false,
             max_reconnect_attempts: 3,
             reconnect_delay: Duration::from_secs(5),
+            max_reconnect_delay: Duration::from_secs(60),
             enable_audio: true,
             enable_video: true,
             buffer_time: 1000,
+            fmle_publish: false,
+            tls_root_cert_path: None,
         }
     }
 }
@@ -71,8 +90,22 @@ impl ClientConfig {
             return Err(Error::config("Chunk size must not exceed 65536"));
         }
 
+        if self.auto_reconnect && self.reconnect_delay.is_zero() {
+            return Err(Error::config("Reconnect delay must be greater than zero"));
+        }
+
+        if self.max_reconnect_delay < self.reconnect_delay {
+            return Err(Error::config("Max reconnect delay must not be less than reconnect delay"));
+        }
+
         Ok(())
     }
+
+    /// Delay before reconnect attempt `attempt`, counting from zero
+    pub fn reconnect_backoff(&self, attempt: usize) -> Duration {
+        let factor = 2u32.saturating_pow(u32::try_from(attempt).unwrap_or(u32::MAX));
+        self.reconnect_delay.saturating_mul(factor).min(self.max_reconnect_delay)
+    }
 }
 
 /// Builder for ClientConfig
@@ -94,6 +127,12 @@ impl ClientConfigBuilder {
         self
     }
 
+    /// Set command response timeout
+    pub fn command_timeout(mut self, timeout: Duration) -> Self {
+        self.config.command_timeout = timeout;
+        self
+    }
+
     /// Set chunk size
     pub fn chunk_size(mut self, size: u32) -> Self {
         self.config.chunk_size = size;
@@ -106,15 +145,73 @@ impl ClientConfigBuilder {
         self
     }
 
+    /// Set maximum reconnect attempts after each disconnect
+    pub fn max_reconnect_attempts(mut self, attempts: usize) -> Self {
+        self.config.max_reconnect_attempts = attempts;
+        self
+    }
+
+    /// Set initial reconnect delay
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.config.reconnect_delay = delay;
+        self
+    }
+
+    /// Set maximum reconnect delay
+    pub fn max_reconnect_delay(mut self, delay: Duration) -> Self {
+        self.config.max_reconnect_delay = delay;
+        self
+    }
+
     /// Set buffer time
     pub fn buffer_time(mut self, ms: u32) -> Self {
         self.config.buffer_time = ms;
         self
     }
 
+    /// Use the FMLE publish sequence, for servers that require it
+    pub fn fmle_publish(mut self, enabled: bool) -> Self {
+        self.config.fmle_publish = enabled;
+        self
+    }
+
+    /// Trust the PEM root certificates in `path` for rtmps
+    pub fn tls_root_cert(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.tls_root_cert_path = Some(path.into());
+        self
+    }
+
     /// Build configuration
     pub fn build(self) -> Result<ClientConfig> {
         self.config.validate()?;
         Ok(self.config)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_backoff_doubles_up_to_max() {
+        let config = ClientConfig::builder()
+            .reconnect_delay(Duration::from_millis(100))
+            .max_reconnect_delay(Duration::from_millis(500))
+            .build()
+            .unwrap();
+
+        assert_eq!(config.reconnect_backoff(0), Duration::from_millis(100));
+        assert_eq!(config.reconnect_backoff(1), Duration::from_millis(200));
+        assert_eq!(config.reconnect_backoff(2), Duration::from_millis(400));
+        assert_eq!(config.reconnect_backoff(3), Duration::from_millis(500));
+        assert_eq!(config.reconnect_backoff(usize::MAX), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_max_reconnect_delay_below_base_is_rejected() {
+        let result = ClientConfig::builder()
+            .reconnect_delay(Duration::from_secs(10))
+            .max_reconnect_delay(Duration::from_secs(1))
+            .build();
+        assert!(result.is_err());
+    }
+}
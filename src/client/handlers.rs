@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use log::warn;
+use tokio::sync::{Mutex, RwLock, mpsc, oneshot};
+use crate::Result;
+use crate::message::{HandlerContext, MessageHandler};
+use crate::protocol::{RtmpCommand, RtmpPacket};
+
+/// Pending command responses keyed by transaction ID
+pub(crate) type PendingTransactions = Arc<Mutex<HashMap<u64, oneshot::Sender<RtmpCommand>>>>;
+
+/// Where received media goes, once the application asks for it
+pub(crate) type MediaSink = Arc<RwLock<Option<mpsc::Sender<RtmpPacket>>>>;
+
+/// Routes `_result` and `_error` responses to the command awaiting them
+pub(crate) struct ResponseHandler {
+    pub(crate) pending: PendingTransactions,
+}
+
+#[async_trait::async_trait]
+impl MessageHandler for ResponseHandler {
+    async fn handle(&self, packet: RtmpPacket, _context: Arc<dyn HandlerContext>) -> Result<()> {
+        let command = RtmpCommand::decode_with_type(&packet.payload, packet.message_type())?;
+
+        // Responses nobody is waiting for (e.g. connect) are ignored
+        if let Some(tx) = self.pending.lock().await.remove(&(command.transaction_id as u64)) {
+            let _ = tx.send(command);
+        }
+
+        Ok(())
+    }
+}
+
+/// Hands audio, video and data messages of the played stream to the
+/// application
+///
+/// Delivery waits while the receiver is full, so a slow reader pushes back
+/// on the server instead of losing packets. Without a receiver, or once it
+/// is dropped, media is discarded.
+pub(crate) struct MediaHandler {
+    pub(crate) sink: MediaSink,
+}
+
+#[async_trait::async_trait]
+impl MessageHandler for MediaHandler {
+    async fn handle(&self, packet: RtmpPacket, _context: Arc<dyn HandlerContext>) -> Result<()> {
+        let sender = self.sink.read().await.clone();
+        if let Some(sender) = sender {
+            let _ = sender.send(packet).await;
+        }
+
+        Ok(())
+    }
+}
+
+/// Accepts messages the client has no use for
+pub(crate) struct IgnoreHandler;
+
+#[async_trait::async_trait]
+impl MessageHandler for IgnoreHandler {
+    async fn handle(&self, _packet: RtmpPacket, _context: Arc<dyn HandlerContext>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Accepts `onFCPublish` and `onFCUnpublish`, reporting refusals
+///
+/// Servers differ in whether they send these at all, so publishing never
+/// waits for one.
+pub(crate) struct FcStatusHandler {
+    /// URL of the session, for the log
+    pub(crate) url: String,
+}
+
+#[async_trait::async_trait]
+impl MessageHandler for FcStatusHandler {
+    async fn handle(&self, packet: RtmpPacket, _context: Arc<dyn HandlerContext>) -> Result<()> {
+        let command = RtmpCommand::decode_with_type(&packet.payload, packet.message_type())?;
+
+        let info = command.arguments.first().and_then(|v| v.as_object());
+        let field = |key: &str| info.and_then(|info| info.get(key)).and_then(|v| v.as_string());
+        if field("level") == Some("error") {
+            let code = field("code").unwrap_or("unknown");
+            warn!(url:% = self.url; "{} refused: {}", command.name, code);
+        }
+
+        Ok(())
+    }
+}
@@ -1,8 +1,11 @@
 mod client;
 mod config;
+mod handlers;
 mod state;
+#[cfg(feature = "tls")]
+mod tls;
 
-pub use client::RtmpClient;
+pub use client::{RtmpClient, MEDIA_QUEUE_SIZE};
 pub use config::{ClientConfig, ClientConfigBuilder};
 
 use tokio::net::TcpStream;
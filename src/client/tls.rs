@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, RootCertStore};
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use crate::{Error, Result};
+use crate::client::config::ClientConfig;
+
+/// Wrap a connected TCP stream in a TLS client session, using `host` for SNI
+pub(crate) async fn connect_tls(
+    stream: TcpStream,
+    host: &str,
+    config: &ClientConfig,
+) -> Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+    if let Some(path) = &config.tls_root_cert_path {
+        let certs = CertificateDer::pem_file_iter(path)
+            .map_err(|e| Error::config(format!("Failed to read root certificates {}: {}", path.display(), e)))?;
+        for cert in certs {
+            let cert = cert
+                .map_err(|e| Error::config(format!("Invalid root certificate in {}: {}", path.display(), e)))?;
+            roots.add(cert)
+                .map_err(|e| Error::config(format!("Invalid root certificate in {}: {}", path.display(), e)))?;
+        }
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let tls_config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::config(format!("Invalid TLS configuration: {}", e)))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|e| Error::config(format!("Invalid TLS server name {}: {}", host, e)))?;
+
+    TlsConnector::from(Arc::new(tls_config))
+        .connect(server_name, stream)
+        .await
+        .map_err(|e| Error::connection(format!("TLS handshake with {} failed: {}", host, e)))
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use crate::{CHUNK_STREAM_AUDIO, CHUNK_STREAM_DATA, CHUNK_STREAM_VIDEO, MSG_TYPE_AUDIO, MSG_TYPE_DATA_AMF0, MSG_TYPE_DATA_AMF3, MSG_TYPE_VIDEO};
+
+/// First ID handed out once a kind's well-known chunk stream is taken
+const FIRST_DYNAMIC_CHUNK_STREAM: u32 = 10;
+
+/// Gives every message stream on a connection its own audio, video and
+/// data chunk streams
+///
+/// Messages from different streams sharing one chunk stream differ in
+/// stream ID and timestamp every time, which defeats header compression.
+/// The first stream to send a kind keeps its well-known ID; later ones get
+/// IDs from 10 up, recycled once their stream is deleted.
+#[derive(Debug)]
+pub(crate) struct ChunkStreamAllocator {
+    /// Chunk stream ID by message stream ID and the kind's well-known ID
+    assigned: HashMap<(u32, u32), u32>,
+
+    /// IDs of deleted streams, handed out again before new ones
+    released: Vec<u32>,
+
+    /// Next never used ID
+    next: u32,
+}
+
+impl ChunkStreamAllocator {
+    pub(crate) fn new() -> Self {
+        ChunkStreamAllocator {
+            assigned: HashMap::new(),
+            released: Vec::new(),
+            next: FIRST_DYNAMIC_CHUNK_STREAM,
+        }
+    }
+
+    /// Chunk stream carrying `message_type` messages of `message_stream_id`
+    ///
+    /// `None` for messages that keep the ID they were built with: those on
+    /// stream 0 and anything other than audio, video or data.
+    pub(crate) fn chunk_stream_for(&mut self, message_stream_id: u32, message_type: u8) -> Option<u32> {
+        let well_known = match message_type {
+            MSG_TYPE_AUDIO => CHUNK_STREAM_AUDIO,
+            MSG_TYPE_VIDEO => CHUNK_STREAM_VIDEO,
+            MSG_TYPE_DATA_AMF0 | MSG_TYPE_DATA_AMF3 => CHUNK_STREAM_DATA,
+            _ => return None,
+        };
+        if message_stream_id == 0 {
+            return None;
+        }
+
+        if let Some(&id) = self.assigned.get(&(message_stream_id, well_known)) {
+            return Some(id);
+        }
+
+        let id = if self.assigned.values().all(|&id| id != well_known) {
+            well_known
+        } else if let Some(id) = self.released.pop() {
+            id
+        } else {
+            let id = self.next;
+            self.next += 1;
+            id
+        };
+        self.assigned.insert((message_stream_id, well_known), id);
+        Some(id)
+    }
+
+    /// Free the chunk streams of a deleted message stream
+    pub(crate) fn release(&mut self, message_stream_id: u32) {
+        let released = &mut self.released;
+        self.assigned.retain(|&(stream_id, well_known), &mut id| {
+            if stream_id != message_stream_id {
+                return true;
+            }
+            if id != well_known {
+                released.push(id);
+            }
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_get_distinct_chunk_streams() {
+        let mut allocator = ChunkStreamAllocator::new();
+
+        assert_eq!(allocator.chunk_stream_for(1, MSG_TYPE_VIDEO), Some(CHUNK_STREAM_VIDEO));
+        assert_eq!(allocator.chunk_stream_for(1, MSG_TYPE_AUDIO), Some(CHUNK_STREAM_AUDIO));
+        assert_eq!(allocator.chunk_stream_for(2, MSG_TYPE_VIDEO), Some(10));
+        assert_eq!(allocator.chunk_stream_for(2, MSG_TYPE_DATA_AMF0), Some(CHUNK_STREAM_DATA));
+        assert_eq!(allocator.chunk_stream_for(1, MSG_TYPE_VIDEO), Some(CHUNK_STREAM_VIDEO));
+        assert_eq!(allocator.chunk_stream_for(0, MSG_TYPE_VIDEO), None);
+
+        allocator.release(2);
+        assert_eq!(allocator.chunk_stream_for(3, MSG_TYPE_DATA_AMF0), Some(CHUNK_STREAM_DATA));
+        assert_eq!(allocator.chunk_stream_for(3, MSG_TYPE_VIDEO), Some(10));
+        assert_eq!(allocator.chunk_stream_for(4, MSG_TYPE_VIDEO), Some(11));
+    }
+}
@@ -1,15 +1,52 @@
-use crate::{Error, Result};
+use crate::{Error, Result, RtmpHeader, CHUNK_STREAM_PROTOCOL, DEFAULT_WINDOW_SIZE, MSG_TYPE_ABORT, MSG_TYPE_ACK, MSG_TYPE_SET_CHUNK_SIZE, MSG_TYPE_WINDOW_ACK};
 use crate::handshake::{HandshakeState, C0C1, S0S1S2, validate_c0c1, generate_s0s1s2, validate_c2};
 use crate::chunk::{ChunkReader, ChunkWriter};
-use crate::message::{MessageDispatcher, MessageQueue};
+use crate::message::{HandlerContext, MessageDispatcher, MessageQueue};
 use crate::protocol::RtmpPacket;
 use tokio::net::TcpStream;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+use log::{debug, warn};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt, ReadBuf};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{RwLock, mpsc, watch};
 use crate::connection::context::ConnectionContext;
+use crate::connection::{parse_abort, parse_chunk_size};
 use crate::connection::state::ConnectionState;
-use crate::connection::stream_manager::StreamManager;
+
+/// How long `close` waits for queued packets to be written
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Buffered bytes past which the write loop flushes before taking more packets
+const WRITE_BUFFER_LIMIT: usize = 64 * 1024;
+
+/// Point-in-time view of a connection, see `RtmpServer::connections_info`
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// Connection ID
+    pub id: String,
+
+    /// Address of the peer, if known
+    pub peer_addr: Option<SocketAddr>,
+
+    /// Connection state, `Publishing` or `Playing` once a stream started
+    pub state: ConnectionState,
+
+    /// Application named in `connect`
+    pub app: Option<String>,
+
+    /// Stream being published or played
+    pub stream_name: Option<String>,
+
+    /// Bytes read from the peer
+    pub bytes_received: u64,
+
+    /// Bytes written to the peer
+    pub bytes_sent: u64,
+}
 
 pub struct Connection {
     /// Connection ID
@@ -33,22 +70,39 @@ pub struct Connection {
     /// Message queue
     message_queue: Arc<MessageQueue>,
 
-    /// Stream manager
-    stream_manager: Arc<RwLock<StreamManager>>,
+    /// Window acknowledgement size announced by the peer
+    window_ack_size: Arc<RwLock<u32>>,
+
+    /// Outgoing packets, fed by the context's packet sender
+    outgoing_rx: Arc<RwLock<mpsc::Receiver<RtmpPacket>>>,
+
+    /// Longest wait for data from the peer, if limited
+    read_timeout: Option<Duration>,
+
+    /// Longest wait for queued packets to be written on close
+    drain_timeout: Duration,
 
     /// Shutdown signal
-    shutdown_tx: mpsc::Sender<()>,
-    shutdown_rx: Arc<RwLock<mpsc::Receiver<()>>>,
+    shutdown_tx: watch::Sender<bool>,
+    shutdown_rx: watch::Receiver<bool>,
+
+    /// Whether the processing loops own the socket
+    running_tx: watch::Sender<bool>,
 }
 
 impl Connection {
     /// Create new connection
+    ///
+    /// `outgoing_rx` must be the receiving half of the channel whose sender
+    /// was given to `context`; everything sent through the context is written
+    /// to the peer by the write loop.
     pub fn new(
         id: String,
         context: Arc<ConnectionContext>,
         dispatcher: Arc<MessageDispatcher>,
+        outgoing_rx: mpsc::Receiver<RtmpPacket>,
     ) -> Self {
-        let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
 
         Connection {
             id,
@@ -58,12 +112,28 @@ impl Connection {
             chunk_writer: Arc::new(RwLock::new(ChunkWriter::new())),
             dispatcher,
             message_queue: Arc::new(MessageQueue::new(1000)),
-            stream_manager: Arc::new(RwLock::new(StreamManager::new())),
+            window_ack_size: Arc::new(RwLock::new(DEFAULT_WINDOW_SIZE)),
+            outgoing_rx: Arc::new(RwLock::new(outgoing_rx)),
+            read_timeout: None,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
             shutdown_tx,
-            shutdown_rx: Arc::new(RwLock::new(shutdown_rx)),
+            shutdown_rx,
+            running_tx: watch::channel(false).0,
         }
     }
 
+    /// Drop the peer if no data arrives within `timeout`
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Give queued packets up to `timeout` to be written on close
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
     /// Get connection ID
     pub fn id(&self) -> &str {
         &self.id
@@ -74,6 +144,33 @@ impl Connection {
         *self.state.read().await
     }
 
+    /// Snapshot of the connection for monitoring
+    ///
+    /// A connected peer shows as publishing or playing once it has started
+    /// a stream.
+    pub async fn info(&self) -> ConnectionInfo {
+        let mut state = self.state().await;
+        let publishing = self.context.get_property("publishing").await.is_some();
+        let playing = self.context.get_property("playing").await.is_some();
+        if state == ConnectionState::Connected {
+            if publishing {
+                state = ConnectionState::Publishing;
+            } else if playing {
+                state = ConnectionState::Playing;
+            }
+        }
+
+        ConnectionInfo {
+            id: self.id.clone(),
+            peer_addr: self.context.peer_addr(),
+            state,
+            app: self.context.get_property("app").await,
+            stream_name: self.context.get_property("stream_name").await,
+            bytes_received: self.context.bytes_received(),
+            bytes_sent: self.context.bytes_sent(),
+        }
+    }
+
     /// Process server connection
     pub async fn process_server<S>(&self, stream: S) -> Result<()>
     where
@@ -82,48 +179,16 @@ impl Connection {
         let (read_half, write_half) = tokio::io::split(stream);
 
         // Perform handshake
-        let (read_half, write_half) = self.server_handshake(read_half, write_half).await?;
-
-        // Update state
-        {
-            let mut state = self.state.write().await;
-            *state = ConnectionState::Connected;
-        }
-
-        // Start processing loops
-        let read_handle = self.start_read_loop(read_half);
-        let write_handle = self.start_write_loop(write_half);
-        let process_handle = self.start_process_loop();
-
-        // Wait for shutdown or error
-        tokio::select! {
-            result = read_handle => {
-                if let Err(e) = result {
-                    eprintln!("Read loop error: {}", e);
-                }
-            }
-            result = write_handle => {
-                if let Err(e) = result {
-                    eprintln!("Write loop error: {}", e);
-                }
-            }
-            result = process_handle => {
-                if let Err(e) = result {
-                    eprintln!("Process loop error: {}", e);
-                }
-            }
-            _ = self.wait_shutdown() => {
-                println!("Connection {} shutting down", self.id);
+        let handshake = self.server_handshake(read_half, write_half);
+        let (read_half, write_half) = match with_timeout(self.read_timeout, handshake).await {
+            Ok(halves) => halves,
+            Err(e) => {
+                *self.state.write().await = ConnectionState::Closed;
+                return Err(e);
             }
-        }
+        };
 
-        // Update state
-        {
-            let mut state = self.state.write().await;
-            *state = ConnectionState::Closed;
-        }
-
-        Ok(())
+        self.run(read_half, write_half).await
     }
 
     /// Process client connection (no handshake needed - done by RtmpClient)
@@ -133,44 +198,80 @@ impl Connection {
     {
         let (read_half, write_half) = tokio::io::split(stream);
 
+        // No handshake - client already did it
+        self.run(read_half, write_half).await
+    }
+
+    /// Run the read, write and process loops until one ends or shutdown
+    async fn run<R, W>(&self, read_half: R, write_half: W) -> Result<()>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
         // Update state
         {
             let mut state = self.state.write().await;
             *state = ConnectionState::Connected;
         }
+        self.running_tx.send_replace(true);
 
-        // Start processing loops (no handshake - client already did it)
-        let read_handle = self.start_read_loop(read_half);
-        let write_handle = self.start_write_loop(write_half);
-        let process_handle = self.start_process_loop();
+        // Start processing loops
+        let mut read_handle = self.start_read_loop(read_half);
+        let mut write_handle = self.start_write_loop(write_half);
+        let mut process_handle = self.start_process_loop();
+        let close_handle = self.context.close_handle();
 
         // Wait for shutdown or error
-        tokio::select! {
-            result = read_handle => {
-                if let Err(e) = result {
-                    eprintln!("Read loop error: {}", e);
+        let write_finished = tokio::select! {
+            result = &mut read_handle => {
+                if let Ok(Err(e)) = result {
+                    warn!(conn_id:% = self.id; "Read loop error: {}", e);
                 }
+                false
             }
-            result = write_handle => {
-                if let Err(e) = result {
-                    eprintln!("Write loop error: {}", e);
+            result = &mut write_handle => {
+                if let Ok(Err(e)) = result {
+                    warn!(conn_id:% = self.id; "Write loop error: {}", e);
                 }
+                true
             }
-            result = process_handle => {
-                if let Err(e) = result {
-                    eprintln!("Process loop error: {}", e);
+            result = &mut process_handle => {
+                if let Ok(Err(e)) = result {
+                    warn!(conn_id:% = self.id; "Process loop error: {}", e);
                 }
+                false
             }
             _ = self.wait_shutdown() => {
-                println!("Connection {} shutting down", self.id);
+                debug!(conn_id:% = self.id; "Connection shutting down");
+                false
+            }
+            _ = close_handle.requested() => {
+                debug!(conn_id:% = self.id; "Connection closed on request");
+                false
+            }
+        };
+
+        // Let the write loop flush what is queued, then drop the socket
+        let _ = self.shutdown_tx.send(true);
+        if !write_finished {
+            match tokio::time::timeout(self.drain_timeout, &mut write_handle).await {
+                Ok(Ok(Err(e))) => warn!(conn_id:% = self.id; "Write loop error: {}", e),
+                Ok(_) => {}
+                Err(_) => {
+                    warn!(conn_id:% = self.id; "Connection did not drain within {:?}", self.drain_timeout);
+                    write_handle.abort();
+                }
             }
         }
+        read_handle.abort();
+        process_handle.abort();
 
         // Update state
         {
             let mut state = self.state.write().await;
             *state = ConnectionState::Closed;
         }
+        self.running_tx.send_replace(false);
 
         Ok(())
     }
@@ -197,8 +298,8 @@ impl Connection {
         writer.flush().await
             .map_err(|e| Error::handshake(format!("Failed to flush: {}", e)))?;
 
-        // For validation
-        let s0s1s2 = S0S1S2::generate(&c0c1)?;
+        // C2 must echo the S1 just sent, so validate against those bytes
+        let s0s1s2 = S0S1S2::parse(&s0s1s2_bytes)?;
 
         handshake_state.transition(crate::handshake::HandshakeEvent::ReceivedC0C1)?;
 
@@ -215,34 +316,72 @@ impl Connection {
     }
 
     /// Start read loop
-    fn start_read_loop<R>(&self, mut reader: R) -> tokio::task::JoinHandle<Result<()>>
+    fn start_read_loop<R>(&self, reader: R) -> tokio::task::JoinHandle<Result<()>>
     where
         R: AsyncRead + Unpin + Send + 'static,
     {
         let chunk_reader = self.chunk_reader.clone();
         let message_queue = self.message_queue.clone();
+        let context = self.context.clone();
+        let window_ack_size = self.window_ack_size.clone();
+        let read_timeout = self.read_timeout;
         let shutdown_rx = self.shutdown_rx.clone();
 
         tokio::spawn(async move {
+            let mut reader = CountingReader::new(reader);
+            let mut last_ack = 0u64;
+
             loop {
                 // Check shutdown
-                {
-                    let mut rx = shutdown_rx.write().await;
-                    if rx.try_recv().is_ok() {
-                        break;
-                    }
+                if *shutdown_rx.borrow() {
+                    break;
                 }
 
-                // Read chunk
+                // Read the next message; none means the peer closed cleanly
                 let packet = {
                     let mut reader_lock = chunk_reader.write().await;
-                    reader_lock.read_chunk(&mut reader).await?
+                    with_timeout(read_timeout, reader_lock.read_chunk(&mut reader)).await?
                 };
+                let Some(packet) = packet else {
+                    break;
+                };
+
+                // Apply a new incoming chunk size before the next chunk is read
+                if packet.message_type() == MSG_TYPE_SET_CHUNK_SIZE {
+                    let size = parse_chunk_size(&packet.payload)? as usize;
+                    chunk_reader.write().await.set_chunk_size(size);
+                    context.set_chunk_size_in(size).await;
+                }
 
-                // Queue message if complete
-                if let Some(packet) = packet {
-                    message_queue.push(packet).await?;
+                // Drop the half-assembled message the peer gave up on
+                if packet.message_type() == MSG_TYPE_ABORT {
+                    let cs_id = parse_abort(&packet.payload)?;
+                    chunk_reader.write().await.abort_message(cs_id);
                 }
+
+                // Track the peer's window size
+                if packet.message_type() == MSG_TYPE_WINDOW_ACK
+                    && packet.payload.len() >= 4
+                {
+                    let size = u32::from_be_bytes([
+                        packet.payload[0],
+                        packet.payload[1],
+                        packet.payload[2],
+                        packet.payload[3],
+                    ]);
+                    *window_ack_size.write().await = size;
+                }
+
+                // Acknowledge once a full window has been received
+                let received = reader.bytes_read();
+                context.set_bytes_received(received);
+                let window = *window_ack_size.read().await as u64;
+                if window > 0 && received - last_ack >= window {
+                    context.send_packet(create_ack_packet(received as u32)).await?;
+                    last_ack = received;
+                }
+
+                message_queue.push(packet).await?;
             }
 
             Ok(())
@@ -250,14 +389,54 @@ impl Connection {
     }
 
     /// Start write loop
-    fn start_write_loop<W>(&self, mut writer: W) -> tokio::task::JoinHandle<Result<()>>
+    ///
+    /// Packets already queued behind the one received are written with it,
+    /// so a burst costs a single flush.
+    fn start_write_loop<W>(&self, writer: W) -> tokio::task::JoinHandle<Result<()>>
     where
         W: AsyncWrite + Unpin + Send + 'static,
     {
-        // Simplified - real implementation would have outgoing queue
+        let chunk_writer = self.chunk_writer.clone();
+        let outgoing_rx = self.outgoing_rx.clone();
+        let context = self.context.clone();
+        let mut shutdown_rx = self.shutdown_rx.clone();
+
         tokio::spawn(async move {
-            // Write loop implementation
-            Ok(())
+            let mut outgoing = outgoing_rx.write().await;
+            let mut writer = BufferedWriter::new(writer);
+
+            loop {
+                tokio::select! {
+                    packet = outgoing.recv() => {
+                        match packet {
+                            Some(packet) => {
+                                writer.push(&chunk_writer, &context, &packet).await?;
+                                while !writer.is_full()
+                                    && let Ok(packet) = outgoing.try_recv()
+                                {
+                                    writer.push(&chunk_writer, &context, &packet).await?;
+                                }
+                                writer.flush().await?;
+                            }
+                            // All senders dropped, nothing more to write
+                            None => break,
+                        }
+                    }
+                    _ = shutdown_rx.changed() => {
+                        // Flush whatever was queued before the shutdown signal
+                        while let Ok(packet) = outgoing.try_recv() {
+                            writer.push(&chunk_writer, &context, &packet).await?;
+                            if writer.is_full() {
+                                writer.flush().await?;
+                            }
+                        }
+                        break;
+                    }
+                }
+            }
+
+            writer.flush().await?;
+            writer.shutdown().await
         })
     }
 
@@ -271,11 +450,8 @@ impl Connection {
         tokio::spawn(async move {
             loop {
                 // Check shutdown
-                {
-                    let mut rx = shutdown_rx.write().await;
-                    if rx.try_recv().is_ok() {
-                        break;
-                    }
+                if *shutdown_rx.borrow() {
+                    break;
                 }
 
                 // Process queued messages
@@ -292,20 +468,27 @@ impl Connection {
 
     /// Wait for shutdown signal
     async fn wait_shutdown(&self) {
-        let mut rx = self.shutdown_rx.write().await;
-        let _ = rx.recv().await;
+        let mut rx = self.shutdown_rx.clone();
+        let _ = rx.wait_for(|shutdown| *shutdown).await;
     }
 
     /// Send packet
     pub async fn send_packet(&self, packet: RtmpPacket) -> Result<()> {
-        // Implementation would write to outgoing queue
-        Ok(())
+        self.context.send_packet(packet).await
     }
 
     /// Close connection
+    ///
+    /// Packets already queued are written before the socket closes, waiting
+    /// at most the drain timeout.
     pub async fn close(&self) -> Result<()> {
         // Send shutdown signal
-        let _ = self.shutdown_tx.send(()).await;
+        let _ = self.shutdown_tx.send(true);
+
+        // Wait for the loops to flush and release the socket
+        let mut running = self.running_tx.subscribe();
+        let stopped = running.wait_for(|running| !*running);
+        let _ = tokio::time::timeout(self.drain_timeout, stopped).await;
 
         // Update state
         let mut state = self.state.write().await;
@@ -313,4 +496,446 @@ impl Connection {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Serialize a single outgoing packet with the connection's current outbound chunk size
+async fn serialize_outgoing_packet(
+    chunk_writer: &RwLock<ChunkWriter>,
+    context: &ConnectionContext,
+    packet: &RtmpPacket,
+) -> Result<Vec<u8>> {
+    let chunk_size = context.chunk_size_out().await;
+
+    // Keep each stream's media on its own chunk streams
+    let mut packet = packet.clone();
+    if let Some(chunk_stream_id) = context.outgoing_chunk_stream(packet.message_stream_id(), packet.message_type()).await {
+        packet.header.chunk_stream_id = chunk_stream_id;
+    }
+
+    let mut writer_lock = chunk_writer.write().await;
+    writer_lock.set_chunk_size(chunk_size);
+    let chunks = writer_lock.serialize(&packet)?;
+    context.add_bytes_sent(chunks.len() as u64);
+
+    // The peer reads with the new size from the next chunk on
+    if packet.message_type() == MSG_TYPE_SET_CHUNK_SIZE {
+        let size = parse_chunk_size(&packet.payload)? as usize;
+        writer_lock.set_chunk_size(size);
+        context.apply_chunk_size_out(size).await;
+    }
+
+    Ok(chunks)
+}
+
+/// Outgoing chunks gathered across packets, written with one flush
+struct BufferedWriter<W> {
+    inner: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> BufferedWriter<W> {
+    fn new(inner: W) -> Self {
+        BufferedWriter { inner, buffer: Vec::new() }
+    }
+
+    /// Serialize a packet behind those already buffered
+    async fn push(
+        &mut self,
+        chunk_writer: &RwLock<ChunkWriter>,
+        context: &ConnectionContext,
+        packet: &RtmpPacket,
+    ) -> Result<()> {
+        let chunks = serialize_outgoing_packet(chunk_writer, context, packet).await?;
+        self.buffer.extend_from_slice(&chunks);
+        Ok(())
+    }
+
+    /// Whether the buffer should be written before taking more packets
+    fn is_full(&self) -> bool {
+        self.buffer.len() >= WRITE_BUFFER_LIMIT
+    }
+
+    /// Write and flush the buffered chunks, if any
+    async fn flush(&mut self) -> Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        self.inner.write_all(&self.buffer).await
+            .map_err(|e| Error::chunk(format!("Failed to write chunks: {}", e)))?;
+        self.inner.flush().await
+            .map_err(|e| Error::connection(format!("Failed to flush writer: {}", e)))?;
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        self.inner.shutdown().await
+            .map_err(|e| Error::connection(format!("Failed to shut down writer: {}", e)))
+    }
+}
+
+/// Fail with a timeout error if `future` does not finish within `timeout`
+async fn with_timeout<F, T>(timeout: Option<Duration>, future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await
+            .map_err(|_| Error::timeout(format!("No data from peer for {:?}", timeout)))?,
+        None => future.await,
+    }
+}
+
+/// Build an Acknowledgement message carrying the received byte count
+fn create_ack_packet(sequence_number: u32) -> RtmpPacket {
+    let payload = sequence_number.to_be_bytes().to_vec();
+
+    let header = RtmpHeader::new(
+        0,
+        payload.len() as u32,
+        MSG_TYPE_ACK,
+        0,
+        CHUNK_STREAM_PROTOCOL,
+    );
+
+    RtmpPacket::new(header, payload)
+}
+
+/// Reader wrapper that counts the bytes read from the peer
+struct CountingReader<R> {
+    inner: R,
+    bytes_read: u64,
+}
+
+impl<R> CountingReader<R> {
+    fn new(inner: R) -> Self {
+        CountingReader { inner, bytes_read: 0 }
+    }
+
+    fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for CountingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = result {
+            self.bytes_read += (buf.filled().len() - before) as u64;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{make_audio_packet, make_video_packet};
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_ack_sent_after_window_received() {
+        let connection = test_connection();
+
+        // Peer announces a 1000 byte window, then sends more than that
+        let mut writer = ChunkWriter::new();
+        let window = 1000u32.to_be_bytes().to_vec();
+        let header = RtmpHeader::new(0, 4, MSG_TYPE_WINDOW_ACK, 0, CHUNK_STREAM_PROTOCOL);
+        let mut bytes = writer.create_chunks(&RtmpPacket::new(header, window)).unwrap();
+        for i in 0..10 {
+            let audio = make_audio_packet(vec![0xAF; 120], i * 20, 1);
+            bytes.extend(writer.create_chunks(&audio).unwrap());
+        }
+        let total = bytes.len() as u32;
+
+        // The loop ends once the input is exhausted
+        let _ = connection.start_read_loop(std::io::Cursor::new(bytes)).await;
+
+        assert_eq!(*connection.window_ack_size.read().await, 1000);
+
+        let ack = connection.outgoing_rx.write().await.try_recv().unwrap();
+        assert_eq!(ack.message_type(), MSG_TYPE_ACK);
+        let sequence = u32::from_be_bytes([ack.payload[0], ack.payload[1], ack.payload[2], ack.payload[3]]);
+        assert!((1000..=total).contains(&sequence));
+    }
+
+    fn test_connection() -> Connection {
+        let (packet_tx, packet_rx) = mpsc::channel(100);
+        let context = Arc::new(ConnectionContext::new("test".to_string(), packet_tx));
+        Connection::new(
+            "test".to_string(),
+            context,
+            Arc::new(MessageDispatcher::new()),
+            packet_rx,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_close_flushes_queued_packets() {
+        let connection = Arc::new(test_connection());
+        let (local, mut peer) = tokio::io::duplex(256);
+
+        let process = tokio::spawn({
+            let connection = connection.clone();
+            async move { connection.process_client(local).await }
+        });
+        while connection.state().await != ConnectionState::Connected {
+            tokio::task::yield_now().await;
+        }
+
+        // More than the pipe holds, so most is still queued at close
+        let packets: Vec<_> = (0..20)
+            .map(|i| make_audio_packet(vec![i as u8; 100], i * 20, 1))
+            .collect();
+        for packet in &packets {
+            connection.send_packet(packet.clone()).await.unwrap();
+        }
+
+        let read = tokio::spawn(async move {
+            let mut bytes = Vec::new();
+            peer.read_to_end(&mut bytes).await.unwrap();
+            bytes
+        });
+        connection.close().await.unwrap();
+        process.await.unwrap().unwrap();
+
+        let bytes = read.await.unwrap();
+        let mut reader = ChunkReader::new();
+        let mut input = std::io::Cursor::new(bytes);
+        let mut received = Vec::new();
+        while let Some(packet) = reader.read_chunk(&mut input).await.unwrap() {
+            received.push(packet);
+        }
+
+        assert_eq!(received.len(), packets.len());
+        assert_eq!(received.last().unwrap().payload, packets.last().unwrap().payload);
+        assert_eq!(connection.state().await, ConnectionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_sent_chunk_size_applies_to_following_packets() {
+        let connection = test_connection();
+        let chunk_writer = RwLock::new(ChunkWriter::new());
+        let mut output = Vec::new();
+
+        let audio = make_audio_packet(vec![0xAF; 1000], 0, 1);
+        for packet in [chunk_size_packet(4096), audio.clone()] {
+            output.extend(serialize_outgoing_packet(&chunk_writer, &connection.context, &packet).await.unwrap());
+        }
+        assert_eq!(connection.context.chunk_size_out().await, 4096);
+
+        // The peer applies the announced size before reading the audio
+        let mut reader = ChunkReader::new();
+        reader.set_chunk_size(4096);
+        let skip = 1 + 11 + 4;
+        let mut input = std::io::Cursor::new(output[skip..].to_vec());
+        let received = reader.read_chunk(&mut input).await.unwrap().unwrap();
+        assert_eq!(received.payload, audio.payload);
+    }
+
+    #[tokio::test]
+    async fn test_connect_raises_outgoing_chunk_size() {
+        let config = crate::ServerConfig::builder().chunk_size(60000).build().unwrap();
+        let (packet_tx, mut packet_rx) = mpsc::channel(100);
+        let context = Arc::new(
+            ConnectionContext::new("test".to_string(), packet_tx).with_server_config(Arc::new(config)),
+        );
+        let chunk_writer = RwLock::new(ChunkWriter::new());
+        let mut output = Vec::new();
+
+        let connect = crate::RtmpCommand::connect("live", "rtmp://localhost/live");
+        let handlers = crate::handlers::CommandHandlerRegistry::new();
+        let response = handlers.handle(connect, context.clone()).await.unwrap().unwrap();
+        context.send_packet(response).await.unwrap();
+        while let Ok(packet) = packet_rx.try_recv() {
+            output.extend(serialize_outgoing_packet(&chunk_writer, &context, &packet).await.unwrap());
+        }
+        assert_eq!(context.chunk_size_out().await, 60000);
+
+        // One chunk, no continuation headers
+        let before = output.len();
+        let video = make_video_packet(vec![0x17; 50000], 0, 1);
+        output.extend(serialize_outgoing_packet(&chunk_writer, &context, &video).await.unwrap());
+        assert_eq!(output.len() - before, 1 + 11 + 50000);
+    }
+
+    #[tokio::test]
+    async fn test_played_streams_get_own_video_chunk_streams() {
+        let connection = test_connection();
+        let chunk_writer = RwLock::new(ChunkWriter::new());
+        let mut output = Vec::new();
+
+        // Two players on one connection, frames interleaved as they arrive
+        for timestamp in [0, 40] {
+            for stream_id in [1, 2] {
+                let video = make_video_packet(vec![0x27, 0x01, stream_id as u8], timestamp, stream_id);
+                output.extend(serialize_outgoing_packet(&chunk_writer, &connection.context, &video).await.unwrap());
+            }
+        }
+
+        let mut reader = ChunkReader::new();
+        let mut input = std::io::Cursor::new(output);
+        let mut chunk_streams = HashMap::new();
+        while let Some(packet) = reader.read_chunk(&mut input).await.unwrap() {
+            assert_eq!(packet.payload[2], packet.message_stream_id() as u8);
+            let previous = chunk_streams.insert(packet.message_stream_id(), packet.header.chunk_stream_id);
+            assert!(previous.is_none_or(|id| id == packet.header.chunk_stream_id));
+        }
+
+        assert_eq!(chunk_streams.len(), 2);
+        assert_ne!(chunk_streams[&1], chunk_streams[&2]);
+    }
+
+    #[tokio::test]
+    async fn test_queued_packets_share_one_flush() {
+        let connection = test_connection();
+        let packets: Vec<_> = (0..5).map(|i| make_audio_packet(vec![0xAF, 0x01, i], i as u32 * 20, 1)).collect();
+        for packet in &packets {
+            connection.send_packet(packet.clone()).await.unwrap();
+        }
+
+        let writer = FlushCounter::default();
+        let written = writer.written.clone();
+        let flushes = writer.flushes.clone();
+        let write = connection.start_write_loop(writer);
+
+        // Shut down only once the queued batch has gone out
+        while flushes.load(std::sync::atomic::Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let _ = connection.shutdown_tx.send(true);
+        write.await.unwrap().unwrap();
+
+        assert_eq!(flushes.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let mut reader = ChunkReader::new();
+        let mut input = std::io::Cursor::new(written.lock().unwrap().clone());
+        for packet in &packets {
+            let received = reader.read_chunk(&mut input).await.unwrap().unwrap();
+            assert_eq!(received.payload, packet.payload);
+        }
+    }
+
+    /// Collects written bytes and counts flushes
+    #[derive(Default)]
+    struct FlushCounter {
+        written: Arc<std::sync::Mutex<Vec<u8>>>,
+        flushes: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl AsyncWrite for FlushCounter {
+        fn poll_write(self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            self.flushes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn chunk_size_packet(size: u32) -> RtmpPacket {
+        let header = RtmpHeader::new(0, 4, MSG_TYPE_SET_CHUNK_SIZE, 0, CHUNK_STREAM_PROTOCOL);
+        RtmpPacket::new(header, size.to_be_bytes().to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_set_chunk_size_applies_to_following_chunks() {
+        let connection = test_connection();
+
+        // Peer raises its chunk size, then sends a message in a single large chunk
+        let mut writer = ChunkWriter::new();
+        let mut bytes = writer.create_chunks(&chunk_size_packet(4096)).unwrap();
+        writer.set_chunk_size(4096);
+        bytes.extend(writer.create_chunks(&make_audio_packet(vec![0xAF; 1000], 0, 1)).unwrap());
+
+        let _ = connection.start_read_loop(std::io::Cursor::new(bytes)).await;
+
+        assert_eq!(connection.context.chunk_size_in().await, 4096);
+
+        let queue = &connection.message_queue;
+        let mut audio = None;
+        while let Some(packet) = queue.pop().await.unwrap() {
+            if packet.is_audio() {
+                audio = Some(packet);
+            }
+        }
+        assert_eq!(audio.unwrap().payload.len(), 1000);
+    }
+
+    #[tokio::test]
+    async fn test_abort_discards_partial_message() {
+        let connection = test_connection();
+        let mut writer = ChunkWriter::new();
+
+        // First chunk of a 300 byte video message on cs_id 6, then Abort
+        let mut abandoned = make_video_packet(vec![0xEE; 300], 0, 1);
+        abandoned.header.chunk_stream_id = 6;
+        let mut bytes = writer.create_chunks(&abandoned).unwrap()[..140].to_vec();
+        let header = RtmpHeader::new(0, 4, MSG_TYPE_ABORT, 0, CHUNK_STREAM_PROTOCOL);
+        bytes.extend(writer.create_chunks(&RtmpPacket::new(header, 6u32.to_be_bytes().to_vec())).unwrap());
+
+        // A type 2 chunk starts the next message, decoded against the
+        // abandoned message's header plus a 40 ms timestamp delta
+        let payload: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        bytes.extend([0x86, 0x00, 0x00, 40]);
+        bytes.extend_from_slice(&payload[..128]);
+        for chunk in payload[128..].chunks(128) {
+            bytes.push(0xC6);
+            bytes.extend_from_slice(chunk);
+        }
+
+        let _ = connection.start_read_loop(std::io::Cursor::new(bytes)).await;
+
+        let queue = &connection.message_queue;
+        let mut video = Vec::new();
+        while let Some(packet) = queue.pop().await.unwrap() {
+            if packet.is_video() {
+                video.push(packet);
+            }
+        }
+        assert_eq!(video.len(), 1);
+        assert_eq!(video[0].payload, payload);
+        assert_eq!(video[0].timestamp(), 40);
+    }
+
+    #[tokio::test]
+    async fn test_read_loop_ends_cleanly_on_close() {
+        let mut writer = ChunkWriter::new();
+        let bytes = writer.create_chunks(&make_video_packet(vec![0x17; 300], 0, 1)).unwrap();
+
+        // Closed after a whole message
+        let connection = test_connection();
+        let result = connection.start_read_loop(std::io::Cursor::new(bytes.clone())).await.unwrap();
+        assert!(result.is_ok());
+        assert!(connection.message_queue.pop().await.unwrap().is_some());
+
+        // Closed partway through one
+        let connection = test_connection();
+        let result = connection.start_read_loop(std::io::Cursor::new(bytes[..140].to_vec())).await.unwrap();
+        assert!(matches!(result, Err(Error::Chunk(_))));
+    }
+
+    #[tokio::test]
+    async fn test_set_chunk_size_out_of_range() {
+        let connection = test_connection();
+
+        let mut writer = ChunkWriter::new();
+        let bytes = writer.create_chunks(&chunk_size_packet(64)).unwrap();
+
+        let result = connection.start_read_loop(std::io::Cursor::new(bytes)).await.unwrap();
+        assert!(matches!(result, Err(Error::Protocol(_))));
+    }
+}
@@ -1,9 +1,32 @@
-use crate::{Error, PublisherRegistry, Result};
-use crate::protocol::RtmpPacket;
+use crate::{Error, EventListeners, PublisherRegistry, Result, RtmpHeader, ServerConfig, CHUNK_STREAM_PROTOCOL, DEFAULT_WINDOW_SIZE, MSG_TYPE_SET_CHUNK_SIZE};
+use crate::protocol::{RtmpCommand, RtmpPacket};
 use crate::message::HandlerContext;
+use crate::connection::parse_chunk_size;
+use crate::connection::stream_manager::StreamManager;
+use crate::connection::chunk_streams::ChunkStreamAllocator;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::{mpsc, oneshot, Mutex, Notify, RwLock};
+
+/// Asks a connection to close from outside its own loops
+#[derive(Clone, Default)]
+pub struct CloseHandle {
+    notify: Arc<Notify>,
+}
+
+impl CloseHandle {
+    /// Request the connection to close
+    pub fn close(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Resolves once a close has been requested
+    pub(crate) async fn requested(&self) {
+        self.notify.notified().await;
+    }
+}
 
 pub struct ConnectionContext {
     /// Connection ID
@@ -18,6 +41,37 @@ pub struct ConnectionContext {
     /// Chunk size settings
     chunk_size_in: Arc<RwLock<usize>>,
     chunk_size_out: Arc<RwLock<usize>>,
+
+    /// Window acknowledgement size announced to the peer
+    window_ack_size_out: Arc<RwLock<u32>>,
+
+    /// Server publisher registry, if running server side
+    publisher_registry: Option<Arc<PublisherRegistry>>,
+
+    /// Server configuration, if running server side
+    server_config: Option<Arc<ServerConfig>>,
+
+    /// Server lifecycle event listeners, if running server side
+    event_listeners: Option<Arc<EventListeners>>,
+
+    /// Lets others close this connection
+    close_handle: CloseHandle,
+
+    /// Address of the peer, if known
+    peer_addr: Option<SocketAddr>,
+
+    /// Calls made to the peer, awaiting its `_result`, by transaction ID
+    pending_results: Arc<Mutex<HashMap<u64, oneshot::Sender<RtmpCommand>>>>,
+
+    /// Streams created on this connection
+    stream_manager: Arc<Mutex<StreamManager>>,
+
+    /// Chunk streams of outgoing media, by message stream
+    chunk_streams: Mutex<ChunkStreamAllocator>,
+
+    /// Bytes read from and written to the peer
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
 }
 
 impl ConnectionContext {
@@ -32,25 +86,173 @@ impl ConnectionContext {
             packet_sender,
             chunk_size_in: Arc::new(RwLock::new(128)),
             chunk_size_out: Arc::new(RwLock::new(128)),
+            window_ack_size_out: Arc::new(RwLock::new(DEFAULT_WINDOW_SIZE)),
+            publisher_registry: None,
+            server_config: None,
+            event_listeners: None,
+            close_handle: CloseHandle::default(),
+            peer_addr: None,
+            pending_results: Arc::new(Mutex::new(HashMap::new())),
+            stream_manager: Arc::new(Mutex::new(StreamManager::new())),
+            chunk_streams: Mutex::new(ChunkStreamAllocator::new()),
+            bytes_received: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
         }
     }
 
+    /// Attach the server's publisher registry
+    pub fn with_publisher_registry(mut self, registry: Arc<PublisherRegistry>) -> Self {
+        self.publisher_registry = Some(registry);
+        self
+    }
+
+    /// Attach the server's configuration
+    pub fn with_server_config(mut self, config: Arc<ServerConfig>) -> Self {
+        let streams = StreamManager::new().with_max_streams(config.max_streams_per_connection);
+        self.stream_manager = Arc::new(Mutex::new(streams));
+        self.server_config = Some(config);
+        self
+    }
+
+    /// Record the address the connection came from
+    pub fn with_peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer_addr = Some(addr);
+        self
+    }
+
+    /// Attach the server's lifecycle event listeners
+    pub fn with_event_listeners(mut self, listeners: Arc<EventListeners>) -> Self {
+        self.event_listeners = Some(listeners);
+        self
+    }
+
+    /// Get the server's event listeners, if running server side
+    pub fn event_listeners(&self) -> Option<Arc<EventListeners>> {
+        self.event_listeners.clone()
+    }
+
+    /// Get the server's configuration, if running server side
+    pub fn server_config(&self) -> Option<Arc<ServerConfig>> {
+        self.server_config.clone()
+    }
+
+    /// Get the streams created on this connection
+    pub fn stream_manager(&self) -> Arc<Mutex<StreamManager>> {
+        self.stream_manager.clone()
+    }
+
+    /// Chunk stream an outgoing message is written on, if not the one it
+    /// was built with
+    pub(crate) async fn outgoing_chunk_stream(&self, message_stream_id: u32, message_type: u8) -> Option<u32> {
+        self.chunk_streams.lock().await.chunk_stream_for(message_stream_id, message_type)
+    }
+
+    /// Let other streams reuse the chunk streams of a deleted one
+    pub(crate) async fn release_chunk_streams(&self, message_stream_id: u32) {
+        self.chunk_streams.lock().await.release(message_stream_id);
+    }
+
+    /// Get a handle that closes this connection
+    pub fn close_handle(&self) -> CloseHandle {
+        self.close_handle.clone()
+    }
+
+    /// Address of the peer, if known
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
     /// Get connection ID
     pub fn connection_id(&self) -> &str {
         &self.connection_id
     }
 
+    /// Bytes read from the peer so far
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Bytes written to the peer so far
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// Record the total read from the peer
+    pub(crate) fn set_bytes_received(&self, total: u64) {
+        self.bytes_received.store(total, Ordering::Relaxed);
+    }
+
+    /// Count bytes written to the peer
+    pub(crate) fn add_bytes_sent(&self, count: u64) {
+        self.bytes_sent.fetch_add(count, Ordering::Relaxed);
+    }
+
     /// Set chunk size for incoming
     pub async fn set_chunk_size_in(&self, size: usize) {
         let mut chunk_size = self.chunk_size_in.write().await;
         *chunk_size = size;
     }
 
-    /// Set chunk size for outgoing
-    pub async fn set_chunk_size_out(&self, size: usize) {
+    /// Raise or lower the outgoing chunk size, at any point in the session
+    ///
+    /// Queues a Set Chunk Size message; the write loop switches the chunk
+    /// writer to `size` right after sending it, so the peer always reads
+    /// with the size it was told.
+    pub async fn set_chunk_size_out(&self, size: u32) -> Result<()> {
+        let payload = size.to_be_bytes().to_vec();
+        parse_chunk_size(&payload)?;
+
+        let header = RtmpHeader::new(0, payload.len() as u32, MSG_TYPE_SET_CHUNK_SIZE, 0, CHUNK_STREAM_PROTOCOL);
+        self.send_packet(RtmpPacket::new(header, payload)).await
+    }
+
+    /// Record the outgoing chunk size once the peer has been told
+    pub(crate) async fn apply_chunk_size_out(&self, size: usize) {
         let mut chunk_size = self.chunk_size_out.write().await;
         *chunk_size = size;
     }
+
+    /// Get chunk size for incoming
+    pub async fn chunk_size_in(&self) -> usize {
+        *self.chunk_size_in.read().await
+    }
+
+    /// Get chunk size for outgoing
+    pub async fn chunk_size_out(&self) -> usize {
+        *self.chunk_size_out.read().await
+    }
+
+    /// Record the window acknowledgement size announced to the peer
+    pub async fn set_window_ack_size_out(&self, size: u32) {
+        *self.window_ack_size_out.write().await = size;
+    }
+
+    /// Bytes the peer may send before it has to acknowledge
+    pub async fn window_ack_size_out(&self) -> u32 {
+        *self.window_ack_size_out.read().await
+    }
+
+    /// Get a sender for packets to this connection's write loop
+    pub fn packet_sender(&self) -> mpsc::Sender<RtmpPacket> {
+        self.packet_sender.clone()
+    }
+
+    /// Wait for the peer's `_result` to a call made with `transaction_id`
+    ///
+    /// Register before sending the call so the reply cannot be missed.
+    pub(crate) async fn expect_result(&self, transaction_id: f64) -> oneshot::Receiver<RtmpCommand> {
+        let (tx, rx) = oneshot::channel();
+        self.pending_results.lock().await.insert(transaction_id as u64, tx);
+        rx
+    }
+
+    /// Hand a `_result` to whoever awaits it; false if nobody does
+    pub(crate) async fn resolve_result(&self, command: RtmpCommand) -> bool {
+        match self.pending_results.lock().await.remove(&(command.transaction_id as u64)) {
+            Some(tx) => tx.send(command).is_ok(),
+            None => false,
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -76,8 +278,10 @@ impl HandlerContext for ConnectionContext {
     }
 
     fn get_publisher_registry(&self) -> Option<Arc<PublisherRegistry>> {
-        // Note: This should be injected during ConnectionContext creation
-        // For now, return None - will be implemented in Phase 10 (Stream Management)
-        None
+        self.publisher_registry.clone()
+    }
+
+    fn connection_context(self: Arc<Self>) -> Option<Arc<ConnectionContext>> {
+        Some(self)
     }
 }
\ No newline at end of file
@@ -1,47 +1,51 @@
 use crate::{Error, Result, RtmpPacket};
+use crate::{MSG_TYPE_ABORT, MSG_TYPE_ACK, MSG_TYPE_SET_CHUNK_SIZE, MSG_TYPE_SET_PEER_BW, MSG_TYPE_WINDOW_ACK};
 
 mod connection;
 mod state;
 mod context;
 mod stream_manager;
+mod chunk_streams;
 
 pub use connection::*;
 pub use state::*;
 pub use context::*;
 pub use stream_manager::*;
 
+/// Parse and validate the payload of a Set Chunk Size message
+pub fn parse_chunk_size(payload: &[u8]) -> Result<u32> {
+    if payload.len() < 4 {
+        return Err(Error::protocol("Invalid chunk size message"));
+    }
+
+    let size = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+
+    if !(128..=0x7FFFFFFF).contains(&size) {
+        return Err(Error::protocol(format!("Invalid chunk size: {}", size)));
+    }
+
+    Ok(size)
+}
+
+/// Parse the chunk stream ID named by an Abort message
+pub fn parse_abort(payload: &[u8]) -> Result<u32> {
+    if payload.len() < 4 {
+        return Err(Error::protocol("Invalid abort message"));
+    }
+
+    Ok(u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]))
+}
+
 pub fn process_control_message(msg: &RtmpPacket) -> Result<()> {
     match msg.message_type() {
         MSG_TYPE_SET_CHUNK_SIZE => {
-            if msg.payload.len() < 4 {
-                return Err(Error::protocol("Invalid chunk size message"));
-            }
-
-            let size = u32::from_be_bytes([
-                msg.payload[0],
-                msg.payload[1],
-                msg.payload[2],
-                msg.payload[3],
-            ]);
-
-            if size < 128 || size > 0x7FFFFFFF {
-                return Err(Error::protocol("Invalid chunk size"));
-            }
+            parse_chunk_size(&msg.payload)?;
 
             // Actual update would be done by caller
             Ok(())
         }
         MSG_TYPE_ABORT => {
-            if msg.payload.len() < 4 {
-                return Err(Error::protocol("Invalid abort message"));
-            }
-
-            let chunk_stream_id = u32::from_be_bytes([
-                msg.payload[0],
-                msg.payload[1],
-                msg.payload[2],
-                msg.payload[3],
-            ]);
+            parse_abort(&msg.payload)?;
 
             // Actual abort would be done by caller
             Ok(())
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use crate::{Error, Result};
 
 #[derive(Debug, Clone)]
@@ -32,11 +32,17 @@ pub enum StreamType {
 }
 
 pub struct StreamManager {
-    /// Next stream ID to allocate
+    /// Next never-used stream ID
     next_stream_id: u32,
 
+    /// IDs released by `delete_stream`, reused lowest first
+    free_ids: BTreeSet<u32>,
+
     /// Active streams
     streams: HashMap<u32, StreamInfo>,
+
+    /// Most network streams open at once; unlimited when unset
+    max_streams: Option<usize>,
 }
 
 impl StreamManager {
@@ -44,7 +50,9 @@ impl StreamManager {
     pub fn new() -> Self {
         let mut manager = StreamManager {
             next_stream_id: 1, // 0 is reserved for commands
+            free_ids: BTreeSet::new(),
             streams: HashMap::new(),
+            max_streams: None,
         };
 
         // Add command stream
@@ -58,10 +66,29 @@ impl StreamManager {
         manager
     }
 
-    /// Create new stream
-    pub fn create_stream(&mut self) -> u32 {
-        let id = self.next_stream_id;
-        self.next_stream_id += 1;
+    /// Limit how many network streams may be open at once
+    pub fn with_max_streams(mut self, max: usize) -> Self {
+        self.max_streams = Some(max);
+        self
+    }
+
+    /// Allocate a stream ID, reusing released ones before new ones
+    pub fn allocate(&mut self) -> Result<u32> {
+        // `streams` also holds the command stream, which is not counted
+        if let Some(max) = self.max_streams
+            && self.streams.len() > max
+        {
+            return Err(Error::stream(format!("Stream limit of {} reached", max)));
+        }
+
+        let id = match self.free_ids.pop_first() {
+            Some(id) => id,
+            None => {
+                let id = self.next_stream_id;
+                self.next_stream_id += 1;
+                id
+            }
+        };
 
         self.streams.insert(id, StreamInfo {
             id,
@@ -70,7 +97,7 @@ impl StreamManager {
             created_at: crate::utils::current_timestamp(),
         });
 
-        id
+        Ok(id)
     }
 
     /// Delete stream
@@ -81,6 +108,7 @@ impl StreamManager {
 
         self.streams.remove(&id)
             .ok_or_else(|| Error::stream(format!("Stream {} not found", id)))?;
+        self.free_ids.insert(id);
 
         Ok(())
     }
@@ -116,4 +144,39 @@ impl StreamManager {
     pub fn get_streams(&self) -> Vec<&StreamInfo> {
         self.streams.values().collect()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate_reuses_deleted_ids() {
+        let mut manager = StreamManager::new();
+
+        let first = manager.allocate().unwrap();
+        let second = manager.allocate().unwrap();
+        assert_eq!((first, second), (1, 2));
+
+        manager.delete_stream(first).unwrap();
+        assert_eq!(manager.allocate().unwrap(), first);
+        assert_eq!(manager.allocate().unwrap(), 3);
+
+        // A stream that is already gone is not freed twice
+        manager.delete_stream(second).unwrap();
+        assert!(manager.delete_stream(second).is_err());
+        assert_eq!(manager.allocate().unwrap(), second);
+        assert_eq!(manager.allocate().unwrap(), 4);
+    }
+
+    #[test]
+    fn test_allocate_respects_limit() {
+        let mut manager = StreamManager::new().with_max_streams(1);
+
+        let id = manager.allocate().unwrap();
+        assert!(manager.allocate().is_err());
+
+        manager.delete_stream(id).unwrap();
+        assert_eq!(manager.allocate().unwrap(), id);
+    }
+}
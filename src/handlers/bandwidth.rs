@@ -0,0 +1,143 @@
+use std::sync::Arc;
+use std::time::Duration;
+use log::warn;
+use tokio::time::Instant;
+use crate::handlers::CommandHandler;
+use crate::{Amf0Value, ConnectionContext, HandlerContext, Result, RtmpCommand, RtmpHeader, RtmpPacket};
+
+/// `onBWCheck` calls sent per check
+const CHECK_ROUNDS: usize = 3;
+
+/// Filler carried by each `onBWCheck` call
+const CHECK_PAYLOAD_SIZE: usize = 32 * 1024;
+
+/// Longest wait for the client to answer every call
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Property marking a connection whose check already ran
+const CHECKED_PROPERTY: &str = "bandwidth_checked";
+
+/// Answers the client's `_checkbw` and starts the check when enabled
+pub struct CheckBandwidthHandler;
+
+impl CheckBandwidthHandler {
+    pub fn new() -> Self {
+        CheckBandwidthHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for CheckBandwidthHandler {
+    fn command_name(&self) -> &str {
+        "_checkbw"
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let response = RtmpCommand::result(command.transaction_id, Amf0Value::Null);
+        let bytes = response.encode()?;
+        let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+        context.send_packet(RtmpPacket::new(header, bytes)).await?;
+
+        if context.server_config().is_some_and(|config| config.bandwidth_check) {
+            start_bandwidth_check(context).await;
+        }
+
+        Ok(None)
+    }
+}
+
+/// Routes the client's `_result` replies to calls the server made
+pub struct ResultHandler;
+
+impl ResultHandler {
+    pub fn new() -> Self {
+        ResultHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for ResultHandler {
+    fn command_name(&self) -> &str {
+        "_result"
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        // Replies nobody waits for any more are dropped
+        context.resolve_result(command).await;
+        Ok(None)
+    }
+}
+
+/// Run the bandwidth check in the background, once per connection
+pub(crate) async fn start_bandwidth_check(context: Arc<ConnectionContext>) {
+    if context.get_property(CHECKED_PROPERTY).await.is_some() {
+        return;
+    }
+    context.set_property(CHECKED_PROPERTY.to_string(), "true".to_string()).await;
+
+    tokio::spawn(async move {
+        if let Err(e) = check_bandwidth(&context).await {
+            warn!(conn_id:% = context.connection_id(); "Bandwidth check failed: {}", e);
+        }
+    });
+}
+
+/// Send sized `onBWCheck` calls, time the replies and report the result
+/// with `onBWDone(kbitDown, deltaDown, deltaTime, latency)`
+///
+/// A client that does not answer in time is reported at 0 kbit/s.
+async fn check_bandwidth(context: &ConnectionContext) -> Result<()> {
+    let filler = Amf0Value::String("x".repeat(CHECK_PAYLOAD_SIZE));
+    let started = Instant::now();
+    let mut sent = 0;
+    let mut replies = Vec::with_capacity(CHECK_ROUNDS);
+
+    for round in 0..CHECK_ROUNDS {
+        let transaction_id = (round + 1) as f64;
+        replies.push(context.expect_result(transaction_id).await);
+
+        let mut call = RtmpCommand::new("onBWCheck".to_string(), transaction_id);
+        call.command_object = Some(Amf0Value::Null);
+        call.arguments.push(filler.clone());
+        let bytes = call.encode()?;
+        sent += bytes.len();
+
+        let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+        context.send_packet(RtmpPacket::new(header, bytes)).await?;
+    }
+
+    let answered = tokio::time::timeout(CHECK_TIMEOUT, async {
+        let mut latency = None;
+        for reply in replies {
+            reply.await.ok()?;
+            latency.get_or_insert_with(|| started.elapsed());
+        }
+        latency
+    }).await.ok().flatten();
+
+    let elapsed = started.elapsed();
+    let kbit_down = sent as f64 * 8.0 / 1000.0;
+    let (kbps, latency) = match answered {
+        Some(latency) => (kbit_down / elapsed.as_secs_f64().max(0.001), latency),
+        None => (0.0, elapsed),
+    };
+
+    let mut done = RtmpCommand::new("onBWDone".to_string(), 0.0);
+    done.command_object = Some(Amf0Value::Null);
+    done.arguments.push(Amf0Value::Number(kbps.round()));
+    done.arguments.push(Amf0Value::Number(kbit_down.round()));
+    done.arguments.push(Amf0Value::Number(elapsed.as_millis() as f64));
+    done.arguments.push(Amf0Value::Number(latency.as_millis() as f64));
+
+    let bytes = done.encode()?;
+    let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+    context.send_packet(RtmpPacket::new(header, bytes)).await
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+use crate::handlers::CommandHandler;
+use crate::handlers::delete_stream::release_stream;
+use crate::{ConnectionContext, Result, RtmpCommand, RtmpPacket};
+
+pub struct CloseStreamHandler;
+
+impl CloseStreamHandler {
+    pub fn new() -> Self {
+        CloseStreamHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for CloseStreamHandler {
+    fn command_name(&self) -> &str {
+        "closeStream"
+    }
+
+    fn stream_scoped(&self) -> bool {
+        true
+    }
+
+    async fn handle(
+        &self,
+        _command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        // Stop publishing/playing but keep the stream ID for reuse
+        release_stream(&context).await?;
+
+        Ok(None)
+    }
+}
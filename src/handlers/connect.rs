@@ -1,10 +1,11 @@
-use crate::{ConnectionContext, Error, HandlerContext, Result};
-use crate::protocol::{RtmpCommand, RtmpPacket, RtmpHeader};
+use crate::{ConnectParams, ConnectionContext, Error, HandlerContext, Result};
+use crate::protocol::{NetStatus, RtmpCommand, RtmpPacket, RtmpHeader};
 use crate::protocol::constants::*;
 use crate::amf::Amf0Value;
 use std::collections::HashMap;
 use std::sync::Arc;
-use crate::handlers::CommandHandler;
+use crate::handlers::{CommandHandler, generate_connect_response};
+use crate::handlers::bandwidth::start_bandwidth_check;
 
 pub struct ConnectHandler {
     /// Supported encoding
@@ -18,8 +19,9 @@ impl ConnectHandler {
         }
     }
 
-    fn validate_connect_params(&self, command: &RtmpCommand) -> Result<ConnectParams> {
-        let params = command.command_object.as_ref()
+    fn validate_connect_params(&self, command: &RtmpCommand, context: &ConnectionContext) -> Result<ConnectParams> {
+        let command_object = command.command_object.as_ref();
+        let params = command_object
             .and_then(|v| v.as_object())
             .ok_or_else(|| Error::protocol("Missing connect parameters"))?;
 
@@ -47,6 +49,8 @@ impl ConnectHandler {
             tc_url,
             flash_ver,
             object_encoding,
+            object_class: command_object.and_then(|v| v.class_name()).map(str::to_string),
+            peer_addr: context.peer_addr(),
         })
     }
 
@@ -59,9 +63,10 @@ impl ConnectHandler {
 
         // Create info object
         let mut info = HashMap::new();
-        info.insert("level".to_string(), Amf0Value::String("status".to_string()));
-        info.insert("code".to_string(), Amf0Value::String("NetConnection.Connect.Success".to_string()));
-        info.insert("description".to_string(), Amf0Value::String("Connection succeeded".to_string()));
+        let status = NetStatus::ConnectSuccess;
+        info.insert("level".to_string(), Amf0Value::String(status.level().as_str().to_string()));
+        info.insert("code".to_string(), Amf0Value::String(status.code().to_string()));
+        info.insert("description".to_string(), Amf0Value::String(status.description().to_string()));
         info.insert("data".to_string(), Amf0Value::Object(HashMap::new()));
         info.insert("objectEncoding".to_string(), Amf0Value::Number(self.object_encoding));
 
@@ -72,18 +77,19 @@ impl ConnectHandler {
     }
 
     async fn send_server_bandwidth(&self, context: Arc<ConnectionContext>) -> Result<()> {
+        let config = context.server_config().unwrap_or_default();
+
         // Send Window Acknowledgement Size
-        let window_ack = create_window_ack_packet(2500000);
+        let window_ack = create_window_ack_packet(config.window_ack_size);
         context.send_packet(window_ack).await?;
+        context.set_window_ack_size_out(config.window_ack_size).await;
 
         // Send Set Peer Bandwidth
-        let peer_bw = create_peer_bandwidth_packet(2500000, 2);
+        let peer_bw = create_peer_bandwidth_packet(config.peer_bandwidth, 2);
         context.send_packet(peer_bw).await?;
 
-        // Send Set Chunk Size
-        let chunk_size = create_chunk_size_packet(4096);
-        context.send_packet(chunk_size).await?;
-        context.set_chunk_size_out(4096).await;
+        // Raise the outgoing chunk size; the write loop switches to it once sent
+        context.set_chunk_size_out(config.chunk_size).await?;
 
         Ok(())
     }
@@ -101,7 +107,18 @@ impl CommandHandler for ConnectHandler {
         context: Arc<ConnectionContext>,
     ) -> Result<Option<RtmpPacket>> {
         // Validate parameters
-        let params = self.validate_connect_params(&command)?;
+        let params = self.validate_connect_params(&command, &context)?;
+
+        // Ask the server's auth hook, if any
+        let auth = context.server_config().and_then(|config| config.connect_auth.clone());
+        if let Some(auth) = auth
+            && !auth.authorize(&params).await
+        {
+            let response = generate_connect_response(false, command.transaction_id);
+            let bytes = response.encode()?;
+            let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+            return Ok(Some(RtmpPacket::new(header, bytes)));
+        }
 
         // Store connection info in context
         context.set_property("app".to_string(), params.app.clone()).await;
@@ -111,20 +128,25 @@ impl CommandHandler for ConnectHandler {
         // Send server bandwidth settings
         self.send_server_bandwidth(context.clone()).await?;
 
+        if let Some(events) = context.event_listeners() {
+            events.notify_connect(context.connection_id(), &params.app, params.peer_addr).await;
+        }
+
         // Create success response
         let response = self.create_connect_result(command.transaction_id);
         let bytes = response.encode()?;
         let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+        let response = RtmpPacket::new(header, bytes);
 
-        Ok(Some(RtmpPacket::new(header, bytes)))
-    }
-}
+        // The bandwidth check has to follow the connect result
+        if context.server_config().is_some_and(|config| config.bandwidth_check) {
+            context.send_packet(response).await?;
+            start_bandwidth_check(context).await;
+            return Ok(None);
+        }
 
-struct ConnectParams {
-    app: String,
-    tc_url: String,
-    flash_ver: String,
-    object_encoding: f64,
+        Ok(Some(response))
+    }
 }
 
 // Helper functions for control messages
@@ -156,20 +178,5 @@ fn create_peer_bandwidth_packet(size: u32, limit_type: u8) -> RtmpPacket {
         CHUNK_STREAM_PROTOCOL,
     );
 
-    RtmpPacket::new(header, payload)
-}
-
-fn create_chunk_size_packet(size: u32) -> RtmpPacket {
-    let mut payload = Vec::new();
-    payload.extend_from_slice(&size.to_be_bytes());
-
-    let header = RtmpHeader::new(
-        0,
-        payload.len() as u32,
-        MSG_TYPE_SET_CHUNK_SIZE,
-        0,
-        CHUNK_STREAM_PROTOCOL,
-    );
-
     RtmpPacket::new(header, payload)
 }
\ No newline at end of file
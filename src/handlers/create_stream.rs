@@ -1,22 +1,15 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use log::warn;
 use crate::amf::Amf0Value;
-use std::sync::atomic::{AtomicU32, Ordering};
 use crate::handlers::CommandHandler;
-use crate::{ConnectionContext, RtmpCommand, RtmpHeader, RtmpPacket, Result, HandlerContext};
+use crate::{ConnectionContext, NetStatus, RtmpCommand, RtmpHeader, RtmpPacket, Result, HandlerContext};
 
-pub struct CreateStreamHandler {
-    next_stream_id: AtomicU32,
-}
+pub struct CreateStreamHandler;
 
 impl CreateStreamHandler {
     pub fn new() -> Self {
-        CreateStreamHandler {
-            next_stream_id: AtomicU32::new(1), // Start from 1, 0 is reserved
-        }
-    }
-
-    fn allocate_stream_id(&self) -> u32 {
-        self.next_stream_id.fetch_add(1, Ordering::SeqCst)
+        CreateStreamHandler
     }
 }
 
@@ -31,21 +24,35 @@ impl CommandHandler for CreateStreamHandler {
         command: RtmpCommand,
         context: Arc<ConnectionContext>,
     ) -> Result<Option<RtmpPacket>> {
-        // Allocate new stream ID
-        let stream_id = self.allocate_stream_id();
-
-        // Store stream ID in context
-        context.set_property("stream_id".to_string(), stream_id.to_string()).await;
-
-        // Create response
-        let response = RtmpCommand::result(
-            command.transaction_id,
-            Amf0Value::Number(stream_id as f64),
-        );
+        // Allocate new stream ID, within the connection's stream limit
+        let allocated = context.stream_manager().lock().await.allocate();
+
+        let response = match allocated {
+            Ok(stream_id) => {
+                // Store stream ID in context
+                context.set_property("stream_id".to_string(), stream_id.to_string()).await;
+
+                RtmpCommand::result(
+                    command.transaction_id,
+                    Amf0Value::Number(stream_id as f64),
+                )
+            }
+            Err(e) => {
+                warn!(conn_id:% = context.connection_id(); "createStream refused: {}", e);
+
+                let status = NetStatus::CallFailed;
+                let mut error = HashMap::new();
+                error.insert("level".to_string(), Amf0Value::String(status.level().as_str().to_string()));
+                error.insert("code".to_string(), Amf0Value::String(status.code().to_string()));
+                error.insert("description".to_string(), Amf0Value::String(e.to_string()));
+
+                RtmpCommand::error(command.transaction_id, Amf0Value::Object(error))
+            }
+        };
 
         let bytes = response.encode()?;
         let header = RtmpHeader::command(0, bytes.len() as u32, 0);
 
         Ok(Some(RtmpPacket::new(header, bytes)))
     }
-}
\ No newline at end of file
+}
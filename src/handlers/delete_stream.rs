@@ -1,7 +1,6 @@
 use std::sync::Arc;
-use async_trait::async_trait;
 use crate::handlers::CommandHandler;
-use crate::{ConnectionContext, Error, HandlerContext, Result, RtmpCommand, RtmpPacket};
+use crate::{ConnectionContext, Error, HandlerContext, NetStatus, PublisherInfo, Result, RtmpCommand, RtmpPacket};
 
 pub struct DeleteStreamHandler;
 
@@ -25,45 +24,100 @@ impl CommandHandler for DeleteStreamHandler {
         // Get stream ID from first argument
         let stream_id = command.arguments.first()
             .and_then(|v| v.as_number())
-            .ok_or_else(|| Error::protocol("Missing stream ID"))?;
+            .ok_or_else(|| Error::protocol("Missing stream ID"))? as u32;
 
-        // Get stream name from context
-        let stream_name = context.get_property("stream_name").await
-            .ok_or_else(|| Error::protocol("No stream name"))?;
+        // Only release the stream if it is the one this connection owns
+        let current_id = context.get_property("stream_id").await
+            .and_then(|s| s.parse::<u32>().ok());
 
-        // Check if publishing
-        let is_publishing = context.get_property("publishing").await
-            .map(|v| v == "true")
-            .unwrap_or(false);
+        if current_id == Some(stream_id) {
+            release_stream(&context).await?;
+            context.remove_property("stream_id").await;
+        }
+
+        // Free the slot for another createStream; unknown IDs are ignored
+        let _ = context.stream_manager().lock().await.delete_stream(stream_id);
+        context.release_chunk_streams(stream_id).await;
 
-        // Check if playing
-        let is_playing = context.get_property("playing").await
-            .map(|v| v == "true")
-            .unwrap_or(false);
+        // Send deleteStream success (no response expected by spec)
+        Ok(None)
+    }
+}
 
-        // Cleanup based on state
-        if is_publishing {
-            if let Some(registry) = context.get_publisher_registry() {
+/// Stop publishing or playing on the connection's current stream.
+///
+/// A publisher is removed from the registry, so the stream name can be
+/// claimed again, and its subscribers are sent `NetStream.Play.UnpublishNotify`.
+pub async fn release_stream(context: &ConnectionContext) -> Result<()> {
+    let Some(stream_name) = context.get_property("stream_name").await else {
+        return Ok(());
+    };
+
+    // Check if publishing
+    let is_publishing = context.get_property("publishing").await
+        .map(|v| v == "true")
+        .unwrap_or(false);
+
+    // Check if playing
+    let is_playing = context.get_property("playing").await
+        .map(|v| v == "true")
+        .unwrap_or(false);
+
+    // Cleanup based on state
+    if is_publishing {
+        if let Some(registry) = context.get_publisher_registry() {
+            let owned = registry.get(&stream_name).await
+                .filter(|info| info.connection_id == context.connection_id());
+
+            if let Some(info) = owned {
                 registry.unregister(&stream_name).await?;
+                end_publishing(&info).await;
             }
-            context.remove_property("publishing").await;
-            context.remove_property("publish_type").await;
         }
+        context.remove_property("publishing").await;
+        context.remove_property("publish_type").await;
+    }
 
-        if is_playing {
-            if let Some(registry) = context.get_publisher_registry() {
-                registry.decrement_subscribers(&stream_name).await?;
+    if is_playing {
+        if let Some(registry) = context.get_publisher_registry() {
+            // The publisher may already be gone
+            if let Some(info) = registry.get(&stream_name).await {
+                info.publisher.remove_subscriber(context.connection_id()).await;
             }
-            context.remove_property("playing").await;
-            context.remove_property("play_start").await;
-            context.remove_property("play_duration").await;
         }
+        context.remove_property("playing").await;
+        context.remove_property("play_start").await;
+        context.remove_property("play_duration").await;
+        context.remove_property("paused").await;
+        context.remove_property("pause_position").await;
+    }
 
-        // Remove stream context
-        context.remove_property("stream_name").await;
-        context.remove_property("stream_id").await;
+    context.remove_property("stream_name").await;
 
-        // Send deleteStream success (no response expected by spec)
-        Ok(None)
+    Ok(())
+}
+
+/// Release everything a closing connection still holds
+///
+/// Besides the current stream, names published on the connection's other
+/// streams are unregistered, so none stay claimed after it is gone.
+pub async fn release_connection(context: &ConnectionContext) -> Result<()> {
+    release_stream(context).await?;
+
+    if let Some(registry) = context.get_publisher_registry() {
+        for info in registry.unregister_connection(context.connection_id()).await {
+            end_publishing(&info).await;
+        }
     }
-}
\ No newline at end of file
+
+    Ok(())
+}
+
+/// Tell an unregistered stream's subscribers that publishing stopped
+pub(crate) async fn end_publishing(info: &PublisherInfo) {
+    let status = NetStatus::PlayUnpublishNotify.to_command_with(
+        &format!("{} is now unpublished", info.stream_name),
+    );
+    info.publisher.notify_subscribers(&status).await;
+    info.publisher.end().await;
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use crate::handlers::CommandHandler;
+use crate::handlers::delete_stream::release_stream;
+use crate::{Amf0Value, ConnectionContext, Error, HandlerContext, NetStatus, Result, RtmpCommand, RtmpHeader, RtmpPacket};
+
+/// Handles `releaseStream`, sent by FMLE-style encoders before publishing
+pub struct ReleaseStreamHandler;
+
+impl ReleaseStreamHandler {
+    pub fn new() -> Self {
+        ReleaseStreamHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for ReleaseStreamHandler {
+    fn command_name(&self) -> &str {
+        "releaseStream"
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let stream_name = fc_stream_name(&command)?;
+
+        // Drop a registration this connection left behind; release_stream
+        // never touches streams owned by other connections
+        if context.get_property("stream_name").await.as_deref() == Some(stream_name) {
+            release_stream(&context).await?;
+        }
+
+        let response = RtmpCommand::result(command.transaction_id, Amf0Value::Undefined);
+        Ok(Some(command_packet(&response)))
+    }
+}
+
+/// Handles `FCPublish`, answered with `onFCPublish`
+pub struct FcPublishHandler;
+
+impl FcPublishHandler {
+    pub fn new() -> Self {
+        FcPublishHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for FcPublishHandler {
+    fn command_name(&self) -> &str {
+        "FCPublish"
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        _context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let stream_name = fc_stream_name(&command)?;
+
+        let mut status = NetStatus::PublishStart.to_command_with(stream_name);
+        status.name = "onFCPublish".to_string();
+
+        Ok(Some(command_packet(&status)))
+    }
+}
+
+/// Handles `FCUnpublish`, answered with `onFCUnpublish`
+///
+/// The stream itself is released by the `deleteStream` that follows.
+pub struct FcUnpublishHandler;
+
+impl FcUnpublishHandler {
+    pub fn new() -> Self {
+        FcUnpublishHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for FcUnpublishHandler {
+    fn command_name(&self) -> &str {
+        "FCUnpublish"
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        _context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let stream_name = fc_stream_name(&command)?;
+
+        let mut status = NetStatus::UnpublishSuccess.to_command_with(stream_name);
+        status.name = "onFCUnpublish".to_string();
+
+        Ok(Some(command_packet(&status)))
+    }
+}
+
+fn fc_stream_name(command: &RtmpCommand) -> Result<&str> {
+    command.arguments.first()
+        .and_then(|v| v.as_string())
+        .ok_or_else(|| Error::protocol(format!("Missing stream name in {}", command.name)))
+}
+
+/// Responses go out on the control stream, like the commands they answer
+fn command_packet(command: &RtmpCommand) -> RtmpPacket {
+    let bytes = command.encode().unwrap();
+    let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+    RtmpPacket::new(header, bytes)
+}
@@ -0,0 +1,66 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::handlers::CommandHandler;
+use crate::processing::read_flv_duration;
+use crate::{Amf0Value, ConnectionContext, Error, HandlerContext, Result, RtmpCommand, RtmpHeader, RtmpPacket};
+
+pub struct GetStreamLengthHandler;
+
+impl GetStreamLengthHandler {
+    pub fn new() -> Self {
+        GetStreamLengthHandler
+    }
+
+    /// Duration in seconds; live and unknown streams report 0
+    async fn stream_length(&self, stream_name: &str, context: &ConnectionContext) -> f64 {
+        if let Some(registry) = context.get_publisher_registry()
+            && registry.is_publishing(stream_name).await
+        {
+            return 0.0;
+        }
+
+        let vod_root = context.server_config().and_then(|config| config.vod_root.clone());
+        let Some(path) = vod_root.and_then(|root| recorded_path(&root, stream_name)) else {
+            return 0.0;
+        };
+
+        match read_flv_duration(&path).await {
+            Ok(duration) => duration.unwrap_or(0.0),
+            Err(_) => 0.0,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for GetStreamLengthHandler {
+    fn command_name(&self) -> &str {
+        "getStreamLength"
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let stream_name = command.arguments.first()
+            .and_then(|v| v.as_string())
+            .ok_or_else(|| Error::protocol("Missing stream name"))?;
+
+        let duration = self.stream_length(stream_name, &context).await;
+
+        let response = RtmpCommand::result(command.transaction_id, Amf0Value::Number(duration));
+        let bytes = response.encode()?;
+        let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+
+        Ok(Some(RtmpPacket::new(header, bytes)))
+    }
+}
+
+/// File for a recorded stream, refusing names that leave `root`
+fn recorded_path(root: &Path, stream_name: &str) -> Option<PathBuf> {
+    if stream_name.is_empty() || stream_name.contains(['/', '\\']) || stream_name.contains("..") {
+        return None;
+    }
+
+    Some(root.join(format!("{}.flv", stream_name)))
+}
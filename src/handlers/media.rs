@@ -0,0 +1,54 @@
+use std::sync::Arc;
+use crate::{HandlerContext, MessageDispatcher, MessageHandler, Result, RtmpPacket};
+use crate::{MSG_TYPE_AUDIO, MSG_TYPE_DATA_AMF0, MSG_TYPE_VIDEO};
+
+/// Feeds audio, video and data messages from a publishing connection into
+/// the stream's publisher, which fans them out to subscribers.
+pub struct MediaHandler;
+
+impl MediaHandler {
+    pub fn new() -> Self {
+        MediaHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageHandler for MediaHandler {
+    async fn handle(&self, packet: RtmpPacket, context: Arc<dyn HandlerContext>) -> Result<()> {
+        // Media is only accepted while publishing
+        let is_publishing = context.get_property("publishing").await
+            .map(|v| v == "true")
+            .unwrap_or(false);
+        if !is_publishing {
+            return Ok(());
+        }
+
+        let Some(stream_name) = context.get_property("stream_name").await else {
+            return Ok(());
+        };
+        let Some(registry) = context.get_publisher_registry() else {
+            return Ok(());
+        };
+        let Some(info) = registry.get(&stream_name).await else {
+            return Ok(());
+        };
+
+        // A publisher that was replaced may still deliver a few packets
+        // before its connection closes
+        if let Some(connection) = context.clone().connection_context()
+            && connection.connection_id() != info.connection_id
+        {
+            return Ok(());
+        }
+
+        info.publisher.ingest(packet).await
+    }
+}
+
+/// Route published media through a dispatcher
+pub async fn register_media_handlers(dispatcher: &MessageDispatcher) {
+    let handler = Arc::new(MediaHandler::new());
+    for message_type in [MSG_TYPE_AUDIO, MSG_TYPE_VIDEO, MSG_TYPE_DATA_AMF0] {
+        dispatcher.register_handler(message_type, handler.clone()).await;
+    }
+}
@@ -1,19 +1,39 @@
+mod bandwidth;
 mod connect;
 mod create_stream;
 mod publish;
 mod play;
 mod delete_stream;
+mod close_stream;
+mod fc_publish;
+mod get_stream_length;
+mod media;
+mod pause;
+mod receive;
+mod seek;
+
+pub(crate) use delete_stream::{end_publishing, release_connection};
+pub(crate) use media::register_media_handlers;
 
 use std::collections::HashMap;
-use crate::{Amf0Value, Error, Result};
-use crate::protocol::{RtmpCommand, RtmpPacket};
+use log::warn;
+use crate::{Amf0Value, Error, Result, MSG_TYPE_COMMAND_AMF0, MSG_TYPE_COMMAND_AMF3};
+use crate::protocol::{NetStatus, RtmpCommand, RtmpPacket};
 use crate::connection::ConnectionContext;
+use crate::message::{HandlerContext, MessageDispatcher, MessageHandler};
 use std::sync::Arc;
+use crate::handlers::bandwidth::{CheckBandwidthHandler, ResultHandler};
 use crate::handlers::connect::ConnectHandler;
 use crate::handlers::create_stream::CreateStreamHandler;
+use crate::handlers::close_stream::CloseStreamHandler;
 use crate::handlers::delete_stream::DeleteStreamHandler;
+use crate::handlers::fc_publish::{FcPublishHandler, FcUnpublishHandler, ReleaseStreamHandler};
+use crate::handlers::get_stream_length::GetStreamLengthHandler;
+use crate::handlers::pause::PauseHandler;
 use crate::handlers::play::PlayHandler;
 use crate::handlers::publish::PublishHandler;
+use crate::handlers::receive::{ReceiveAudioHandler, ReceiveVideoHandler};
+use crate::handlers::seek::SeekHandler;
 
 #[async_trait::async_trait]
 pub trait CommandHandler: Send + Sync {
@@ -27,12 +47,41 @@ pub trait CommandHandler: Send + Sync {
         context: Arc<ConnectionContext>,
     ) -> Result<Option<RtmpPacket>>;
 
+    /// Check if the command must arrive on the stream `createStream` assigned
+    fn stream_scoped(&self) -> bool {
+        false
+    }
+
     /// Check if can handle command
     fn can_handle(&self, command_name: &str) -> bool {
         self.command_name() == command_name
     }
 }
 
+/// Command name a fallback handler registers under; it receives every
+/// command no other handler is registered for
+pub const FALLBACK_COMMAND: &str = "*";
+
+/// Logs and ignores commands nothing else handles, such as `onStatus` echoes or
+/// `ping`, so they do not fail the connection
+struct IgnoreUnknownHandler;
+
+#[async_trait::async_trait]
+impl CommandHandler for IgnoreUnknownHandler {
+    fn command_name(&self) -> &str {
+        FALLBACK_COMMAND
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        warn!(conn_id:% = context.connection_id(); "Ignoring unknown command '{}'", command.name);
+        Ok(None)
+    }
+}
+
 /// Command handler registry
 pub struct CommandHandlerRegistry {
     handlers: HashMap<String, Arc<dyn CommandHandler>>,
@@ -50,6 +99,18 @@ impl CommandHandlerRegistry {
         registry.register(Arc::new(PublishHandler::new()));
         registry.register(Arc::new(PlayHandler::new()));
         registry.register(Arc::new(DeleteStreamHandler::new()));
+        registry.register(Arc::new(CloseStreamHandler::new()));
+        registry.register(Arc::new(PauseHandler::new()));
+        registry.register(Arc::new(SeekHandler::new()));
+        registry.register(Arc::new(ReceiveAudioHandler::new()));
+        registry.register(Arc::new(ReceiveVideoHandler::new()));
+        registry.register(Arc::new(ReleaseStreamHandler::new()));
+        registry.register(Arc::new(FcPublishHandler::new()));
+        registry.register(Arc::new(FcUnpublishHandler::new()));
+        registry.register(Arc::new(GetStreamLengthHandler::new()));
+        registry.register(Arc::new(CheckBandwidthHandler::new()));
+        registry.register(Arc::new(ResultHandler::new()));
+        registry.register(Arc::new(IgnoreUnknownHandler));
 
         registry
     }
@@ -66,12 +127,86 @@ impl CommandHandlerRegistry {
         command: RtmpCommand,
         context: Arc<ConnectionContext>,
     ) -> Result<Option<RtmpPacket>> {
-        if let Some(handler) = self.handlers.get(&command.name) {
+        if let Some(handler) = self.lookup(&command.name) {
             handler.handle(command, context).await
         } else {
             Err(Error::protocol(format!("Unknown command: {}", command.name)))
         }
     }
+
+    /// Handler for `command_name`, or the fallback if none is registered
+    fn lookup(&self, command_name: &str) -> Option<&Arc<dyn CommandHandler>> {
+        self.handlers.get(command_name)
+            .or_else(|| self.handlers.get(FALLBACK_COMMAND))
+    }
+
+    /// Decode and handle a command packet
+    ///
+    /// Stream-scoped commands are rejected unless the packet's
+    /// `message_stream_id` matches the stream `createStream` assigned.
+    pub async fn handle_packet(
+        &self,
+        packet: &RtmpPacket,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let command = RtmpCommand::decode_with_type(&packet.payload, packet.message_type())?;
+        let handler = self.lookup(&command.name)
+            .ok_or_else(|| Error::protocol(format!("Unknown command: {}", command.name)))?;
+
+        if handler.stream_scoped() {
+            validate_stream_id(&command.name, packet.message_stream_id(), &context).await?;
+        }
+
+        handler.handle(command, context).await
+    }
+}
+
+/// Runs command messages through a `CommandHandlerRegistry` and sends
+/// any response back on the same connection
+struct CommandRouter {
+    registry: CommandHandlerRegistry,
+}
+
+#[async_trait::async_trait]
+impl MessageHandler for CommandRouter {
+    async fn handle(&self, packet: RtmpPacket, context: Arc<dyn HandlerContext>) -> Result<()> {
+        let context = context.connection_context()
+            .ok_or_else(|| Error::invalid_state("Commands need a connection context"))?;
+
+        if let Some(response) = self.registry.handle_packet(&packet, context.clone()).await? {
+            context.send_packet(response).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Route AMF0 and AMF3 commands through the default command handlers
+pub(crate) async fn register_command_handlers(dispatcher: &MessageDispatcher) {
+    let router = Arc::new(CommandRouter { registry: CommandHandlerRegistry::new() });
+    for message_type in [MSG_TYPE_COMMAND_AMF0, MSG_TYPE_COMMAND_AMF3] {
+        dispatcher.register_handler(message_type, router.clone()).await;
+    }
+}
+
+/// Check a stream command's header against the stream created on this connection
+async fn validate_stream_id(
+    command_name: &str,
+    message_stream_id: u32,
+    context: &ConnectionContext,
+) -> Result<()> {
+    let assigned = context.get_property("stream_id").await
+        .and_then(|s| s.parse::<u32>().ok())
+        .ok_or_else(|| Error::protocol(format!("{} before createStream", command_name)))?;
+
+    if message_stream_id != assigned {
+        return Err(Error::protocol(format!(
+            "{} on stream {}, but createStream assigned {}",
+            command_name, message_stream_id, assigned
+        )));
+    }
+
+    Ok(())
 }
 
 pub fn validate_connect_params(params: &Amf0Value) -> Result<()> {
@@ -97,19 +232,774 @@ pub fn generate_connect_response(success: bool, transaction_id: f64) -> RtmpComm
         props.insert("capabilities".to_string(), Amf0Value::Number(31.0));
 
         let mut info = HashMap::new();
-        info.insert("level".to_string(), Amf0Value::String("status".to_string()));
-        info.insert("code".to_string(), Amf0Value::String("NetConnection.Connect.Success".to_string()));
-        info.insert("description".to_string(), Amf0Value::String("Connection succeeded".to_string()));
+        let status = NetStatus::ConnectSuccess;
+        info.insert("level".to_string(), Amf0Value::String(status.level().as_str().to_string()));
+        info.insert("code".to_string(), Amf0Value::String(status.code().to_string()));
+        info.insert("description".to_string(), Amf0Value::String(status.description().to_string()));
 
         let mut cmd = RtmpCommand::result(transaction_id, Amf0Value::Object(props));
         cmd.arguments.push(Amf0Value::Object(info));
         cmd
     } else {
         let mut error = HashMap::new();
-        error.insert("level".to_string(), Amf0Value::String("error".to_string()));
-        error.insert("code".to_string(), Amf0Value::String("NetConnection.Connect.Rejected".to_string()));
-        error.insert("description".to_string(), Amf0Value::String("Connection rejected".to_string()));
+        let status = NetStatus::ConnectRejected;
+        error.insert("level".to_string(), Amf0Value::String(status.level().as_str().to_string()));
+        error.insert("code".to_string(), Amf0Value::String(status.code().to_string()));
+        error.insert("description".to_string(), Amf0Value::String(status.description().to_string()));
 
         RtmpCommand::error(transaction_id, Amf0Value::Object(error))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PublisherRegistry, MSG_TYPE_COMMAND_AMF0};
+    use crate::handlers::delete_stream::release_stream;
+    use tokio::sync::mpsc;
+
+    fn create_context(
+        id: &str,
+        registry: Arc<PublisherRegistry>,
+    ) -> (Arc<ConnectionContext>, mpsc::Receiver<RtmpPacket>) {
+        let (tx, rx) = mpsc::channel(100);
+        let context = ConnectionContext::new(id.to_string(), tx).with_publisher_registry(registry);
+        (Arc::new(context), rx)
+    }
+
+    async fn publish(
+        handlers: &CommandHandlerRegistry,
+        context: &Arc<ConnectionContext>,
+        name: &str,
+    ) -> Result<Option<RtmpPacket>> {
+        context.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::publish(name, "live"), context.clone()).await
+    }
+
+    #[tokio::test]
+    async fn test_publish_same_name_after_close_stream() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (first, _first_rx) = create_context("conn-1", registry.clone());
+        let (second, _second_rx) = create_context("conn-2", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-3", registry.clone());
+
+        publish(&handlers, &first, "live").await.unwrap();
+        assert!(publish(&handlers, &second, "live").await.is_err());
+
+        // A viewer plays the stream and should hear about the unpublish
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+        while viewer_rx.try_recv().is_ok() {}
+
+        let close = RtmpCommand::new("closeStream".to_string(), 0.0);
+        handlers.handle(close, first.clone()).await.unwrap();
+        assert!(!registry.is_publishing("live").await);
+        assert!(first.get_property("publishing").await.is_none());
+
+        let notify = next_matching(&mut viewer_rx, |p| p.message_type() == MSG_TYPE_COMMAND_AMF0).await.unwrap();
+        let status = RtmpCommand::decode(&notify.payload).unwrap();
+        assert_eq!(
+            status.arguments[0].get_property("code").and_then(|v| v.as_string()),
+            Some("NetStream.Play.UnpublishNotify")
+        );
+
+        publish(&handlers, &second, "live").await.unwrap();
+        assert_eq!(registry.get("live").await.unwrap().connection_id, "conn-2");
+    }
+
+    #[tokio::test]
+    async fn test_republish_replace_takes_over_stream() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(
+            PublisherRegistry::new().with_republish_policy(crate::RepublishPolicy::Replace),
+        );
+        let (first, _first_rx) = create_context("conn-1", registry.clone());
+        let (second, _second_rx) = create_context("conn-2", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-3", registry.clone());
+
+        publish(&handlers, &first, "live").await.unwrap();
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+        while viewer_rx.try_recv().is_ok() {}
+
+        let response = publish(&handlers, &second, "live").await.unwrap().unwrap();
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Publish.Start"));
+        assert_eq!(registry.get("live").await.unwrap().connection_id, "conn-2");
+
+        // The old publisher is asked to close
+        let closed = first.close_handle();
+        tokio::time::timeout(std::time::Duration::from_millis(200), closed.requested())
+            .await
+            .expect("displaced publisher was not closed");
+
+        // The viewer stays subscribed and hears about the new source
+        let notify = next_matching(&mut viewer_rx, |p| p.message_type() == MSG_TYPE_COMMAND_AMF0).await.unwrap();
+        assert_eq!(status_code(&notify).as_deref(), Some("NetStream.Publish.Start"));
+
+        // Late media from the old publisher is dropped
+        let dispatcher = crate::MessageDispatcher::new();
+        register_media_handlers(&dispatcher).await;
+        let keyframe = crate::make_video_packet(vec![0x17, 0x01, 0x00, 0x00], 0, 1);
+        dispatcher.dispatch(keyframe.clone(), first.clone()).await.unwrap();
+        assert!(viewer_rx.try_recv().is_err());
+
+        dispatcher.dispatch(keyframe, second.clone()).await.unwrap();
+        assert!(next_matching(&mut viewer_rx, |p| p.is_video()).await.is_some());
+    }
+
+    /// Records every event it hears as a string
+    #[derive(Default)]
+    struct RecordingListener {
+        events: tokio::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::EventListener for RecordingListener {
+        async fn on_publish(&self, stream_name: &str, conn_id: &str) {
+            self.events.lock().await.push(format!("publish {} {}", stream_name, conn_id));
+        }
+
+        async fn on_play(&self, stream_name: &str, conn_id: &str) {
+            self.events.lock().await.push(format!("play {} {}", stream_name, conn_id));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_listeners_hear_publish_and_play() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let listeners = Arc::new(crate::EventListeners::new());
+        let first = Arc::new(RecordingListener::default());
+        let second = Arc::new(RecordingListener::default());
+        listeners.add(first.clone()).await;
+        listeners.add(second.clone()).await;
+
+        let with_listeners = |id: &str| {
+            let (tx, rx) = mpsc::channel(100);
+            let context = ConnectionContext::new(id.to_string(), tx)
+                .with_publisher_registry(registry.clone())
+                .with_event_listeners(listeners.clone());
+            (Arc::new(context), rx)
+        };
+        let (publisher, _publisher_rx) = with_listeners("conn-1");
+        let (viewer, _viewer_rx) = with_listeners("conn-2");
+
+        publish(&handlers, &publisher, "live").await.unwrap();
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+
+        let expected = vec!["publish live conn-1".to_string(), "play live conn-2".to_string()];
+        assert_eq!(*first.events.lock().await, expected);
+        assert_eq!(*second.events.lock().await, expected);
+    }
+
+    fn command_packet(command: &RtmpCommand, stream_id: u32) -> RtmpPacket {
+        let bytes = command.encode().unwrap();
+        let header = crate::RtmpHeader::command(0, bytes.len() as u32, stream_id);
+        RtmpPacket::new(header, bytes)
+    }
+
+    #[tokio::test]
+    async fn test_stream_command_on_wrong_stream_rejected() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (context, _rx) = create_context("conn-1", registry.clone());
+
+        let publish = RtmpCommand::publish("live", "live");
+        assert!(handlers.handle_packet(&command_packet(&publish, 1), context.clone()).await.is_err());
+
+        let create = RtmpCommand::create_stream(2.0);
+        handlers.handle_packet(&command_packet(&create, 0), context.clone()).await.unwrap();
+        assert_eq!(context.get_property("stream_id").await.as_deref(), Some("1"));
+
+        let result = handlers.handle_packet(&command_packet(&publish, 2), context.clone()).await;
+        assert!(matches!(result, Err(Error::Protocol(_))));
+        assert!(!registry.is_publishing("live").await);
+
+        handlers.handle_packet(&command_packet(&publish, 1), context.clone()).await.unwrap();
+        assert!(registry.is_publishing("live").await);
+    }
+
+    #[tokio::test]
+    async fn test_published_media_reaches_player() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let dispatcher = crate::MessageDispatcher::new();
+        register_media_handlers(&dispatcher).await;
+
+        let (publisher, _publisher_rx) = create_context("conn-1", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-2", registry.clone());
+        publish(&handlers, &publisher, "live").await.unwrap();
+
+        // A keyframe published before the viewer joins is replayed from the GOP cache
+        let keyframe = crate::make_video_packet(vec![0x17, 0x01, 0xAA], 0, 1);
+        dispatcher.dispatch(keyframe, publisher.clone()).await.unwrap();
+
+        viewer.set_property("stream_id".to_string(), "3".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+
+        let frame = crate::make_video_packet(vec![0x27, 0x01, 0xBB], 40, 1);
+        dispatcher.dispatch(frame, publisher.clone()).await.unwrap();
+
+        let mut video = Vec::new();
+        while video.len() < 2 {
+            let packet = tokio::time::timeout(std::time::Duration::from_secs(1), viewer_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            if packet.is_video() {
+                video.push(packet);
+            }
+        }
+        assert_eq!(video[0].payload, vec![0x17, 0x01, 0xAA]);
+        assert_eq!(video[1].payload, vec![0x27, 0x01, 0xBB]);
+        assert!(video.iter().all(|p| p.message_stream_id() == 3));
+
+        // Unpublishing ends delivery
+        release_stream(&publisher).await.unwrap();
+        assert_eq!(registry.get("live").await.map(|_| ()), None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_when_publisher_disconnects() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (publisher, _publisher_rx) = create_context("conn-1", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-2", registry.clone());
+
+        publish(&handlers, &publisher, "live").await.unwrap();
+        let info = registry.get("live").await.unwrap();
+        let mut state = info.publisher.stream().watch_state();
+        assert_eq!(*state.borrow(), crate::StreamState::Publishing);
+
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+        assert_eq!(*state.borrow_and_update(), crate::StreamState::Playing);
+
+        // The connection's teardown releases the stream
+        release_stream(&publisher).await.unwrap();
+        state.changed().await.unwrap();
+        assert_eq!(*state.borrow(), crate::StreamState::Ended);
+
+        let stop = next_matching(&mut viewer_rx, |p| {
+            status_code(p).as_deref() == Some("NetStream.Play.Stop")
+        }).await;
+        assert!(stop.is_some());
+
+        // The closed subscription ends with Stream EOF
+        let eof = next_matching(&mut viewer_rx, |p| p.message_type() == crate::MSG_TYPE_USER_CONTROL).await;
+        let eof = crate::UserControlEvent::decode(&eof.unwrap().payload).unwrap();
+        assert_eq!(eof, crate::UserControlEvent::StreamEof(1));
+    }
+
+    /// Next packet delivered to a context, skipping anything but `wanted`
+    async fn next_matching<F>(rx: &mut mpsc::Receiver<RtmpPacket>, wanted: F) -> Option<RtmpPacket>
+    where
+        F: Fn(&RtmpPacket) -> bool,
+    {
+        loop {
+            let packet = tokio::time::timeout(std::time::Duration::from_millis(200), rx.recv())
+                .await
+                .ok()??;
+            if wanted(&packet) {
+                return Some(packet);
+            }
+        }
+    }
+
+    fn status_code(packet: &RtmpPacket) -> Option<String> {
+        let status = RtmpCommand::decode(&packet.payload).ok()?;
+        status.arguments.first()?
+            .get_property("code")
+            .and_then(|v| v.as_string())
+            .map(|s| s.to_string())
+    }
+
+    fn command_name(packet: &RtmpPacket) -> String {
+        RtmpCommand::decode(&packet.payload).map(|c| c.name).unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_check_sends_on_bw_done() {
+        let handlers = CommandHandlerRegistry::new();
+        let config = crate::ServerConfig::builder().bandwidth_check(true).build().unwrap();
+        let (tx, mut rx) = mpsc::channel(100);
+        let context = Arc::new(
+            ConnectionContext::new("conn-1".to_string(), tx).with_server_config(Arc::new(config)),
+        );
+
+        let connect = RtmpCommand::connect("live", "rtmp://localhost/live");
+        assert!(handlers.handle(connect, context.clone()).await.unwrap().is_none());
+        assert!(next_matching(&mut rx, |p| command_name(p) == "_result").await.is_some());
+
+        // Answer each onBWCheck as a Flash client would
+        for _ in 0..3 {
+            let call = next_matching(&mut rx, |p| command_name(p) == "onBWCheck").await.unwrap();
+            let call = RtmpCommand::decode(&call.payload).unwrap();
+            let reply = RtmpCommand::result(call.transaction_id, Amf0Value::Null);
+            assert!(handlers.handle(reply, context.clone()).await.unwrap().is_none());
+        }
+
+        let done = next_matching(&mut rx, |p| command_name(p) == "onBWDone").await.unwrap();
+        let done = RtmpCommand::decode(&done.payload).unwrap();
+        let kbps = done.arguments[0].as_number().unwrap();
+        assert!(kbps >= 0.0);
+    }
+
+    fn pause_command(paused: bool) -> RtmpCommand {
+        let mut pause = RtmpCommand::new("pause".to_string(), 0.0);
+        pause.arguments.push(Amf0Value::Boolean(paused));
+        pause.arguments.push(Amf0Value::Number(0.0));
+        pause
+    }
+
+    #[tokio::test]
+    async fn test_pause_stops_and_resumes_delivery() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let dispatcher = crate::MessageDispatcher::new();
+        register_media_handlers(&dispatcher).await;
+
+        let (publisher, _publisher_rx) = create_context("conn-1", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-2", registry.clone());
+        publish(&handlers, &publisher, "live").await.unwrap();
+
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+
+        let keyframe = crate::make_video_packet(vec![0x17, 0x01, 0xAA], 0, 1);
+        dispatcher.dispatch(keyframe, publisher.clone()).await.unwrap();
+        let video = next_matching(&mut viewer_rx, |p| p.is_video()).await.unwrap();
+        assert_eq!(video.payload, vec![0x17, 0x01, 0xAA]);
+
+        // Paused: status arrives, media does not
+        handlers.handle(pause_command(true), viewer.clone()).await.unwrap();
+        let status = next_matching(&mut viewer_rx, |p| p.message_type() == MSG_TYPE_COMMAND_AMF0).await.unwrap();
+        assert_eq!(status_code(&status).as_deref(), Some("NetStream.Pause.Notify"));
+
+        let frame = crate::make_video_packet(vec![0x27, 0x01, 0xBB], 40, 1);
+        dispatcher.dispatch(frame, publisher.clone()).await.unwrap();
+        assert!(next_matching(&mut viewer_rx, |p| p.is_video()).await.is_none());
+        assert_eq!(registry.get("live").await.unwrap().publisher.subscriber_count().await, 1);
+
+        // Unpaused: status, then the GOP from its keyframe, then live media
+        handlers.handle(pause_command(false), viewer.clone()).await.unwrap();
+        let status = next_matching(&mut viewer_rx, |p| p.message_type() == MSG_TYPE_COMMAND_AMF0).await.unwrap();
+        assert_eq!(status_code(&status).as_deref(), Some("NetStream.Unpause.Notify"));
+
+        let frame = crate::make_video_packet(vec![0x27, 0x01, 0xCC], 80, 1);
+        dispatcher.dispatch(frame, publisher.clone()).await.unwrap();
+
+        let mut payloads = Vec::new();
+        while let Some(video) = next_matching(&mut viewer_rx, |p| p.is_video()).await {
+            payloads.push(video.payload);
+        }
+        assert_eq!(payloads, vec![
+            vec![0x17, 0x01, 0xAA],
+            vec![0x27, 0x01, 0xBB],
+            vec![0x27, 0x01, 0xCC],
+        ]);
+        assert!(viewer.get_property("paused").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_seek_backward_resends_keyframe() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::with_gop_cache_size(3));
+        let dispatcher = crate::MessageDispatcher::new();
+        register_media_handlers(&dispatcher).await;
+
+        let (publisher, _publisher_rx) = create_context("conn-1", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-2", registry.clone());
+        publish(&handlers, &publisher, "live").await.unwrap();
+
+        // Seeking requires playback
+        let mut seek = RtmpCommand::new("seek".to_string(), 0.0);
+        seek.arguments.push(Amf0Value::Number(1500.0));
+        assert!(handlers.handle(seek.clone(), viewer.clone()).await.is_err());
+
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+
+        for base in [1000, 2000] {
+            let keyframe = crate::make_video_packet(vec![0x17, 0x01], base, 1);
+            dispatcher.dispatch(keyframe, publisher.clone()).await.unwrap();
+            let frame = crate::make_video_packet(vec![0x27, 0x01], base + 40, 1);
+            dispatcher.dispatch(frame, publisher.clone()).await.unwrap();
+        }
+        while next_matching(&mut viewer_rx, |_| true).await.is_some() {}
+
+        handlers.handle(seek, viewer.clone()).await.unwrap();
+
+        let notify = next_matching(&mut viewer_rx, |p| p.message_type() == MSG_TYPE_COMMAND_AMF0).await.unwrap();
+        assert_eq!(status_code(&notify).as_deref(), Some("NetStream.Seek.Notify"));
+        let start = next_matching(&mut viewer_rx, |p| p.message_type() == MSG_TYPE_COMMAND_AMF0).await.unwrap();
+        assert_eq!(status_code(&start).as_deref(), Some("NetStream.Play.Start"));
+
+        // Delivery restarts at the keyframe before the requested position
+        let video = next_matching(&mut viewer_rx, |p| p.is_video()).await.unwrap();
+        assert_eq!(video.timestamp(), 1000);
+        assert_eq!(video.payload[0], 0x17);
+        assert_eq!(viewer.get_property("play_start").await.as_deref(), Some("1000"));
+    }
+
+    #[tokio::test]
+    async fn test_receive_video_false_keeps_audio() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let dispatcher = crate::MessageDispatcher::new();
+        register_media_handlers(&dispatcher).await;
+
+        let (publisher, _publisher_rx) = create_context("conn-1", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-2", registry.clone());
+        publish(&handlers, &publisher, "live").await.unwrap();
+
+        let sequence_header = crate::make_video_packet(vec![0x17, 0x00, 0x01], 0, 1);
+        dispatcher.dispatch(sequence_header, publisher.clone()).await.unwrap();
+
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("live", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+        while next_matching(&mut viewer_rx, |_| true).await.is_some() {}
+
+        let mut receive_video = RtmpCommand::new("receiveVideo".to_string(), 0.0);
+        receive_video.arguments.push(Amf0Value::Boolean(false));
+        handlers.handle(receive_video.clone(), viewer.clone()).await.unwrap();
+
+        let frame = crate::make_video_packet(vec![0x27, 0x01], 40, 1);
+        dispatcher.dispatch(frame, publisher.clone()).await.unwrap();
+        let audio = crate::make_audio_packet(vec![0xAF, 0x01], 40, 1);
+        dispatcher.dispatch(audio, publisher.clone()).await.unwrap();
+
+        let mut received = Vec::new();
+        while let Some(packet) = next_matching(&mut viewer_rx, |_| true).await {
+            received.push(packet.message_type());
+        }
+        assert_eq!(received, vec![crate::MSG_TYPE_AUDIO]);
+
+        // Re-enabling video starts with the sequence header
+        receive_video.arguments[0] = Amf0Value::Boolean(true);
+        handlers.handle(receive_video, viewer.clone()).await.unwrap();
+        let video = next_matching(&mut viewer_rx, |p| p.is_video()).await.unwrap();
+        assert_eq!(video.payload, vec![0x17, 0x00, 0x01]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_auth_rejects_app() {
+        let config = crate::ServerConfig::builder()
+            .connect_auth(|params| {
+                let allowed = params.app != "private";
+                Box::pin(async move { allowed })
+            })
+            .build()
+            .unwrap();
+        let config = Arc::new(config);
+        let handlers = CommandHandlerRegistry::new();
+
+        let (tx, _rx) = mpsc::channel(100);
+        let context = Arc::new(
+            ConnectionContext::new("conn-1".to_string(), tx).with_server_config(config),
+        );
+
+        let command = RtmpCommand::connect("private", "rtmp://localhost/private");
+        let response = handlers.handle(command, context.clone()).await.unwrap().unwrap();
+        assert_eq!(RtmpCommand::decode(&response.payload).unwrap().name, "_error");
+        assert!(context.get_property("app").await.is_none());
+
+        let command = RtmpCommand::connect("live", "rtmp://localhost/live");
+        let response = handlers.handle(command, context.clone()).await.unwrap().unwrap();
+        assert_eq!(RtmpCommand::decode(&response.payload).unwrap().name, "_result");
+    }
+
+    #[tokio::test]
+    async fn test_connect_auth_sees_typed_object_class() {
+        let config = crate::ServerConfig::builder()
+            .connect_auth(|params| {
+                let allowed = params.object_class.as_deref() == Some("flex.messaging.io.ConnectObject");
+                Box::pin(async move { allowed })
+            })
+            .build()
+            .unwrap();
+        let handlers = CommandHandlerRegistry::new();
+
+        let (tx, _rx) = mpsc::channel(100);
+        let context = Arc::new(
+            ConnectionContext::new("conn-1".to_string(), tx).with_server_config(Arc::new(config)),
+        );
+
+        let mut command = RtmpCommand::connect("live", "rtmp://localhost/live");
+        let properties = command.command_object.as_ref().and_then(|v| v.as_object()).unwrap().clone();
+        command.command_object = Some(Amf0Value::TypedObject("flex.messaging.io.ConnectObject".to_string(), properties));
+
+        let response = handlers.handle(command, context).await.unwrap().unwrap();
+        assert_eq!(RtmpCommand::decode(&response.payload).unwrap().name, "_result");
+    }
+
+    #[tokio::test]
+    async fn test_connect_announces_configured_window() {
+        let config = crate::ServerConfig::builder()
+            .window_ack_size(1_000_000)
+            .peer_bandwidth(3_000_000)
+            .chunk_size(8192)
+            .build()
+            .unwrap();
+        let handlers = CommandHandlerRegistry::new();
+
+        let (tx, mut rx) = mpsc::channel(100);
+        let context = Arc::new(
+            ConnectionContext::new("conn-1".to_string(), tx).with_server_config(Arc::new(config)),
+        );
+
+        let command = RtmpCommand::connect("live", "rtmp://localhost/live");
+        handlers.handle(command, context.clone()).await.unwrap();
+
+        let window_ack = rx.try_recv().unwrap();
+        assert_eq!(window_ack.message_type(), crate::MSG_TYPE_WINDOW_ACK);
+        assert_eq!(window_ack.payload[..], 1_000_000u32.to_be_bytes());
+        assert_eq!(context.window_ack_size_out().await, 1_000_000);
+
+        let peer_bw = rx.try_recv().unwrap();
+        assert_eq!(peer_bw.message_type(), crate::MSG_TYPE_SET_PEER_BW);
+        assert_eq!(peer_bw.payload[..4], 3_000_000u32.to_be_bytes());
+
+        let chunk_size = rx.try_recv().unwrap();
+        assert_eq!(chunk_size.message_type(), crate::MSG_TYPE_SET_CHUNK_SIZE);
+        assert_eq!(chunk_size.payload[..], 8192u32.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_publish_auth_checks_stream_key() {
+        let config = crate::ServerConfig::builder()
+            .publish_auth(|params| {
+                let allowed = params.stream_name == "live?key=secret";
+                Box::pin(async move { allowed })
+            })
+            .build()
+            .unwrap();
+        let config = Arc::new(config);
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+
+        let (tx, _rx) = mpsc::channel(100);
+        let context = Arc::new(
+            ConnectionContext::new("conn-1".to_string(), tx)
+                .with_publisher_registry(registry.clone())
+                .with_server_config(config),
+        );
+
+        let response = publish(&handlers, &context, "live?key=wrong").await.unwrap().unwrap();
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Publish.BadName"));
+        assert!(!registry.is_publishing("live?key=wrong").await);
+        assert!(context.get_property("publishing").await.is_none());
+
+        let response = publish(&handlers, &context, "live?key=secret").await.unwrap().unwrap();
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Publish.Start"));
+        assert!(registry.is_publishing("live?key=secret").await);
+    }
+
+    /// Fallback that remembers which commands reached it
+    #[derive(Default)]
+    struct RecordingFallback {
+        seen: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl CommandHandler for RecordingFallback {
+        fn command_name(&self) -> &str {
+            FALLBACK_COMMAND
+        }
+
+        async fn handle(
+            &self,
+            command: RtmpCommand,
+            _context: Arc<ConnectionContext>,
+        ) -> Result<Option<RtmpPacket>> {
+            self.seen.lock().unwrap().push(command.name);
+            Ok(None)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_command_goes_to_fallback() {
+        let registry = Arc::new(PublisherRegistry::new());
+        let (context, _rx) = create_context("conn-1", registry);
+
+        // The default fallback ignores it
+        let mut handlers = CommandHandlerRegistry::new();
+        let ping = RtmpCommand::new("ping".to_string(), 0.0);
+        assert!(handlers.handle(ping.clone(), context.clone()).await.unwrap().is_none());
+
+        let fallback = Arc::new(RecordingFallback::default());
+        handlers.register(fallback.clone());
+        handlers.handle(ping, context.clone()).await.unwrap();
+        handlers.handle(RtmpCommand::new("secureTokenResponse".to_string(), 0.0), context.clone()).await.unwrap();
+
+        // Registered commands still reach their own handlers
+        let response = handlers.handle(RtmpCommand::new("createStream".to_string(), 2.0), context).await.unwrap();
+        assert_eq!(command_name(&response.unwrap()), "_result");
+        assert_eq!(*fallback.seen.lock().unwrap(), ["ping", "secureTokenResponse"]);
+    }
+
+    #[tokio::test]
+    async fn test_publish_type_is_validated() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (context, _rx) = create_context("conn-1", registry.clone());
+        context.set_property("stream_id".to_string(), "1".to_string()).await;
+
+        let response = handlers.handle(RtmpCommand::publish("live", "garbage"), context.clone()).await.unwrap().unwrap();
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Publish.BadName"));
+        assert!(!registry.is_publishing("live").await);
+
+        let response = handlers.handle(RtmpCommand::publish("live", "record"), context.clone()).await.unwrap().unwrap();
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Publish.Start"));
+        let info = registry.get("live").await.unwrap();
+        assert_eq!(info.publisher.stream().info().await.stream_type, crate::stream::StreamType::Record);
+    }
+
+    #[tokio::test]
+    async fn test_delete_stream_ignores_other_stream_id() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (context, _rx) = create_context("conn-1", registry.clone());
+
+        publish(&handlers, &context, "live").await.unwrap();
+
+        let mut delete = RtmpCommand::new("deleteStream".to_string(), 0.0);
+        delete.arguments.push(Amf0Value::Number(2.0));
+        handlers.handle(delete.clone(), context.clone()).await.unwrap();
+        assert!(registry.is_publishing("live").await);
+
+        delete.arguments[0] = Amf0Value::Number(1.0);
+        handlers.handle(delete, context.clone()).await.unwrap();
+        assert!(!registry.is_publishing("live").await);
+        assert!(context.get_property("stream_id").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_stream_past_limit_is_refused() {
+        let handlers = CommandHandlerRegistry::new();
+        let config = crate::ServerConfig::builder().max_streams_per_connection(2).build().unwrap();
+        let (tx, _rx) = mpsc::channel(100);
+        let context = Arc::new(
+            ConnectionContext::new("conn-1".to_string(), tx).with_server_config(Arc::new(config)),
+        );
+
+        let create = RtmpCommand::new("createStream".to_string(), 2.0);
+        for expected in [1.0, 2.0] {
+            let response = handlers.handle(create.clone(), context.clone()).await.unwrap().unwrap();
+            let response = RtmpCommand::decode(&response.payload).unwrap();
+            assert_eq!(response.name, "_result");
+            assert_eq!(response.arguments[0].as_number(), Some(expected));
+        }
+
+        let response = handlers.handle(create.clone(), context.clone()).await.unwrap().unwrap();
+        assert_eq!(command_name(&response), "_error");
+        assert_eq!(status_code(&response).as_deref(), Some("NetConnection.Call.Failed"));
+
+        // Deleting a stream frees its slot and its ID
+        let mut delete = RtmpCommand::new("deleteStream".to_string(), 0.0);
+        delete.arguments.push(Amf0Value::Number(1.0));
+        handlers.handle(delete, context.clone()).await.unwrap();
+
+        let response = handlers.handle(create, context.clone()).await.unwrap().unwrap();
+        let response = RtmpCommand::decode(&response.payload).unwrap();
+        assert_eq!(response.name, "_result");
+        assert_eq!(response.arguments[0].as_number(), Some(1.0));
+    }
+
+    fn fc_command(name: &str, transaction_id: f64, stream_name: &str) -> RtmpCommand {
+        let mut command = RtmpCommand::new(name.to_string(), transaction_id);
+        command.command_object = Some(Amf0Value::Null);
+        command.arguments.push(Amf0Value::String(stream_name.to_string()));
+        command
+    }
+
+    #[tokio::test]
+    async fn test_ffmpeg_publish_sequence() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (context, _rx) = create_context("conn-1", registry.clone());
+
+        let response = handlers.handle(fc_command("releaseStream", 2.0, "live"), context.clone()).await.unwrap().unwrap();
+        let result = RtmpCommand::decode(&response.payload).unwrap();
+        assert_eq!(result.name, "_result");
+        assert_eq!(result.transaction_id, 2.0);
+
+        let response = handlers.handle(fc_command("FCPublish", 3.0, "live"), context.clone()).await.unwrap().unwrap();
+        assert_eq!(RtmpCommand::decode(&response.payload).unwrap().name, "onFCPublish");
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Publish.Start"));
+
+        let response = publish(&handlers, &context, "live").await.unwrap().unwrap();
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Publish.Start"));
+        assert!(registry.is_publishing("live").await);
+
+        let response = handlers.handle(fc_command("FCUnpublish", 5.0, "live"), context.clone()).await.unwrap().unwrap();
+        assert_eq!(RtmpCommand::decode(&response.payload).unwrap().name, "onFCUnpublish");
+        assert_eq!(status_code(&response).as_deref(), Some("NetStream.Unpublish.Success"));
+    }
+
+    #[tokio::test]
+    async fn test_release_stream_only_clears_own_registration() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (first, _first_rx) = create_context("conn-1", registry.clone());
+        let (second, _second_rx) = create_context("conn-2", registry.clone());
+
+        publish(&handlers, &first, "live").await.unwrap();
+
+        // Another connection cannot release someone else's stream
+        handlers.handle(fc_command("releaseStream", 2.0, "live"), second.clone()).await.unwrap();
+        assert_eq!(registry.get("live").await.unwrap().connection_id, "conn-1");
+
+        // The owner re-publishing the same name clears its stale registration
+        handlers.handle(fc_command("releaseStream", 2.0, "live"), first.clone()).await.unwrap();
+        assert!(!registry.is_publishing("live").await);
+        publish(&handlers, &first, "live").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_release_connection_unregisters_every_stream() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (publisher, _publisher_rx) = create_context("conn-1", registry.clone());
+        let (viewer, mut viewer_rx) = create_context("conn-2", registry.clone());
+
+        // Two names from one connection; only the last is its current stream
+        publish(&handlers, &publisher, "first").await.unwrap();
+        publish(&handlers, &publisher, "second").await.unwrap();
+
+        viewer.set_property("stream_id".to_string(), "1".to_string()).await;
+        handlers.handle(RtmpCommand::play("first", 0.0, -1.0, true), viewer.clone()).await.unwrap();
+        while viewer_rx.try_recv().is_ok() {}
+
+        // What the server runs once the connection is gone
+        release_connection(&publisher).await.unwrap();
+
+        assert!(!registry.is_publishing("first").await);
+        assert!(!registry.is_publishing("second").await);
+        let notify = next_matching(&mut viewer_rx, |p| status_code(p).is_some()).await.unwrap();
+        assert_eq!(status_code(&notify).as_deref(), Some("NetStream.Play.UnpublishNotify"));
+    }
+
+    #[tokio::test]
+    async fn test_get_stream_length_of_live_stream() {
+        let handlers = CommandHandlerRegistry::new();
+        let registry = Arc::new(PublisherRegistry::new());
+        let (publisher, _publisher_rx) = create_context("conn-1", registry.clone());
+        let (viewer, _viewer_rx) = create_context("conn-2", registry.clone());
+
+        publish(&handlers, &publisher, "live").await.unwrap();
+
+        for name in ["live", "missing"] {
+            let response = handlers.handle(fc_command("getStreamLength", 4.0, name), viewer.clone())
+                .await
+                .unwrap()
+                .unwrap();
+            let result = RtmpCommand::decode(&response.payload).unwrap();
+            assert_eq!(result.name, "_result");
+            assert_eq!(result.transaction_id, 4.0);
+            assert_eq!(result.arguments.first().and_then(|v| v.as_number()), Some(0.0));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use crate::handlers::CommandHandler;
+use crate::{ConnectionContext, Error, HandlerContext, NetStatus, Result, RtmpCommand, RtmpHeader, RtmpPacket};
+
+pub struct PauseHandler;
+
+impl PauseHandler {
+    pub fn new() -> Self {
+        PauseHandler
+    }
+
+    fn create_pause_status(&self, paused: bool, stream_name: &str, stream_id: u32) -> RtmpPacket {
+        let status = if paused {
+            NetStatus::PauseNotify.to_command_with(&format!("Paused {}", stream_name))
+        } else {
+            NetStatus::UnpauseNotify.to_command_with(&format!("Unpaused {}", stream_name))
+        };
+
+        let bytes = status.encode().unwrap();
+        let header = RtmpHeader::command(0, bytes.len() as u32, stream_id);
+
+        RtmpPacket::new(header, bytes)
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for PauseHandler {
+    fn command_name(&self) -> &str {
+        "pause"
+    }
+
+    fn stream_scoped(&self) -> bool {
+        true
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        // Extract parameters
+        let paused = command.arguments.first()
+            .and_then(|v| v.as_boolean())
+            .ok_or_else(|| Error::protocol("Missing pause flag"))?;
+
+        let position = command.arguments.get(1)
+            .and_then(|v| v.as_number())
+            .unwrap_or(0.0);
+
+        // Only a playing connection can pause
+        let is_playing = context.get_property("playing").await
+            .map(|v| v == "true")
+            .unwrap_or(false);
+        if !is_playing {
+            return Err(Error::stream("Not playing"));
+        }
+
+        let stream_name = context.get_property("stream_name").await
+            .ok_or_else(|| Error::stream("No stream name"))?;
+        let stream_id = context.get_property("stream_id").await
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(|| Error::protocol("No stream ID"))?;
+
+        // Status goes out first so it precedes any replayed media
+        let status = self.create_pause_status(paused, &stream_name, stream_id);
+        context.send_packet(status).await?;
+
+        // Suspend or resume delivery; the subscription itself stays registered
+        if let Some(registry) = context.get_publisher_registry()
+            && let Some(info) = registry.get(&stream_name).await
+        {
+            info.publisher.set_paused(context.connection_id(), paused).await;
+        }
+
+        if paused {
+            context.set_property("paused".to_string(), "true".to_string()).await;
+            context.set_property("pause_position".to_string(), position.to_string()).await;
+        } else {
+            context.remove_property("paused").await;
+            context.remove_property("pause_position").await;
+        }
+
+        Ok(None) // Status sent directly
+    }
+}
@@ -1,6 +1,8 @@
 use std::sync::Arc;
-use crate::{Amf0Value, ConnectionContext, Error, RtmpCommand, RtmpData, RtmpHeader, RtmpPacket, Result, HandlerContext, PublisherInfo};
+use tokio::sync::mpsc;
+use crate::{Amf0Value, ConnectionContext, Error, NetStatus, RtmpCommand, RtmpData, RtmpHeader, RtmpPacket, Result, HandlerContext, PublisherInfo, SendPacer, UserControlEvent, MSG_TYPE_USER_CONTROL};
 use crate::handlers::CommandHandler;
+use crate::protocol::make_stream_eof_packet;
 use crate::handlers::publish::create_stream_begin_packet;
 
 pub struct PlayHandler;
@@ -29,9 +31,7 @@ impl PlayHandler {
         packets.push(create_stream_begin_packet(stream_id));
 
         // Play.Reset
-        let reset = RtmpCommand::on_status(
-            "status",
-            "NetStream.Play.Reset",
+        let reset = NetStatus::PlayReset.to_command_with(
             &format!("Playing and resetting {}", stream_name),
         );
         let bytes = reset.encode().unwrap();
@@ -39,9 +39,7 @@ impl PlayHandler {
         packets.push(RtmpPacket::new(header, bytes));
 
         // Play.Start
-        let start = RtmpCommand::on_status(
-            "status",
-            "NetStream.Play.Start",
+        let start = NetStatus::PlayStart.to_command_with(
             &format!("Started playing {}", stream_name),
         );
         let bytes = start.encode().unwrap();
@@ -52,11 +50,7 @@ impl PlayHandler {
         packets.push(create_sample_access_packet(stream_id));
 
         // Data.Start
-        let data_start = RtmpCommand::on_status(
-            "status",
-            "NetStream.Data.Start",
-            "Data start",
-        );
+        let data_start = NetStatus::DataStart.to_command();
         let bytes = data_start.encode().unwrap();
         let header = RtmpHeader::data(0, bytes.len() as u32, stream_id);
         packets.push(RtmpPacket::new(header, bytes));
@@ -71,6 +65,10 @@ impl CommandHandler for PlayHandler {
         "play"
     }
 
+    fn stream_scoped(&self) -> bool {
+        true
+    }
+
     async fn handle(
         &self,
         command: RtmpCommand,
@@ -100,12 +98,7 @@ impl CommandHandler for PlayHandler {
             .ok_or_else(|| Error::protocol("No stream ID"))?;
 
         // Find publisher
-        let publisher = self.find_publisher(&stream_name, context.clone()).await?;
-
-        // Subscribe to publisher
-        if let Some(registry) = context.get_publisher_registry() {
-            registry.increment_subscribers(&stream_name).await?;
-        }
+        let info = self.find_publisher(&stream_name, context.clone()).await?;
 
         // Update context
         context.set_property("playing".to_string(), "true".to_string()).await;
@@ -119,12 +112,57 @@ impl CommandHandler for PlayHandler {
             context.send_packet(msg).await?;
         }
 
-        // Note: Actual data delivery would be handled by stream management (Phase 10)
+        // Deliver the stream's media to this connection's write loop
+        let config = context.server_config();
+        let join_mode = config.as_ref()
+            .map(|config| config.join_mode)
+            .unwrap_or_default();
+        let pacer = config.and_then(|config| config.max_send_bitrate).map(SendPacer::new);
+        let receiver = info.publisher.add_subscriber_with_mode(
+            context.connection_id().to_string(),
+            stream_id,
+            join_mode,
+        ).await;
+        tokio::spawn(forward_media(receiver, context.clone(), pacer, stream_id));
+
+        if let Some(events) = context.event_listeners() {
+            events.notify_play(&stream_name, context.connection_id()).await;
+        }
 
         Ok(None) // All responses sent directly
     }
 }
 
+/// Forward packets from a publisher subscription to the connection.
+///
+/// Ends when the publisher drops the subscription (unpublish, stop, or a
+/// subscriber too slow to keep up), which the player hears as Stream EOF,
+/// or when the connection stops accepting packets.
+/// With a pacer, packets are held back to the configured send rate.
+async fn forward_media(
+    mut receiver: mpsc::Receiver<RtmpPacket>,
+    context: Arc<ConnectionContext>,
+    mut pacer: Option<SendPacer>,
+    stream_id: u32,
+) {
+    let mut eof_sent = false;
+    while let Some(packet) = receiver.recv().await {
+        if let Some(pacer) = pacer.as_mut() {
+            pacer.wait(&packet).await;
+        }
+        eof_sent = packet.message_type() == MSG_TYPE_USER_CONTROL
+            && matches!(UserControlEvent::decode(&packet.payload), Ok(UserControlEvent::StreamEof(_)));
+        if context.send_packet(packet).await.is_err() {
+            return;
+        }
+    }
+
+    // A publisher that stopped cleanly already sent Stream EOF
+    if !eof_sent {
+        let _ = context.send_packet(make_stream_eof_packet(stream_id)).await;
+    }
+}
+
 fn create_sample_access_packet(stream_id: u32) -> RtmpPacket {
     let mut data = RtmpData::new("|RtmpSampleAccess".to_string());
     data.values.push(Amf0Value::Boolean(true)); // Audio
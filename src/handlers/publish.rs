@@ -1,5 +1,6 @@
 use std::sync::Arc;
-use crate::{ConnectionContext, Error, RtmpCommand, RtmpHeader, RtmpPacket, Result, HandlerContext, MSG_TYPE_USER_CONTROL, CHUNK_STREAM_PROTOCOL};
+use crate::{ConnectionContext, Error, NetStatus, PublishParams, PublisherInfo, RepublishPolicy, RtmpCommand, RtmpHeader, RtmpPacket, Result, HandlerContext, UserControlEvent};
+use crate::stream::StreamType;
 use crate::handlers::CommandHandler;
 
 pub struct PublishHandler;
@@ -14,23 +15,45 @@ impl PublishHandler {
         stream_name: &str,
         context: Arc<ConnectionContext>,
     ) -> Result<()> {
-        // Check if stream already exists
-        if let Some(registry) = context.get_publisher_registry() {
-            if registry.is_publishing(stream_name).await {
-                return Err(Error::stream(format!(
-                    "Stream '{}' is already being published",
-                    stream_name
-                )));
-            }
+        // Check if stream already exists, unless a new publisher may take it over
+        if let Some(registry) = context.get_publisher_registry()
+            && registry.republish_policy() == RepublishPolicy::Reject
+            && registry.is_publishing(stream_name).await
+        {
+            return Err(Error::stream(format!(
+                "Stream '{}' is already being published",
+                stream_name
+            )));
         }
 
         Ok(())
     }
 
+    /// Close the replaced publisher and tell viewers the stream restarted
+    async fn take_over(&self, displaced: PublisherInfo, context: &ConnectionContext) {
+        if displaced.connection_id != context.connection_id()
+            && let Some(close_handle) = &displaced.close_handle
+        {
+            close_handle.close();
+        }
+
+        let status = NetStatus::PublishStart.to_command_with(
+            &format!("{} is now published", displaced.stream_name),
+        );
+        displaced.publisher.notify_subscribers(&status).await;
+    }
+
+    fn create_bad_name_status(&self, description: &str, stream_id: u32) -> RtmpPacket {
+        let status = NetStatus::PublishBadName.to_command_with(description);
+
+        let bytes = status.encode().unwrap();
+        let header = RtmpHeader::command(0, bytes.len() as u32, stream_id);
+
+        RtmpPacket::new(header, bytes)
+    }
+
     fn create_publish_status(&self, stream_name: &str, stream_id: u32) -> RtmpPacket {
-        let status = RtmpCommand::on_status(
-            "status",
-            "NetStream.Publish.Start",
+        let status = NetStatus::PublishStart.to_command_with(
             &format!("{} is now published", stream_name),
         );
 
@@ -47,6 +70,10 @@ impl CommandHandler for PublishHandler {
         "publish"
     }
 
+    fn stream_scoped(&self) -> bool {
+        true
+    }
+
     async fn handle(
         &self,
         command: RtmpCommand,
@@ -68,16 +95,45 @@ impl CommandHandler for PublishHandler {
             .and_then(|s| s.parse::<u32>().ok())
             .ok_or_else(|| Error::protocol("No stream ID"))?;
 
+        // Only live, record and append are defined publish types
+        let Some(stream_type) = StreamType::from_publish_type(&publish_type) else {
+            let description = format!("Unknown publish type '{}'", publish_type);
+            return Ok(Some(self.create_bad_name_status(&description, stream_id)));
+        };
+
+        // Ask the server's auth hook, if any
+        let auth = context.server_config().and_then(|config| config.publish_auth.clone());
+        if let Some(auth) = auth {
+            let params = PublishParams {
+                stream_name: stream_name.clone(),
+                publish_type: publish_type.clone(),
+                peer_addr: context.peer_addr(),
+            };
+            if !auth.authorize(&params).await {
+                let description = format!("Not authorized to publish {}", stream_name);
+                return Ok(Some(self.create_bad_name_status(&description, stream_id)));
+            }
+        }
+
         // Validate
         self.validate_publish(&stream_name, context.clone()).await?;
 
         // Register publisher
         if let Some(registry) = context.get_publisher_registry() {
-            registry.register(
+            let displaced = registry.register(
                 stream_name.clone(),
                 context.connection_id().to_string(),
                 stream_id,
+                Some(context.close_handle()),
             ).await?;
+
+            if let Some(displaced) = displaced {
+                self.take_over(displaced, &context).await;
+            }
+
+            if let Some(info) = registry.get(&stream_name).await {
+                info.publisher.stream().set_stream_type(stream_type).await;
+            }
         }
 
         // Update context state
@@ -85,6 +141,10 @@ impl CommandHandler for PublishHandler {
         context.set_property("stream_name".to_string(), stream_name.clone()).await;
         context.set_property("publish_type".to_string(), publish_type).await;
 
+        if let Some(events) = context.event_listeners() {
+            events.notify_publish(&stream_name, context.connection_id()).await;
+        }
+
         // Send Stream Begin
         let stream_begin = create_stream_begin_packet(stream_id);
         context.send_packet(stream_begin).await?;
@@ -97,17 +157,5 @@ impl CommandHandler for PublishHandler {
 }
 
 pub fn create_stream_begin_packet(stream_id: u32) -> RtmpPacket {
-    let mut payload = Vec::new();
-    payload.extend_from_slice(&[0x00, 0x00]); // Event type: Stream Begin
-    payload.extend_from_slice(&stream_id.to_be_bytes());
-
-    let header = RtmpHeader::new(
-        0,
-        payload.len() as u32,
-        MSG_TYPE_USER_CONTROL,
-        0,
-        CHUNK_STREAM_PROTOCOL,
-    );
-
-    RtmpPacket::new(header, payload)
-}
\ No newline at end of file
+    UserControlEvent::StreamBegin(stream_id).to_packet()
+}
@@ -0,0 +1,92 @@
+use std::sync::Arc;
+use crate::handlers::CommandHandler;
+use crate::{ConnectionContext, Error, HandlerContext, PublisherInfo, Result, RtmpCommand, RtmpPacket};
+
+pub struct ReceiveAudioHandler;
+
+impl ReceiveAudioHandler {
+    pub fn new() -> Self {
+        ReceiveAudioHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for ReceiveAudioHandler {
+    fn command_name(&self) -> &str {
+        "receiveAudio"
+    }
+
+    fn stream_scoped(&self) -> bool {
+        true
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let enabled = receive_flag(&command)?;
+
+        if let Some(info) = playing_publisher(&context).await {
+            info.publisher.set_receive_audio(context.connection_id(), enabled).await;
+        }
+        context.set_property("receive_audio".to_string(), enabled.to_string()).await;
+
+        // No response is defined for receiveAudio
+        Ok(None)
+    }
+}
+
+pub struct ReceiveVideoHandler;
+
+impl ReceiveVideoHandler {
+    pub fn new() -> Self {
+        ReceiveVideoHandler
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for ReceiveVideoHandler {
+    fn command_name(&self) -> &str {
+        "receiveVideo"
+    }
+
+    fn stream_scoped(&self) -> bool {
+        true
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        let enabled = receive_flag(&command)?;
+
+        if let Some(info) = playing_publisher(&context).await {
+            info.publisher.set_receive_video(context.connection_id(), enabled).await;
+        }
+        context.set_property("receive_video".to_string(), enabled.to_string()).await;
+
+        // No response is defined for receiveVideo
+        Ok(None)
+    }
+}
+
+fn receive_flag(command: &RtmpCommand) -> Result<bool> {
+    command.arguments.first()
+        .and_then(|v| v.as_boolean())
+        .ok_or_else(|| Error::protocol(format!("Missing {} flag", command.name)))
+}
+
+/// Publisher of the stream the connection is playing, if any
+async fn playing_publisher(context: &ConnectionContext) -> Option<PublisherInfo> {
+    let is_playing = context.get_property("playing").await
+        .map(|v| v == "true")
+        .unwrap_or(false);
+    if !is_playing {
+        return None;
+    }
+
+    let stream_name = context.get_property("stream_name").await?;
+    context.get_publisher_registry()?.get(&stream_name).await
+}
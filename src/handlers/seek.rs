@@ -0,0 +1,87 @@
+use std::sync::Arc;
+use crate::handlers::CommandHandler;
+use crate::{ConnectionContext, Error, HandlerContext, NetStatus, Result, RtmpCommand, RtmpHeader, RtmpPacket};
+
+pub struct SeekHandler;
+
+impl SeekHandler {
+    pub fn new() -> Self {
+        SeekHandler
+    }
+
+    fn create_status(&self, status: NetStatus, description: &str, stream_id: u32) -> RtmpPacket {
+        let status = status.to_command_with(description);
+
+        let bytes = status.encode().unwrap();
+        let header = RtmpHeader::command(0, bytes.len() as u32, stream_id);
+
+        RtmpPacket::new(header, bytes)
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandHandler for SeekHandler {
+    fn command_name(&self) -> &str {
+        "seek"
+    }
+
+    fn stream_scoped(&self) -> bool {
+        true
+    }
+
+    async fn handle(
+        &self,
+        command: RtmpCommand,
+        context: Arc<ConnectionContext>,
+    ) -> Result<Option<RtmpPacket>> {
+        // Extract requested position in milliseconds
+        let requested = command.arguments.first()
+            .and_then(|v| v.as_number())
+            .ok_or_else(|| Error::protocol("Missing seek position"))?;
+
+        // Only a playing connection can seek
+        let is_playing = context.get_property("playing").await
+            .map(|v| v == "true")
+            .unwrap_or(false);
+        if !is_playing {
+            return Err(Error::stream("Not playing"));
+        }
+
+        let stream_name = context.get_property("stream_name").await
+            .ok_or_else(|| Error::stream("No stream name"))?;
+        let stream_id = context.get_property("stream_id").await
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(|| Error::protocol("No stream ID"))?;
+
+        let info = context.get_publisher_registry()
+            .ok_or_else(|| Error::stream("No publisher registry"))?
+            .get(&stream_name).await
+            .ok_or_else(|| Error::stream(format!("Stream '{}' not found", stream_name)))?;
+
+        // Timestamps are u32 milliseconds; NaN clamps to the start
+        let position = if requested.is_nan() { 0 } else { requested.clamp(0.0, u32::MAX as f64) as u32 };
+
+        // Status goes out first so it precedes the replayed media
+        let notify = self.create_status(
+            NetStatus::SeekNotify,
+            &format!("Seeking {} to {}", stream_name, position),
+            stream_id,
+        );
+        context.send_packet(notify).await?;
+
+        let start = self.create_status(
+            NetStatus::PlayStart,
+            &format!("Started playing {}", stream_name),
+            stream_id,
+        );
+        context.send_packet(start).await?;
+
+        // Restart delivery at the nearest cached keyframe
+        let resumed_at = info.publisher.seek(context.connection_id(), position).await
+            .ok_or_else(|| Error::stream("Not subscribed"))?;
+
+        context.set_property("play_start".to_string(), resumed_at.to_string()).await;
+
+        Ok(None) // Status sent directly
+    }
+}
@@ -1,6 +1,7 @@
 use crate::{ByteBuffer, Error, Result};
+use crate::handshake::digest::{compute_digest, digest_offset, find_digest, DIGEST_SIZE, FP_KEY_TEXT};
 use crate::handshake::state::HandshakeFormat;
-use crate::utils::{generate_random_bytes, calculate_hmac_sha256, current_timestamp};
+use crate::utils::{generate_random_bytes, current_timestamp};
 
 /// RTMP version
 pub const RTMP_VERSION: u8 = 3;
@@ -8,7 +9,7 @@ pub const RTMP_VERSION: u8 = 3;
 /// Handshake packet size (C1/S1/C2/S2)
 pub const HANDSHAKE_SIZE: usize = 1536;
 
-/// FMS version for complex handshake
+/// FMS version announced in S1 for complex handshake
 pub const FMS_VERSION: [u8; 4] = [0x05, 0x00, 0x01, 0x01];
 
 /// Client handshake (C0 + C1)
@@ -94,36 +95,45 @@ impl C0C1 {
         result
     }
 
+    /// C1 as sent, without C0
+    pub fn c1_bytes(&self) -> Vec<u8> {
+        self.encode()[1..].to_vec()
+    }
+
     /// Detect handshake format
+    ///
+    /// Complex handshakes put a client version where C1 has zeros and sign
+    /// C1 at one of two positions; a C1 with neither digest is treated as
+    /// simple, like other servers do.
     pub fn detect_format(&self) -> HandshakeFormat {
-        // Check for FMS version bytes at different positions
-        // This is simplified - real implementation would check digest positions
-
-        // Check format 1 position (offset 4)
-        if self.random_data.len() > 4 &&
-            &self.random_data[0..4] == &FMS_VERSION {
-            return HandshakeFormat::Format1;
+        if self.zero == 0 {
+            return HandshakeFormat::Simple;
         }
 
-        // Check format 2 position (offset 768)
-        if self.random_data.len() > 768 + 4 &&
-            &self.random_data[764..768] == &FMS_VERSION {
-            return HandshakeFormat::Format2;
-        }
+        find_digest(&self.c1_bytes(), FP_KEY_TEXT)
+            .map(|(format, _)| format)
+            .unwrap_or(HandshakeFormat::Simple)
+    }
 
-        HandshakeFormat::Simple
+    /// Client digest for a complex handshake format
+    pub fn digest(&self, format: HandshakeFormat) -> Option<[u8; DIGEST_SIZE]> {
+        let c1 = self.c1_bytes();
+        let offset = digest_offset(&c1, format)?;
+        c1[offset..offset + DIGEST_SIZE].try_into().ok()
     }
 
     /// Validate C1 digest for complex handshake
     pub fn validate_digest(&self, format: HandshakeFormat) -> Result<()> {
-        match format {
-            HandshakeFormat::Simple => Ok(()),
-            HandshakeFormat::Format1 | HandshakeFormat::Format2 => {
-                // Simplified validation - real implementation would verify HMAC
-                // For educational purposes, we accept all complex handshakes
-                Ok(())
-            }
+        let c1 = self.c1_bytes();
+        let Some(offset) = digest_offset(&c1, format) else {
+            return Ok(());
+        };
+
+        if c1[offset..offset + DIGEST_SIZE] != compute_digest(&c1, offset, FP_KEY_TEXT) {
+            return Err(Error::handshake("Invalid C1 digest"));
         }
+
+        Ok(())
     }
 }
 
@@ -0,0 +1,72 @@
+use crate::handshake::c0c1::HANDSHAKE_SIZE;
+use crate::handshake::state::HandshakeFormat;
+use crate::utils::calculate_hmac_sha256;
+
+/// Length of a handshake digest
+pub(crate) const DIGEST_SIZE: usize = 32;
+
+/// Constant appended to both keys
+const KEY_SUFFIX: [u8; 32] = [
+    0xF0, 0xEE, 0xC2, 0x4A, 0x80, 0x68, 0xBE, 0xE8,
+    0x2E, 0x00, 0xD0, 0xD1, 0x02, 0x9E, 0x7E, 0x57,
+    0x6E, 0xEC, 0x5D, 0x2D, 0x29, 0x80, 0x6F, 0xAB,
+    0x93, 0xB8, 0xE6, 0x36, 0xCF, 0xEB, 0x31, 0xAE,
+];
+
+/// Text part of the client key, which alone signs C1
+pub(crate) const FP_KEY_TEXT: &[u8] = b"Genuine Adobe Flash Player 001";
+
+/// Text part of the server key, which alone signs S1
+pub(crate) const FMS_KEY_TEXT: &[u8] = b"Genuine Adobe Flash Media Server 001";
+
+/// Full client key, which keys the C2 signature
+pub(crate) fn fp_key() -> Vec<u8> {
+    [FP_KEY_TEXT, &KEY_SUFFIX].concat()
+}
+
+/// Full server key, which keys the S2 signature
+pub(crate) fn fms_key() -> Vec<u8> {
+    [FMS_KEY_TEXT, &KEY_SUFFIX].concat()
+}
+
+/// Offset of the digest in a 1536 byte C1 or S1
+///
+/// The four bytes at the start of the digest block pick where in the
+/// block the digest sits.
+pub(crate) fn digest_offset(packet: &[u8], format: HandshakeFormat) -> Option<usize> {
+    let base = match format {
+        HandshakeFormat::Simple => return None,
+        HandshakeFormat::Format1 => 8,
+        HandshakeFormat::Format2 => 772,
+    };
+
+    let sum: usize = packet.get(base..base + 4)?.iter().map(|&b| b as usize).sum();
+    Some(base + 4 + sum % 728)
+}
+
+/// HMAC-SHA256 of the packet with the digest itself left out
+pub(crate) fn compute_digest(packet: &[u8], offset: usize, key: &[u8]) -> [u8; DIGEST_SIZE] {
+    let mut message = Vec::with_capacity(HANDSHAKE_SIZE - DIGEST_SIZE);
+    message.extend_from_slice(&packet[..offset]);
+    message.extend_from_slice(&packet[offset + DIGEST_SIZE..]);
+    calculate_hmac_sha256(key, &message)
+}
+
+/// Find the format whose digest validates with `key`, and the digest
+pub(crate) fn find_digest(packet: &[u8], key: &[u8]) -> Option<(HandshakeFormat, [u8; DIGEST_SIZE])> {
+    if packet.len() != HANDSHAKE_SIZE {
+        return None;
+    }
+
+    [HandshakeFormat::Format1, HandshakeFormat::Format2].into_iter().find_map(|format| {
+        let offset = digest_offset(packet, format)?;
+        let digest = compute_digest(packet, offset, key);
+        (packet[offset..offset + DIGEST_SIZE] == digest).then_some((format, digest))
+    })
+}
+
+/// Signature closing S2 or C2: an HMAC keyed by the peer's digest
+pub(crate) fn response_signature(full_key: &[u8], peer_digest: &[u8], data: &[u8]) -> [u8; DIGEST_SIZE] {
+    let key = calculate_hmac_sha256(full_key, peer_digest);
+    calculate_hmac_sha256(&key, data)
+}
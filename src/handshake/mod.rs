@@ -1,5 +1,6 @@
 mod state;
 mod c0c1;
+mod digest;
 mod s0s1s2;
 
 pub use state::*;
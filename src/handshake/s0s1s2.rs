@@ -1,7 +1,11 @@
 use crate::{ByteBuffer, Error, Result};
-use crate::handshake::c0c1::{C0C1, RTMP_VERSION, HANDSHAKE_SIZE};
+use crate::handshake::c0c1::{C0C1, FMS_VERSION, RTMP_VERSION, HANDSHAKE_SIZE};
+use crate::handshake::digest::{
+    compute_digest, digest_offset, find_digest, fms_key, fp_key, response_signature,
+    DIGEST_SIZE, FMS_KEY_TEXT,
+};
 use crate::handshake::state::HandshakeFormat;
-use crate::utils::{generate_random_bytes, calculate_hmac_sha256, current_timestamp};
+use crate::utils::{generate_random_bytes, current_timestamp};
 
 /// Server handshake (S0 + S1 + S2)
 #[derive(Debug, Clone)]
@@ -54,50 +58,65 @@ impl S0S1S2 {
     }
 
     /// Generate with complex handshake (HMAC-SHA256)
+    ///
+    /// S1 is signed with the server key at the position `format` selects,
+    /// the one the client used for C1. S2 is random data whose last 32
+    /// bytes are signed with a key derived from the client's C1 digest.
     pub fn generate_complex(c0c1: &C0C1, format: HandshakeFormat) -> Result<Self> {
         // First generate simple response
         let mut response = Self::generate(c0c1)?;
-
-        // Add digest for complex handshake
-        match format {
-            HandshakeFormat::Simple => {}
-            HandshakeFormat::Format1 | HandshakeFormat::Format2 => {
-                // For educational purposes, we generate valid-looking data
-                // Real implementation would calculate proper HMAC digest
-                response.add_digest(format)?;
-            }
+        if format == HandshakeFormat::Simple {
+            return Ok(response);
         }
 
+        let client_digest = c0c1.digest(format)
+            .ok_or_else(|| Error::handshake("C1 has no digest"))?;
+
+        response.s1_zero = u32::from_be_bytes(FMS_VERSION);
+        response.sign_s1(format);
+        response.sign_s2(&client_digest);
+
         Ok(response)
     }
 
-    /// Add digest for complex handshake
-    fn add_digest(&mut self, format: HandshakeFormat) -> Result<()> {
-        // Simplified digest generation
-        // Real implementation would:
-        // 1. Calculate digest position based on format
-        // 2. Generate HMAC-SHA256 digest
-        // 3. Insert digest at correct position
+    /// Place the S1 digest at the offset `format` selects
+    fn sign_s1(&mut self, format: HandshakeFormat) {
+        let s1 = self.s1_bytes();
+        let Some(offset) = digest_offset(&s1, format) else {
+            return;
+        };
 
-        let digest_key = b"Genuine Adobe Flash Media Server 001";
-        let digest = calculate_hmac_sha256(digest_key, &self.s1_random[0..32]);
+        let digest = compute_digest(&s1, offset, FMS_KEY_TEXT);
+        let start = offset - 8;
+        self.s1_random[start..start + DIGEST_SIZE].copy_from_slice(&digest);
+    }
 
-        // Insert digest at appropriate position
-        match format {
-            HandshakeFormat::Format1 => {
-                // Digest at offset 8
-                self.s1_random[0..32].copy_from_slice(&digest);
-            }
-            HandshakeFormat::Format2 => {
-                // Digest at offset 772
-                if self.s1_random.len() >= 772 + 32 {
-                    self.s1_random[772..772+32].copy_from_slice(&digest);
-                }
-            }
-            _ => {}
-        }
+    /// Replace the S2 echo with random data ending in its signature
+    fn sign_s2(&mut self, client_digest: &[u8]) {
+        self.s2_random_echo = generate_random_bytes(HANDSHAKE_SIZE - 8);
 
-        Ok(())
+        let s2 = self.s2_bytes();
+        let signature = response_signature(&fms_key(), client_digest, &s2[..HANDSHAKE_SIZE - DIGEST_SIZE]);
+        let start = self.s2_random_echo.len() - DIGEST_SIZE;
+        self.s2_random_echo[start..].copy_from_slice(&signature);
+    }
+
+    /// S1 as sent
+    pub fn s1_bytes(&self) -> Vec<u8> {
+        let mut s1_buffer = ByteBuffer::with_capacity(HANDSHAKE_SIZE);
+        s1_buffer.write_u32_be(self.s1_timestamp).unwrap();
+        s1_buffer.write_u32_be(self.s1_zero).unwrap();
+        s1_buffer.write_bytes(&self.s1_random).unwrap();
+        s1_buffer.to_vec()
+    }
+
+    /// S2 as sent
+    pub fn s2_bytes(&self) -> Vec<u8> {
+        let mut s2_buffer = ByteBuffer::with_capacity(HANDSHAKE_SIZE);
+        s2_buffer.write_u32_be(self.s2_timestamp).unwrap();
+        s2_buffer.write_u32_be(self.s2_timestamp2).unwrap();
+        s2_buffer.write_bytes(&self.s2_random_echo).unwrap();
+        s2_buffer.to_vec()
     }
 
     /// Encode to bytes
@@ -108,18 +127,10 @@ impl S0S1S2 {
         result.push(self.version);
 
         // S1
-        let mut s1_buffer = ByteBuffer::with_capacity(HANDSHAKE_SIZE);
-        s1_buffer.write_u32_be(self.s1_timestamp).unwrap();
-        s1_buffer.write_u32_be(self.s1_zero).unwrap();
-        s1_buffer.write_bytes(&self.s1_random).unwrap();
-        result.extend_from_slice(&s1_buffer.to_vec());
+        result.extend_from_slice(&self.s1_bytes());
 
         // S2
-        let mut s2_buffer = ByteBuffer::with_capacity(HANDSHAKE_SIZE);
-        s2_buffer.write_u32_be(self.s2_timestamp).unwrap();
-        s2_buffer.write_u32_be(self.s2_timestamp2).unwrap();
-        s2_buffer.write_bytes(&self.s2_random_echo).unwrap();
-        result.extend_from_slice(&s2_buffer.to_vec());
+        result.extend_from_slice(&self.s2_bytes());
 
         result
     }
@@ -202,7 +213,18 @@ impl C2 {
     }
 
     /// Validate C2 against S1
+    ///
+    /// After a complex handshake the client may answer with a signed C2
+    /// instead of an echo of S1.
     pub fn validate(&self, s0s1s2: &S0S1S2) -> Result<()> {
+        if let Some((_, s1_digest)) = find_digest(&s0s1s2.s1_bytes(), FMS_KEY_TEXT) {
+            let c2 = self.encode();
+            let (data, signature) = c2.split_at(HANDSHAKE_SIZE - DIGEST_SIZE);
+            if signature == response_signature(&fp_key(), &s1_digest, data) {
+                return Ok(());
+            }
+        }
+
         // Verify timestamp echo
         if self.timestamp != s0s1s2.s1_timestamp {
             return Err(Error::handshake("C2 timestamp mismatch"));
@@ -229,6 +251,7 @@ impl C2 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::calculate_hmac_sha256;
 
     #[test]
     fn test_handshake_flow() {
@@ -246,4 +269,64 @@ mod tests {
         // Server validates C2
         c2.validate(&s0s1s2).unwrap();
     }
-}
\ No newline at end of file
+
+    /// C1 signed the way Flash Player and librtmp sign it
+    fn complex_c1(format: HandshakeFormat) -> Vec<u8> {
+        let mut c1 = vec![0u8; HANDSHAKE_SIZE];
+        c1[4..8].copy_from_slice(&[0x80, 0x00, 0x07, 0x02]);
+        for (i, byte) in c1.iter_mut().enumerate().skip(8) {
+            *byte = (i * 7 + 3) as u8;
+        }
+
+        let base = if format == HandshakeFormat::Format1 { 8 } else { 772 };
+        let sum: usize = c1[base..base + 4].iter().map(|&b| b as usize).sum();
+        let offset = base + 4 + sum % 728;
+
+        let mut signed = c1[..offset].to_vec();
+        signed.extend_from_slice(&c1[offset + 32..]);
+        let digest = calculate_hmac_sha256(b"Genuine Adobe Flash Player 001", &signed);
+        c1[offset..offset + 32].copy_from_slice(&digest);
+        c1
+    }
+
+    #[test]
+    fn test_complex_handshake_digests_validate() {
+        for format in [HandshakeFormat::Format1, HandshakeFormat::Format2] {
+            let c1 = complex_c1(format);
+            let c0c1 = crate::handshake::validate_c0c1(&[&[RTMP_VERSION][..], &c1].concat()).unwrap();
+            assert_eq!(c0c1.detect_format(), format);
+
+            let s0s1s2 = S0S1S2::parse(&crate::handshake::generate_s0s1s2(&c0c1).unwrap()).unwrap();
+            let s1 = s0s1s2.s1_bytes();
+            let s2 = s0s1s2.s2_bytes();
+
+            // The client finds the server digest in the same position it used
+            let (s1_format, s1_digest) = find_digest(&s1, b"Genuine Adobe Flash Media Server 001").unwrap();
+            assert_eq!(s1_format, format);
+
+            // S2 is signed with a key derived from the client's digest
+            let client_digest = c0c1.digest(format).unwrap();
+            let key = calculate_hmac_sha256(&fms_key(), &client_digest);
+            assert_eq!(s2[1504..], calculate_hmac_sha256(&key, &s2[..1504]));
+
+            // A signed C2 is accepted in place of an echo
+            let mut c2 = generate_random_bytes(HANDSHAKE_SIZE);
+            let key = calculate_hmac_sha256(&fp_key(), &s1_digest);
+            let signature = calculate_hmac_sha256(&key, &c2[..1504]);
+            c2[1504..].copy_from_slice(&signature);
+            crate::handshake::validate_c2(&c2, &s0s1s2).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_tampered_c1_digest_rejected() {
+        let mut c1 = complex_c1(HandshakeFormat::Format2);
+        let c0c1 = C0C1::parse(&[&[RTMP_VERSION][..], &c1].concat()).unwrap();
+        assert!(c0c1.validate_digest(HandshakeFormat::Format2).is_ok());
+
+        c1[100] ^= 0xFF;
+        let c0c1 = C0C1::parse(&[&[RTMP_VERSION][..], &c1].concat()).unwrap();
+        assert!(c0c1.validate_digest(HandshakeFormat::Format2).is_err());
+        assert_eq!(c0c1.detect_format(), HandshakeFormat::Simple);
+    }
+}
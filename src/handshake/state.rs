@@ -69,9 +69,9 @@ pub enum HandshakeFormat {
     /// Simple handshake (format 0) - random data
     Simple,
 
-    /// Format 1 - with digest
+    /// Format 1 - digest block first, offset picked by bytes 8..12
     Format1,
 
-    /// Format 2 - with digest at different position
+    /// Format 2 - digest block second, offset picked by bytes 772..776
     Format2,
 }
\ No newline at end of file
@@ -10,6 +10,7 @@ mod client;
 mod handlers;
 mod stream;
 mod processing;
+mod transport;
 
 // Re-export commonly used types at crate root
 pub use utils::*;
@@ -21,10 +22,19 @@ pub use chunk::*;
 pub use handshake::*;
 
 // Server exports
-pub use server::{RtmpServer, ServerConfig, ServerContext, PublisherRegistry, PublisherInfo};
+pub use server::{RtmpServer, ServerConfig, ListenSpec, ServerContext, PublisherRegistry, PublisherInfo, RegistryEvent, RepublishPolicy, RenditionParser};
+pub use server::{AuthFuture, ConnectAuth, ConnectParams, PublishAuth, PublishParams};
+pub use server::{EventListener, EventListeners};
+pub use server::{ServerMetrics, StreamMetrics};
+pub use server::RtmptListener;
+#[cfg(feature = "admin")]
+pub use server::AdminListener;
+
+// Transport exports
+pub use transport::*;
 
 // Client exports
-pub use client::{RtmpClient, ClientConfig};
+pub use client::{RtmpClient, ClientConfig, MEDIA_QUEUE_SIZE};
 
 // Stream exports
 pub use stream::*;
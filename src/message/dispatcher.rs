@@ -1,6 +1,7 @@
-use crate::{Error, PublisherRegistry, Result, MSG_TYPE_COMMAND_AMF0, MSG_TYPE_COMMAND_AMF3};
-use crate::protocol::{RtmpPacket, RtmpCommand, RtmpData};
+use crate::{Amf0Value, ConnectionContext, Error, PublisherRegistry, Result, MSG_TYPE_COMMAND_AMF0, MSG_TYPE_COMMAND_AMF3, MSG_TYPE_USER_CONTROL};
+use crate::protocol::{NetStatus, RtmpCommand, RtmpData, RtmpHeader, RtmpPacket, UserControlEvent};
 use std::collections::HashMap;
+use log::{debug, warn};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -18,6 +19,11 @@ pub trait HandlerContext: Send + Sync {
     async fn set_property(&self, key: String, value: String);
     async fn remove_property(&self, key: &str);
     fn get_publisher_registry(&self) -> Option<Arc<PublisherRegistry>>;
+
+    /// The concrete connection context, if this is one
+    fn connection_context(self: Arc<Self>) -> Option<Arc<ConnectionContext>> {
+        None
+    }
 }
 
 /// Type-erased handler
@@ -32,6 +38,9 @@ pub struct MessageDispatcher {
 
     /// Default handler for unhandled messages
     default_handler: Option<Handler>,
+
+    /// Fail on undecodable commands instead of answering `_error`
+    strict_decode: bool,
 }
 
 impl MessageDispatcher {
@@ -41,9 +50,20 @@ impl MessageDispatcher {
             handlers: Arc::new(RwLock::new(HashMap::new())),
             command_handlers: Arc::new(RwLock::new(HashMap::new())),
             default_handler: None,
+            strict_decode: false,
         }
     }
 
+    /// Choose how undecodable commands are treated
+    ///
+    /// Strict dispatch fails with `Error::MalformedCommand`, which ends the
+    /// connection. Otherwise the command is dropped and the peer gets an
+    /// `_error` on transaction 0.
+    pub fn with_strict_decode(mut self, strict: bool) -> Self {
+        self.strict_decode = strict;
+        self
+    }
+
     /// Register handler for message type
     pub async fn register_handler(&self, message_type: u8, handler: Handler) {
         let mut handlers = self.handlers.write().await;
@@ -76,6 +96,11 @@ impl MessageDispatcher {
             return self.dispatch_command(packet, context).await;
         }
 
+        // Answer pings before any registered handlers see the message
+        if message_type == MSG_TYPE_USER_CONTROL {
+            self.handle_user_control(&packet, context.clone()).await?;
+        }
+
         // Get handlers for message type
         let handlers = self.handlers.read().await;
         if let Some(type_handlers) = handlers.get(&message_type) {
@@ -90,6 +115,16 @@ impl MessageDispatcher {
             return handler.handle(packet, context).await;
         }
 
+        // User control messages need no handler beyond the built-in ping reply
+        if message_type == MSG_TYPE_USER_CONTROL {
+            return Ok(());
+        }
+
+        // Protocol control messages were already applied by the read loop
+        if packet.is_control() {
+            return Ok(());
+        }
+
         // No handler found
         Err(Error::protocol(format!(
             "No handler for message type: {}",
@@ -97,6 +132,28 @@ impl MessageDispatcher {
         )))
     }
 
+    /// Handle built-in user control events
+    async fn handle_user_control(
+        &self,
+        packet: &RtmpPacket,
+        context: Arc<dyn HandlerContext>
+    ) -> Result<()> {
+        // Players also send events not modelled here, such as BufferEmpty
+        let event = match UserControlEvent::decode(&packet.payload) {
+            Ok(event) => event,
+            Err(e) => {
+                debug!("Ignoring user control event: {}", e);
+                return Ok(());
+            }
+        };
+
+        if let UserControlEvent::PingRequest(timestamp) = event {
+            context.send_packet(UserControlEvent::PingResponse(timestamp).to_packet()).await?;
+        }
+
+        Ok(())
+    }
+
     /// Dispatch command message
     async fn dispatch_command(
         &self,
@@ -104,7 +161,17 @@ impl MessageDispatcher {
         context: Arc<dyn HandlerContext>
     ) -> Result<()> {
         // Decode command
-        let command = RtmpCommand::decode(&packet.payload)?;
+        let command = match RtmpCommand::decode_with_type(&packet.payload, packet.message_type()) {
+            Ok(command) => command,
+            Err(e) if self.strict_decode => return Err(Error::malformed_command(e.to_string())),
+            Err(e) => {
+                let conn_id = context.clone().connection_context()
+                    .map(|connection| connection.connection_id().to_string())
+                    .unwrap_or_default();
+                warn!(conn_id:% = conn_id; "Dropping malformed command: {}", e);
+                return context.send_packet(malformed_command_error()).await;
+            }
+        };
 
         // Find handler for command
         let handlers = self.command_handlers.read().await;
@@ -129,6 +196,19 @@ impl MessageDispatcher {
     }
 }
 
+/// `_error` answering a command that could not be decoded
+fn malformed_command_error() -> RtmpPacket {
+    let status = NetStatus::CallFailed;
+    let mut info = HashMap::new();
+    info.insert("level".to_string(), Amf0Value::String(status.level().as_str().to_string()));
+    info.insert("code".to_string(), Amf0Value::String(status.code().to_string()));
+    info.insert("description".to_string(), Amf0Value::String("Malformed command".to_string()));
+
+    let bytes = RtmpCommand::error(0.0, Amf0Value::Object(info)).encode().unwrap();
+    let header = RtmpHeader::command(0, bytes.len() as u32, 0);
+    RtmpPacket::new(header, bytes)
+}
+
 /// Example handler implementation
 pub struct LoggingHandler;
 
@@ -182,4 +262,82 @@ mod tests {
         let audio_packet = crate::protocol::make_audio_packet(vec![1, 2, 3], 1000, 1);
         assert!(dispatcher.dispatch(audio_packet, context.clone()).await.is_ok());
     }
-}
\ No newline at end of file
+
+    /// Context that records every packet sent through it
+    struct RecordingContext {
+        sent: tokio::sync::Mutex<Vec<RtmpPacket>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HandlerContext for RecordingContext {
+        async fn send_packet(&self, packet: RtmpPacket) -> Result<()> {
+            self.sent.lock().await.push(packet);
+            Ok(())
+        }
+        async fn get_property(&self, _key: &str) -> Option<String> {
+            None
+        }
+        async fn set_property(&self, _key: String, _value: String) {}
+        async fn remove_property(&self, _key: &str) {}
+        fn get_publisher_registry(&self) -> Option<Arc<PublisherRegistry>> {
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_command_answered_with_error() {
+        let context = Arc::new(RecordingContext { sent: tokio::sync::Mutex::new(Vec::new()) });
+        let garbage = || {
+            let payload = vec![0x02, 0x00, 0x40, b'x'];
+            RtmpPacket::new(RtmpHeader::command(0, payload.len() as u32, 0), payload)
+        };
+
+        let dispatcher = MessageDispatcher::new();
+        dispatcher.dispatch(garbage(), context.clone()).await.unwrap();
+
+        let sent = context.sent.lock().await;
+        assert_eq!(sent.len(), 1);
+        let response = RtmpCommand::decode(&sent[0].payload).unwrap();
+        assert_eq!(response.name, "_error");
+        assert_eq!(response.transaction_id, 0.0);
+        assert_eq!(
+            response.arguments[0].get_property("code").and_then(|v| v.as_string()),
+            Some("NetConnection.Call.Failed")
+        );
+        drop(sent);
+
+        let strict = MessageDispatcher::new().with_strict_decode(true);
+        let result = strict.dispatch(garbage(), context.clone()).await;
+        assert!(matches!(result, Err(Error::MalformedCommand(_))));
+    }
+
+    #[tokio::test]
+    async fn test_ping_request_answered() {
+        let dispatcher = MessageDispatcher::new();
+        let context = Arc::new(RecordingContext { sent: tokio::sync::Mutex::new(Vec::new()) });
+
+        let ping = UserControlEvent::PingRequest(42000).to_packet();
+        dispatcher.dispatch(ping, context.clone()).await.unwrap();
+
+        let sent = context.sent.lock().await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].message_type(), MSG_TYPE_USER_CONTROL);
+        assert_eq!(
+            UserControlEvent::decode(&sent[0].payload).unwrap(),
+            UserControlEvent::PingResponse(42000)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_user_control_event_ignored() {
+        let dispatcher = MessageDispatcher::new();
+        let context = Arc::new(RecordingContext { sent: tokio::sync::Mutex::new(Vec::new()) });
+
+        // BufferEmpty (31), sent by Flash players when their buffer runs dry
+        let payload = vec![0x00, 0x1F, 0x00, 0x00, 0x00, 0x01];
+        let header = RtmpHeader::new(0, payload.len() as u32, MSG_TYPE_USER_CONTROL, 0, crate::CHUNK_STREAM_PROTOCOL);
+        dispatcher.dispatch(RtmpPacket::new(header, payload), context.clone()).await.unwrap();
+
+        assert!(context.sent.lock().await.is_empty());
+    }
+}
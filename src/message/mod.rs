@@ -15,7 +15,7 @@ pub fn classify_message(packet: &RtmpPacket) -> MessageType {
 
 pub fn create_response(request: &RtmpPacket, result: Amf0Value) -> RtmpPacket {
     // Decode request command to get transaction ID
-    let command = match RtmpCommand::decode(&request.payload) {
+    let command = match RtmpCommand::decode_with_type(&request.payload, request.message_type()) {
         Ok(cmd) => cmd,
         Err(_) => {
             // Create error response
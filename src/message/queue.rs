@@ -1,8 +1,12 @@
 use crate::{Error, Result};
 use crate::protocol::{RtmpPacket, *};
+use crate::processing::is_keyframe;
+use crate::stream::is_avc_sequence_header;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
-use tokio::sync::{mpsc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering as AtomicOrdering};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
 use std::sync::Arc;
 
 /// Priority wrapper for packets
@@ -10,20 +14,24 @@ use std::sync::Arc;
 struct PriorityPacket {
     packet: RtmpPacket,
     priority: u8,
+
+    /// Push order, keeping packets of equal priority FIFO
+    sequence: u64,
 }
 
 impl Eq for PriorityPacket {}
 
 impl PartialEq for PriorityPacket {
     fn eq(&self, other: &Self) -> bool {
-        self.priority == other.priority
+        self.priority == other.priority && self.sequence == other.sequence
     }
 }
 
 impl Ord for PriorityPacket {
     fn cmp(&self, other: &Self) -> Ordering {
-        // Higher priority first
-        other.priority.cmp(&self.priority)
+        // Higher priority first, then earlier pushes first
+        self.priority.cmp(&other.priority)
+            .then_with(|| other.sequence.cmp(&self.sequence))
     }
 }
 
@@ -33,95 +41,126 @@ impl PartialOrd for PriorityPacket {
     }
 }
 
-pub struct MessageQueue {
-    /// Sender for queue
-    sender: mpsc::Sender<PriorityPacket>,
+/// What a full queue does with a new packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Refuse the new packet
+    #[default]
+    Reject,
 
-    /// Receiver for queue
-    receiver: Arc<RwLock<mpsc::Receiver<PriorityPacket>>>,
+    /// Evict the oldest buffered video frame that is not a sequence header
+    DropOldestVideo,
+
+    /// Evict buffered inter-frames and metadata, then skip inter-frames
+    /// until the next keyframe; sequence headers and audio are kept
+    DropToKeyframe,
+}
 
+pub struct MessageQueue {
     /// Priority queue for ordering
     priority_queue: Arc<RwLock<BinaryHeap<PriorityPacket>>>,
 
+    /// Wakes a waiting `pop_timeout` after a push
+    notify: Arc<Notify>,
+
     /// Queue size limit
     max_size: usize,
 
-    /// Current queue size
-    current_size: Arc<RwLock<usize>>,
+    /// Sequence number of the next push
+    next_sequence: AtomicU64,
+
+    /// Behaviour when the queue is full
+    overflow_policy: OverflowPolicy,
+
+    /// Inter-frames are skipped until a keyframe after a `DropToKeyframe` eviction
+    awaiting_keyframe: AtomicBool,
 }
 
 impl MessageQueue {
     /// Create new message queue
     pub fn new(max_size: usize) -> Self {
-        let (sender, receiver) = mpsc::channel(max_size);
-
         MessageQueue {
-            sender,
-            receiver: Arc::new(RwLock::new(receiver)),
             priority_queue: Arc::new(RwLock::new(BinaryHeap::new())),
+            notify: Arc::new(Notify::new()),
             max_size,
-            current_size: Arc::new(RwLock::new(0)),
+            next_sequence: AtomicU64::new(0),
+            overflow_policy: OverflowPolicy::default(),
+            awaiting_keyframe: AtomicBool::new(false),
         }
     }
 
+    /// Set what happens when a packet is pushed to a full queue
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
     /// Push message to queue
+    ///
+    /// Under `DropToKeyframe`, inter-frames skipped while waiting for a
+    /// keyframe are discarded without an error.
     pub async fn push(&self, packet: RtmpPacket) -> Result<()> {
+        let mut queue = self.priority_queue.write().await;
+
+        if self.overflow_policy == OverflowPolicy::DropToKeyframe && packet.is_video() {
+            if is_keyframe(&packet.payload) {
+                self.awaiting_keyframe.store(false, AtomicOrdering::Relaxed);
+            } else if self.awaiting_keyframe.load(AtomicOrdering::Relaxed) && is_inter_frame(&packet) {
+                return Ok(());
+            }
+        }
+
         // Check queue size
-        let mut size = self.current_size.write().await;
-        if *size >= self.max_size {
-            return Err(Error::protocol("Message queue full"));
+        if queue.len() >= self.max_size {
+            if !self.make_room(&mut queue) {
+                return Err(Error::protocol("Message queue full"));
+            }
+
+            // Frames after the evicted ones cannot be decoded either
+            if self.overflow_policy == OverflowPolicy::DropToKeyframe && is_inter_frame(&packet) {
+                self.awaiting_keyframe.store(true, AtomicOrdering::Relaxed);
+                return Ok(());
+            }
         }
 
         // Determine priority based on message type
         let priority = self.get_priority(&packet);
+        let sequence = self.next_sequence.fetch_add(1, AtomicOrdering::Relaxed);
 
-        // Create priority packet
-        let priority_packet = PriorityPacket { packet, priority };
-
-        // Send to channel
-        self.sender.send(priority_packet).await
-            .map_err(|_| Error::protocol("Failed to send to queue"))?;
+        queue.push(PriorityPacket { packet, priority, sequence });
+        drop(queue);
 
-        *size += 1;
+        self.notify.notify_one();
         Ok(())
     }
 
-    /// Pop message from queue
+    /// Pop the highest priority message, if any
     pub async fn pop(&self) -> Result<Option<RtmpPacket>> {
-        let mut receiver = self.receiver.write().await;
-
-        // Try to receive from channel
-        match receiver.try_recv() {
-            Ok(priority_packet) => {
-                let mut size = self.current_size.write().await;
-                *size = size.saturating_sub(1);
-                Ok(Some(priority_packet.packet))
-            }
-            Err(mpsc::error::TryRecvError::Empty) => Ok(None),
-            Err(mpsc::error::TryRecvError::Disconnected) => {
-                Err(Error::protocol("Queue disconnected"))
-            }
-        }
+        let mut queue = self.priority_queue.write().await;
+        Ok(queue.pop().map(|priority_packet| priority_packet.packet))
     }
 
     /// Pop with timeout
-    pub async fn pop_timeout(&self, timeout: std::time::Duration) -> Result<Option<RtmpPacket>> {
-        let mut receiver = self.receiver.write().await;
-
-        match tokio::time::timeout(timeout, receiver.recv()).await {
-            Ok(Some(priority_packet)) => {
-                let mut size = self.current_size.write().await;
-                *size = size.saturating_sub(1);
-                Ok(Some(priority_packet.packet))
+    pub async fn pop_timeout(&self, timeout: Duration) -> Result<Option<RtmpPacket>> {
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        loop {
+            // Register before checking so a push in between is not missed
+            let notified = self.notify.notified();
+
+            if let Some(packet) = self.pop().await? {
+                return Ok(Some(packet));
+            }
+
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Ok(None); // Timeout
             }
-            Ok(None) => Ok(None),
-            Err(_) => Ok(None), // Timeout
         }
     }
 
     /// Get queue size
     pub async fn size(&self) -> usize {
-        *self.current_size.read().await
+        self.priority_queue.read().await.len()
     }
 
     /// Check if queue is empty
@@ -131,11 +170,29 @@ impl MessageQueue {
 
     /// Clear queue
     pub async fn clear(&self) {
-        let mut receiver = self.receiver.write().await;
-        while receiver.try_recv().is_ok() {}
+        self.priority_queue.write().await.clear();
+    }
 
-        let mut size = self.current_size.write().await;
-        *size = 0;
+    /// Evict packets according to the overflow policy; false if nothing could go
+    fn make_room(&self, queue: &mut BinaryHeap<PriorityPacket>) -> bool {
+        match self.overflow_policy {
+            OverflowPolicy::Reject => false,
+            OverflowPolicy::DropOldestVideo => {
+                let oldest = queue.iter()
+                    .filter(|p| is_droppable_video(&p.packet))
+                    .map(|p| p.sequence)
+                    .min();
+                let Some(oldest) = oldest else {
+                    return false;
+                };
+                queue.retain(|p| p.sequence != oldest);
+                true
+            }
+            OverflowPolicy::DropToKeyframe => {
+                queue.retain(|p| !is_inter_frame(&p.packet) && !p.packet.is_data());
+                queue.len() < self.max_size
+            }
+        }
     }
 
     /// Get priority for packet
@@ -177,6 +234,16 @@ impl MessageQueue {
     }
 }
 
+/// Video that can be dropped without breaking the decoder configuration
+pub(crate) fn is_droppable_video(packet: &RtmpPacket) -> bool {
+    packet.is_video() && !is_avc_sequence_header(&packet.payload)
+}
+
+/// Video frame that depends on an earlier keyframe
+pub(crate) fn is_inter_frame(packet: &RtmpPacket) -> bool {
+    is_droppable_video(packet) && !is_keyframe(&packet.payload)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::MSG_TYPE_AUDIO;
@@ -211,4 +278,96 @@ mod tests {
         assert!(queue.push(packet2).await.is_ok());
         assert!(queue.push(packet3).await.is_err()); // Should fail - queue full
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_control_message_overtakes_queued_video() {
+        let queue = MessageQueue::new(100);
+
+        for i in 0..50 {
+            queue.push(make_video_packet(vec![0x27, 0x01], i * 40, 1)).await.unwrap();
+        }
+        let header = RtmpHeader::new(0, 4, MSG_TYPE_SET_CHUNK_SIZE, 0, CHUNK_STREAM_PROTOCOL);
+        queue.push(RtmpPacket::new(header, 4096u32.to_be_bytes().to_vec())).await.unwrap();
+
+        let first = queue.pop().await.unwrap().unwrap();
+        assert_eq!(first.message_type(), MSG_TYPE_SET_CHUNK_SIZE);
+
+        // Video keeps its original order
+        for i in 0..50 {
+            let packet = queue.pop().await.unwrap().unwrap();
+            assert_eq!(packet.timestamp(), i * 40);
+        }
+        assert!(queue.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_pop_timeout_wakes_on_push() {
+        let queue = Arc::new(MessageQueue::new(10));
+
+        let pusher = queue.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            pusher.push(make_audio_packet(vec![1], 0, 1)).await.unwrap();
+        });
+
+        let packet = queue.pop_timeout(Duration::from_secs(2)).await.unwrap();
+        assert!(packet.unwrap().is_audio());
+        assert!(queue.pop_timeout(Duration::from_millis(10)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_drop_to_keyframe_evicts_inter_frames() {
+        let queue = MessageQueue::new(10).with_overflow_policy(OverflowPolicy::DropToKeyframe);
+
+        queue.push(make_video_packet(vec![0x17, 0x00, 0x01], 0, 1)).await.unwrap();
+        queue.push(make_audio_packet(vec![0xAF, 0x01], 0, 1)).await.unwrap();
+        for i in 0..8 {
+            queue.push(make_video_packet(vec![0x27, 0x01], i * 40, 1)).await.unwrap();
+        }
+
+        // A full queue makes room for the keyframe by shedding inter-frames
+        queue.push(make_video_packet(vec![0x17, 0x01], 320, 1)).await.unwrap();
+        assert_eq!(queue.size().await, 3);
+
+        let audio = queue.pop().await.unwrap().unwrap();
+        assert!(audio.is_audio());
+        let sequence_header = queue.pop().await.unwrap().unwrap();
+        assert_eq!(sequence_header.payload, vec![0x17, 0x00, 0x01]);
+        let keyframe = queue.pop().await.unwrap().unwrap();
+        assert_eq!(keyframe.timestamp(), 320);
+    }
+
+    #[tokio::test]
+    async fn test_drop_to_keyframe_skips_until_keyframe() {
+        let queue = MessageQueue::new(2).with_overflow_policy(OverflowPolicy::DropToKeyframe);
+
+        queue.push(make_video_packet(vec![0x27, 0x01], 0, 1)).await.unwrap();
+        queue.push(make_video_packet(vec![0x27, 0x01], 40, 1)).await.unwrap();
+        queue.push(make_video_packet(vec![0x27, 0x01], 80, 1)).await.unwrap();
+        assert!(queue.is_empty().await);
+
+        // Inter-frames stay out until a keyframe arrives
+        queue.push(make_video_packet(vec![0x27, 0x01], 120, 1)).await.unwrap();
+        assert!(queue.is_empty().await);
+        queue.push(make_video_packet(vec![0x17, 0x01], 160, 1)).await.unwrap();
+        queue.push(make_video_packet(vec![0x27, 0x01], 200, 1)).await.unwrap();
+        assert_eq!(queue.size().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_video() {
+        let queue = MessageQueue::new(3).with_overflow_policy(OverflowPolicy::DropOldestVideo);
+
+        queue.push(make_video_packet(vec![0x17, 0x00, 0x01], 0, 1)).await.unwrap();
+        queue.push(make_video_packet(vec![0x17, 0x01], 0, 1)).await.unwrap();
+        queue.push(make_video_packet(vec![0x27, 0x01], 40, 1)).await.unwrap();
+        queue.push(make_video_packet(vec![0x27, 0x01], 80, 1)).await.unwrap();
+
+        let timestamps: Vec<_> = [
+            queue.pop().await.unwrap().unwrap(),
+            queue.pop().await.unwrap().unwrap(),
+            queue.pop().await.unwrap().unwrap(),
+        ].iter().map(|p| (p.payload[1], p.timestamp())).collect();
+        assert_eq!(timestamps, vec![(0x00, 0), (0x01, 40), (0x01, 80)]);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{Error, Result};
 use crate::protocol::RtmpPacket;
+use crate::processing::bits::BitReader;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AudioCodec {
@@ -31,8 +32,18 @@ pub enum AudioCodec {
     MP38kHz,
     /// Device specific
     DeviceSpecific,
+    /// Opus (Enhanced RTMP)
+    Opus,
+    /// FLAC (Enhanced RTMP)
+    FLAC,
 }
 
+/// Sound format that escapes to an Enhanced RTMP FourCC header
+pub const AUDIO_FORMAT_EX_HEADER: u8 = 9;
+
+// Enhanced RTMP audio packet types carried in the low nibble
+const AUDIO_PACKET_SEQUENCE_START: u8 = 0;
+
 impl AudioCodec {
     /// Parse from sound format field
     pub fn from_sound_format(format: u8) -> Self {
@@ -55,6 +66,17 @@ impl AudioCodec {
         }
     }
 
+    /// Parse from an Enhanced RTMP FourCC
+    pub fn from_fourcc(fourcc: &[u8; 4]) -> Self {
+        match fourcc {
+            b"Opus" => AudioCodec::Opus,
+            b"fLaC" => AudioCodec::FLAC,
+            b"mp4a" => AudioCodec::AAC,
+            b".mp3" => AudioCodec::MP3,
+            _ => AudioCodec::Reserved,
+        }
+    }
+
     /// Get codec name
     pub fn name(&self) -> &str {
         match self {
@@ -72,6 +94,8 @@ impl AudioCodec {
             AudioCodec::Speex => "Speex",
             AudioCodec::MP38kHz => "MP3-8kHz",
             AudioCodec::DeviceSpecific => "Device",
+            AudioCodec::Opus => "Opus",
+            AudioCodec::FLAC => "FLAC",
         }
     }
 }
@@ -144,8 +168,34 @@ pub struct AACAudioConfig {
 
     /// Channel configuration
     pub channel_config: u8,
+
+    /// Sample rate in Hz, from the table or the explicit frequency
+    pub sample_rate: u32,
 }
 
+impl AACAudioConfig {
+    /// Sampling index for an ADTS header, which cannot carry an explicit
+    /// frequency; None if the explicit frequency has no index
+    pub fn adts_sampling_index(&self) -> Option<u8> {
+        if (self.sampling_index as usize) < AAC_SAMPLE_RATES.len() {
+            return Some(self.sampling_index);
+        }
+
+        AAC_SAMPLE_RATES.iter().position(|&rate| rate == self.sample_rate).map(|index| index as u8)
+    }
+}
+
+/// Sample rates by AAC sampling frequency index; 13 and 14 are reserved
+const AAC_SAMPLE_RATES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// Sampling frequency index followed by a 24-bit explicit frequency
+const AAC_EXPLICIT_FREQUENCY_INDEX: u32 = 15;
+
+/// Highest audio object type ISO 14496-3 defines
+const AAC_MAX_OBJECT_TYPE: u32 = 46;
+
 impl AudioProcessor {
     /// Create new audio processor
     pub fn new() -> Self {
@@ -168,6 +218,11 @@ impl AudioProcessor {
 
         // Parse audio tag header
         let sound_format = (tag_header >> 4) & 0x0F;
+
+        if sound_format == AUDIO_FORMAT_EX_HEADER {
+            return self.process_enhanced(packet);
+        }
+
         let sound_rate = (tag_header >> 2) & 0x03;
         let sound_size = (tag_header >> 1) & 0x01;
         let sound_type = tag_header & 0x01;
@@ -204,6 +259,41 @@ impl AudioProcessor {
         })
     }
 
+    /// Process an Enhanced RTMP audio packet: packet type in the low
+    /// nibble, then the codec FourCC
+    fn process_enhanced(&mut self, packet: &RtmpPacket) -> Result<AudioInfo> {
+        if packet.payload.len() < 5 {
+            return Err(Error::protocol("Enhanced audio header too short"));
+        }
+
+        let packet_type = packet.payload[0] & 0x0F;
+        let fourcc = [packet.payload[1], packet.payload[2], packet.payload[3], packet.payload[4]];
+        let codec = AudioCodec::from_fourcc(&fourcc);
+
+        // The legacy rate/size/type bits are not sent; report what AAC uses
+        let rate = SoundRate::Rate44kHz;
+        let size = SoundSize::Bits16;
+        let sound = SoundType::Stereo;
+
+        self.codec = Some(codec);
+        self.sample_rate = Some(rate);
+        self.sample_size = Some(size);
+        self.sound_type = Some(sound);
+
+        let is_sequence_header = packet_type == AUDIO_PACKET_SEQUENCE_START;
+        if is_sequence_header && codec == AudioCodec::AAC {
+            self.parse_aac_config(&packet.payload[5..])?;
+        }
+
+        Ok(AudioInfo {
+            codec,
+            sample_rate: rate,
+            sample_size: size,
+            sound_type: sound,
+            is_sequence_header,
+        })
+    }
+
     /// Parse AAC audio specific config
     fn parse_aac_config(&mut self, data: &[u8]) -> Result<()> {
         if data.len() < 2 {
@@ -211,17 +301,32 @@ impl AudioProcessor {
         }
 
         // AudioSpecificConfig ISO 14496-3
-        let byte1 = data[0];
-        let byte2 = data[1];
+        let mut bits = BitReader::new(data);
+
+        // Object type 31 escapes to a 6-bit extension
+        let mut object_type = bits.read(5)?;
+        if object_type == 31 {
+            object_type = 32 + bits.read(6)?;
+        }
+        if object_type == 0 || object_type > AAC_MAX_OBJECT_TYPE {
+            return Err(Error::protocol(format!("Unsupported AAC object type: {}", object_type)));
+        }
+
+        let sampling_index = bits.read(4)?;
+        let sample_rate = if sampling_index == AAC_EXPLICIT_FREQUENCY_INDEX {
+            bits.read(24)?
+        } else {
+            *AAC_SAMPLE_RATES.get(sampling_index as usize)
+                .ok_or_else(|| Error::protocol(format!("Reserved AAC sampling index: {}", sampling_index)))?
+        };
 
-        let object_type = byte1 >> 3;
-        let sampling_index = ((byte1 & 0x07) << 1) | (byte2 >> 7);
-        let channel_config = (byte2 >> 3) & 0x0F;
+        let channel_config = bits.read(4)?;
 
         self.aac_config = Some(AACAudioConfig {
-            object_type,
-            sampling_index,
-            channel_config,
+            object_type: object_type as u8,
+            sampling_index: sampling_index as u8,
+            channel_config: channel_config as u8,
+            sample_rate,
         });
 
         Ok(())
@@ -232,6 +337,11 @@ impl AudioProcessor {
         self.codec
     }
 
+    /// Get parsed AAC configuration
+    pub fn aac_config(&self) -> Option<&AACAudioConfig> {
+        self.aac_config.as_ref()
+    }
+
     /// Get sample rate
     pub fn sample_rate(&self) -> Option<u32> {
         self.sample_rate.map(|r| r.as_hz())
@@ -244,4 +354,82 @@ pub struct AudioInfo {
     pub sample_size: SoundSize,
     pub sound_type: SoundType,
     pub is_sequence_header: bool,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::make_audio_packet;
+
+    #[test]
+    fn test_classic_aac_sequence_header() {
+        let mut processor = AudioProcessor::new();
+        let info = processor.process(&make_audio_packet(vec![0xAF, 0x00, 0x12, 0x10], 0, 1)).unwrap();
+
+        assert_eq!(info.codec, AudioCodec::AAC);
+        assert!(info.is_sequence_header);
+        assert_eq!(info.sample_rate, SoundRate::Rate44kHz);
+        assert_eq!(info.sound_type, SoundType::Stereo);
+
+        let config = processor.aac_config.as_ref().unwrap();
+        assert_eq!(config.object_type, 2);
+        assert_eq!(config.sampling_index, 4);
+        assert_eq!(config.channel_config, 2);
+        assert_eq!(config.sample_rate, 44100);
+        assert_eq!(config.adts_sampling_index(), Some(4));
+    }
+
+    #[test]
+    fn test_aac_explicit_sampling_frequency() {
+        // AAC-LC, sampling index 15, 37800 Hz explicit, 1 channel
+        let header = vec![0xAF, 0x00, 0x17, 0x80, 0x49, 0xD4, 0x08];
+        let mut processor = AudioProcessor::new();
+        processor.process(&make_audio_packet(header.clone(), 0, 1)).unwrap();
+
+        let config = processor.aac_config().unwrap();
+        assert_eq!(config.object_type, 2);
+        assert_eq!(config.sampling_index, 15);
+        assert_eq!(config.sample_rate, 37800);
+        assert_eq!(config.channel_config, 1);
+        assert_eq!(config.adts_sampling_index(), None);
+
+        // The same header cut off inside the explicit frequency
+        let result = AudioProcessor::new().process(&make_audio_packet(header[..4].to_vec(), 0, 1));
+        assert!(matches!(result, Err(Error::Protocol(_))));
+    }
+
+    #[test]
+    fn test_invalid_aac_config_rejected() {
+        let mut processor = AudioProcessor::new();
+
+        // Object type 0
+        assert!(processor.process(&make_audio_packet(vec![0xAF, 0x00, 0x02, 0x10], 0, 1)).is_err());
+        // Reserved sampling index 13
+        assert!(processor.process(&make_audio_packet(vec![0xAF, 0x00, 0x16, 0x90], 0, 1)).is_err());
+        assert!(processor.aac_config().is_none());
+    }
+
+    #[test]
+    fn test_enhanced_opus_header() {
+        let mut processor = AudioProcessor::new();
+
+        // Sound format 9, SequenceStart, then the FourCC and OpusHead
+        let mut payload = vec![0x90];
+        payload.extend_from_slice(b"Opus");
+        payload.extend_from_slice(b"OpusHead");
+        let info = processor.process(&make_audio_packet(payload, 0, 1)).unwrap();
+        assert_eq!(info.codec, AudioCodec::Opus);
+        assert!(info.is_sequence_header);
+        assert_eq!(processor.codec(), Some(AudioCodec::Opus));
+
+        // CodedFrames
+        let mut payload = vec![0x91];
+        payload.extend_from_slice(b"fLaC");
+        payload.push(0xFF);
+        let info = processor.process(&make_audio_packet(payload, 20, 1)).unwrap();
+        assert_eq!(info.codec, AudioCodec::FLAC);
+        assert!(!info.is_sequence_header);
+
+        assert!(processor.process(&make_audio_packet(vec![0x90, b'O', b'p'], 0, 1)).is_err());
+    }
+}
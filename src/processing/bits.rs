@@ -0,0 +1,53 @@
+use crate::{Error, Result};
+
+/// Reads big-endian bit fields, as codec configuration records pack them
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        BitReader { data, position: 0 }
+    }
+
+    /// Read `count` bits, at most 32
+    pub(crate) fn read(&mut self, count: usize) -> Result<u32> {
+        if self.position + count > self.data.len() * 8 {
+            return Err(Error::protocol("Bitstream truncated"));
+        }
+
+        let mut value = 0;
+        for _ in 0..count {
+            let bit = (self.data[self.position / 8] >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.position += 1;
+        }
+
+        Ok(value)
+    }
+
+    pub(crate) fn read_bit(&mut self) -> Result<bool> {
+        Ok(self.read(1)? == 1)
+    }
+
+    /// Unsigned exp-Golomb code
+    pub(crate) fn read_ue(&mut self) -> Result<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err(Error::protocol("Exp-Golomb code too long"));
+            }
+        }
+
+        Ok(((1u64 << leading_zeros) - 1 + self.read(leading_zeros)? as u64) as u32)
+    }
+
+    /// Signed exp-Golomb code
+    pub(crate) fn read_se(&mut self) -> Result<i32> {
+        let code = self.read_ue()? as i64;
+        let value = if code % 2 == 1 { (code + 1) / 2 } else { -(code / 2) };
+        Ok(value as i32)
+    }
+}
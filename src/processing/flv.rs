@@ -0,0 +1,114 @@
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use crate::{ByteBuffer, Error, Result, RtmpData};
+
+/// FLV file header length
+const FLV_HEADER_SIZE: usize = 9;
+
+/// FLV tag header length
+const FLV_TAG_HEADER_SIZE: usize = 11;
+
+/// Tag type of script data such as onMetaData
+const FLV_TAG_SCRIPT: u8 = 18;
+
+/// Read `duration` in seconds from the onMetaData tag at the start of an FLV file
+pub async fn read_flv_duration(path: &Path) -> Result<Option<f64>> {
+    let mut file = File::open(path).await?;
+
+    let mut header = [0u8; FLV_HEADER_SIZE];
+    file.read_exact(&mut header).await?;
+    let data_offset = parse_flv_header(&header)?;
+
+    // Skip any header extension and the first PreviousTagSize
+    let mut skip = vec![0u8; data_offset - FLV_HEADER_SIZE + 4];
+    file.read_exact(&mut skip).await?;
+
+    let mut tag_header = [0u8; FLV_TAG_HEADER_SIZE];
+    file.read_exact(&mut tag_header).await?;
+    let mut buffer = ByteBuffer::new(tag_header.to_vec());
+    let tag_type = buffer.read_u8()?;
+    let data_size = buffer.read_u24_be()? as usize;
+
+    // Writers put onMetaData first; anything else means no metadata
+    if tag_type != FLV_TAG_SCRIPT {
+        return Ok(None);
+    }
+
+    let mut body = vec![0u8; data_size];
+    file.read_exact(&mut body).await?;
+
+    Ok(metadata_duration(&body))
+}
+
+/// Validate the FLV signature and return the offset of the first tag
+fn parse_flv_header(header: &[u8; FLV_HEADER_SIZE]) -> Result<usize> {
+    if &header[..3] != b"FLV" {
+        return Err(Error::protocol("Not an FLV file"));
+    }
+
+    let data_offset = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+    if !(FLV_HEADER_SIZE..=1024).contains(&data_offset) {
+        return Err(Error::protocol("Invalid FLV header size"));
+    }
+
+    Ok(data_offset)
+}
+
+fn metadata_duration(body: &[u8]) -> Option<f64> {
+    let data = RtmpData::decode(body).ok()?;
+    data.get_metadata()?
+        .get("duration")
+        .and_then(|v| v.as_number())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::Amf0Value;
+
+    /// Minimal FLV with an onMetaData tag and no media
+    fn flv_with_duration(duration: f64) -> Vec<u8> {
+        let mut metadata = HashMap::new();
+        metadata.insert("duration".to_string(), Amf0Value::Number(duration));
+        let body = RtmpData::on_metadata(metadata).encode().unwrap();
+
+        let mut buffer = ByteBuffer::with_capacity(64);
+        buffer.write_bytes(b"FLV").unwrap();
+        buffer.write_u8(1).unwrap();
+        buffer.write_u8(0x05).unwrap();
+        buffer.write_u32_be(FLV_HEADER_SIZE as u32).unwrap();
+        buffer.write_u32_be(0).unwrap();
+
+        buffer.write_u8(FLV_TAG_SCRIPT).unwrap();
+        buffer.write_u24_be(body.len() as u32).unwrap();
+        buffer.write_u24_be(0).unwrap();
+        buffer.write_u8(0).unwrap();
+        buffer.write_u24_be(0).unwrap();
+        buffer.write_bytes(&body).unwrap();
+        buffer.write_u32_be((FLV_TAG_HEADER_SIZE + body.len()) as u32).unwrap();
+
+        buffer.to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_read_flv_duration() {
+        let path = std::env::temp_dir().join(format!("rtmp-flv-duration-{}.flv", std::process::id()));
+        tokio::fs::write(&path, flv_with_duration(42.5)).await.unwrap();
+
+        let duration = read_flv_duration(&path).await.unwrap();
+        let _ = tokio::fs::remove_file(&path).await;
+        assert_eq!(duration, Some(42.5));
+    }
+
+    #[tokio::test]
+    async fn test_read_flv_duration_rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("rtmp-not-flv-{}.flv", std::process::id()));
+        tokio::fs::write(&path, b"GIF89a not a video").await.unwrap();
+
+        let result = read_flv_duration(&path).await;
+        let _ = tokio::fs::remove_file(&path).await;
+        assert!(result.is_err());
+    }
+}
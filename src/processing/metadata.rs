@@ -22,21 +22,13 @@ impl MetadataProcessor {
 
     /// Process metadata packet
     pub fn process(&mut self, data: &RtmpData, timestamp: u32) -> Result<Metadata> {
-        // Check for onMetaData
-        if data.data_type != "onMetaData" && data.data_type != "@setDataFrame" {
+        // Check for onMetaData; decoding already unwrapped any @setDataFrame
+        if data.data_type != "onMetaData" {
             return Err(Error::protocol("Not a metadata message"));
         }
 
-        // Extract metadata object
-        let metadata_obj = if data.data_type == "@setDataFrame" && data.values.len() > 1 {
-            // @setDataFrame has key as first value
-            data.values.get(1)
-        } else {
-            // onMetaData has metadata as first value
-            data.values.first()
-        };
-
-        let metadata_obj = metadata_obj
+        // onMetaData has metadata as first value
+        let metadata_obj = data.values.first()
             .and_then(|v| v.as_object())
             .ok_or_else(|| Error::protocol("Invalid metadata format"))?;
 
@@ -48,43 +40,7 @@ impl MetadataProcessor {
         self.last_update = Some(timestamp);
 
         // Parse into structured metadata
-        Ok(self.parse_metadata(metadata_obj))
-    }
-
-    /// Parse metadata object
-    fn parse_metadata(&self, obj: &HashMap<String, Amf0Value>) -> Metadata {
-        Metadata {
-            // Video properties
-            width: obj.get("width").and_then(|v| v.as_number()),
-            height: obj.get("height").and_then(|v| v.as_number()),
-            video_codec_id: obj.get("videocodecid")
-                .and_then(|v| v.as_string())
-                .map(String::from),
-            video_data_rate: obj.get("videodatarate").and_then(|v| v.as_number()),
-            framerate: obj.get("framerate").and_then(|v| v.as_number()),
-
-            // Audio properties
-            audio_codec_id: obj.get("audiocodecid")
-                .and_then(|v| v.as_string())
-                .map(String::from),
-            audio_data_rate: obj.get("audiodatarate").and_then(|v| v.as_number()),
-            audio_sample_rate: obj.get("audiosamplerate").and_then(|v| v.as_number()),
-            audio_sample_size: obj.get("audiosamplesize").and_then(|v| v.as_number()),
-            audio_channels: obj.get("audiochannels").and_then(|v| v.as_number()),
-            stereo: obj.get("stereo").and_then(|v| v.as_boolean()),
-
-            // File properties
-            duration: obj.get("duration").and_then(|v| v.as_number()),
-            file_size: obj.get("filesize").and_then(|v| v.as_number()),
-
-            // Encoder info
-            encoder: obj.get("encoder")
-                .and_then(|v| v.as_string())
-                .map(String::from),
-
-            // Other properties
-            can_seek_to_end: obj.get("canSeekToEnd").and_then(|v| v.as_boolean()),
-        }
+        Ok(Metadata::from_amf(metadata_obj))
     }
 
     /// Get cached metadata
@@ -125,9 +81,59 @@ pub struct Metadata {
 
     // Other
     pub can_seek_to_end: Option<bool>,
+
+    /// Properties the typed fields do not hold, such as dates
+    pub custom: HashMap<String, Amf0Value>,
 }
 
 impl Metadata {
+    /// Parse an onMetaData object
+    pub fn from_amf(obj: &HashMap<String, Amf0Value>) -> Self {
+        let mut metadata = Metadata {
+            // Video properties
+            width: obj.get("width").and_then(|v| v.as_number()),
+            height: obj.get("height").and_then(|v| v.as_number()),
+            video_codec_id: obj.get("videocodecid")
+                .and_then(|v| v.as_string())
+                .map(String::from),
+            video_data_rate: obj.get("videodatarate").and_then(|v| v.as_number()),
+            framerate: obj.get("framerate").and_then(|v| v.as_number()),
+
+            // Audio properties
+            audio_codec_id: obj.get("audiocodecid")
+                .and_then(|v| v.as_string())
+                .map(String::from),
+            audio_data_rate: obj.get("audiodatarate").and_then(|v| v.as_number()),
+            audio_sample_rate: obj.get("audiosamplerate").and_then(|v| v.as_number()),
+            audio_sample_size: obj.get("audiosamplesize").and_then(|v| v.as_number()),
+            audio_channels: obj.get("audiochannels").and_then(|v| v.as_number()),
+            stereo: obj.get("stereo").and_then(|v| v.as_boolean()),
+
+            // File properties
+            duration: obj.get("duration").and_then(|v| v.as_number()),
+            file_size: obj.get("filesize").and_then(|v| v.as_number()),
+
+            // Encoder info
+            encoder: obj.get("encoder")
+                .and_then(|v| v.as_string())
+                .map(String::from),
+
+            // Other properties
+            can_seek_to_end: obj.get("canSeekToEnd").and_then(|v| v.as_boolean()),
+
+            custom: HashMap::new(),
+        };
+
+        // Keep whatever the typed fields would not reproduce as custom
+        let typed = metadata.to_amf();
+        metadata.custom = obj.iter()
+            .filter(|(key, value)| typed.get(*key) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        metadata
+    }
+
     /// Check if has video
     pub fn has_video(&self) -> bool {
         self.video_codec_id.is_some() || self.width.is_some()
@@ -175,15 +181,54 @@ impl Metadata {
         if let Some(rate) = self.audio_sample_rate {
             obj.insert("audiosamplerate".to_string(), Amf0Value::Number(rate));
         }
+        if let Some(size) = self.audio_sample_size {
+            obj.insert("audiosamplesize".to_string(), Amf0Value::Number(size));
+        }
+        if let Some(channels) = self.audio_channels {
+            obj.insert("audiochannels".to_string(), Amf0Value::Number(channels));
+        }
+        if let Some(stereo) = self.stereo {
+            obj.insert("stereo".to_string(), Amf0Value::Boolean(stereo));
+        }
 
         if let Some(duration) = self.duration {
             obj.insert("duration".to_string(), Amf0Value::Number(duration));
         }
+        if let Some(size) = self.file_size {
+            obj.insert("filesize".to_string(), Amf0Value::Number(size));
+        }
 
         if let Some(ref encoder) = self.encoder {
             obj.insert("encoder".to_string(), Amf0Value::String(encoder.clone()));
         }
 
+        if let Some(can_seek) = self.can_seek_to_end {
+            obj.insert("canSeekToEnd".to_string(), Amf0Value::Boolean(can_seek));
+        }
+
+        for (key, value) in &self.custom {
+            obj.insert(key.clone(), value.clone());
+        }
+
         obj
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_round_trip_keeps_dates() {
+        let mut obj = HashMap::new();
+        obj.insert("width".to_string(), Amf0Value::Number(1920.0));
+        obj.insert("stereo".to_string(), Amf0Value::Boolean(true));
+        obj.insert("creationdate".to_string(), Amf0Value::Date(1_700_000_000_000.0, 0));
+        obj.insert("audiocodecid".to_string(), Amf0Value::Number(10.0));
+
+        let metadata = Metadata::from_amf(&obj);
+        assert_eq!(metadata.width, Some(1920.0));
+        assert_eq!(metadata.custom.get("creationdate"), obj.get("creationdate"));
+        assert_eq!(metadata.to_amf(), obj);
+    }
+}
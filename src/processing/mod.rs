@@ -1,9 +1,17 @@
-use crate::processing::audio::AudioCodec;
-use crate::processing::video::VideoCodec;
+use crate::processing::audio::AUDIO_FORMAT_EX_HEADER;
+use crate::processing::video::VIDEO_EX_HEADER;
 
 mod audio;
+mod bits;
 mod video;
 mod metadata;
+mod flv;
+mod timestamp;
+
+pub(crate) use audio::{AudioCodec, AudioProcessor};
+pub(crate) use flv::read_flv_duration;
+pub(crate) use timestamp::TimestampNormalizer;
+pub use video::{AVCVideoConfig, FrameType, HEVCVideoConfig, VideoCodec, VideoInfo, VideoPacketType, VideoProcessor};
 
 pub fn detect_audio_codec(data: &[u8]) -> AudioCodec {
     if data.is_empty() {
@@ -11,14 +19,33 @@ pub fn detect_audio_codec(data: &[u8]) -> AudioCodec {
     }
 
     let sound_format = (data[0] >> 4) & 0x0F;
+    if sound_format == AUDIO_FORMAT_EX_HEADER && data.len() >= 5 {
+        return AudioCodec::from_fourcc(&[data[1], data[2], data[3], data[4]]);
+    }
+
     AudioCodec::from_sound_format(sound_format)
 }
 
+/// Frame type from a video tag header, legacy or enhanced
+pub fn detect_frame_type(data: &[u8]) -> FrameType {
+    match data.first() {
+        Some(&header) => FrameType::from_bits((header & !VIDEO_EX_HEADER) >> 4),
+        None => FrameType::InterFrame,
+    }
+}
+
 pub fn detect_video_codec(data: &[u8]) -> VideoCodec {
     if data.is_empty() {
         return VideoCodec::Unknown(0);
     }
 
+    if data[0] & VIDEO_EX_HEADER != 0 {
+        if data.len() < 5 {
+            return VideoCodec::Unknown(0);
+        }
+        return VideoCodec::from_fourcc(&[data[1], data[2], data[3], data[4]]);
+    }
+
     let codec_id = data[0] & 0x0F;
     VideoCodec::from_codec_id(codec_id)
 }
@@ -28,6 +55,7 @@ pub fn is_keyframe(video_data: &[u8]) -> bool {
         return false;
     }
 
-    let frame_type = (video_data[0] >> 4) & 0x0F;
+    // Enhanced headers keep three frame type bits below the ex-header flag
+    let frame_type = (video_data[0] & !VIDEO_EX_HEADER) >> 4;
     frame_type == 1 || frame_type == 4 // Keyframe or Generated keyframe
 }
\ No newline at end of file
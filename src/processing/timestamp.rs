@@ -0,0 +1,51 @@
+/// Maps the 32 bit timestamps of one chunk stream onto a 64 bit timeline
+///
+/// Wire timestamps wrap after about 49.7 days. Each one is placed at the
+/// shortest signed distance from the previous, so a wrap carries on past
+/// `u32::MAX` while a small step back stays a small step back.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TimestampNormalizer {
+    /// Previous wire timestamp and where it landed
+    last: Option<(u32, u64)>,
+}
+
+impl TimestampNormalizer {
+    /// Place a wire timestamp on the timeline
+    pub(crate) fn normalize(&mut self, timestamp: u32) -> u64 {
+        let normalized = match self.last {
+            None => timestamp as u64,
+            Some((wire, normalized)) => {
+                let delta = timestamp.wrapping_sub(wire) as i32 as i64;
+                (normalized as i64 + delta).max(0) as u64
+            }
+        };
+
+        self.last = Some((timestamp, normalized));
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrapped_timestamps_stay_monotonic() {
+        let mut normalizer = TimestampNormalizer::default();
+        let wire = [0xFFFF_FF00, 0xFFFF_FF40, 0xFFFF_FFE0, 0x0000_0020, 0x0000_0060, 0x0001_0000];
+
+        let normalized: Vec<u64> = wire.iter().map(|&ts| normalizer.normalize(ts)).collect();
+
+        assert!(normalized.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(normalized[3], 0x1_0000_0020);
+        assert_eq!(normalized[5], 0x1_0001_0000);
+    }
+
+    #[test]
+    fn test_small_step_back_is_kept() {
+        let mut normalizer = TimestampNormalizer::default();
+        assert_eq!(normalizer.normalize(1000), 1000);
+        assert_eq!(normalizer.normalize(960), 960);
+        assert_eq!(normalizer.normalize(1040), 1040);
+    }
+}
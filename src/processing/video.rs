@@ -1,5 +1,6 @@
 use crate::{Error, Result};
 use crate::protocol::RtmpPacket;
+use crate::processing::bits::BitReader;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum VideoCodec {
@@ -19,6 +20,8 @@ pub enum VideoCodec {
     H265,
     /// AV1
     AV1,
+    /// VP9
+    VP9,
     /// Unknown
     Unknown(u8),
 }
@@ -39,6 +42,17 @@ impl VideoCodec {
         }
     }
 
+    /// Parse from an Enhanced RTMP FourCC
+    pub fn from_fourcc(fourcc: &[u8; 4]) -> Self {
+        match fourcc {
+            b"avc1" => VideoCodec::H264,
+            b"hvc1" => VideoCodec::H265,
+            b"av01" => VideoCodec::AV1,
+            b"vp09" => VideoCodec::VP9,
+            _ => VideoCodec::Unknown(0),
+        }
+    }
+
     /// Get codec name
     pub fn name(&self) -> &str {
         match self {
@@ -50,6 +64,7 @@ impl VideoCodec {
             VideoCodec::H264 => "H.264",
             VideoCodec::H265 => "H.265",
             VideoCodec::AV1 => "AV1",
+            VideoCodec::VP9 => "VP9",
             VideoCodec::Unknown(_) => "Unknown",
         }
     }
@@ -86,6 +101,48 @@ impl FrameType {
     }
 }
 
+/// Tag header bit announcing an Enhanced RTMP video header
+pub const VIDEO_EX_HEADER: u8 = 0x80;
+
+/// Enhanced RTMP video packet type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoPacketType {
+    /// Codec configuration record
+    SequenceStart,
+    /// Frames with a composition time
+    CodedFrames,
+    /// End of sequence
+    SequenceEnd,
+    /// Frames without a composition time
+    CodedFramesX,
+    /// AMF metadata such as HDR information
+    Metadata,
+    /// MPEG-2 TS codec configuration
+    Mpeg2TsSequenceStart,
+    /// Multiple tracks in one message
+    Multitrack,
+    /// Modifier extension
+    ModEx,
+    /// Unknown
+    Unknown(u8),
+}
+
+impl VideoPacketType {
+    pub fn from_bits(bits: u8) -> Self {
+        match bits {
+            0 => VideoPacketType::SequenceStart,
+            1 => VideoPacketType::CodedFrames,
+            2 => VideoPacketType::SequenceEnd,
+            3 => VideoPacketType::CodedFramesX,
+            4 => VideoPacketType::Metadata,
+            5 => VideoPacketType::Mpeg2TsSequenceStart,
+            6 => VideoPacketType::Multitrack,
+            7 => VideoPacketType::ModEx,
+            _ => VideoPacketType::Unknown(bits),
+        }
+    }
+}
+
 pub struct VideoProcessor {
     /// Current codec
     codec: Option<VideoCodec>,
@@ -96,8 +153,11 @@ pub struct VideoProcessor {
     /// Frame count since last keyframe
     frames_since_keyframe: u32,
 
-    /// AVC/HEVC configuration
+    /// AVC configuration
     avc_config: Option<AVCVideoConfig>,
+
+    /// HEVC configuration
+    hevc_config: Option<HEVCVideoConfig>,
 }
 
 #[derive(Debug, Clone)]
@@ -114,13 +174,45 @@ pub struct AVCVideoConfig {
     /// AVC level
     pub level: u8,
 
+    /// Bytes in each NAL unit length prefix
+    pub nal_length_size: u8,
+
     /// SPS (Sequence Parameter Sets)
     pub sps: Vec<Vec<u8>>,
 
     /// PPS (Picture Parameter Sets)
     pub pps: Vec<Vec<u8>>,
+
+    /// Frame width in pixels, from the first SPS
+    pub width: Option<u32>,
+
+    /// Frame height in pixels, from the first SPS
+    pub height: Option<u32>,
 }
 
+#[derive(Debug, Clone)]
+pub struct HEVCVideoConfig {
+    /// VPS (Video Parameter Sets)
+    pub vps: Vec<Vec<u8>>,
+
+    /// SPS (Sequence Parameter Sets)
+    pub sps: Vec<Vec<u8>>,
+
+    /// PPS (Picture Parameter Sets)
+    pub pps: Vec<Vec<u8>>,
+
+    /// HEVC general profile IDC
+    pub profile: u8,
+
+    /// HEVC general level IDC
+    pub level: u8,
+}
+
+// HEVC NAL unit types carried in the configuration record
+const HEVC_NAL_VPS: u8 = 32;
+const HEVC_NAL_SPS: u8 = 33;
+const HEVC_NAL_PPS: u8 = 34;
+
 impl VideoProcessor {
     /// Create new video processor
     pub fn new() -> Self {
@@ -129,6 +221,7 @@ impl VideoProcessor {
             last_keyframe_timestamp: None,
             frames_since_keyframe: 0,
             avc_config: None,
+            hevc_config: None,
         }
     }
 
@@ -140,6 +233,10 @@ impl VideoProcessor {
 
         let tag_header = packet.payload[0];
 
+        if tag_header & VIDEO_EX_HEADER != 0 {
+            return self.process_enhanced(packet);
+        }
+
         // Parse video tag header
         let frame_type = (tag_header >> 4) & 0x0F;
         let codec_id = tag_header & 0x0F;
@@ -148,22 +245,31 @@ impl VideoProcessor {
         let codec = VideoCodec::from_codec_id(codec_id);
 
         // Update state
-        self.codec = Some(codec);
+        self.track_frame(codec, frame, packet.timestamp());
 
-        if frame.is_keyframe() {
-            self.last_keyframe_timestamp = Some(packet.timestamp());
-            self.frames_since_keyframe = 0;
-        } else {
-            self.frames_since_keyframe += 1;
-        }
+        let mut composition_time = 0;
 
         // Handle AVC/HEVC specific
         if (codec == VideoCodec::H264 || codec == VideoCodec::H265) && packet.payload.len() > 1 {
             let avc_packet_type = packet.payload[1];
 
+            if avc_packet_type == 1 {
+                if packet.payload.len() < 5 {
+                    return Err(Error::protocol("Video NALU packet too short"));
+                }
+                composition_time = parse_composition_time(&packet.payload[2..5]);
+            }
+
             if avc_packet_type == 0 {
-                // AVC sequence header
-                self.parse_avc_config(&packet.payload[2..])?;
+                // Sequence header, after the 3-byte composition time
+                if packet.payload.len() < 5 {
+                    return Err(Error::protocol("Video sequence header too short"));
+                }
+                if codec == VideoCodec::H265 {
+                    self.parse_hevc_config(&packet.payload[5..])?;
+                } else {
+                    self.parse_avc_config(&packet.payload[5..])?;
+                }
             }
         }
 
@@ -175,9 +281,68 @@ impl VideoProcessor {
                 packet.payload[1] == 0,
             is_keyframe: frame.is_keyframe(),
             frames_since_keyframe: self.frames_since_keyframe,
+            composition_time,
+            packet_type: None,
         })
     }
 
+    /// Process an Enhanced RTMP video packet: frame type and packet type
+    /// in the tag header, then the codec FourCC
+    fn process_enhanced(&mut self, packet: &RtmpPacket) -> Result<VideoInfo> {
+        if packet.payload.len() < 5 {
+            return Err(Error::protocol("Enhanced video header too short"));
+        }
+
+        let tag_header = packet.payload[0];
+        let frame = FrameType::from_bits((tag_header >> 4) & 0x07);
+        let packet_type = VideoPacketType::from_bits(tag_header & 0x0F);
+        let fourcc = [packet.payload[1], packet.payload[2], packet.payload[3], packet.payload[4]];
+        let codec = VideoCodec::from_fourcc(&fourcc);
+
+        self.track_frame(codec, frame, packet.timestamp());
+
+        let mut composition_time = 0;
+        let body = &packet.payload[5..];
+
+        match packet_type {
+            VideoPacketType::SequenceStart => match codec {
+                VideoCodec::H264 => self.parse_avc_config(body)?,
+                VideoCodec::H265 => self.parse_hevc_config(body)?,
+                _ => {}
+            },
+            // Only AVC and HEVC coded frames carry a composition time
+            VideoPacketType::CodedFrames if codec == VideoCodec::H264 || codec == VideoCodec::H265 => {
+                if body.len() < 3 {
+                    return Err(Error::protocol("Enhanced video frame too short"));
+                }
+                composition_time = parse_composition_time(&body[..3]);
+            }
+            _ => {}
+        }
+
+        Ok(VideoInfo {
+            codec,
+            frame_type: frame,
+            is_sequence_header: packet_type == VideoPacketType::SequenceStart,
+            is_keyframe: frame.is_keyframe(),
+            frames_since_keyframe: self.frames_since_keyframe,
+            composition_time,
+            packet_type: Some(packet_type),
+        })
+    }
+
+    /// Record the codec and keyframe spacing
+    fn track_frame(&mut self, codec: VideoCodec, frame: FrameType, timestamp: u32) {
+        self.codec = Some(codec);
+
+        if frame.is_keyframe() {
+            self.last_keyframe_timestamp = Some(timestamp);
+            self.frames_since_keyframe = 0;
+        } else {
+            self.frames_since_keyframe += 1;
+        }
+    }
+
     /// Parse AVC video configuration
     fn parse_avc_config(&mut self, data: &[u8]) -> Result<()> {
         if data.len() < 7 {
@@ -195,52 +360,102 @@ impl VideoProcessor {
             profile,
             profile_compat,
             level,
+            nal_length_size: (data[4] & 0x03) + 1,
             sps: Vec::new(),
             pps: Vec::new(),
+            width: None,
+            height: None,
         };
 
-        // Parse SPS
-        let num_sps = data[5] & 0x1F;
+        // Parameter set counts and lengths come from the peer, so every
+        // read is bounds checked and a short record is rejected
+        let num_sps = (data[5] & 0x1F) as usize;
         let mut offset = 6;
+        config.sps = read_parameter_sets(data, &mut offset, num_sps, "SPS")?;
 
-        for _ in 0..num_sps {
-            if offset + 2 > data.len() {
-                break;
-            }
+        let num_pps = *data.get(offset)
+            .ok_or_else(|| Error::protocol("AVC config truncated before PPS count"))? as usize;
+        offset += 1;
+        config.pps = read_parameter_sets(data, &mut offset, num_pps, "PPS")?;
 
-            let sps_len = u16::from_be_bytes([data[offset], data[offset + 1]]) as usize;
-            offset += 2;
+        // A resolution the SPS does not yield is left unknown
+        if let Some(Ok((width, height))) = config.sps.first().map(|sps| parse_sps_resolution(sps)) {
+            config.width = Some(width);
+            config.height = Some(height);
+        }
 
-            if offset + sps_len <= data.len() {
-                config.sps.push(data[offset..offset + sps_len].to_vec());
-                offset += sps_len;
-            }
+        self.avc_config = Some(config);
+        Ok(())
+    }
+
+    /// Parse HEVC video configuration
+    fn parse_hevc_config(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() < 23 {
+            return Err(Error::protocol("HEVC config too short"));
         }
 
-        // Parse PPS
-        if offset < data.len() {
-            let num_pps = data[offset];
-            offset += 1;
+        // HEVCDecoderConfigurationRecord
+        let profile = data[1] & 0x1F;
+        let level = data[12];
+        let num_arrays = data[22];
 
-            for _ in 0..num_pps {
+        let mut config = HEVCVideoConfig {
+            vps: Vec::new(),
+            sps: Vec::new(),
+            pps: Vec::new(),
+            profile,
+            level,
+        };
+
+        let mut offset = 23;
+
+        for _ in 0..num_arrays {
+            if offset + 3 > data.len() {
+                break;
+            }
+
+            let nal_type = data[offset] & 0x3F;
+            let num_nalus = u16::from_be_bytes([data[offset + 1], data[offset + 2]]);
+            offset += 3;
+
+            for _ in 0..num_nalus {
                 if offset + 2 > data.len() {
                     break;
                 }
 
-                let pps_len = u16::from_be_bytes([data[offset], data[offset + 1]]) as usize;
+                let nalu_len = u16::from_be_bytes([data[offset], data[offset + 1]]) as usize;
                 offset += 2;
 
-                if offset + pps_len <= data.len() {
-                    config.pps.push(data[offset..offset + pps_len].to_vec());
-                    offset += pps_len;
+                if offset + nalu_len > data.len() {
+                    break;
+                }
+
+                let nalu = data[offset..offset + nalu_len].to_vec();
+                offset += nalu_len;
+
+                match nal_type {
+                    HEVC_NAL_VPS => config.vps.push(nalu),
+                    HEVC_NAL_SPS => config.sps.push(nalu),
+                    HEVC_NAL_PPS => config.pps.push(nalu),
+                    _ => {}
                 }
             }
         }
 
-        self.avc_config = Some(config);
+        self.hevc_config = Some(config);
         Ok(())
     }
 
+    /// Get parsed AVC configuration
+    pub fn avc_config(&self) -> Option<&AVCVideoConfig> {
+        self.avc_config.as_ref()
+    }
+
+    /// Get parsed HEVC configuration
+    pub fn hevc_config(&self) -> Option<&HEVCVideoConfig> {
+        self.hevc_config.as_ref()
+    }
+
     /// Check if GOP is too large
     pub fn gop_too_large(&self, max_gop_size: u32) -> bool {
         self.frames_since_keyframe > max_gop_size
@@ -252,10 +467,334 @@ impl VideoProcessor {
     }
 }
 
+impl Default for VideoProcessor {
+    fn default() -> Self {
+        VideoProcessor::new()
+    }
+}
+
 pub struct VideoInfo {
     pub codec: VideoCodec,
     pub frame_type: FrameType,
     pub is_sequence_header: bool,
     pub is_keyframe: bool,
     pub frames_since_keyframe: u32,
-}
\ No newline at end of file
+    /// Presentation offset from the decode timestamp in milliseconds
+    pub composition_time: i32,
+    /// Enhanced RTMP packet type, `None` for legacy tags
+    pub packet_type: Option<VideoPacketType>,
+}
+
+impl VideoInfo {
+    /// Presentation timestamp of the frame decoded at `dts`
+    ///
+    /// Callers pass the decode time unwrapped past the 32 bit message
+    /// timestamp, as segmenters track it.
+    pub fn pts(&self, dts: u64) -> u64 {
+        dts.saturating_add_signed(self.composition_time as i64)
+    }
+}
+
+/// Sign-extend the 24-bit big-endian composition time
+fn parse_composition_time(bytes: &[u8]) -> i32 {
+    let raw = i32::from_be_bytes([bytes[0], bytes[1], bytes[2], 0]);
+    raw >> 8
+}
+
+/// H.264 profiles whose SPS carries chroma format and bit depth fields
+const AVC_HIGH_PROFILES: [u32; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// Read `count` length-prefixed parameter sets starting at `offset`
+fn read_parameter_sets(data: &[u8], offset: &mut usize, count: usize, kind: &str) -> Result<Vec<Vec<u8>>> {
+    let mut sets = Vec::with_capacity(count);
+    for _ in 0..count {
+        let length = data.get(*offset..*offset + 2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+            .ok_or_else(|| Error::protocol(format!("AVC config truncated in {} length", kind)))?;
+        *offset += 2;
+
+        let set = data.get(*offset..*offset + length)
+            .ok_or_else(|| Error::protocol(format!("AVC config {} length {} overruns record", kind, length)))?;
+        sets.push(set.to_vec());
+        *offset += length;
+    }
+    Ok(sets)
+}
+
+/// Frame width and height, after cropping, from an H.264 SPS NAL unit
+pub(crate) fn parse_sps_resolution(sps: &[u8]) -> Result<(u32, u32)> {
+    let rbsp = remove_emulation_prevention(sps);
+    let mut bits = BitReader::new(&rbsp);
+
+    // NAL header, then profile_idc, constraint flags and level_idc
+    bits.read(8)?;
+    let profile_idc = bits.read(8)?;
+    bits.read(16)?;
+    bits.read_ue()?; // seq_parameter_set_id
+
+    let mut chroma_format_idc = 1;
+    let mut separate_colour_plane = false;
+    if AVC_HIGH_PROFILES.contains(&profile_idc) {
+        chroma_format_idc = bits.read_ue()?;
+        if chroma_format_idc == 3 {
+            separate_colour_plane = bits.read_bit()?;
+        }
+        bits.read_ue()?; // bit_depth_luma_minus8
+        bits.read_ue()?; // bit_depth_chroma_minus8
+        bits.read_bit()?; // qpprime_y_zero_transform_bypass_flag
+
+        if bits.read_bit()? {
+            let lists = if chroma_format_idc == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if bits.read_bit()? {
+                    skip_scaling_list(&mut bits, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+
+    bits.read_ue()?; // log2_max_frame_num_minus4
+    match bits.read_ue()? {
+        0 => {
+            bits.read_ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            bits.read_bit()?; // delta_pic_order_always_zero_flag
+            bits.read_se()?; // offset_for_non_ref_pic
+            bits.read_se()?; // offset_for_top_to_bottom_field
+            for _ in 0..bits.read_ue()? {
+                bits.read_se()?;
+            }
+        }
+        _ => {}
+    }
+
+    bits.read_ue()?; // max_num_ref_frames
+    bits.read_bit()?; // gaps_in_frame_num_value_allowed_flag
+
+    let width_in_mbs = bits.read_ue()? + 1;
+    let height_in_map_units = bits.read_ue()? + 1;
+    let frame_mbs_only = bits.read_bit()?;
+    if !frame_mbs_only {
+        bits.read_bit()?; // mb_adaptive_frame_field_flag
+    }
+    bits.read_bit()?; // direct_8x8_inference_flag
+
+    let (mut crop_left, mut crop_right, mut crop_top, mut crop_bottom) = (0, 0, 0, 0);
+    if bits.read_bit()? {
+        crop_left = bits.read_ue()?;
+        crop_right = bits.read_ue()?;
+        crop_top = bits.read_ue()?;
+        crop_bottom = bits.read_ue()?;
+    }
+
+    // Crop offsets count in chroma samples, and in field pairs for interlaced
+    let field_factor = if frame_mbs_only { 1 } else { 2 };
+    let (crop_unit_x, crop_unit_y) = match (separate_colour_plane, chroma_format_idc) {
+        (true, _) | (_, 0) => (1, field_factor),
+        (_, 1) => (2, 2 * field_factor),
+        (_, 2) => (2, field_factor),
+        _ => (1, field_factor),
+    };
+
+    let width = (width_in_mbs * 16)
+        .checked_sub(crop_unit_x * (crop_left + crop_right))
+        .ok_or_else(|| Error::protocol("SPS cropping exceeds frame width"))?;
+    let height = (field_factor * height_in_map_units * 16)
+        .checked_sub(crop_unit_y * (crop_top + crop_bottom))
+        .ok_or_else(|| Error::protocol("SPS cropping exceeds frame height"))?;
+
+    Ok((width, height))
+}
+
+/// Skip a scaling_list() of `size` coefficients
+fn skip_scaling_list(bits: &mut BitReader, size: usize) -> Result<()> {
+    let mut last_scale = 8;
+    let mut next_scale = 8;
+    for _ in 0..size {
+        if next_scale != 0 {
+            next_scale = (last_scale + bits.read_se()? + 256) % 256;
+        }
+        if next_scale != 0 {
+            last_scale = next_scale;
+        }
+    }
+    Ok(())
+}
+
+/// Drop the 0x03 bytes that keep a NAL unit free of start codes
+fn remove_emulation_prevention(nalu: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nalu.len());
+    let mut zeros = 0;
+    for &byte in nalu {
+        if zeros >= 2 && byte == 0x03 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::make_video_packet;
+
+    #[test]
+    fn test_parse_hevc_config() {
+        let mut payload = vec![
+            0x1C,             // Keyframe, HEVC
+            0x00,             // Sequence header
+            0x00, 0x00, 0x00, // Composition time
+        ];
+
+        // HEVCDecoderConfigurationRecord header
+        payload.extend_from_slice(&[
+            0x01,                               // Version
+            0x01,                               // Main profile
+            0x60, 0x00, 0x00, 0x00,             // Profile compatibility
+            0x90, 0x00, 0x00, 0x00, 0x00, 0x00, // Constraint flags
+            0x5D,                               // Level 3.1
+            0xF0, 0x00, 0xFC, 0xFD, 0xF8, 0xF8,
+            0x00, 0x00, 0x0F,
+            0x03,                               // Number of arrays
+        ]);
+
+        // VPS, SPS and PPS arrays with one NAL unit each
+        payload.extend_from_slice(&[0xA0, 0x00, 0x01, 0x00, 0x02, 0x40, 0x01]);
+        payload.extend_from_slice(&[0xA1, 0x00, 0x01, 0x00, 0x03, 0x42, 0x01, 0x01]);
+        payload.extend_from_slice(&[0xA2, 0x00, 0x01, 0x00, 0x02, 0x44, 0x01]);
+
+        let mut processor = VideoProcessor::new();
+        let info = processor.process(&make_video_packet(payload, 0, 1)).unwrap();
+        assert_eq!(info.codec, VideoCodec::H265);
+        assert!(info.is_sequence_header);
+        assert_eq!(processor.codec(), Some(VideoCodec::H265));
+
+        let config = processor.hevc_config().unwrap();
+        assert_eq!(config.profile, 1);
+        assert_eq!(config.level, 0x5D);
+        assert_eq!(config.vps, vec![vec![0x40, 0x01]]);
+        assert_eq!(config.sps, vec![vec![0x42, 0x01, 0x01]]);
+        assert_eq!(config.pps, vec![vec![0x44, 0x01]]);
+        assert!(processor.avc_config.is_none());
+    }
+
+    #[test]
+    fn test_avc_config_resolution_from_sps() {
+        // x264 1080p High profile SPS: 1088 coded lines cropped to 1080,
+        // with emulation prevention bytes
+        let sps_1080p = [
+            0x67, 0x64, 0x00, 0x28, 0xAC, 0xD9, 0x40, 0x78, 0x02, 0x27, 0xE5, 0xC0, 0x44, 0x00,
+            0x00, 0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xF0, 0x3C, 0x60, 0xC6, 0x58,
+        ];
+        assert_eq!(parse_sps_resolution(&sps_1080p).unwrap(), (1920, 1080));
+
+        // 720p Baseline SPS inside a sequence header
+        let sps_720p = [
+            0x67, 0x42, 0xC0, 0x1F, 0xDA, 0x01, 0x40, 0x16, 0xEC, 0x04, 0x40, 0x00, 0x00, 0x03,
+            0x00, 0x40, 0x00, 0x00, 0x0C, 0x83, 0xC6, 0x0C, 0xA8,
+        ];
+        let mut payload = vec![0x17, 0x00, 0x00, 0x00, 0x00, 0x01, 0x42, 0xC0, 0x1F, 0xFF, 0xE1];
+        payload.extend_from_slice(&(sps_720p.len() as u16).to_be_bytes());
+        payload.extend_from_slice(&sps_720p);
+        payload.extend_from_slice(&[0x01, 0x00, 0x04, 0x68, 0xCE, 0x3C, 0x80]);
+
+        let mut processor = VideoProcessor::new();
+        processor.process(&make_video_packet(payload, 0, 1)).unwrap();
+        let config = processor.avc_config().unwrap();
+        assert_eq!((config.width, config.height), (Some(1280), Some(720)));
+
+        assert!(parse_sps_resolution(&sps_720p[..8]).is_err());
+    }
+
+    #[test]
+    fn test_malformed_avc_config_is_rejected() {
+        let mut payload = vec![0x17, 0x00, 0x00, 0x00, 0x00, 0x01, 0x42, 0xC0, 0x1F, 0xFF, 0xE1, 0x00, 0x04];
+        payload.extend_from_slice(&[0x67, 0x42, 0xC0, 0x1F]);
+        payload.extend_from_slice(&[0x01, 0x00, 0x04, 0x68, 0xCE, 0x3C, 0x80]);
+        assert!(VideoProcessor::new().process(&make_video_packet(payload.clone(), 0, 1)).is_ok());
+
+        // Every truncation of a valid record is an error, not a panic
+        for len in 5..payload.len() {
+            let result = VideoProcessor::new().process(&make_video_packet(payload[..len].to_vec(), 0, 1));
+            assert!(result.is_err(), "truncated to {} bytes", len);
+        }
+
+        // Garbage after the AVC sequence header prefix never panics
+        let mut seed = 0x2545_F491u32;
+        for _ in 0..2000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let len = 5 + (seed % 40) as usize;
+
+            let mut garbage = vec![0x17, 0x00, 0x00, 0x00, 0x00];
+            garbage.extend((5..len).map(|i| (seed.rotate_left(i as u32) ^ i as u32) as u8));
+            let _ = VideoProcessor::new().process(&make_video_packet(garbage, 0, 1));
+        }
+    }
+
+    #[test]
+    fn test_composition_time() {
+        let mut processor = VideoProcessor::new();
+
+        // B-frame presented 80ms after it is decoded
+        let info = processor.process(&make_video_packet(vec![0x27, 0x01, 0x00, 0x00, 0x50, 0x00], 1000, 1)).unwrap();
+        assert_eq!(info.composition_time, 80);
+        assert_eq!(info.pts(1000), 1080);
+
+        // Negative offsets are sign-extended from 24 bits
+        let info = processor.process(&make_video_packet(vec![0x27, 0x01, 0xFF, 0xFF, 0xD8, 0x00], 1000, 1)).unwrap();
+        assert_eq!(info.composition_time, -40);
+        assert_eq!(info.pts(1000), 960);
+
+        // Only NALU packets carry a composition time
+        let info = processor.process(&make_video_packet(vec![0x17, 0x02, 0x00, 0x00, 0x50], 1000, 1)).unwrap();
+        assert_eq!(info.composition_time, 0);
+        assert_eq!(info.pts(1000), 1000);
+    }
+
+    #[test]
+    fn test_enhanced_av1_sequence_start() {
+        let mut payload = vec![0x90]; // Ex header, keyframe, SequenceStart
+        payload.extend_from_slice(b"av01");
+        payload.extend_from_slice(&[0x81, 0x00, 0x0C, 0x00]); // AV1CodecConfigurationRecord
+
+        let mut processor = VideoProcessor::new();
+        let info = processor.process(&make_video_packet(payload, 0, 1)).unwrap();
+        assert_eq!(info.codec, VideoCodec::AV1);
+        assert_eq!(info.packet_type, Some(VideoPacketType::SequenceStart));
+        assert!(info.is_sequence_header);
+        assert!(info.is_keyframe);
+        assert_eq!(processor.codec(), Some(VideoCodec::AV1));
+    }
+
+    #[test]
+    fn test_enhanced_hevc_coded_frame() {
+        let mut payload = vec![0xA1]; // Ex header, inter frame, CodedFrames
+        payload.extend_from_slice(b"hvc1");
+        payload.extend_from_slice(&[0xFF, 0xFF, 0xEC]); // Composition time -20
+        payload.extend_from_slice(&[0x00, 0x00, 0x00, 0x02, 0x02, 0x01]);
+
+        let mut processor = VideoProcessor::new();
+        let info = processor.process(&make_video_packet(payload, 500, 1)).unwrap();
+        assert_eq!(info.codec, VideoCodec::H265);
+        assert_eq!(info.packet_type, Some(VideoPacketType::CodedFrames));
+        assert_eq!(info.frame_type, FrameType::InterFrame);
+        assert!(!info.is_sequence_header);
+        assert!(!info.is_keyframe);
+        assert_eq!(info.composition_time, -20);
+        assert_eq!(info.pts(500), 480);
+
+        // CodedFramesX omits the composition time
+        let mut payload = vec![0xA3];
+        payload.extend_from_slice(b"hvc1");
+        payload.extend_from_slice(&[0x00, 0x00, 0x00, 0x02, 0x02, 0x01]);
+        let info = processor.process(&make_video_packet(payload, 540, 1)).unwrap();
+        assert_eq!(info.composition_time, 0);
+        assert_eq!(info.frames_since_keyframe, 2);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Error, Result};
+use crate::{Error, Result, MSG_TYPE_COMMAND_AMF3};
 use crate::amf::{Amf0Value, Amf0Encoder, Amf0Decoder};
 use crate::ByteBuffer;
 use std::collections::HashMap;
@@ -118,6 +118,21 @@ impl RtmpCommand {
         Ok(encoder.get_bytes())
     }
 
+    /// Decode command from a message payload of the given message type.
+    ///
+    /// AMF3 command messages (type 17) carry a leading format byte before the
+    /// AMF0-encoded command, which is skipped here.
+    pub fn decode_with_type(data: &[u8], message_type: u8) -> Result<Self> {
+        if message_type == MSG_TYPE_COMMAND_AMF3 {
+            let body = data.split_first()
+                .map(|(_, rest)| rest)
+                .ok_or_else(|| Error::amf_decode("Empty AMF3 command"))?;
+            return Self::decode(body);
+        }
+
+        Self::decode(data)
+    }
+
     /// Decode command from bytes
     pub fn decode(data: &[u8]) -> Result<Self> {
         let mut buffer = ByteBuffer::new(data.to_vec());
@@ -179,4 +194,30 @@ mod tests {
         assert_eq!(original.name, decoded.name);
         assert_eq!(original.transaction_id, decoded.transaction_id);
     }
+
+    #[test]
+    fn test_typed_command_object_round_trip() {
+        let mut properties = HashMap::new();
+        properties.insert("app".to_string(), Amf0Value::String("live".to_string()));
+
+        let mut original = RtmpCommand::new("connect".to_string(), 1.0);
+        original.command_object = Some(Amf0Value::TypedObject("flex.messaging.io.ConnectObject".to_string(), properties));
+        let decoded = RtmpCommand::decode(&original.encode().unwrap()).unwrap();
+
+        let command_object = decoded.command_object.as_ref().unwrap();
+        assert_eq!(command_object.class_name(), Some("flex.messaging.io.ConnectObject"));
+        assert_eq!(command_object.get_property("app").and_then(|v| v.as_string()), Some("live"));
+        assert_eq!(decoded.encode().unwrap(), original.encode().unwrap());
+    }
+
+    #[test]
+    fn test_amf3_command_skips_format_byte() {
+        let original = RtmpCommand::publish("stream", "live");
+        let mut bytes = vec![0x00];
+        bytes.extend_from_slice(&original.encode().unwrap());
+
+        let decoded = RtmpCommand::decode_with_type(&bytes, MSG_TYPE_COMMAND_AMF3).unwrap();
+        assert_eq!(decoded.name, "publish");
+        assert_eq!(decoded.arguments[0].as_string(), Some("stream"));
+    }
 }
\ No newline at end of file
@@ -24,4 +24,5 @@ pub const CHUNK_STREAM_DATA: u32 = 8;
 
 // Default values
 pub const DEFAULT_CHUNK_SIZE: u32 = 128;
-pub const DEFAULT_WINDOW_SIZE: u32 = 2500000;
\ No newline at end of file
+pub const DEFAULT_WINDOW_SIZE: u32 = 2500000;
+pub const DEFAULT_MAX_MESSAGE_LENGTH: u32 = 8 * 1024 * 1024;
\ No newline at end of file
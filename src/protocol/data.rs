@@ -7,14 +7,21 @@ use std::collections::HashMap;
 pub struct RtmpData {
     pub data_type: String,
     pub values: Vec<Amf0Value>,
+
+    /// Wrapped in `@setDataFrame`, as publishers send stream metadata
+    pub data_frame: bool,
 }
 
+/// Handler name publishers prefix to metadata they want the server to keep
+const SET_DATA_FRAME: &str = "@setDataFrame";
+
 impl RtmpData {
     /// Create new data message
     pub fn new(data_type: String) -> Self {
         RtmpData {
             data_type,
             values: Vec::new(),
+            data_frame: false,
         }
     }
 
@@ -25,11 +32,24 @@ impl RtmpData {
         data
     }
 
-    /// Create setDataFrame message
-    pub fn set_data_frame(key: &str, value: Amf0Value) -> Self {
-        let mut data = RtmpData::new("@setDataFrame".to_string());
-        data.values.push(Amf0Value::String(key.to_string()));
-        data.values.push(value);
+    /// Create `@setDataFrame` message carrying onMetaData, as OBS sends it
+    pub fn set_data_frame(metadata: HashMap<String, Amf0Value>) -> Self {
+        let mut data = RtmpData::new("onMetaData".to_string());
+        data.values.push(Amf0Value::EcmaArray(metadata));
+        data.data_frame = true;
+        data
+    }
+
+    /// Create onCuePoint message marking `name` at `time` seconds
+    pub fn on_cue_point(name: &str, time: f64, params: HashMap<String, Amf0Value>) -> Self {
+        let mut cue_point = HashMap::new();
+        cue_point.insert("name".to_string(), Amf0Value::String(name.to_string()));
+        cue_point.insert("time".to_string(), Amf0Value::Number(time));
+        cue_point.insert("type".to_string(), Amf0Value::String("event".to_string()));
+        cue_point.insert("parameters".to_string(), Amf0Value::Object(params));
+
+        let mut data = RtmpData::new("onCuePoint".to_string());
+        data.values.push(Amf0Value::Object(cue_point));
         data
     }
 
@@ -55,6 +75,10 @@ impl RtmpData {
     pub fn encode(&self) -> Result<Vec<u8>> {
         let mut encoder = Amf0Encoder::new();
 
+        if self.data_frame {
+            encoder.encode(&Amf0Value::String(SET_DATA_FRAME.to_string()))?;
+        }
+
         // Encode data type
         encoder.encode(&Amf0Value::String(self.data_type.clone()))?;
 
@@ -73,10 +97,18 @@ impl RtmpData {
 
         // Decode data type
         let type_val = decoder.decode()?;
-        let data_type = type_val.as_string()
+        let mut data_type = type_val.as_string()
             .ok_or_else(|| Error::amf_decode("Data type must be string"))?
             .to_string();
 
+        // Unwrap @setDataFrame so the inner handler name is the data type
+        let data_frame = data_type == SET_DATA_FRAME;
+        if data_frame {
+            data_type = decoder.decode()?.as_string()
+                .ok_or_else(|| Error::amf_decode("@setDataFrame handler must be string"))?
+                .to_string();
+        }
+
         // Decode remaining values
         let mut values = Vec::new();
         while decoder.has_remaining() {
@@ -86,6 +118,7 @@ impl RtmpData {
         Ok(RtmpData {
             data_type,
             values,
+            data_frame,
         })
     }
 
@@ -97,4 +130,45 @@ impl RtmpData {
             None
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_data_frame_round_trip() {
+        let mut metadata = HashMap::new();
+        metadata.insert("width".to_string(), Amf0Value::Number(1920.0));
+        metadata.insert("encoder".to_string(), Amf0Value::String("obs-output module".to_string()));
+
+        let bytes = RtmpData::set_data_frame(metadata.clone()).encode().unwrap();
+
+        // Three values on the wire: the wrapper, the handler name, the array
+        let mut buffer = ByteBuffer::new(bytes.clone());
+        let mut decoder = Amf0Decoder::new(&mut buffer);
+        assert_eq!(decoder.decode().unwrap(), Amf0Value::String("@setDataFrame".to_string()));
+        assert_eq!(decoder.decode().unwrap(), Amf0Value::String("onMetaData".to_string()));
+        assert!(matches!(decoder.decode().unwrap(), Amf0Value::EcmaArray(_)));
+        assert!(!decoder.has_remaining());
+
+        let data = RtmpData::decode(&bytes).unwrap();
+        assert_eq!(data.data_type, "onMetaData");
+        assert!(data.data_frame);
+        assert_eq!(data.get_metadata(), Some(&metadata));
+    }
+
+    #[test]
+    fn test_cue_point_round_trip() {
+        let mut params = HashMap::new();
+        params.insert("duration".to_string(), Amf0Value::Number(30.0));
+
+        let bytes = RtmpData::on_cue_point("ad-break", 12.5, params.clone()).encode().unwrap();
+        let data = RtmpData::decode(&bytes).unwrap();
+
+        assert_eq!(data.data_type, "onCuePoint");
+        let cue_point = &data.values[0];
+        assert_eq!(cue_point.get_property("name").and_then(|v| v.as_string()), Some("ad-break"));
+        assert_eq!(cue_point.get_property("time").and_then(|v| v.as_number()), Some(12.5));
+        assert_eq!(cue_point.get_property("parameters"), Some(&Amf0Value::Object(params)));
+    }
+}
@@ -1,9 +1,13 @@
 mod packet;
 mod command;
 mod data;
+mod user_control;
+mod status;
 pub mod constants;
 
 pub use packet::*;
 pub use command::*;
 pub use data::*;
+pub use user_control::*;
+pub use status::*;
 pub use constants::*;
@@ -1,15 +1,22 @@
+use bytes::Bytes;
+use crate::Result;
 use crate::protocol::constants::*;
+use crate::protocol::{NetStatus, UserControlEvent};
 
+/// One RTMP message
+///
+/// The payload is reference counted, so cloning a packet for every player
+/// copies only the header.
 #[derive(Debug, Clone)]
 pub struct RtmpPacket {
     pub header: RtmpHeader,
-    pub payload: Vec<u8>,
+    pub payload: Bytes,
 }
 
 impl RtmpPacket {
-    /// Create new packet
-    pub fn new(header: RtmpHeader, payload: Vec<u8>) -> Self {
-        RtmpPacket { header, payload }
+    /// Create new packet from a `Vec<u8>`, `Bytes` or static slice payload
+    pub fn new(header: RtmpHeader, payload: impl Into<Bytes>) -> Self {
+        RtmpPacket { header, payload: payload.into() }
     }
 
     /// Get message type
@@ -158,16 +165,35 @@ impl RtmpHeader {
     }
 }
 
-pub fn make_audio_packet(data: Vec<u8>, timestamp: u32, stream_id: u32) -> RtmpPacket {
+/// Audio message on the well-known audio chunk stream; connections move it
+/// to a chunk stream of its own when several streams send audio
+pub fn make_audio_packet(data: impl Into<Bytes>, timestamp: u32, stream_id: u32) -> RtmpPacket {
+    let data = data.into();
     let header = RtmpHeader::audio(timestamp, data.len() as u32, stream_id);
     RtmpPacket::new(header, data)
 }
 
-pub fn make_video_packet(data: Vec<u8>, timestamp: u32, stream_id: u32) -> RtmpPacket {
+/// Video message on the well-known video chunk stream; connections move it
+/// to a chunk stream of its own when several streams send video
+pub fn make_video_packet(data: impl Into<Bytes>, timestamp: u32, stream_id: u32) -> RtmpPacket {
+    let data = data.into();
     let header = RtmpHeader::video(timestamp, data.len() as u32, stream_id);
     RtmpPacket::new(header, data)
 }
 
+/// `NetStream.Play.Stop` status telling a player `stream_name` stopped
+pub fn make_play_stop_packet(stream_name: &str, stream_id: u32) -> Result<RtmpPacket> {
+    let stop = NetStatus::PlayStop.to_command_with(&format!("Stopped playing {}", stream_name));
+    let bytes = stop.encode()?;
+    let header = RtmpHeader::command(0, bytes.len() as u32, stream_id);
+    Ok(RtmpPacket::new(header, bytes))
+}
+
+/// Stream EOF user control event, sent after a stream's last message
+pub fn make_stream_eof_packet(stream_id: u32) -> RtmpPacket {
+    UserControlEvent::StreamEof(stream_id).to_packet()
+}
+
 pub fn parse_basic_header(byte: u8) -> (u8, u32) {
     let fmt = (byte >> 6) & 0x03;
     let chunk_stream_id = match byte & 0x3F {
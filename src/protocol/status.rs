@@ -0,0 +1,139 @@
+use crate::protocol::RtmpCommand;
+
+/// Level of an `onStatus` info object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusLevel {
+    Status,
+    Warning,
+    Error,
+}
+
+impl StatusLevel {
+    /// Wire value of the `level` property
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusLevel::Status => "status",
+            StatusLevel::Warning => "warning",
+            StatusLevel::Error => "error",
+        }
+    }
+}
+
+/// Well-known NetConnection and NetStream status codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetStatus {
+    ConnectSuccess,
+    ConnectRejected,
+    ConnectClosed,
+    CallFailed,
+    PublishStart,
+    PublishBadName,
+    UnpublishSuccess,
+    PlayStart,
+    PlayReset,
+    PlayStop,
+    PlayStreamNotFound,
+    PlayUnpublishNotify,
+    DataStart,
+    PauseNotify,
+    UnpauseNotify,
+    SeekNotify,
+}
+
+impl NetStatus {
+    /// Wire value of the `code` property
+    pub fn code(&self) -> &'static str {
+        match self {
+            NetStatus::ConnectSuccess => "NetConnection.Connect.Success",
+            NetStatus::ConnectRejected => "NetConnection.Connect.Rejected",
+            NetStatus::ConnectClosed => "NetConnection.Connect.Closed",
+            NetStatus::CallFailed => "NetConnection.Call.Failed",
+            NetStatus::PublishStart => "NetStream.Publish.Start",
+            NetStatus::PublishBadName => "NetStream.Publish.BadName",
+            NetStatus::UnpublishSuccess => "NetStream.Unpublish.Success",
+            NetStatus::PlayStart => "NetStream.Play.Start",
+            NetStatus::PlayReset => "NetStream.Play.Reset",
+            NetStatus::PlayStop => "NetStream.Play.Stop",
+            NetStatus::PlayStreamNotFound => "NetStream.Play.StreamNotFound",
+            NetStatus::PlayUnpublishNotify => "NetStream.Play.UnpublishNotify",
+            NetStatus::DataStart => "NetStream.Data.Start",
+            NetStatus::PauseNotify => "NetStream.Pause.Notify",
+            NetStatus::UnpauseNotify => "NetStream.Unpause.Notify",
+            NetStatus::SeekNotify => "NetStream.Seek.Notify",
+        }
+    }
+
+    /// Level the code is reported with
+    pub fn level(&self) -> StatusLevel {
+        match self {
+            NetStatus::ConnectRejected |
+            NetStatus::CallFailed |
+            NetStatus::PublishBadName |
+            NetStatus::PlayStreamNotFound => StatusLevel::Error,
+            _ => StatusLevel::Status,
+        }
+    }
+
+    /// Default human readable description
+    pub fn description(&self) -> &'static str {
+        match self {
+            NetStatus::ConnectSuccess => "Connection succeeded",
+            NetStatus::ConnectRejected => "Connection rejected",
+            NetStatus::ConnectClosed => "Connection closed",
+            NetStatus::CallFailed => "Call failed",
+            NetStatus::PublishStart => "Publishing started",
+            NetStatus::PublishBadName => "Stream name cannot be published",
+            NetStatus::UnpublishSuccess => "Publishing stopped",
+            NetStatus::PlayStart => "Playback started",
+            NetStatus::PlayReset => "Playlist reset",
+            NetStatus::PlayStop => "Playback stopped",
+            NetStatus::PlayStreamNotFound => "Stream not found",
+            NetStatus::PlayUnpublishNotify => "Stream unpublished",
+            NetStatus::DataStart => "Data start",
+            NetStatus::PauseNotify => "Paused",
+            NetStatus::UnpauseNotify => "Unpaused",
+            NetStatus::SeekNotify => "Seeking",
+        }
+    }
+
+    /// `onStatus` command with the default description
+    pub fn to_command(&self) -> RtmpCommand {
+        self.to_command_with(self.description())
+    }
+
+    /// `onStatus` command with a custom description
+    pub fn to_command_with(&self, description: &str) -> RtmpCommand {
+        RtmpCommand::on_status(self.level().as_str(), self.code(), description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::Amf0Value;
+
+    #[test]
+    fn test_publish_start_command() {
+        let bytes = NetStatus::PublishStart.to_command().encode().unwrap();
+        let command = RtmpCommand::decode(&bytes).unwrap();
+
+        assert_eq!(command.name, "onStatus");
+        assert_eq!(command.transaction_id, 0.0);
+        assert_eq!(command.command_object, Some(Amf0Value::Null));
+
+        let Some(Amf0Value::Object(info)) = command.arguments.first() else {
+            panic!("missing info object");
+        };
+        assert_eq!(info.len(), 3);
+        assert_eq!(info.get("level"), Some(&Amf0Value::String("status".to_string())));
+        assert_eq!(info.get("code"), Some(&Amf0Value::String("NetStream.Publish.Start".to_string())));
+        assert_eq!(info.get("description"), Some(&Amf0Value::String("Publishing started".to_string())));
+    }
+
+    #[test]
+    fn test_error_levels() {
+        assert_eq!(NetStatus::PublishBadName.level(), StatusLevel::Error);
+        assert_eq!(NetStatus::PlayStreamNotFound.level(), StatusLevel::Error);
+        assert_eq!(NetStatus::PlayStart.level(), StatusLevel::Status);
+    }
+}
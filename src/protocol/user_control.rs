@@ -0,0 +1,150 @@
+use crate::{Error, Result, ByteBuffer};
+use crate::protocol::{RtmpHeader, RtmpPacket};
+use crate::protocol::constants::{MSG_TYPE_USER_CONTROL, CHUNK_STREAM_PROTOCOL};
+
+// User control event types
+pub mod user_control_events {
+    pub const STREAM_BEGIN: u16 = 0;
+    pub const STREAM_EOF: u16 = 1;
+    pub const STREAM_DRY: u16 = 2;
+    pub const SET_BUFFER_LENGTH: u16 = 3;
+    pub const STREAM_IS_RECORDED: u16 = 4;
+    pub const PING_REQUEST: u16 = 6;
+    pub const PING_RESPONSE: u16 = 7;
+}
+
+/// User Control Message (type 4) events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserControlEvent {
+    StreamBegin(u32),
+    StreamEof(u32),
+    StreamDry(u32),
+    SetBufferLength { stream_id: u32, buffer_ms: u32 },
+    StreamIsRecorded(u32),
+    PingRequest(u32),
+    PingResponse(u32),
+}
+
+impl UserControlEvent {
+    /// Get the 2-byte event type
+    pub fn event_type(&self) -> u16 {
+        use user_control_events::*;
+
+        match self {
+            UserControlEvent::StreamBegin(_) => STREAM_BEGIN,
+            UserControlEvent::StreamEof(_) => STREAM_EOF,
+            UserControlEvent::StreamDry(_) => STREAM_DRY,
+            UserControlEvent::SetBufferLength { .. } => SET_BUFFER_LENGTH,
+            UserControlEvent::StreamIsRecorded(_) => STREAM_IS_RECORDED,
+            UserControlEvent::PingRequest(_) => PING_REQUEST,
+            UserControlEvent::PingResponse(_) => PING_RESPONSE,
+        }
+    }
+
+    /// Encode event type and event data
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(10);
+        bytes.extend_from_slice(&self.event_type().to_be_bytes());
+
+        match *self {
+            UserControlEvent::StreamBegin(value)
+            | UserControlEvent::StreamEof(value)
+            | UserControlEvent::StreamDry(value)
+            | UserControlEvent::StreamIsRecorded(value)
+            | UserControlEvent::PingRequest(value)
+            | UserControlEvent::PingResponse(value) => {
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+            UserControlEvent::SetBufferLength { stream_id, buffer_ms } => {
+                bytes.extend_from_slice(&stream_id.to_be_bytes());
+                bytes.extend_from_slice(&buffer_ms.to_be_bytes());
+            }
+        }
+
+        bytes
+    }
+
+    /// Decode event from a user control message payload
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        use user_control_events::*;
+
+        let mut buffer = ByteBuffer::new(data.to_vec());
+        let event_type = buffer.read_u16_be()
+            .map_err(|_| Error::protocol("User control message too short"))?;
+        let value = buffer.read_u32_be()
+            .map_err(|_| Error::protocol("Missing user control event data"))?;
+
+        let event = match event_type {
+            STREAM_BEGIN => UserControlEvent::StreamBegin(value),
+            STREAM_EOF => UserControlEvent::StreamEof(value),
+            STREAM_DRY => UserControlEvent::StreamDry(value),
+            SET_BUFFER_LENGTH => {
+                let buffer_ms = buffer.read_u32_be()
+                    .map_err(|_| Error::protocol("Missing buffer length"))?;
+                UserControlEvent::SetBufferLength { stream_id: value, buffer_ms }
+            }
+            STREAM_IS_RECORDED => UserControlEvent::StreamIsRecorded(value),
+            PING_REQUEST => UserControlEvent::PingRequest(value),
+            PING_RESPONSE => UserControlEvent::PingResponse(value),
+            _ => return Err(Error::protocol(format!("Unknown user control event: {}", event_type))),
+        };
+
+        Ok(event)
+    }
+
+    /// Build a user control message packet for this event
+    pub fn to_packet(&self) -> RtmpPacket {
+        let payload = self.encode();
+
+        let header = RtmpHeader::new(
+            0,
+            payload.len() as u32,
+            MSG_TYPE_USER_CONTROL,
+            0,
+            CHUNK_STREAM_PROTOCOL,
+        );
+
+        RtmpPacket::new(header, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_events() {
+        let events = [
+            UserControlEvent::StreamBegin(1),
+            UserControlEvent::StreamEof(2),
+            UserControlEvent::StreamDry(3),
+            UserControlEvent::SetBufferLength { stream_id: 1, buffer_ms: 3000 },
+            UserControlEvent::StreamIsRecorded(4),
+            UserControlEvent::PingRequest(123456),
+            UserControlEvent::PingResponse(123456),
+        ];
+
+        for event in events {
+            let bytes = event.encode();
+            assert_eq!(u16::from_be_bytes([bytes[0], bytes[1]]), event.event_type());
+            assert_eq!(UserControlEvent::decode(&bytes).unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn test_encode_stream_begin() {
+        let bytes = UserControlEvent::StreamBegin(1).encode();
+        assert_eq!(bytes, vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x01]);
+
+        let packet = UserControlEvent::StreamBegin(1).to_packet();
+        assert_eq!(packet.message_type(), MSG_TYPE_USER_CONTROL);
+        assert_eq!(packet.message_stream_id(), 0);
+    }
+
+    #[test]
+    fn test_decode_invalid_event() {
+        assert!(UserControlEvent::decode(&[0x00]).is_err());
+        assert!(UserControlEvent::decode(&[0x00, 0x05, 0x00, 0x00, 0x00, 0x00]).is_err());
+        assert!(UserControlEvent::decode(&[0x00, 0x03, 0x00, 0x00, 0x00, 0x01]).is_err());
+    }
+}
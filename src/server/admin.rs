@@ -0,0 +1,184 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use log::warn;
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+use tokio::net::TcpListener;
+use crate::{ConnectionInfo, Result};
+use crate::server::registry::StreamMetrics;
+use crate::server::rtmpt::{read_request, write_response};
+use crate::server::server::RtmpServer;
+
+/// Serves operational status over plain HTTP
+///
+/// `GET /healthz` answers `ok` while the server runs, and `GET /stats`
+/// returns the published streams and active connections as JSON.
+pub struct AdminListener {
+    server: Arc<RtmpServer>,
+}
+
+impl AdminListener {
+    /// Create a listener reporting on `server`
+    pub fn new(server: Arc<RtmpServer>) -> Self {
+        AdminListener { server }
+    }
+
+    /// Accept HTTP connections until accepting fails
+    pub async fn listen(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer_addr) = listener.accept().await?;
+            let this = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = this.serve_http(stream).await {
+                    warn!(peer:% = peer_addr; "Admin connection failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Answer the requests arriving on one HTTP connection
+    pub async fn serve_http<S>(&self, stream: S) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (read_half, mut write_half) = tokio::io::split(stream);
+        let mut reader = BufReader::new(read_half);
+
+        while let Some(request) = read_request(&mut reader).await? {
+            let (status, content_type, body) = match (request.method.as_str(), request.path.as_str()) {
+                ("GET", "/healthz") => ("200 OK", "text/plain", b"ok\n".to_vec()),
+                ("GET", "/stats") => ("200 OK", "application/json", self.stats_json().await.into_bytes()),
+                (_, "/healthz" | "/stats") => ("405 Method Not Allowed", "text/plain", Vec::new()),
+                _ => ("404 Not Found", "text/plain", Vec::new()),
+            };
+            write_response(&mut write_half, status, content_type, &body).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Streams and connections as a JSON document
+    async fn stats_json(&self) -> String {
+        let streams = self.server.context().publishers().stream_metrics().await;
+        let connections = self.server.connections_info().await;
+
+        let streams: Vec<String> = streams.iter().map(stream_json).collect();
+        let connections: Vec<String> = connections.iter().map(connection_json).collect();
+        format!(
+            "{{\"streams\":[{}],\"connections\":[{}]}}",
+            streams.join(","),
+            connections.join(",")
+        )
+    }
+}
+
+fn stream_json(metrics: &StreamMetrics) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"name\":{},\"subscribers\":{},\"bytes_in\":{},\"video_packets\":{},\"audio_packets\":{}}}",
+        json_string(&metrics.stream_name),
+        metrics.subscribers,
+        metrics.stats.bytes_in,
+        metrics.stats.video_packets,
+        metrics.stats.audio_packets,
+    );
+    out
+}
+
+fn connection_json(info: &ConnectionInfo) -> String {
+    let optional = |value: Option<String>| value.as_deref().map_or("null".to_string(), json_string);
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"id\":{},\"peer_addr\":{},\"state\":{},\"app\":{},\"stream_name\":{},\"bytes_received\":{},\"bytes_sent\":{}}}",
+        json_string(&info.id),
+        optional(info.peer_addr.map(|addr| addr.to_string())),
+        json_string(&format!("{:?}", info.state)),
+        optional(info.app.clone()),
+        optional(info.stream_name.clone()),
+        info.bytes_received,
+        info.bytes_sent,
+    );
+    out
+}
+
+/// Quote and escape a JSON string
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use crate::server::rtmpt::read_line;
+    use crate::ServerConfig;
+
+    /// Send a GET for `path` and return the status line and response body
+    async fn get<S>(client: &mut BufReader<S>, path: &str) -> (String, String)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let head = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+        client.get_mut().write_all(head.as_bytes()).await.unwrap();
+
+        let status = read_line(client).await.unwrap().unwrap();
+        let mut content_length = 0;
+        loop {
+            let line = read_line(client).await.unwrap().unwrap();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                content_length = value.parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0u8; content_length];
+        client.read_exact(&mut body).await.unwrap();
+        (status, String::from_utf8(body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_healthz_and_stats() {
+        let server = Arc::new(RtmpServer::new(ServerConfig::default()));
+        server.context().publishers()
+            .register("live\"1".to_string(), "conn-1".to_string(), 1, None)
+            .await
+            .unwrap();
+        let admin = AdminListener::new(server);
+
+        let (client, server_side) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move { admin.serve_http(server_side).await });
+        let mut client = BufReader::new(client);
+
+        let (status, body) = get(&mut client, "/healthz").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(body, "ok\n");
+
+        let (status, body) = get(&mut client, "/stats").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert!(body.starts_with("{\"streams\":[{\"name\":\"live\\\"1\",\"subscribers\":0,"));
+        assert!(body.ends_with("\"connections\":[]}"));
+
+        let (status, _) = get(&mut client, "/missing").await;
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+    }
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// Future returned by authorization hooks; resolves to true to allow
+pub type AuthFuture = Pin<Box<dyn Future<Output = bool> + Send>>;
+
+type ConnectCallback = dyn Fn(&ConnectParams) -> AuthFuture + Send + Sync;
+type PublishCallback = dyn Fn(&PublishParams) -> AuthFuture + Send + Sync;
+
+/// Parameters of a client's `connect` command
+#[derive(Debug, Clone)]
+pub struct ConnectParams {
+    /// Application name
+    pub app: String,
+
+    /// Target URL, may carry a query string token
+    pub tc_url: String,
+
+    /// Client version string
+    pub flash_ver: String,
+
+    /// Requested AMF object encoding
+    pub object_encoding: f64,
+
+    /// Class name when the connect object was sent as an AMF0 typed object
+    pub object_class: Option<String>,
+
+    /// Client address, when known
+    pub peer_addr: Option<SocketAddr>,
+}
+
+/// Parameters of a client's `publish` command
+#[derive(Debug, Clone)]
+pub struct PublishParams {
+    /// Stream name, including any query string key
+    pub stream_name: String,
+
+    /// Publish type such as `live` or `record`
+    pub publish_type: String,
+
+    /// Client address, when known
+    pub peer_addr: Option<SocketAddr>,
+}
+
+/// Hook deciding whether a client may connect
+#[derive(Clone)]
+pub struct ConnectAuth(Arc<ConnectCallback>);
+
+impl ConnectAuth {
+    /// Wrap an authorization callback
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&ConnectParams) -> AuthFuture + Send + Sync + 'static,
+    {
+        ConnectAuth(Arc::new(callback))
+    }
+
+    /// Check if the connection is allowed
+    pub async fn authorize(&self, params: &ConnectParams) -> bool {
+        (self.0)(params).await
+    }
+}
+
+impl fmt::Debug for ConnectAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ConnectAuth")
+    }
+}
+
+/// Hook deciding whether a stream name may be published
+#[derive(Clone)]
+pub struct PublishAuth(Arc<PublishCallback>);
+
+impl PublishAuth {
+    /// Wrap an authorization callback
+    pub fn new<F>(callback: F) -> Self
+    where
+        F: Fn(&PublishParams) -> AuthFuture + Send + Sync + 'static,
+    {
+        PublishAuth(Arc::new(callback))
+    }
+
+    /// Check if publishing is allowed
+    pub async fn authorize(&self, params: &PublishParams) -> bool {
+        (self.0)(params).await
+    }
+}
+
+impl fmt::Debug for PublishAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PublishAuth")
+    }
+}
@@ -1,5 +1,37 @@
+use std::path::PathBuf;
 use std::time::Duration;
-use crate::{Error, Result};
+use crate::{Error, JoinMode, RepublishPolicy, Result};
+use crate::server::auth::{AuthFuture, ConnectAuth, ConnectParams, PublishAuth, PublishParams};
+
+/// One address the server accepts connections on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenSpec {
+    /// Host to bind
+    pub host: String,
+
+    /// Port to bind
+    pub port: u16,
+
+    /// Accept TLS (rtmps) instead of plain RTMP
+    pub tls: bool,
+}
+
+impl ListenSpec {
+    /// Plain RTMP on `host`:`port`
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        ListenSpec {
+            host: host.into(),
+            port,
+            tls: false,
+        }
+    }
+
+    /// Accept TLS on this address, using the configured certificate
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct ServerConfig {
@@ -9,19 +41,31 @@ pub struct ServerConfig {
     /// Port to bind
     pub port: u16,
 
+    /// Addresses to accept on; when empty, `host`:`port` alone is used
+    pub listen: Vec<ListenSpec>,
+
     /// Maximum connections
     pub max_connections: usize,
 
     /// Maximum connections per IP
     pub max_connections_per_ip: usize,
 
-    /// Chunk size
+    /// New connections each IP may open per second; unlimited when unset
+    pub connect_rate_per_ip: Option<f64>,
+
+    /// Connections an IP may open at once before `connect_rate_per_ip` applies
+    pub connect_burst_per_ip: u32,
+
+    /// Maximum streams each connection may create with `createStream`
+    pub max_streams_per_connection: usize,
+
+    /// Chunk size announced at connect and used for writing to clients
     pub chunk_size: u32,
 
-    /// Window acknowledgement size
+    /// Window acknowledgement size announced at connect
     pub window_ack_size: u32,
 
-    /// Peer bandwidth
+    /// Peer bandwidth announced at connect
     pub peer_bandwidth: u32,
 
     /// Ping interval
@@ -30,6 +74,10 @@ pub struct ServerConfig {
     /// Timeout for idle connections
     pub idle_timeout: Duration,
 
+    /// Longest wait for the next chunk (or handshake bytes) before a peer
+    /// is considered dead and dropped
+    pub read_timeout: Duration,
+
     /// GOP cache size
     pub gop_cache_size: usize,
 
@@ -41,6 +89,41 @@ pub struct ServerConfig {
 
     /// Allow playing
     pub allow_play: bool,
+
+    /// PEM certificate chain for accepting TLS (rtmps) connections
+    pub tls_cert_path: Option<PathBuf>,
+
+    /// PEM private key matching `tls_cert_path`
+    pub tls_key_path: Option<PathBuf>,
+
+    /// Connect authorization hook; every connection is allowed when unset
+    pub connect_auth: Option<ConnectAuth>,
+
+    /// Publish authorization hook; any stream name may be published when unset
+    pub publish_auth: Option<PublishAuth>,
+
+    /// Directory of recorded `<stream>.flv` files
+    pub vod_root: Option<PathBuf>,
+
+    /// How much cached media a new player starts with
+    pub join_mode: JoinMode,
+
+    /// What happens when a live stream name is published again
+    pub republish_policy: RepublishPolicy,
+
+    /// Cap on the bits per second sent to each player; unlimited when unset
+    pub max_send_bitrate: Option<u64>,
+
+    /// Run an `onBWCheck`/`onBWDone` bandwidth check after each connect
+    pub bandwidth_check: bool,
+
+    /// Unpublish a stream that receives no audio or video for this long;
+    /// streams never time out when unset
+    pub publish_idle_timeout: Option<Duration>,
+
+    /// Set `SO_REUSEPORT` on listening sockets so several processes can
+    /// share a port
+    pub reuse_port: bool,
 }
 
 impl Default for ServerConfig {
@@ -48,17 +131,33 @@ impl Default for ServerConfig {
         ServerConfig {
             host: "0.0.0.0".to_string(),
             port: 1935,
+            listen: Vec::new(),
             max_connections: 1000,
             max_connections_per_ip: 10,
+            connect_rate_per_ip: None,
+            connect_burst_per_ip: 10,
+            max_streams_per_connection: 16,
             chunk_size: 4096,
             window_ack_size: 2500000,
             peer_bandwidth: 2500000,
             ping_interval: Duration::from_secs(60),
             idle_timeout: Duration::from_secs(300),
+            read_timeout: Duration::from_secs(60),
             gop_cache_size: 10,
             gop_cache_enabled: true,
             allow_publish: true,
             allow_play: true,
+            tls_cert_path: None,
+            tls_key_path: None,
+            connect_auth: None,
+            publish_auth: None,
+            vod_root: None,
+            join_mode: JoinMode::Reliable,
+            republish_policy: RepublishPolicy::Reject,
+            max_send_bitrate: None,
+            bandwidth_check: false,
+            publish_idle_timeout: None,
+            reuse_port: false,
         }
     }
 }
@@ -75,10 +174,37 @@ impl ServerConfig {
             return Err(Error::config("Invalid port: 0"));
         }
 
+        for spec in &self.listen {
+            if spec.port == 0 {
+                return Err(Error::config(format!("Invalid port for {}: 0", spec.host)));
+            }
+
+            if spec.tls && !self.tls_enabled() {
+                return Err(Error::config(format!(
+                    "TLS listener {}:{} requires a certificate and a private key",
+                    spec.host, spec.port
+                )));
+            }
+        }
+
         if self.max_connections == 0 {
             return Err(Error::config("Invalid max_connections: 0"));
         }
 
+        if let Some(rate) = self.connect_rate_per_ip
+            && !(rate.is_finite() && rate > 0.0)
+        {
+            return Err(Error::config(format!("Invalid connect_rate_per_ip: {}", rate)));
+        }
+
+        if self.connect_burst_per_ip == 0 {
+            return Err(Error::config("Invalid connect_burst_per_ip: 0"));
+        }
+
+        if self.max_streams_per_connection == 0 {
+            return Err(Error::config("Invalid max_streams_per_connection: 0"));
+        }
+
         if self.chunk_size < 128 {
             return Err(Error::config("Chunk size must be at least 128"));
         }
@@ -87,8 +213,57 @@ impl ServerConfig {
             return Err(Error::config("Chunk size must not exceed 65536"));
         }
 
+        if self.window_ack_size == 0 {
+            return Err(Error::config("Invalid window_ack_size: 0"));
+        }
+
+        if self.peer_bandwidth == 0 {
+            return Err(Error::config("Invalid peer_bandwidth: 0"));
+        }
+
+        if self.max_send_bitrate == Some(0) {
+            return Err(Error::config("Invalid max_send_bitrate: 0"));
+        }
+
+        if self.publish_idle_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            return Err(Error::config("Invalid publish_idle_timeout: 0"));
+        }
+
+        if self.read_timeout.is_zero() {
+            return Err(Error::config("Invalid read_timeout: 0"));
+        }
+
+        if self.tls_cert_path.is_some() != self.tls_key_path.is_some() {
+            return Err(Error::config("TLS requires both a certificate and a private key"));
+        }
+
+        #[cfg(not(feature = "tls"))]
+        if self.tls_enabled() {
+            return Err(Error::config("TLS requires the `tls` feature"));
+        }
+
+        #[cfg(not(unix))]
+        if self.reuse_port {
+            return Err(Error::config("reuse_port is only supported on Unix"));
+        }
+
         Ok(())
     }
+
+    /// Check if connections are accepted over TLS
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert_path.is_some() && self.tls_key_path.is_some()
+    }
+
+    /// Addresses `listen` binds: the `listen` list, or `host`:`port`
+    /// (over TLS when configured) if the list is empty
+    pub fn listen_specs(&self) -> Vec<ListenSpec> {
+        if !self.listen.is_empty() {
+            return self.listen.clone();
+        }
+
+        vec![ListenSpec::new(self.host.clone(), self.port).with_tls(self.tls_enabled())]
+    }
 }
 
 /// Builder for ServerConfig
@@ -116,18 +291,129 @@ impl ServerConfigBuilder {
         self
     }
 
+    /// Accept connections on another address; once any is added, `host`
+    /// and `port` are no longer bound
+    pub fn listen_on(mut self, spec: ListenSpec) -> Self {
+        self.config.listen.push(spec);
+        self
+    }
+
     /// Set max connections
     pub fn max_connections(mut self, max: usize) -> Self {
         self.config.max_connections = max;
         self
     }
 
+    /// Limit each IP to `per_second` new connections, after an initial `burst`
+    pub fn connect_rate_limit(mut self, per_second: f64, burst: u32) -> Self {
+        self.config.connect_rate_per_ip = Some(per_second);
+        self.config.connect_burst_per_ip = burst;
+        self
+    }
+
+    /// Set max streams per connection
+    pub fn max_streams_per_connection(mut self, max: usize) -> Self {
+        self.config.max_streams_per_connection = max;
+        self
+    }
+
     /// Set chunk size
     pub fn chunk_size(mut self, size: u32) -> Self {
         self.config.chunk_size = size;
         self
     }
 
+    /// Set the window acknowledgement size announced to clients
+    pub fn window_ack_size(mut self, size: u32) -> Self {
+        self.config.window_ack_size = size;
+        self
+    }
+
+    /// Set the peer bandwidth announced to clients
+    pub fn peer_bandwidth(mut self, bandwidth: u32) -> Self {
+        self.config.peer_bandwidth = bandwidth;
+        self
+    }
+
+    /// Set read timeout
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.read_timeout = timeout;
+        self
+    }
+
+    /// Set TLS certificate chain (PEM)
+    pub fn tls_cert_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.tls_cert_path = Some(path.into());
+        self
+    }
+
+    /// Set TLS private key (PEM)
+    pub fn tls_key_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.tls_key_path = Some(path.into());
+        self
+    }
+
+    /// Look up recorded streams as `<dir>/<stream>.flv`
+    pub fn vod_root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.config.vod_root = Some(dir.into());
+        self
+    }
+
+    /// Set how much cached media a new player starts with
+    pub fn join_mode(mut self, mode: JoinMode) -> Self {
+        self.config.join_mode = mode;
+        self
+    }
+
+    /// Set what happens when a live stream name is published again
+    pub fn republish_policy(mut self, policy: RepublishPolicy) -> Self {
+        self.config.republish_policy = policy;
+        self
+    }
+
+    /// Cap the bits per second sent to each player
+    pub fn max_send_bitrate(mut self, bits_per_second: u64) -> Self {
+        self.config.max_send_bitrate = Some(bits_per_second);
+        self
+    }
+
+    /// Run a bandwidth check after connect, as FMLE-style encoders expect
+    pub fn bandwidth_check(mut self, enabled: bool) -> Self {
+        self.config.bandwidth_check = enabled;
+        self
+    }
+
+    /// Unpublish streams whose source stops sending media for `timeout`
+    pub fn publish_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.config.publish_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Let other processes bind the same port, with the kernel balancing
+    /// connections between them; Unix only
+    pub fn reuse_port(mut self, enabled: bool) -> Self {
+        self.config.reuse_port = enabled;
+        self
+    }
+
+    /// Set a hook deciding which clients may connect
+    pub fn connect_auth<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&ConnectParams) -> AuthFuture + Send + Sync + 'static,
+    {
+        self.config.connect_auth = Some(ConnectAuth::new(callback));
+        self
+    }
+
+    /// Set a hook deciding which stream names (keys) may be published
+    pub fn publish_auth<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&PublishParams) -> AuthFuture + Send + Sync + 'static,
+    {
+        self.config.publish_auth = Some(PublishAuth::new(callback));
+        self
+    }
+
     /// Build configuration
     pub fn build(self) -> Result<ServerConfig> {
         self.config.validate()?;
@@ -1,12 +1,32 @@
 use crate::{Error, Result};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::Instant;
 use std::collections::HashMap;
 use std::net::IpAddr;
 use crate::server::config::ServerConfig;
+use crate::server::events::EventListeners;
 use crate::server::registry::PublisherRegistry;
 
+/// Buckets kept before idle, full ones are pruned
+const MAX_IDLE_RATE_BUCKETS: usize = 1024;
+
+/// Connect allowance of one IP
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Top up for the time since the last update, capped at `burst`
+    fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.updated = now;
+    }
+}
+
 pub struct ServerContext {
     /// Server configuration
     config: Arc<ServerConfig>,
@@ -19,16 +39,30 @@ pub struct ServerContext {
 
     /// IP connection counts
     ip_counts: Arc<RwLock<HashMap<IpAddr, usize>>>,
+
+    /// Connect rate allowance per IP
+    ip_rate_buckets: Arc<Mutex<HashMap<IpAddr, TokenBucket>>>,
+
+    /// Lifecycle event listeners
+    events: Arc<EventListeners>,
 }
 
 impl ServerContext {
     /// Create new context
     pub fn new(config: Arc<ServerConfig>) -> Self {
+        let gop_cache_size = if config.gop_cache_enabled { config.gop_cache_size } else { 0 };
+
+        let publishers = PublisherRegistry::with_gop_cache_size(gop_cache_size)
+            .with_republish_policy(config.republish_policy)
+            .with_publish_idle_timeout(config.publish_idle_timeout);
+
         ServerContext {
             config,
-            publishers: Arc::new(PublisherRegistry::new()),
+            publishers: Arc::new(publishers),
             connection_counter: AtomicU64::new(0),
             ip_counts: Arc::new(RwLock::new(HashMap::new())),
+            ip_rate_buckets: Arc::new(Mutex::new(HashMap::new())),
+            events: Arc::new(EventListeners::new()),
         }
     }
 
@@ -42,6 +76,11 @@ impl ServerContext {
         self.publishers.clone()
     }
 
+    /// Get lifecycle event listeners
+    pub fn events(&self) -> Arc<EventListeners> {
+        self.events.clone()
+    }
+
     /// Generate unique connection ID
     pub fn generate_connection_id(&self) -> String {
         let id = self.connection_counter.fetch_add(1, Ordering::SeqCst);
@@ -55,12 +94,47 @@ impl ServerContext {
         count < self.config.max_connections_per_ip
     }
 
+    /// Take one connect from the IP's rate allowance
+    ///
+    /// Always true without `connect_rate_per_ip`; otherwise false once the
+    /// IP has used its burst faster than the rate refills it.
+    pub async fn allow_connect_from_ip(&self, ip: IpAddr) -> bool {
+        let Some(rate) = self.config.connect_rate_per_ip else {
+            return true;
+        };
+        let burst = self.config.connect_burst_per_ip as f64;
+        let now = Instant::now();
+
+        let mut buckets = self.ip_rate_buckets.lock().await;
+        if buckets.len() >= MAX_IDLE_RATE_BUCKETS {
+            // A full bucket behaves like a missing one
+            buckets.retain(|_, bucket| {
+                bucket.refill(now, rate, burst);
+                bucket.tokens < burst
+            });
+        }
+
+        let bucket = buckets.entry(ip).or_insert(TokenBucket { tokens: burst, updated: now });
+        bucket.refill(now, rate, burst);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+
+        bucket.tokens -= 1.0;
+        true
+    }
+
     /// Increment IP connection count
     pub async fn increment_ip_count(&self, ip: IpAddr) {
         let mut counts = self.ip_counts.write().await;
         *counts.entry(ip).or_insert(0) += 1;
     }
 
+    /// Connections currently open from an IP
+    pub async fn ip_count(&self, ip: IpAddr) -> usize {
+        self.ip_counts.read().await.get(&ip).copied().unwrap_or(0)
+    }
+
     /// Decrement IP connection count
     pub async fn decrement_ip_count(&self, ip: IpAddr) {
         let mut counts = self.ip_counts.write().await;
@@ -0,0 +1,70 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Callbacks for connection and stream lifecycle events
+///
+/// Every method has an empty default, so a listener implements only the
+/// events it cares about.
+#[async_trait::async_trait]
+pub trait EventListener: Send + Sync {
+    /// A client's `connect` was accepted; `peer_addr` is the client's
+    /// address, when known
+    async fn on_connect(&self, _conn_id: &str, _app: &str, _peer_addr: Option<SocketAddr>) {}
+
+    /// A connection started publishing a stream
+    async fn on_publish(&self, _stream_name: &str, _conn_id: &str) {}
+
+    /// A connection started playing a stream
+    async fn on_play(&self, _stream_name: &str, _conn_id: &str) {}
+
+    /// A connection closed and its streams were released
+    async fn on_disconnect(&self, _conn_id: &str) {}
+}
+
+/// Listeners registered on a server, notified in registration order
+#[derive(Default)]
+pub struct EventListeners {
+    listeners: RwLock<Vec<Arc<dyn EventListener>>>,
+}
+
+impl EventListeners {
+    /// Create an empty listener list
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a listener
+    pub async fn add(&self, listener: Arc<dyn EventListener>) {
+        self.listeners.write().await.push(listener);
+    }
+
+    pub(crate) async fn notify_connect(&self, conn_id: &str, app: &str, peer_addr: Option<SocketAddr>) {
+        for listener in self.snapshot().await {
+            listener.on_connect(conn_id, app, peer_addr).await;
+        }
+    }
+
+    pub(crate) async fn notify_publish(&self, stream_name: &str, conn_id: &str) {
+        for listener in self.snapshot().await {
+            listener.on_publish(stream_name, conn_id).await;
+        }
+    }
+
+    pub(crate) async fn notify_play(&self, stream_name: &str, conn_id: &str) {
+        for listener in self.snapshot().await {
+            listener.on_play(stream_name, conn_id).await;
+        }
+    }
+
+    pub(crate) async fn notify_disconnect(&self, conn_id: &str) {
+        for listener in self.snapshot().await {
+            listener.on_disconnect(conn_id).await;
+        }
+    }
+
+    /// Copy the list so callbacks run without holding the lock
+    async fn snapshot(&self) -> Vec<Arc<dyn EventListener>> {
+        self.listeners.read().await.clone()
+    }
+}
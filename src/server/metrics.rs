@@ -0,0 +1,90 @@
+use std::fmt::Write;
+use crate::server::registry::StreamMetrics;
+
+/// Reads one per-stream value
+type StreamValue = fn(&StreamMetrics) -> u64;
+
+/// Point-in-time server metrics, see `RtmpServer::metrics_snapshot`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ServerMetrics {
+    /// Active connections
+    pub connections: usize,
+
+    /// Published streams
+    pub streams: Vec<StreamMetrics>,
+}
+
+impl ServerMetrics {
+    /// Render in the Prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        write_metric(&mut out, "rtmp_connections", "gauge", "Active connections");
+        let _ = writeln!(out, "rtmp_connections {}", self.connections);
+
+        write_metric(&mut out, "rtmp_streams", "gauge", "Published streams");
+        let _ = writeln!(out, "rtmp_streams {}", self.streams.len());
+
+        let per_stream: [(&str, &str, &str, StreamValue); 4] = [
+            ("rtmp_stream_bytes_in_total", "counter", "Payload bytes received from the publisher", |m| m.stats.bytes_in),
+            ("rtmp_stream_video_packets_total", "counter", "Video packets received from the publisher", |m| m.stats.video_packets),
+            ("rtmp_stream_audio_packets_total", "counter", "Audio packets received from the publisher", |m| m.stats.audio_packets),
+            ("rtmp_stream_subscribers", "gauge", "Subscribers playing the stream", |m| m.subscribers as u64),
+        ];
+
+        for (name, kind, help, value) in per_stream {
+            write_metric(&mut out, name, kind, help);
+            for stream in &self.streams {
+                let _ = writeln!(
+                    out,
+                    "{}{{stream=\"{}\"}} {}",
+                    name,
+                    escape_label(&stream.stream_name),
+                    value(stream)
+                );
+            }
+        }
+
+        out
+    }
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value per the exposition format
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{RtmpServer, ServerConfig};
+
+    #[tokio::test]
+    async fn test_metrics_after_publish() {
+        let server = RtmpServer::new(ServerConfig::default());
+        let registry = server.context().publishers();
+        registry.register("live".to_string(), "conn-1".to_string(), 1, None).await.unwrap();
+
+        let publisher = registry.get("live").await.unwrap().publisher;
+        publisher.process_video(crate::make_video_packet(vec![0x17, 0x01, 0x00, 0x00], 0, 1)).await.unwrap();
+        publisher.process_audio(crate::make_audio_packet(vec![0xAF, 0x01], 0, 1)).await.unwrap();
+
+        let metrics = server.metrics_snapshot().await;
+        assert_eq!(metrics.connections, 0);
+        assert_eq!(metrics.streams.len(), 1);
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("rtmp_connections 0\n"));
+        assert!(text.contains("rtmp_streams 1\n"));
+        assert!(text.contains("rtmp_stream_bytes_in_total{stream=\"live\"} 6\n"));
+        assert!(text.contains("rtmp_stream_video_packets_total{stream=\"live\"} 1\n"));
+        assert!(text.contains("rtmp_stream_audio_packets_total{stream=\"live\"} 1\n"));
+        assert!(text.contains("rtmp_stream_subscribers{stream=\"live\"} 0\n"));
+        assert!(text.contains("# TYPE rtmp_stream_bytes_in_total counter\n"));
+    }
+}
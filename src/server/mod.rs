@@ -5,18 +5,47 @@ mod server;
 mod config;
 mod context;
 mod registry;
+mod auth;
+mod events;
+mod metrics;
+mod rtmpt;
+#[cfg(feature = "admin")]
+mod admin;
+#[cfg(feature = "tls")]
+mod tls;
 
 pub use server::RtmpServer;
-pub use config::{ServerConfig, ServerConfigBuilder};
+pub use config::{ListenSpec, ServerConfig, ServerConfigBuilder};
 pub use context::ServerContext;
 pub use registry::*;
+pub use metrics::ServerMetrics;
+pub use rtmpt::RtmptListener;
+#[cfg(feature = "admin")]
+pub use admin::AdminListener;
+pub use auth::{AuthFuture, ConnectAuth, ConnectParams, PublishAuth, PublishParams};
+pub use events::{EventListener, EventListeners};
 
 
 pub async fn bind_server(config: &config::ServerConfig) -> Result<TcpListener> {
-    let addr = format!("{}:{}", config.host, config.port);
+    bind_address(&config.host, config.port, config.reuse_port).await
+}
+
+/// Bind `host`:`port`, resolving host names to their first address
+///
+/// With `reuse_port`, other sockets that also set `SO_REUSEPORT` may bind
+/// the same address and the kernel spreads connections between them.
+pub async fn bind_address(host: &str, port: u16, reuse_port: bool) -> Result<TcpListener> {
+    let addr = format!("{}:{}", host, port);
+    let resolved = match addr.parse::<std::net::SocketAddr>() {
+        Ok(addr) => Ok(addr),
+        Err(e) => match tokio::net::lookup_host(&addr).await.map(|mut addrs| addrs.next()) {
+            Ok(Some(addr)) => Ok(addr),
+            _ => Err(e),
+        },
+    };
 
     // Try binding with SO_REUSEADDR
-    let socket = match &addr.parse::<std::net::SocketAddr>() {
+    let socket = match &resolved {
         Ok(addr) => {
             let socket = if addr.is_ipv4() {
                 tokio::net::TcpSocket::new_v4()?
@@ -25,6 +54,9 @@ pub async fn bind_server(config: &config::ServerConfig) -> Result<TcpListener> {
             };
 
             socket.set_reuseaddr(true)?;
+            if reuse_port {
+                set_reuseport(&socket)?;
+            }
             socket.bind(*addr)?;
             socket
         }
@@ -35,4 +67,52 @@ pub async fn bind_server(config: &config::ServerConfig) -> Result<TcpListener> {
 
     let listener = socket.listen(1024)?;
     Ok(listener)
-}
\ No newline at end of file
+}
+
+#[cfg(all(
+    unix,
+    not(target_os = "solaris"),
+    not(target_os = "illumos"),
+    not(target_os = "cygwin"),
+    not(target_os = "nuttx"),
+))]
+fn set_reuseport(socket: &tokio::net::TcpSocket) -> Result<()> {
+    Ok(socket.set_reuseport(true)?)
+}
+
+#[cfg(not(all(
+    unix,
+    not(target_os = "solaris"),
+    not(target_os = "illumos"),
+    not(target_os = "cygwin"),
+    not(target_os = "nuttx"),
+)))]
+fn set_reuseport(_socket: &tokio::net::TcpSocket) -> Result<()> {
+    Err(Error::config("SO_REUSEPORT is not supported on this platform"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_reuse_port_allows_shared_bind() {
+        let first = bind_address("127.0.0.1", 0, true).await.unwrap();
+        let port = first.local_addr().unwrap().port();
+
+        let second = bind_address("127.0.0.1", port, true).await.unwrap();
+        assert_eq!(second.local_addr().unwrap().port(), port);
+
+        let config = ServerConfig::builder().host("127.0.0.1").port(port).reuse_port(true).build().unwrap();
+        assert!(bind_server(&config).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_port_is_exclusive_without_reuse_port() {
+        let first = bind_address("127.0.0.1", 0, false).await.unwrap();
+        let port = first.local_addr().unwrap().port();
+
+        assert!(bind_address("127.0.0.1", port, false).await.is_err());
+    }
+}
@@ -1,7 +1,47 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::{Error, Result};
+use std::time::Duration;
+use log::warn;
+use tokio::sync::{broadcast, RwLock};
+use crate::{CloseHandle, Error, Publisher, Result, StreamStats};
+
+/// Default number of GOPs cached per published stream
+pub const DEFAULT_GOP_CACHE_SIZE: usize = 1;
+
+/// Registry events buffered for each subscriber before it starts lagging
+const REGISTRY_EVENT_CAPACITY: usize = 64;
+
+/// A stream appearing in or leaving the registry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryEvent {
+    /// A stream name started being published
+    Published(String),
+
+    /// A stream name stopped being published
+    Unpublished(String),
+}
+
+/// What happens when a stream name that is already live is published again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepublishPolicy {
+    /// Refuse the new publisher
+    #[default]
+    Reject,
+
+    /// Close the current publisher and hand the stream to the new one;
+    /// viewers stay subscribed
+    Replace,
+}
+
+/// Splits a stream name into its base name and rendition suffix,
+/// or `None` if the name is not a rendition
+pub type RenditionParser = Arc<dyn Fn(&str) -> Option<(&str, &str)> + Send + Sync>;
+
+/// Default rendition parser: the suffix after the last `_`, as in `stream_720p`
+fn split_rendition(stream_name: &str) -> Option<(&str, &str)> {
+    stream_name.rsplit_once('_')
+        .filter(|(base, suffix)| !base.is_empty() && !suffix.is_empty())
+}
 
 #[derive(Clone)]
 pub struct PublisherInfo {
@@ -20,51 +60,180 @@ pub struct PublisherInfo {
     /// Metadata
     pub metadata: Option<HashMap<String, crate::amf::Amf0Value>>,
 
-    /// Subscriber count
-    pub subscriber_count: Arc<RwLock<usize>>,
+    /// Media fan-out for the stream, and its subscribers
+    pub publisher: Arc<Publisher>,
+
+    /// Closes the publishing connection, if it can be closed
+    pub close_handle: Option<CloseHandle>,
+}
+
+/// Point-in-time metrics for one published stream
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StreamMetrics {
+    /// Stream name
+    pub stream_name: String,
+
+    /// Connected subscribers
+    pub subscribers: usize,
+
+    /// Traffic counters
+    pub stats: StreamStats,
 }
 
 pub struct PublisherRegistry {
     /// Publishers by stream name
     publishers: Arc<RwLock<HashMap<String, PublisherInfo>>>,
+
+    /// GOPs cached for each new publisher
+    gop_cache_size: usize,
+
+    /// What to do when a live stream name is published again
+    republish_policy: RepublishPolicy,
+
+    /// Groups stream names into renditions of a base name
+    rendition_parser: RenditionParser,
+
+    /// Silence after which a stream is unpublished
+    publish_idle_timeout: Option<Duration>,
+
+    /// Announces streams being published and unpublished
+    events: broadcast::Sender<RegistryEvent>,
 }
 
 impl PublisherRegistry {
     /// Create new registry
     pub fn new() -> Self {
+        PublisherRegistry::with_gop_cache_size(DEFAULT_GOP_CACHE_SIZE)
+    }
+
+    /// Create registry whose publishers cache `gop_cache_size` GOPs
+    pub fn with_gop_cache_size(gop_cache_size: usize) -> Self {
         PublisherRegistry {
             publishers: Arc::new(RwLock::new(HashMap::new())),
+            gop_cache_size,
+            republish_policy: RepublishPolicy::Reject,
+            rendition_parser: Arc::new(split_rendition),
+            publish_idle_timeout: None,
+            events: broadcast::channel(REGISTRY_EVENT_CAPACITY).0,
         }
     }
 
+    /// Set what happens when a live stream name is published again
+    pub fn with_republish_policy(mut self, policy: RepublishPolicy) -> Self {
+        self.republish_policy = policy;
+        self
+    }
+
+    /// Unpublish streams that receive no media for `timeout`, if set
+    pub fn with_publish_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.publish_idle_timeout = timeout;
+        self
+    }
+
+    /// Set how stream names are split into base name and rendition
+    pub fn with_rendition_parser<F>(mut self, parser: F) -> Self
+    where
+        F: Fn(&str) -> Option<(&str, &str)> + Send + Sync + 'static,
+    {
+        self.rendition_parser = Arc::new(parser);
+        self
+    }
+
+    /// Receive an event whenever a stream is published or unpublished
+    ///
+    /// A stream handed to a new publisher under `RepublishPolicy::Replace`
+    /// stays published and raises no events.
+    pub fn subscribe(&self) -> broadcast::Receiver<RegistryEvent> {
+        self.events.subscribe()
+    }
+
+    /// Get the republish policy
+    pub fn republish_policy(&self) -> RepublishPolicy {
+        self.republish_policy
+    }
+
     /// Register publisher
+    ///
+    /// If the name is already live, the republish policy decides: `Reject`
+    /// fails, `Replace` hands the existing stream and its subscribers to the
+    /// new connection and returns the displaced publisher.
     pub async fn register(
         &self,
         stream_name: String,
         connection_id: String,
         stream_id: u32,
-    ) -> Result<()> {
+        close_handle: Option<CloseHandle>,
+    ) -> Result<Option<PublisherInfo>> {
         let mut publishers = self.publishers.write().await;
 
         // Check if already publishing
-        if publishers.contains_key(&stream_name) {
-            return Err(Error::stream(format!(
-                "Stream '{}' is already being published",
-                stream_name
-            )));
+        if let Some(current) = publishers.get_mut(&stream_name) {
+            if self.republish_policy == RepublishPolicy::Reject {
+                return Err(Error::stream(format!(
+                    "Stream '{}' is already being published",
+                    stream_name
+                )));
+            }
+
+            let displaced = current.clone();
+            current.connection_id = connection_id;
+            current.stream_id = stream_id;
+            current.started_at = crate::utils::current_timestamp();
+            current.metadata = None;
+            current.close_handle = close_handle;
+            current.publisher.reset_source().await;
+
+            return Ok(Some(displaced));
         }
 
         // Add publisher
+        let publisher = Arc::new(Publisher::live(stream_id, stream_name.clone(), self.gop_cache_size));
+        publisher.start().await;
+        if let Some(timeout) = self.publish_idle_timeout {
+            self.spawn_idle_watchdog(stream_name.clone(), publisher.clone(), timeout);
+        }
         publishers.insert(stream_name.clone(), PublisherInfo {
             connection_id,
-            stream_name,
+            stream_name: stream_name.clone(),
             stream_id,
             started_at: crate::utils::current_timestamp(),
             metadata: None,
-            subscriber_count: Arc::new(RwLock::new(0)),
+            publisher,
+            close_handle,
         });
+        let _ = self.events.send(RegistryEvent::Published(stream_name));
 
-        Ok(())
+        Ok(None)
+    }
+
+    /// Unpublish `stream_name` once `publisher` goes silent for `timeout`
+    ///
+    /// Viewers are told the stream stopped and the publishing connection is
+    /// closed. A stream unpublished or handed to another publisher first is
+    /// left alone.
+    fn spawn_idle_watchdog(&self, stream_name: String, publisher: Arc<Publisher>, timeout: Duration) {
+        let publishers = self.publishers.clone();
+        let events = self.events.clone();
+        tokio::spawn(async move {
+            publisher.wait_for_silence(timeout).await;
+
+            let removed = {
+                let mut publishers = publishers.write().await;
+                let current = publishers.get(&stream_name)
+                    .is_some_and(|info| Arc::ptr_eq(&info.publisher, &publisher));
+                if current { publishers.remove(&stream_name) } else { None }
+            };
+
+            if let Some(info) = removed {
+                warn!(conn_id:% = info.connection_id, stream:% = stream_name; "No media for {:?}, unpublishing", timeout);
+                let _ = events.send(RegistryEvent::Unpublished(stream_name));
+                crate::handlers::end_publishing(&info).await;
+                if let Some(close_handle) = &info.close_handle {
+                    close_handle.close();
+                }
+            }
+        });
     }
 
     /// Unregister publisher
@@ -72,9 +241,25 @@ impl PublisherRegistry {
         let mut publishers = self.publishers.write().await;
         publishers.remove(stream_name)
             .ok_or_else(|| Error::stream(format!("Stream '{}' not found", stream_name)))?;
+        let _ = self.events.send(RegistryEvent::Unpublished(stream_name.to_string()));
         Ok(())
     }
 
+    /// Unregister every stream a connection publishes, returning them
+    pub async fn unregister_connection(&self, connection_id: &str) -> Vec<PublisherInfo> {
+        let mut publishers = self.publishers.write().await;
+        let owned: Vec<String> = publishers.iter()
+            .filter(|(_, info)| info.connection_id == connection_id)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let removed: Vec<PublisherInfo> = owned.iter().filter_map(|name| publishers.remove(name)).collect();
+        for info in &removed {
+            let _ = self.events.send(RegistryEvent::Unpublished(info.stream_name.clone()));
+        }
+        removed
+    }
+
     /// Get publisher info
     pub async fn get(&self, stream_name: &str) -> Option<PublisherInfo> {
         let publishers = self.publishers.read().await;
@@ -106,25 +291,139 @@ impl PublisherRegistry {
         publishers.values().cloned().collect()
     }
 
-    /// Increment subscriber count
-    pub async fn increment_subscribers(&self, stream_name: &str) -> Result<()> {
-        let publishers = self.publishers.read().await;
-        let publisher = publishers.get(stream_name)
-            .ok_or_else(|| Error::stream(format!("Stream '{}' not found", stream_name)))?;
+    /// Published renditions of `base`, lowest bitrate first
+    ///
+    /// Bitrates come from the `videodatarate` and `audiodatarate` of each
+    /// stream's `onMetaData`; renditions without metadata count as zero.
+    pub async fn get_renditions(&self, base: &str) -> Vec<PublisherInfo> {
+        let renditions: Vec<PublisherInfo> = self.publishers.read().await.values()
+            .filter(|info| {
+                (self.rendition_parser)(&info.stream_name).is_some_and(|(name, _)| name == base)
+            })
+            .cloned()
+            .collect();
 
-        let mut count = publisher.subscriber_count.write().await;
-        *count += 1;
-        Ok(())
+        let mut by_bitrate = Vec::with_capacity(renditions.len());
+        for info in renditions {
+            by_bitrate.push((metadata_bitrate(&info).await, info));
+        }
+
+        by_bitrate.sort_by(|(a_bitrate, a), (b_bitrate, b)| {
+            a_bitrate.total_cmp(b_bitrate)
+                .then_with(|| a.stream_name.cmp(&b.stream_name))
+        });
+        by_bitrate.into_iter().map(|(_, info)| info).collect()
     }
 
-    /// Decrement subscriber count
-    pub async fn decrement_subscribers(&self, stream_name: &str) -> Result<()> {
-        let publishers = self.publishers.read().await;
-        let publisher = publishers.get(stream_name)
-            .ok_or_else(|| Error::stream(format!("Stream '{}' not found", stream_name)))?;
+    /// Collect metrics for every published stream, sorted by name
+    pub async fn stream_metrics(&self) -> Vec<StreamMetrics> {
+        let publishers = self.get_all().await;
 
-        let mut count = publisher.subscriber_count.write().await;
-        *count = count.saturating_sub(1);
-        Ok(())
+        let mut metrics = Vec::with_capacity(publishers.len());
+        for info in publishers {
+            metrics.push(StreamMetrics {
+                subscribers: info.publisher.subscriber_count().await,
+                stats: info.publisher.stats().await,
+                stream_name: info.stream_name,
+            });
+        }
+
+        metrics.sort_by(|a, b| a.stream_name.cmp(&b.stream_name));
+        metrics
     }
-}
\ No newline at end of file
+}
+
+/// Combined video and audio bitrate announced in a stream's metadata
+async fn metadata_bitrate(info: &PublisherInfo) -> f64 {
+    let Some(metadata) = info.publisher.stream().info().await.metadata else {
+        return 0.0;
+    };
+
+    metadata.video_bitrate.unwrap_or(0.0) + metadata.audio_bitrate.unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amf::Amf0Value;
+
+    async fn publish_rendition(registry: &PublisherRegistry, name: &str, video_kbps: f64) {
+        registry.register(name.to_string(), format!("conn-{}", name), 1, None).await.unwrap();
+
+        let mut metadata = HashMap::new();
+        metadata.insert("videodatarate".to_string(), Amf0Value::Number(video_kbps));
+        metadata.insert("audiodatarate".to_string(), Amf0Value::Number(128.0));
+        let bytes = crate::RtmpData::on_metadata(metadata).encode().unwrap();
+        let packet = crate::RtmpPacket::new(crate::RtmpHeader::data(0, bytes.len() as u32, 1), bytes);
+        registry.get(name).await.unwrap().publisher.ingest(packet).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_renditions_sorted_by_bitrate() {
+        let registry = PublisherRegistry::new();
+        publish_rendition(&registry, "stream_720p", 2500.0).await;
+        publish_rendition(&registry, "stream_1080p", 4500.0).await;
+        publish_rendition(&registry, "stream_480p", 1000.0).await;
+        publish_rendition(&registry, "other_480p", 1000.0).await;
+
+        let names: Vec<String> = registry.get_renditions("stream").await
+            .into_iter()
+            .map(|info| info.stream_name)
+            .collect();
+        assert_eq!(names, ["stream_480p", "stream_720p", "stream_1080p"]);
+        assert!(registry.get_renditions("stream_720p").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_hear_publish_and_unpublish() {
+        let registry = PublisherRegistry::new();
+        let mut events = registry.subscribe();
+
+        registry.register("live".to_string(), "conn-1".to_string(), 1, None).await.unwrap();
+        assert_eq!(events.recv().await.unwrap(), RegistryEvent::Published("live".to_string()));
+
+        registry.unregister_connection("conn-1").await;
+        assert_eq!(events.recv().await.unwrap(), RegistryEvent::Unpublished("live".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_custom_rendition_parser() {
+        let registry = PublisherRegistry::new()
+            .with_rendition_parser(|name| name.split_once('@'));
+        publish_rendition(&registry, "stream@high", 2500.0).await;
+        publish_rendition(&registry, "stream_low", 1000.0).await;
+
+        let renditions = registry.get_renditions("stream").await;
+        assert_eq!(renditions.len(), 1);
+        assert_eq!(renditions[0].stream_name, "stream@high");
+    }
+
+    #[tokio::test]
+    async fn test_silent_publisher_is_unpublished() {
+        let registry = PublisherRegistry::new()
+            .with_publish_idle_timeout(Some(Duration::from_millis(100)));
+        registry.register("live".to_string(), "conn-1".to_string(), 1, None).await.unwrap();
+        let publisher = registry.get("live").await.unwrap().publisher;
+        let mut viewer = publisher.add_subscriber("conn-2".to_string(), 1).await;
+
+        // Media arriving within the timeout keeps the stream up
+        for i in 0..5 {
+            let audio = crate::protocol::make_audio_packet(vec![0xAF, 0x01, 0x21], i * 40, 1);
+            publisher.process_audio(audio).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(40)).await;
+        }
+        assert!(registry.is_publishing("live").await);
+
+        // Then the encoder freezes
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(!registry.is_publishing("live").await);
+        assert_eq!(publisher.stream().state(), crate::StreamState::Ended);
+
+        let mut last = None;
+        while let Some(packet) = viewer.recv().await {
+            last = Some(packet);
+        }
+        let eof = crate::UserControlEvent::decode(&last.unwrap().payload).unwrap();
+        assert_eq!(eof, crate::UserControlEvent::StreamEof(1));
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use log::warn;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::net::TcpListener;
+use crate::{Error, Result};
+use crate::server::server::RtmpServer;
+
+/// Largest request body accepted from a client
+const MAX_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// Bytes buffered for a client that has not polled yet before writes wait
+const MAX_PENDING_REPLY: usize = MAX_BODY_SIZE;
+
+/// Longest request line or header line accepted
+const MAX_LINE_SIZE: usize = 8 * 1024;
+
+/// Content type of every RTMPT response
+const RTMPT_CONTENT_TYPE: &str = "application/x-fcs";
+
+/// Poll delay sent while the session has traffic
+const MIN_POLL_DELAY: u8 = 0x01;
+
+/// Poll delay ceiling for an idle session
+const MAX_POLL_DELAY: u8 = 0x21;
+
+/// Bytes waiting in each direction of one RTMPT session
+#[derive(Default)]
+struct SessionBuffers {
+    /// Posted by the client, not yet read by the connection
+    incoming: Vec<u8>,
+
+    /// Written by the connection, not yet fetched by the client
+    outgoing: Vec<u8>,
+
+    /// Wakes the connection's read loop when bytes arrive
+    read_waker: Option<Waker>,
+
+    /// Wakes the connection's write loop once the client fetches replies
+    write_waker: Option<Waker>,
+
+    /// The client closed the session
+    closed: bool,
+
+    /// The connection dropped its transport
+    detached: bool,
+
+    /// Delay the client is told to wait before polling again
+    poll_delay: u8,
+}
+
+type Session = Arc<Mutex<SessionBuffers>>;
+
+impl SessionBuffers {
+    fn feed(&mut self, data: &[u8]) {
+        self.incoming.extend_from_slice(data);
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        for waker in [self.read_waker.take(), self.write_waker.take()].into_iter().flatten() {
+            waker.wake();
+        }
+    }
+
+    /// Poll delay byte followed by everything the connection has written;
+    /// the delay backs off while there is nothing to send
+    fn take_reply(&mut self) -> Vec<u8> {
+        self.poll_delay = if self.outgoing.is_empty() {
+            self.poll_delay.saturating_mul(2).clamp(MIN_POLL_DELAY, MAX_POLL_DELAY)
+        } else {
+            MIN_POLL_DELAY
+        };
+
+        let mut reply = Vec::with_capacity(1 + self.outgoing.len());
+        reply.push(self.poll_delay);
+        reply.append(&mut self.outgoing);
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+        reply
+    }
+}
+
+/// Transport handed to the connection: reads what the client posts and
+/// buffers writes until the client polls for them
+///
+/// Writes wait once `MAX_PENDING_REPLY` bytes are buffered, so a client
+/// that stops polling pushes back like a full socket would.
+struct RtmptStream {
+    session: Session,
+}
+
+impl AsyncRead for RtmptStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let mut session = self.session.lock().unwrap();
+        if session.incoming.is_empty() {
+            // End of stream once the client has closed the session
+            if !session.closed {
+                session.read_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            return Poll::Ready(Ok(()));
+        }
+
+        let n = buf.remaining().min(session.incoming.len());
+        buf.put_slice(&session.incoming[..n]);
+        session.incoming.drain(..n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for RtmptStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let mut session = self.session.lock().unwrap();
+        if session.closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+
+        let room = MAX_PENDING_REPLY.saturating_sub(session.outgoing.len());
+        if room == 0 {
+            session.write_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = buf.len().min(room);
+        session.outgoing.extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for RtmptStream {
+    fn drop(&mut self) {
+        if let Ok(mut session) = self.session.lock() {
+            session.detached = true;
+        }
+    }
+}
+
+/// One parsed HTTP request
+pub(super) struct HttpRequest {
+    pub(super) method: String,
+    pub(super) path: String,
+    pub(super) body: Vec<u8>,
+}
+
+/// Serves RTMP tunneled over HTTP (RTMPT)
+///
+/// Clients open a session with `POST /open/1`, then push RTMP bytes with
+/// `/send/<id>/<seq>` and fetch the server's bytes with `/idle/<id>/<seq>`,
+/// until `/close/<id>/<seq>`. Each session is handled by the server like
+/// any other connection.
+pub struct RtmptListener {
+    server: Arc<RtmpServer>,
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl RtmptListener {
+    /// Create a listener feeding sessions into `server`
+    pub fn new(server: Arc<RtmpServer>) -> Self {
+        RtmptListener {
+            server,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Accept HTTP connections until accepting fails
+    pub async fn listen(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, peer_addr) = listener.accept().await?;
+            let this = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = this.serve_http(stream, peer_addr).await {
+                    warn!(peer:% = peer_addr; "RTMPT connection failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Answer the RTMPT requests arriving on one HTTP connection
+    pub async fn serve_http<S>(&self, stream: S, peer_addr: SocketAddr) -> Result<()>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (read_half, mut write_half) = tokio::io::split(stream);
+        let mut reader = BufReader::new(read_half);
+
+        while let Some(request) = read_request(&mut reader).await? {
+            let (status, body) = self.handle_request(request, peer_addr).await;
+            write_response(&mut write_half, status, RTMPT_CONTENT_TYPE, &body).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Number of open sessions
+    pub fn session_count(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    async fn handle_request(&self, request: HttpRequest, peer_addr: SocketAddr) -> (&'static str, Vec<u8>) {
+        if request.method != "POST" {
+            return ("405 Method Not Allowed", Vec::new());
+        }
+
+        let mut parts = request.path.trim_start_matches('/').split('/');
+        match (parts.next(), parts.next()) {
+            (Some("open"), _) => {
+                let id = self.open(peer_addr).await;
+                ("200 OK", format!("{}\n", id).into_bytes())
+            }
+            (Some("send"), Some(id)) => match self.session(id) {
+                Some(session) => {
+                    let mut session = session.lock().unwrap();
+                    session.feed(&request.body);
+                    ("200 OK", session.take_reply())
+                }
+                None => ("404 Not Found", Vec::new()),
+            },
+            (Some("idle"), Some(id)) => match self.session(id) {
+                Some(session) => ("200 OK", session.lock().unwrap().take_reply()),
+                None => ("404 Not Found", Vec::new()),
+            },
+            (Some("close"), Some(id)) => {
+                if let Some(session) = self.sessions.lock().unwrap().remove(id) {
+                    session.lock().unwrap().close();
+                }
+                ("200 OK", vec![0x00])
+            }
+            _ => ("404 Not Found", Vec::new()),
+        }
+    }
+
+    /// Start a session and hand its transport to the server
+    async fn open(&self, peer_addr: SocketAddr) -> String {
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let session = Session::default();
+
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            // Sessions whose connection already ended are of no further use
+            sessions.retain(|_, session| !session.lock().unwrap().detached);
+            sessions.insert(id.clone(), session.clone());
+        }
+
+        self.server.serve_stream(RtmptStream { session }, peer_addr).await;
+        id
+    }
+
+    /// Look up a live session, forgetting it if its connection ended
+    fn session(&self, id: &str) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions.get(id)?.clone();
+        if session.lock().unwrap().detached {
+            sessions.remove(id);
+            return None;
+        }
+        Some(session)
+    }
+}
+
+/// Read one request; `None` once the client closes the connection
+pub(super) async fn read_request<R>(reader: &mut BufReader<R>) -> Result<Option<HttpRequest>>
+where
+    R: AsyncRead + Unpin,
+{
+    let Some(request_line) = read_line(reader).await? else {
+        return Ok(None);
+    };
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(Error::protocol(format!("Invalid HTTP request line: {}", request_line)));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut content_length = 0;
+    loop {
+        let line = read_line(reader).await?
+            .ok_or_else(|| Error::connection("Connection closed inside HTTP headers"))?;
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse::<usize>()
+                .map_err(|_| Error::protocol(format!("Invalid Content-Length: {}", value.trim())))?;
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Err(Error::protocol(format!("HTTP body too large: {} bytes", content_length)));
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).await?;
+
+    Ok(Some(HttpRequest { method, path, body }))
+}
+
+/// Read a CRLF terminated line without the terminator
+pub(super) async fn read_line<R>(reader: &mut BufReader<R>) -> Result<Option<String>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut line = Vec::new();
+    let read = (&mut *reader).take(MAX_LINE_SIZE as u64 + 1).read_until(b'\n', &mut line).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.len() > MAX_LINE_SIZE {
+        return Err(Error::protocol("HTTP line too long"));
+    }
+
+    let line = String::from_utf8(line)
+        .map_err(|_| Error::protocol("Invalid UTF-8 in HTTP request"))?;
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+pub(super) async fn write_response<W>(writer: &mut W, status: &str, content_type: &str, body: &[u8]) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::ServerConfig;
+
+    /// Post `body` to `path` and return the status line and response body
+    async fn post<S>(client: &mut BufReader<S>, path: &str, body: &[u8]) -> (String, Vec<u8>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let head = format!("POST {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n", path, body.len());
+        client.get_mut().write_all(head.as_bytes()).await.unwrap();
+        client.get_mut().write_all(body).await.unwrap();
+
+        let status = read_line(client).await.unwrap().unwrap();
+        let mut content_length = 0;
+        loop {
+            let line = read_line(client).await.unwrap().unwrap();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                content_length = value.parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0u8; content_length];
+        client.read_exact(&mut body).await.unwrap();
+        (status, body)
+    }
+
+    /// Records whether it was woken
+    struct WakeFlag(std::sync::atomic::AtomicBool);
+
+    impl std::task::Wake for WakeFlag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_wait_for_client_to_poll() {
+        let session = Session::default();
+        let mut stream = RtmptStream { session: session.clone() };
+        stream.write_all(&vec![0u8; MAX_PENDING_REPLY]).await.unwrap();
+
+        // Nothing more is buffered until the client fetches a reply
+        let flag = Arc::new(WakeFlag(std::sync::atomic::AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+        assert!(Pin::new(&mut stream).poll_write(&mut cx, b"more").is_pending());
+
+        let reply = session.lock().unwrap().take_reply();
+        assert_eq!(reply.len(), 1 + MAX_PENDING_REPLY);
+        assert!(flag.0.load(std::sync::atomic::Ordering::SeqCst));
+        assert!(matches!(Pin::new(&mut stream).poll_write(&mut cx, b"more"), Poll::Ready(Ok(4))));
+    }
+
+    #[tokio::test]
+    async fn test_open_then_send_reaches_connection() {
+        let server = Arc::new(RtmpServer::new(ServerConfig::default()));
+        let listener = Arc::new(RtmptListener::new(server));
+
+        let (client, server_side) = tokio::io::duplex(64 * 1024);
+        let peer_addr = "192.0.2.10:50000".parse().unwrap();
+        let http = listener.clone();
+        tokio::spawn(async move { http.serve_http(server_side, peer_addr).await });
+        let mut client = BufReader::new(client);
+
+        let (status, body) = post(&mut client, "/open/1", &[]).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let id = String::from_utf8(body).unwrap().trim_end().to_string();
+        assert!(!id.is_empty());
+        assert_eq!(listener.session_count(), 1);
+
+        // C0 and a simple-format C1
+        let mut c0c1 = vec![0x03];
+        c0c1.extend_from_slice(&[0u8; 1536]);
+        let (status, mut received) = post(&mut client, &format!("/send/{}/1", id), &c0c1).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        received.remove(0);
+
+        // The connection answers with S0, S1 and S2
+        let handshake_reply = 1 + 2 * 1536;
+        for seq in 2..50 {
+            if received.len() >= handshake_reply {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let (_, mut reply) = post(&mut client, &format!("/idle/{}/{}", id, seq), &[]).await;
+            assert!((MIN_POLL_DELAY..=MAX_POLL_DELAY).contains(&reply[0]));
+            received.extend(reply.drain(1..));
+        }
+        assert_eq!(received.len(), handshake_reply);
+        assert_eq!(received[0], 0x03);
+
+        let (status, _) = post(&mut client, &format!("/close/{}/50", id), &[]).await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        let (status, _) = post(&mut client, &format!("/idle/{}/51", id), &[]).await;
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+    }
+}
@@ -1,12 +1,26 @@
 use crate::{Error, Result};
-use crate::connection::Connection;
+use crate::connection::{Connection, ConnectionInfo};
 use crate::message::MessageDispatcher;
-use tokio::net::{TcpListener, TcpStream};
+use log::{debug, error, info, warn};
+use crate::transport::{TcpTransport, Transport};
+use tokio::io::{AsyncRead, AsyncWrite};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify, OnceCell, RwLock};
 use std::collections::HashMap;
 use crate::server::config::ServerConfig;
 use crate::server::context::ServerContext;
+use crate::server::events::EventListener;
+use crate::server::metrics::ServerMetrics;
+
+#[cfg(feature = "tls")]
+use tokio_rustls::TlsAcceptor;
+
+/// Stand-in so the accept path reads the same with TLS compiled out
+#[cfg(not(feature = "tls"))]
+type TlsAcceptor = std::convert::Infallible;
 
 pub struct RtmpServer {
     /// Server configuration
@@ -21,8 +35,17 @@ pub struct RtmpServer {
     /// Message dispatcher template
     dispatcher: Arc<MessageDispatcher>,
 
+    /// Set once the built-in handlers are on the dispatcher
+    handlers_registered: OnceCell<()>,
+
     /// Shutdown flag
     shutdown: Arc<RwLock<bool>>,
+
+    /// Wakes the accept loop on shutdown
+    shutdown_notify: Arc<Notify>,
+
+    /// Signalled whenever a connection task finishes
+    connection_closed: Arc<Notify>,
 }
 
 impl RtmpServer {
@@ -37,7 +60,10 @@ impl RtmpServer {
             context,
             connections: Arc::new(RwLock::new(HashMap::new())),
             dispatcher,
+            handlers_registered: OnceCell::new(),
             shutdown: Arc::new(RwLock::new(false)),
+            shutdown_notify: Arc::new(Notify::new()),
+            connection_closed: Arc::new(Notify::new()),
         }
     }
 
@@ -51,13 +77,71 @@ impl RtmpServer {
         self.context.clone()
     }
 
+    /// Register a listener for connection and stream lifecycle events
+    pub async fn add_event_listener(&self, listener: Arc<dyn EventListener>) {
+        self.context.events().add(listener).await;
+    }
+
     /// Listen and accept connections
+    ///
+    /// Every address from `ServerConfig::listen_specs` is bound before any
+    /// connection is accepted; all of them share one set of connections,
+    /// limits and streams.
     pub async fn listen(&self) -> Result<()> {
-        let addr = format!("{}:{}", self.config.host, self.config.port);
-        let listener = TcpListener::bind(&addr).await
-            .map_err(|e| Error::connection(format!("Failed to bind {}: {}", addr, e)))?;
+        #[cfg(feature = "tls")]
+        let tls_acceptor = crate::server::tls::build_acceptor(&self.config)?;
+        #[cfg(not(feature = "tls"))]
+        let tls_acceptor = None;
+
+        let mut transports = Vec::new();
+        for spec in self.config.listen_specs() {
+            #[cfg(feature = "tls")]
+            if spec.tls && tls_acceptor.is_none() {
+                return Err(Error::config(format!("TLS listener {}:{} has no certificate", spec.host, spec.port)));
+            }
+            #[cfg(not(feature = "tls"))]
+            if spec.tls {
+                return Err(Error::config("TLS requires the `tls` feature"));
+            }
+
+            let transport = TcpTransport::bind_with_reuse_port(&spec.host, spec.port, self.config.reuse_port).await?;
+            transports.push((transport, spec));
+        }
+
+        let transports = transports.into_iter()
+            .map(|(transport, spec)| {
+                info!(tls = spec.tls; "RTMP server listening on {}:{}", spec.host, spec.port);
+                (transport, spec.tls)
+            })
+            .collect();
+        self.serve_transports(transports, tls_acceptor).await
+    }
+
+    /// Accept connections from another transport, such as a Unix socket
+    ///
+    /// Connections go through the same limits and handlers as those from
+    /// `listen`, until `shutdown` is called.
+    pub async fn listen_on<T: Transport>(&self, transport: T) -> Result<()> {
+        self.serve_transports(vec![(transport, false)], None).await
+    }
 
-        println!("RTMP Server listening on {}", addr);
+    /// Accept from every transport until shutdown; `tls` marks the ones
+    /// whose streams are wrapped in TLS first
+    async fn serve_transports<T: Transport>(
+        &self,
+        transports: Vec<(T, bool)>,
+        tls_acceptor: Option<TlsAcceptor>,
+    ) -> Result<()> {
+        self.register_handlers().await;
+
+        // One accept task per transport, feeding the loop below; dropping
+        // the set on return stops them
+        let (accepted_tx, mut accepted_rx) = mpsc::channel(64);
+        let mut accept_tasks = tokio::task::JoinSet::new();
+        for (transport, tls) in transports {
+            accept_tasks.spawn(accept_loop(transport, tls, accepted_tx.clone()));
+        }
+        drop(accepted_tx);
 
         // Accept loop
         loop {
@@ -66,63 +150,111 @@ impl RtmpServer {
                 break;
             }
 
-            // Accept connection
-            let (stream, peer_addr) = match listener.accept().await {
-                Ok((s, a)) => (s, a),
-                Err(e) => {
-                    eprintln!("Accept error: {}", e);
-                    continue;
-                }
+            // Accept connection, unless shutdown is requested first
+            let accepted = tokio::select! {
+                accepted = accepted_rx.recv() => accepted,
+                _ = self.shutdown_notify.notified() => break,
+            };
+            let Some((stream, peer_addr, tls)) = accepted else {
+                break;
             };
 
-            println!("New connection from {}", peer_addr);
+            debug!(peer:% = peer_addr; "Incoming connection");
 
             // Check connection limit
             if self.connections.read().await.len() >= self.config.max_connections {
-                eprintln!("Connection limit reached, rejecting {}", peer_addr);
+                warn!(peer:% = peer_addr; "Connection limit reached, rejecting");
                 drop(stream);
                 continue;
             }
 
             // Check IP limits
             let ip = peer_addr.ip();
+            if !self.context.allow_connect_from_ip(ip).await {
+                warn!(peer:% = peer_addr; "Connect rate limit reached, rejecting");
+                drop(stream);
+                continue;
+            }
+
             if !self.context.can_accept_from_ip(ip).await {
-                eprintln!("IP limit reached for {}, rejecting", ip);
+                warn!(peer:% = peer_addr; "IP limit reached, rejecting");
                 drop(stream);
                 continue;
             }
 
-            // Handle connection
-            self.handle_connection(stream, peer_addr.to_string()).await;
+            // Handle connection, terminating TLS first when configured
+            #[cfg(feature = "tls")]
+            if tls && let Some(acceptor) = &tls_acceptor {
+                let acceptor = acceptor.clone();
+                self.handle_connection(peer_addr, async move {
+                    acceptor.accept(stream).await
+                        .map_err(|e| Error::connection(format!("TLS handshake failed: {}", e)))
+                }).await;
+                continue;
+            }
+
+            #[cfg(not(feature = "tls"))]
+            let _ = (tls, &tls_acceptor);
+
+            self.handle_connection(peer_addr, async move { Ok(stream) }).await;
         }
 
-        println!("Server stopped");
+        accept_tasks.abort_all();
+        info!("Server stopped");
         Ok(())
     }
 
-    /// Handle new connection
-    async fn handle_connection(&self, stream: TcpStream, peer_addr: String) {
-        // Configure TCP
-        if let Err(e) = stream.set_nodelay(true) {
-            eprintln!("Failed to set TCP_NODELAY: {}", e);
-        }
+    /// Serve an already connected transport, such as an in-memory stream
+    ///
+    /// The peer goes through the RTMP handshake and is handled like an
+    /// accepted socket from `peer_addr`, without the connection and IP limits.
+    pub async fn serve_stream<S>(&self, stream: S, peer_addr: SocketAddr)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.register_handlers().await;
+        self.handle_connection(peer_addr, async move { Ok(stream) }).await;
+    }
 
+    /// Put the command and media handlers on the dispatcher, once
+    async fn register_handlers(&self) {
+        self.handlers_registered.get_or_init(|| async {
+            crate::handlers::register_command_handlers(&self.dispatcher).await;
+
+            // Published media is fanned out to players by the stream's publisher
+            crate::handlers::register_media_handlers(&self.dispatcher).await;
+        }).await;
+    }
+
+    /// Handle new connection
+    ///
+    /// `stream` resolves to the transport once any TLS handshake is done; it
+    /// runs inside the connection task so it never blocks the accept loop.
+    async fn handle_connection<F, S>(&self, peer_addr: SocketAddr, stream: F)
+    where
+        F: Future<Output = Result<S>> + Send + 'static,
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
         // Generate connection ID
         let conn_id = self.context.generate_connection_id();
 
         // Create connection context
         let (packet_tx, packet_rx) = tokio::sync::mpsc::channel(100);
-        let conn_context = Arc::new(crate::connection::ConnectionContext::new(
-            conn_id.clone(),
-            packet_tx,
-        ));
+        let conn_context = Arc::new(
+            crate::connection::ConnectionContext::new(conn_id.clone(), packet_tx)
+                .with_publisher_registry(self.context.publishers())
+                .with_server_config(self.config.clone())
+                .with_event_listeners(self.context.events())
+                .with_peer_addr(peer_addr),
+        );
 
         // Create connection
         let connection = Arc::new(Connection::new(
             conn_id.clone(),
-            conn_context,
+            conn_context.clone(),
             self.dispatcher.clone(),
-        ));
+            packet_rx,
+        ).with_read_timeout(self.config.read_timeout));
 
         // Store connection
         {
@@ -131,51 +263,276 @@ impl RtmpServer {
         }
 
         // Increment IP counter
-        let ip = peer_addr.parse::<std::net::SocketAddr>()
-            .map(|a| a.ip())
-            .unwrap_or(std::net::IpAddr::V4(std::net::Ipv4Addr::new(0, 0, 0, 0)));
+        let ip = peer_addr.ip();
         self.context.increment_ip_count(ip).await;
 
+        info!(conn_id:% = conn_id, peer:% = peer_addr; "Connection accepted");
+
         // Process connection
-        let connections = self.connections.clone();
         let context = self.context.clone();
         let conn_id_clone = conn_id.clone();
+        let slot = ConnectionSlot {
+            conn_id,
+            ip,
+            connections: self.connections.clone(),
+            context: self.context.clone(),
+            connection_closed: self.connection_closed.clone(),
+        };
 
         tokio::spawn(async move {
+            // Frees the connection's slot however the task ends
+            let _slot = slot;
+
             // Process connection
-            if let Err(e) = connection.process_server(stream).await {
-                eprintln!("Connection {} error: {}", conn_id_clone, e);
+            let result = match stream.await {
+                Ok(stream) => connection.process_server(stream).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!(conn_id:% = conn_id_clone, peer:% = peer_addr; "Connection error: {}", e);
             }
 
-            // Remove connection
-            connections.write().await.remove(&conn_id_clone);
+            // Release streams left behind by an abrupt disconnect
+            if let Err(e) = crate::handlers::release_connection(&conn_context).await {
+                error!(conn_id:% = conn_id_clone, peer:% = peer_addr; "Connection cleanup error: {}", e);
+            }
 
-            // Decrement IP counter
-            context.decrement_ip_count(ip).await;
+            context.events().notify_disconnect(&conn_id_clone).await;
 
-            println!("Connection {} closed", conn_id_clone);
+            info!(conn_id:% = conn_id_clone, peer:% = peer_addr; "Connection closed");
         });
     }
 
     /// Shutdown server
+    ///
+    /// Stops accepting, so `listen` returns, and closes every connection,
+    /// waiting only for packets already queued to each to be written.
     pub async fn shutdown(&self) {
-        println!("Shutting down server...");
+        info!("Shutting down server");
 
-        // Set shutdown flag
+        // Set shutdown flag and wake the accept loop
         *self.shutdown.write().await = true;
-
-        // Close all connections
-        let connections = self.connections.read().await;
-        for (id, conn) in connections.iter() {
-            println!("Closing connection {}", id);
+        self.shutdown_notify.notify_one();
+
+        // Close all connections; the lock is released first because each
+        // connection removes itself once its loops stop
+        let connections: Vec<_> = self.connections.read().await
+            .iter()
+            .map(|(id, conn)| (id.clone(), conn.clone()))
+            .collect();
+        for (id, conn) in connections {
+            debug!(conn_id:% = id; "Closing connection");
             if let Err(e) = conn.close().await {
-                eprintln!("Error closing connection {}: {}", id, e);
+                error!(conn_id:% = id; "Error closing connection: {}", e);
             }
         }
     }
 
+    /// Shutdown server and wait up to `timeout` for connections to drain
+    ///
+    /// Returns true if every connection finished in time.
+    pub async fn shutdown_graceful(&self, timeout: Duration) -> bool {
+        self.shutdown().await;
+
+        let drained = async {
+            loop {
+                // Register before checking so a close in between is not missed
+                let closed = self.connection_closed.notified();
+                if self.connections.read().await.is_empty() {
+                    break;
+                }
+                closed.await;
+            }
+        };
+
+        tokio::time::timeout(timeout, drained).await.is_ok()
+    }
+
+    /// Close a connection by ID, writing what is already queued to it
+    ///
+    /// The streams it published or played are released as its task ends.
+    pub async fn disconnect(&self, conn_id: &str) -> Result<()> {
+        let connection = self.connections.read().await.get(conn_id).cloned()
+            .ok_or_else(|| Error::connection(format!("No connection {}", conn_id)))?;
+
+        info!(conn_id:% = conn_id; "Disconnecting connection");
+        connection.close().await
+    }
+
+    /// Close the connection publishing `stream_name`
+    pub async fn disconnect_stream(&self, stream_name: &str) -> Result<()> {
+        let publisher = self.context.publishers().get(stream_name).await
+            .ok_or_else(|| Error::stream(format!("Stream {} is not published", stream_name)))?;
+
+        self.disconnect(&publisher.connection_id).await
+    }
+
+    /// Collect connection and per-stream metrics
+    pub async fn metrics_snapshot(&self) -> ServerMetrics {
+        ServerMetrics {
+            connections: self.connection_count().await,
+            streams: self.context.publishers().stream_metrics().await,
+        }
+    }
+
     /// Get active connections count
     pub async fn connection_count(&self) -> usize {
         self.connections.read().await.len()
     }
-}
\ No newline at end of file
+
+    /// Snapshot of every active connection
+    pub async fn connections_info(&self) -> Vec<ConnectionInfo> {
+        // Gather outside the lock, so closing connections are not held up
+        let connections: Vec<_> = self.connections.read().await.values().cloned().collect();
+
+        let mut info = Vec::with_capacity(connections.len());
+        for connection in connections {
+            info.push(connection.info().await);
+        }
+        info
+    }
+}
+
+/// A connection's place in the server, released on drop
+///
+/// Dropping runs on normal exit and on panic alike, so the IP count and
+/// connection map never keep a dead connection.
+struct ConnectionSlot {
+    conn_id: String,
+    ip: IpAddr,
+    connections: Arc<RwLock<HashMap<String, Arc<Connection>>>>,
+    context: Arc<ServerContext>,
+    connection_closed: Arc<Notify>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let conn_id = std::mem::take(&mut self.conn_id);
+        let ip = self.ip;
+        let connections = self.connections.clone();
+        let context = self.context.clone();
+        let connection_closed = self.connection_closed.clone();
+
+        // The maps sit behind async locks, so release them on a task
+        tokio::spawn(async move {
+            connections.write().await.remove(&conn_id);
+            context.decrement_ip_count(ip).await;
+            connection_closed.notify_waiters();
+        });
+    }
+}
+
+/// Hand every connection accepted on `listener` to the server's accept loop
+async fn accept_loop<T: Transport>(transport: T, tls: bool, accepted: mpsc::Sender<(T::Stream, SocketAddr, bool)>) {
+    loop {
+        match transport.accept().await {
+            Ok((stream, peer_addr)) => {
+                if accepted.send((stream, peer_addr, tls)).await.is_err() {
+                    break;
+                }
+            }
+            Err(e) => error!("Accept error: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConnectionState;
+    use log::kv::{Key, Value, VisitSource};
+    use std::sync::Mutex;
+
+    /// Fields and level of each record logged
+    static RECORDS: Mutex<Vec<(log::Level, HashMap<String, String>)>> = Mutex::new(Vec::new());
+
+    struct CapturingLogger;
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            struct Fields(HashMap<String, String>);
+
+            impl<'kvs> VisitSource<'kvs> for Fields {
+                fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> std::result::Result<(), log::kv::Error> {
+                    self.0.insert(key.to_string(), value.to_string());
+                    Ok(())
+                }
+            }
+
+            let mut fields = Fields(HashMap::new());
+            let _ = record.key_values().visit(&mut fields);
+            RECORDS.lock().unwrap().push((record.level(), fields.0));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[tokio::test]
+    async fn test_panicking_connection_releases_ip() {
+        let server = RtmpServer::new(ServerConfig::default());
+        let peer_addr: SocketAddr = "192.0.2.8:40000".parse().unwrap();
+
+        let transport = async { panic!("transport setup failed") };
+        server.handle_connection::<_, tokio::io::DuplexStream>(peer_addr, transport).await;
+        assert_eq!(server.context.ip_count(peer_addr.ip()).await, 1);
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while server.context.ip_count(peer_addr.ip()).await > 0 || server.connection_count().await > 0 {
+                tokio::task::yield_now().await;
+            }
+        }).await.expect("slot should be released after the panic");
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_stream_closes_publisher() {
+        let server = RtmpServer::new(ServerConfig::default());
+        let (client_side, server_side) = tokio::io::duplex(64 * 1024);
+        server.serve_stream(server_side, "192.0.2.9:40000".parse().unwrap()).await;
+
+        let mut client = crate::RtmpClient::new();
+        client.connect_stream(client_side, "rtmp://localhost/live").await.unwrap();
+        client.publish("kicked", "live").await.unwrap();
+
+        let registry = server.context.publishers();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !registry.is_publishing("kicked").await {
+                tokio::task::yield_now().await;
+            }
+        }).await.expect("stream should be published");
+
+        let connection = server.connections.read().await.values().next().cloned().unwrap();
+        server.disconnect_stream("kicked").await.unwrap();
+        assert_eq!(connection.state().await, ConnectionState::Closed);
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while registry.is_publishing("kicked").await || server.connection_count().await > 0 {
+                tokio::task::yield_now().await;
+            }
+        }).await.expect("stream should be released");
+
+        assert!(server.disconnect_stream("kicked").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_connect_logs_connection_id() {
+        let _ = log::set_logger(&CapturingLogger);
+        log::set_max_level(log::LevelFilter::Info);
+
+        let server = RtmpServer::new(ServerConfig::default());
+        let (_client, transport) = tokio::io::duplex(4096);
+        let peer_addr: SocketAddr = "192.0.2.7:40000".parse().unwrap();
+        server.serve_stream(transport, peer_addr).await;
+
+        let conn_id = server.connections.read().await.keys().next().cloned().unwrap();
+        let records = RECORDS.lock().unwrap();
+        assert!(records.iter().any(|(level, fields)| {
+            *level == log::Level::Info
+                && fields.get("conn_id") == Some(&conn_id)
+                && fields.get("peer").map(String::as_str) == Some("192.0.2.7:40000")
+        }));
+    }
+}
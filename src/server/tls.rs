@@ -0,0 +1,31 @@
+use std::sync::Arc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use crate::{Error, Result};
+use crate::server::config::ServerConfig;
+
+/// Build a TLS acceptor from the configured certificate and key, if any
+pub(crate) fn build_acceptor(config: &ServerConfig) -> Result<Option<TlsAcceptor>> {
+    let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path) else {
+        return Ok(None);
+    };
+
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| Error::config(format!("Failed to load TLS certificate {}: {}", cert_path.display(), e)))?;
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| Error::config(format!("Failed to load TLS key {}: {}", key_path.display(), e)))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let tls_config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::config(format!("Invalid TLS configuration: {}", e)))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| Error::config(format!("Invalid TLS certificate or key: {}", e)))?;
+
+    Ok(Some(TlsAcceptor::from(Arc::new(tls_config))))
+}
@@ -1,28 +1,58 @@
+use crate::processing::FrameType;
 use crate::protocol::RtmpPacket;
 use std::collections::VecDeque;
 
+/// Default maximum number of cached packets
+pub const DEFAULT_GOP_CACHE_MAX_PACKETS: usize = 4096;
+
+/// Default maximum cached payload size in bytes
+pub const DEFAULT_GOP_CACHE_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+/// Cached packet with the frame type it was added as
+struct CachedFrame {
+    packet: RtmpPacket,
+    frame_type: FrameType,
+}
+
 pub struct GopCache {
     /// Maximum GOPs to cache
     max_gops: usize,
 
+    /// Maximum cached packets across all GOPs
+    max_packets: usize,
+
+    /// Maximum cached payload bytes across all GOPs
+    max_bytes: usize,
+
     /// Current GOP being built
-    current_gop: Vec<RtmpPacket>,
+    current_gop: Vec<CachedFrame>,
 
     /// Completed GOPs
-    cached_gops: VecDeque<Vec<RtmpPacket>>,
+    cached_gops: VecDeque<Vec<CachedFrame>>,
 
     /// Total cached packets
     total_packets: usize,
+
+    /// Total cached payload bytes
+    total_bytes: usize,
 }
 
 impl GopCache {
     /// Create new GOP cache
     pub fn new(max_gops: usize) -> Self {
+        GopCache::with_limits(max_gops, DEFAULT_GOP_CACHE_MAX_PACKETS, DEFAULT_GOP_CACHE_MAX_BYTES)
+    }
+
+    /// Create GOP cache with packet count and byte size limits
+    pub fn with_limits(max_gops: usize, max_packets: usize, max_bytes: usize) -> Self {
         GopCache {
             max_gops,
+            max_packets,
+            max_bytes,
             current_gop: Vec::new(),
             cached_gops: VecDeque::new(),
             total_packets: 0,
+            total_bytes: 0,
         }
     }
 
@@ -34,15 +64,22 @@ impl GopCache {
         }
 
         // Start new GOP with keyframe
-        self.current_gop.push(packet);
         self.total_packets += 1;
+        self.total_bytes += packet.payload.len();
+        self.current_gop.push(CachedFrame { packet, frame_type: FrameType::Keyframe });
+        self.enforce_limits();
     }
 
     /// Add regular frame to current GOP
-    pub fn add_frame(&mut self, packet: RtmpPacket) {
+    ///
+    /// Disposable inter-frames are the first to go when the cache is over
+    /// its limits.
+    pub fn add_frame(&mut self, packet: RtmpPacket, frame_type: FrameType) {
         if !self.current_gop.is_empty() {
-            self.current_gop.push(packet);
             self.total_packets += 1;
+            self.total_bytes += packet.payload.len();
+            self.current_gop.push(CachedFrame { packet, frame_type });
+            self.enforce_limits();
         }
         // Ignore frames without keyframe
     }
@@ -58,9 +95,66 @@ impl GopCache {
 
         // Limit cache size
         while self.cached_gops.len() > self.max_gops {
-            if let Some(removed) = self.cached_gops.pop_front() {
-                self.total_packets -= removed.len();
+            self.evict_oldest_gop();
+        }
+    }
+
+    /// Drop disposable frames, then whole GOPs, oldest first, until the
+    /// packet and byte limits hold. If the current GOP alone is over the
+    /// limit it is dropped too, and frames are ignored until the next
+    /// keyframe starts a clean GOP.
+    fn enforce_limits(&mut self) {
+        if self.over_limits() {
+            self.drop_disposable_frames();
+        }
+
+        while self.over_limits() && !self.cached_gops.is_empty() {
+            self.evict_oldest_gop();
+        }
+
+        if self.over_limits() {
+            self.current_gop.clear();
+            self.total_packets = 0;
+            self.total_bytes = 0;
+        }
+    }
+
+    fn over_limits(&self) -> bool {
+        self.total_packets > self.max_packets || self.total_bytes > self.max_bytes
+    }
+
+    fn evict_oldest_gop(&mut self) {
+        if let Some(removed) = self.cached_gops.pop_front() {
+            self.total_packets -= removed.len();
+            self.total_bytes -= removed.iter().map(|f| f.packet.payload.len()).sum::<usize>();
+        }
+    }
+
+    /// Drop disposable inter-frames GOP by GOP, oldest first, stopping once
+    /// the limits hold; no other frame depends on them
+    fn drop_disposable_frames(&mut self) {
+        for index in 0..=self.cached_gops.len() {
+            if !self.over_limits() {
+                break;
             }
+
+            let gop = match self.cached_gops.get_mut(index) {
+                Some(gop) => gop,
+                None => &mut self.current_gop,
+            };
+
+            let mut dropped_bytes = 0;
+            let before = gop.len();
+            gop.retain(|frame| {
+                let disposable = frame.frame_type == FrameType::DisposableInterFrame;
+                if disposable {
+                    dropped_bytes += frame.packet.payload.len();
+                }
+                !disposable
+            });
+
+            self.total_packets -= before - gop.len();
+            self.total_bytes -= dropped_bytes;
         }
     }
 
@@ -68,24 +162,42 @@ impl GopCache {
     pub fn get_gop(&self) -> Vec<RtmpPacket> {
         let mut packets = Vec::with_capacity(self.total_packets);
 
-        // Add all cached GOPs
-        for gop in &self.cached_gops {
-            packets.extend_from_slice(gop);
-        }
-
-        // Add current GOP if has keyframe
-        if !self.current_gop.is_empty() {
-            packets.extend_from_slice(&self.current_gop);
+        // Add all cached GOPs, then the current GOP
+        for gop in self.cached_gops.iter().chain(std::iter::once(&self.current_gop)) {
+            packets.extend(gop.iter().map(|f| f.packet.clone()));
         }
 
         packets
     }
 
+    /// Get cached packets starting at the keyframe nearest to `timestamp`
+    ///
+    /// Picks the last GOP whose keyframe is at or before `timestamp`, or the
+    /// oldest GOP if `timestamp` precedes the whole cache.
+    pub fn get_gop_from(&self, timestamp: u32) -> Vec<RtmpPacket> {
+        let gops: Vec<&Vec<CachedFrame>> = self.cached_gops.iter()
+            .chain(std::iter::once(&self.current_gop))
+            .filter(|gop| !gop.is_empty())
+            .collect();
+
+        let start = gops.iter()
+            .rposition(|gop| gop[0].packet.timestamp() <= timestamp)
+            .unwrap_or(0);
+
+        gops[start..].iter().flat_map(|gop| gop.iter().map(|f| f.packet.clone())).collect()
+    }
+
+    /// Get cached packets from the most recent keyframe onward
+    pub fn get_gop_from_latest_keyframe(&self) -> Vec<RtmpPacket> {
+        self.get_gop_from(u32::MAX)
+    }
+
     /// Clear cache
     pub fn clear(&mut self) {
         self.current_gop.clear();
         self.cached_gops.clear();
         self.total_packets = 0;
+        self.total_bytes = 0;
     }
 
     /// Get cache size
@@ -93,6 +205,21 @@ impl GopCache {
         self.total_packets
     }
 
+    /// Get number of cached packets
+    pub fn len(&self) -> usize {
+        self.total_packets
+    }
+
+    /// Check if cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.total_packets == 0
+    }
+
+    /// Get total cached payload size in bytes
+    pub fn byte_size(&self) -> usize {
+        self.total_bytes
+    }
+
     /// Get GOP count
     pub fn gop_count(&self) -> usize {
         self.cached_gops.len() + if self.current_gop.is_empty() { 0 } else { 1 }
@@ -115,8 +242,8 @@ mod tests {
 
         // Add first GOP
         cache.add_keyframe(keyframe1);
-        cache.add_frame(frame1);
-        cache.add_frame(frame2);
+        cache.add_frame(frame1, FrameType::InterFrame);
+        cache.add_frame(frame2, FrameType::InterFrame);
 
         // Add second GOP
         cache.add_keyframe(keyframe2);
@@ -130,6 +257,108 @@ mod tests {
         assert_eq!(packets.len(), 4);
     }
 
+    #[test]
+    fn test_gop_cache_evicts_past_byte_limit() {
+        let mut cache = GopCache::with_limits(10, 100, 1000);
+
+        // Two GOPs of 3 x 200 bytes each; the second pushes past 1000 bytes
+        for gop in 0..2 {
+            let base = gop * 1000;
+            cache.add_keyframe(create_sized_packet(0x17, base, 200));
+            cache.add_frame(create_sized_packet(0x27, base + 33, 200), FrameType::InterFrame);
+            cache.add_frame(create_sized_packet(0x27, base + 66, 200), FrameType::InterFrame);
+        }
+
+        // The first GOP was dropped as a whole
+        assert_eq!(cache.gop_count(), 1);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.byte_size(), 600);
+
+        let packets = cache.get_gop();
+        assert_eq!(packets[0].timestamp(), 1000);
+        assert_eq!(packets[0].payload[0], 0x17);
+    }
+
+    #[test]
+    fn test_gop_cache_drops_oversized_gop() {
+        let mut cache = GopCache::with_limits(10, 3, usize::MAX);
+
+        cache.add_keyframe(create_test_keyframe(0));
+        for i in 1..4 {
+            cache.add_frame(create_test_frame(i * 33), FrameType::InterFrame);
+        }
+
+        // The current GOP overflowed, so frames wait for the next keyframe
+        assert!(cache.is_empty());
+        cache.add_frame(create_test_frame(200), FrameType::InterFrame);
+        assert!(cache.is_empty());
+
+        cache.add_keyframe(create_test_keyframe(1000));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.byte_size(), 2);
+    }
+
+    #[test]
+    fn test_gop_cache_get_gop_from_nearest_keyframe() {
+        let mut cache = GopCache::new(3);
+        for base in [1000, 2000, 3000] {
+            cache.add_keyframe(create_test_keyframe(base));
+            cache.add_frame(create_test_frame(base + 33), FrameType::InterFrame);
+        }
+
+        let packets = cache.get_gop_from(2500);
+        assert_eq!(packets.len(), 4);
+        assert_eq!(packets[0].timestamp(), 2000);
+
+        // Before the cache starts, delivery begins at the oldest keyframe
+        assert_eq!(cache.get_gop_from(0).len(), 6);
+        assert_eq!(cache.get_gop_from(9999)[0].timestamp(), 3000);
+    }
+
+    #[test]
+    fn test_gop_cache_from_latest_keyframe() {
+        let mut cache = GopCache::new(3);
+        for base in [1000, 2000, 3000] {
+            cache.add_keyframe(create_test_keyframe(base));
+            cache.add_frame(create_test_frame(base + 33), FrameType::InterFrame);
+        }
+
+        let packets = cache.get_gop_from_latest_keyframe();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(packets[0].timestamp(), 3000);
+        assert_eq!(packets[0].payload[0], 0x17);
+        assert!(GopCache::new(3).get_gop_from_latest_keyframe().is_empty());
+    }
+
+    #[test]
+    fn test_gop_cache_drops_disposable_frames_first() {
+        let mut cache = GopCache::with_limits(10, 100, 1000);
+
+        // Keyframe, P-frame, then disposable B-frames, 100 bytes each
+        cache.add_keyframe(create_sized_packet(0x17, 0, 100));
+        cache.add_frame(create_sized_packet(0x27, 33, 100), FrameType::InterFrame);
+        for i in 2..8 {
+            cache.add_frame(create_sized_packet(0x37, i * 33, 100), FrameType::DisposableInterFrame);
+        }
+        cache.add_frame(create_sized_packet(0x27, 264, 100), FrameType::InterFrame);
+        assert_eq!(cache.byte_size(), 900);
+
+        // Crossing the byte limit sheds the B-frames, not the GOP
+        cache.add_frame(create_sized_packet(0x27, 297, 200), FrameType::InterFrame);
+        assert_eq!(cache.gop_count(), 1);
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.byte_size(), 500);
+
+        let markers: Vec<u8> = cache.get_gop().iter().map(|p| p.payload[0]).collect();
+        assert_eq!(markers, vec![0x17, 0x27, 0x27, 0x27]);
+    }
+
+    fn create_sized_packet(marker: u8, timestamp: u32, size: usize) -> RtmpPacket {
+        let mut data = vec![0u8; size];
+        data[0] = marker;
+        crate::protocol::make_video_packet(data, timestamp, 1)
+    }
+
     fn create_test_keyframe(timestamp: u32) -> RtmpPacket {
         let data = vec![0x17, 0x00]; // Keyframe marker
         crate::protocol::make_video_packet(data, timestamp, 1)
@@ -0,0 +1,616 @@
+use crate::processing::{AudioCodec, AudioProcessor, VideoCodec, VideoProcessor};
+use crate::protocol::RtmpPacket;
+use crate::{Error, Result};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Playlist file written into the output directory
+pub const HLS_PLAYLIST_NAME: &str = "index.m3u8";
+
+/// MPEG-TS packet length
+const TS_PACKET_SIZE: usize = 188;
+
+/// Payload bytes after the 4 byte TS packet header
+const TS_PAYLOAD_SIZE: usize = TS_PACKET_SIZE - 4;
+
+const PAT_PID: u16 = 0x0000;
+const PMT_PID: u16 = 0x1000;
+const VIDEO_PID: u16 = 0x0100;
+const AUDIO_PID: u16 = 0x0101;
+
+const STREAM_TYPE_H264: u8 = 0x1B;
+const STREAM_TYPE_AAC: u8 = 0x0F;
+
+const PES_STREAM_VIDEO: u8 = 0xE0;
+const PES_STREAM_AUDIO: u8 = 0xC0;
+
+/// H.264 access unit delimiter, sent before every frame
+const AUD_NAL: [u8; 6] = [0x00, 0x00, 0x00, 0x01, 0x09, 0xF0];
+const START_CODE: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+/// HLS output settings
+#[derive(Debug, Clone)]
+pub struct HlsOptions {
+    /// Target segment length; a segment ends at the first keyframe after it
+    pub segment_duration: Duration,
+
+    /// Segments listed in the playlist; older segment files are deleted
+    pub window_size: usize,
+}
+
+impl Default for HlsOptions {
+    fn default() -> Self {
+        HlsOptions {
+            segment_duration: Duration::from_secs(6),
+            window_size: 5,
+        }
+    }
+}
+
+impl HlsOptions {
+    /// Set the target segment length
+    pub fn segment_duration(mut self, duration: Duration) -> Self {
+        self.segment_duration = duration;
+        self
+    }
+
+    /// Set how many segments the playlist keeps
+    pub fn window_size(mut self, segments: usize) -> Self {
+        self.window_size = segments;
+        self
+    }
+
+    /// Validate options
+    pub fn validate(&self) -> Result<()> {
+        if self.segment_duration.is_zero() {
+            return Err(Error::config("HLS segment duration must be non-zero"));
+        }
+        if self.window_size == 0 {
+            return Err(Error::config("HLS window size must be at least 1"));
+        }
+        Ok(())
+    }
+}
+
+/// Segment being written
+struct OpenSegment {
+    sequence: u64,
+    start_timestamp: u64,
+    data: Vec<u8>,
+}
+
+/// Segment listed in the playlist
+struct SegmentEntry {
+    sequence: u64,
+    duration: f64,
+}
+
+/// Muxes H.264/AAC packets into MPEG-TS segments and keeps a rolling
+/// `.m3u8` playlist on disk
+///
+/// Segments start on keyframes, so nothing is written until the first one
+/// arrives. Other codecs are ignored.
+pub struct HlsSegmenter {
+    /// Directory holding the playlist and segments
+    dir: PathBuf,
+
+    options: HlsOptions,
+
+    /// Tracks codec configs and frame types
+    video: VideoProcessor,
+    audio: AudioProcessor,
+
+    muxer: TsMuxer,
+
+    current: Option<OpenSegment>,
+    segments: VecDeque<SegmentEntry>,
+    next_sequence: u64,
+
+    /// Timestamp of the latest packet, ending the final segment
+    last_timestamp: u64,
+}
+
+impl HlsSegmenter {
+    /// Create segmenter writing into `dir`, which must exist
+    pub fn new(dir: impl Into<PathBuf>, options: HlsOptions) -> Self {
+        HlsSegmenter {
+            dir: dir.into(),
+            options,
+            video: VideoProcessor::new(),
+            audio: AudioProcessor::new(),
+            muxer: TsMuxer::new(),
+            current: None,
+            segments: VecDeque::new(),
+            next_sequence: 0,
+            last_timestamp: 0,
+        }
+    }
+
+    /// Add a packet from the publisher, at `timestamp` milliseconds on a
+    /// timeline that does not wrap like the packet's own timestamp
+    ///
+    /// Malformed media is skipped; errors come from writing files.
+    pub async fn push(&mut self, packet: &RtmpPacket, timestamp: u64) -> Result<()> {
+        if packet.is_video() {
+            self.push_video(packet, timestamp).await
+        } else if packet.is_audio() {
+            self.push_audio(packet, timestamp);
+            Ok(())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Close the last segment and mark the playlist as ended
+    pub async fn finish(&mut self) -> Result<()> {
+        if let Some(segment) = self.current.take() {
+            self.close_segment(segment, self.last_timestamp).await?;
+        }
+        self.write_playlist(true).await
+    }
+
+    async fn push_video(&mut self, packet: &RtmpPacket, dts: u64) -> Result<()> {
+        let Ok(info) = self.video.process(packet) else {
+            return Ok(());
+        };
+        if info.codec != VideoCodec::H264 || info.is_sequence_header || packet.payload.len() < 5 {
+            return Ok(());
+        }
+        let Some(config) = self.video.avc_config() else {
+            return Ok(());
+        };
+
+        let mut frame = AUD_NAL.to_vec();
+        if info.is_keyframe {
+            for nal in config.sps.iter().chain(&config.pps) {
+                frame.extend_from_slice(&START_CODE);
+                frame.extend_from_slice(nal);
+            }
+        }
+        avcc_to_annex_b(&packet.payload[5..], config.nal_length_size as usize, &mut frame);
+
+        if info.is_keyframe {
+            let elapsed = self.current.as_ref()
+                .map(|segment| dts.saturating_sub(segment.start_timestamp));
+            match elapsed {
+                Some(elapsed) if elapsed as u128 >= self.options.segment_duration.as_millis() => {
+                    if let Some(segment) = self.current.take() {
+                        self.close_segment(segment, dts).await?;
+                    }
+                    self.open_segment(dts);
+                }
+                Some(_) => {}
+                None => self.open_segment(dts),
+            }
+        }
+
+        self.last_timestamp = dts;
+        let pts = info.pts(dts);
+        if let Some(segment) = self.current.as_mut() {
+            self.muxer.write_video(&mut segment.data, &frame, pts, dts, info.is_keyframe);
+        }
+        Ok(())
+    }
+
+    fn push_audio(&mut self, packet: &RtmpPacket, timestamp: u64) {
+        let Ok(info) = self.audio.process(packet) else {
+            return;
+        };
+        if info.codec != AudioCodec::AAC || info.is_sequence_header || packet.payload.len() < 3 {
+            return;
+        }
+        let (Some(config), Some(segment)) = (self.audio.aac_config(), self.current.as_mut()) else {
+            return;
+        };
+        let Some(sampling_index) = config.adts_sampling_index() else {
+            return;
+        };
+
+        let raw = &packet.payload[2..];
+        let mut frame = adts_header(config.object_type, sampling_index, config.channel_config, raw.len()).to_vec();
+        frame.extend_from_slice(raw);
+
+        self.muxer.write_audio(&mut segment.data, &frame, timestamp);
+    }
+
+    fn open_segment(&mut self, start_timestamp: u64) {
+        let mut data = Vec::new();
+        self.muxer.write_tables(&mut data);
+
+        self.current = Some(OpenSegment {
+            sequence: self.next_sequence,
+            start_timestamp,
+            data,
+        });
+        self.next_sequence += 1;
+    }
+
+    async fn close_segment(&mut self, segment: OpenSegment, end_timestamp: u64) -> Result<()> {
+        tokio::fs::write(self.segment_path(segment.sequence), &segment.data).await?;
+
+        let duration = end_timestamp.saturating_sub(segment.start_timestamp) as f64 / 1000.0;
+        self.segments.push_back(SegmentEntry {
+            sequence: segment.sequence,
+            duration,
+        });
+
+        while self.segments.len() > self.options.window_size {
+            if let Some(expired) = self.segments.pop_front() {
+                let _ = tokio::fs::remove_file(self.segment_path(expired.sequence)).await;
+            }
+        }
+
+        self.write_playlist(false).await
+    }
+
+    fn segment_path(&self, sequence: u64) -> PathBuf {
+        self.dir.join(segment_name(sequence))
+    }
+
+    /// Replace the playlist atomically so players never read half of it
+    async fn write_playlist(&self, ended: bool) -> Result<()> {
+        let playlist = render_playlist(&self.segments, ended);
+        let temp = self.dir.join(format!("{}.tmp", HLS_PLAYLIST_NAME));
+        tokio::fs::write(&temp, playlist).await?;
+        tokio::fs::rename(&temp, self.dir.join(HLS_PLAYLIST_NAME)).await?;
+        Ok(())
+    }
+}
+
+fn segment_name(sequence: u64) -> String {
+    format!("segment{}.ts", sequence)
+}
+
+fn render_playlist(segments: &VecDeque<SegmentEntry>, ended: bool) -> String {
+    let target = segments.iter()
+        .map(|segment| segment.duration.ceil() as u64)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let media_sequence = segments.front().map(|segment| segment.sequence).unwrap_or(0);
+
+    let mut playlist = String::new();
+    playlist.push_str("#EXTM3U\n");
+    playlist.push_str("#EXT-X-VERSION:3\n");
+    playlist.push_str(&format!("#EXT-X-TARGETDURATION:{}\n", target));
+    playlist.push_str(&format!("#EXT-X-MEDIA-SEQUENCE:{}\n", media_sequence));
+    for segment in segments {
+        playlist.push_str(&format!("#EXTINF:{:.3},\n{}\n", segment.duration, segment_name(segment.sequence)));
+    }
+    if ended {
+        playlist.push_str("#EXT-X-ENDLIST\n");
+    }
+    playlist
+}
+
+/// Rewrite length-prefixed NAL units as Annex B start-code delimited ones
+fn avcc_to_annex_b(mut data: &[u8], length_size: usize, out: &mut Vec<u8>) {
+    while data.len() >= length_size {
+        let len = data[..length_size].iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        data = &data[length_size..];
+        if len > data.len() {
+            break;
+        }
+
+        out.extend_from_slice(&START_CODE);
+        out.extend_from_slice(&data[..len]);
+        data = &data[len..];
+    }
+}
+
+/// ADTS header for one raw AAC frame
+fn adts_header(object_type: u8, sampling_index: u8, channels: u8, raw_len: usize) -> [u8; 7] {
+    let frame_len = raw_len + 7;
+    let profile = object_type.saturating_sub(1) & 0x03;
+
+    [
+        0xFF,
+        0xF1, // MPEG-4, no CRC
+        (profile << 6) | ((sampling_index & 0x0F) << 2) | ((channels >> 2) & 0x01),
+        ((channels & 0x03) << 6) | ((frame_len >> 11) & 0x03) as u8,
+        ((frame_len >> 3) & 0xFF) as u8,
+        (((frame_len & 0x07) << 5) as u8) | 0x1F,
+        0xFC,
+    ]
+}
+
+/// Packs PES packets into 188 byte transport stream packets
+struct TsMuxer {
+    pat_counter: u8,
+    pmt_counter: u8,
+    video_counter: u8,
+    audio_counter: u8,
+}
+
+impl TsMuxer {
+    fn new() -> Self {
+        TsMuxer {
+            pat_counter: 0,
+            pmt_counter: 0,
+            video_counter: 0,
+            audio_counter: 0,
+        }
+    }
+
+    /// PAT and PMT, written at the start of every segment
+    fn write_tables(&mut self, out: &mut Vec<u8>) {
+        let pat = [
+            0x00, 0x01, // transport_stream_id
+            0xC1, 0x00, 0x00, // version 0, current, section 0 of 0
+            0x00, 0x01, // program_number
+            0xE0 | (PMT_PID >> 8) as u8, PMT_PID as u8,
+        ];
+        write_section(out, PAT_PID, &mut self.pat_counter, 0x00, &pat);
+
+        let mut pmt = vec![
+            0x00, 0x01, // program_number
+            0xC1, 0x00, 0x00,
+            0xE0 | (VIDEO_PID >> 8) as u8, VIDEO_PID as u8, // PCR PID
+            0xF0, 0x00, // no program info
+        ];
+        for (stream_type, pid) in [(STREAM_TYPE_H264, VIDEO_PID), (STREAM_TYPE_AAC, AUDIO_PID)] {
+            pmt.extend_from_slice(&[stream_type, 0xE0 | (pid >> 8) as u8, pid as u8, 0xF0, 0x00]);
+        }
+        write_section(out, PMT_PID, &mut self.pmt_counter, 0x02, &pmt);
+    }
+
+    fn write_video(&mut self, out: &mut Vec<u8>, frame: &[u8], pts_ms: u64, dts_ms: u64, keyframe: bool) {
+        let pts = pts_ms * 90;
+        let dts = dts_ms * 90;
+        let pes = pes_packet(PES_STREAM_VIDEO, frame, pts, (pts != dts).then_some(dts));
+        write_pes(out, VIDEO_PID, &mut self.video_counter, &pes, Some(dts), keyframe);
+    }
+
+    fn write_audio(&mut self, out: &mut Vec<u8>, frame: &[u8], pts_ms: u64) {
+        let pes = pes_packet(PES_STREAM_AUDIO, frame, pts_ms * 90, None);
+        write_pes(out, AUDIO_PID, &mut self.audio_counter, &pes, None, false);
+    }
+}
+
+/// Wrap an elementary stream frame in a PES header
+fn pes_packet(stream_id: u8, frame: &[u8], pts: u64, dts: Option<u64>) -> Vec<u8> {
+    let header_len = if dts.is_some() { 10 } else { 5 };
+    let pes_len = 3 + header_len + frame.len();
+
+    let mut pes = Vec::with_capacity(9 + header_len + frame.len());
+    pes.extend_from_slice(&[0x00, 0x00, 0x01, stream_id]);
+    // Video PES may exceed the 16 bit length; 0 means unbounded
+    let declared = if pes_len > 0xFFFF { 0 } else { pes_len as u16 };
+    pes.extend_from_slice(&declared.to_be_bytes());
+    pes.push(0x80);
+
+    match dts {
+        Some(dts) => {
+            pes.extend_from_slice(&[0xC0, header_len as u8]);
+            write_timestamp(&mut pes, 0x3, pts);
+            write_timestamp(&mut pes, 0x1, dts);
+        }
+        None => {
+            pes.extend_from_slice(&[0x80, header_len as u8]);
+            write_timestamp(&mut pes, 0x2, pts);
+        }
+    }
+
+    pes.extend_from_slice(frame);
+    pes
+}
+
+/// 33 bit PES timestamp with marker bits
+fn write_timestamp(out: &mut Vec<u8>, prefix: u8, ts: u64) {
+    out.push((prefix << 4) | ((((ts >> 30) & 0x07) as u8) << 1) | 1);
+    out.push((ts >> 22) as u8);
+    out.push(((((ts >> 15) & 0x7F) as u8) << 1) | 1);
+    out.push((ts >> 7) as u8);
+    out.push((((ts & 0x7F) as u8) << 1) | 1);
+}
+
+/// Split a PES packet across TS packets, stuffing the last one
+fn write_pes(out: &mut Vec<u8>, pid: u16, counter: &mut u8, pes: &[u8], pcr: Option<u64>, random_access: bool) {
+    let mut remaining = pes;
+    let mut first = true;
+
+    while !remaining.is_empty() {
+        // Adaptation field body, after its length byte
+        let mut adaptation: Option<Vec<u8>> = None;
+        if first && (pcr.is_some() || random_access) {
+            let mut field = vec![if random_access { 0x40 } else { 0x00 }];
+            if let Some(pcr) = pcr {
+                field[0] |= 0x10;
+                field.extend_from_slice(&[
+                    (pcr >> 25) as u8,
+                    (pcr >> 17) as u8,
+                    (pcr >> 9) as u8,
+                    (pcr >> 1) as u8,
+                    (((pcr & 0x01) as u8) << 7) | 0x7E,
+                    0x00,
+                ]);
+            }
+            adaptation = Some(field);
+        }
+
+        let overhead = adaptation.as_ref().map_or(0, |field| field.len() + 1);
+        let space = TS_PAYLOAD_SIZE - overhead;
+        if remaining.len() < space {
+            let stuffing = space - remaining.len();
+            match adaptation.as_mut() {
+                Some(field) => field.resize(field.len() + stuffing, 0xFF),
+                None if stuffing == 1 => adaptation = Some(Vec::new()),
+                None => {
+                    let mut field = vec![0x00];
+                    field.resize(stuffing - 1, 0xFF);
+                    adaptation = Some(field);
+                }
+            }
+        }
+
+        let overhead = adaptation.as_ref().map_or(0, |field| field.len() + 1);
+        let take = (TS_PAYLOAD_SIZE - overhead).min(remaining.len());
+
+        let control = if adaptation.is_some() { 0x30 } else { 0x10 };
+        write_ts_header(out, pid, first, control, counter);
+        if let Some(field) = adaptation {
+            out.push(field.len() as u8);
+            out.extend_from_slice(&field);
+        }
+        out.extend_from_slice(&remaining[..take]);
+
+        remaining = &remaining[take..];
+        first = false;
+    }
+}
+
+/// Write a PSI section in a single TS packet
+fn write_section(out: &mut Vec<u8>, pid: u16, counter: &mut u8, table_id: u8, body: &[u8]) {
+    let section_len = body.len() + 4; // plus CRC
+    let mut section = vec![table_id, 0xB0 | ((section_len >> 8) & 0x0F) as u8, section_len as u8];
+    section.extend_from_slice(body);
+    let crc = crc32_mpeg2(&section);
+    section.extend_from_slice(&crc.to_be_bytes());
+
+    write_ts_header(out, pid, true, 0x10, counter);
+    out.push(0x00); // pointer_field
+    out.extend_from_slice(&section);
+    out.resize(out.len() + TS_PAYLOAD_SIZE - 1 - section.len(), 0xFF);
+}
+
+fn write_ts_header(out: &mut Vec<u8>, pid: u16, unit_start: bool, control: u8, counter: &mut u8) {
+    out.push(0x47);
+    let unit_start = if unit_start { 0x40 } else { 0x00 };
+    out.push(unit_start | ((pid >> 8) & 0x1F) as u8);
+    out.push(pid as u8);
+    out.push(control | *counter);
+    *counter = (*counter + 1) & 0x0F;
+}
+
+/// CRC used by MPEG-2 PSI sections
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{make_audio_packet, make_video_packet};
+    use crate::stream::Publisher;
+
+    /// AVCDecoderConfigurationRecord with one SPS and one PPS
+    fn avc_sequence_header() -> RtmpPacket {
+        let mut payload = vec![0x17, 0x00, 0x00, 0x00, 0x00];
+        payload.extend_from_slice(&[0x01, 0x64, 0x00, 0x1F, 0xFF, 0xE1]);
+        payload.extend_from_slice(&[0x00, 0x04, 0x67, 0x64, 0x00, 0x1F]);
+        payload.extend_from_slice(&[0x01, 0x00, 0x03, 0x68, 0xEE, 0x3C]);
+        make_video_packet(payload, 0, 1)
+    }
+
+    fn frame(keyframe: bool, timestamp: u32) -> RtmpPacket {
+        let nal_type = if keyframe { 0x65 } else { 0x41 };
+        let mut payload = vec![if keyframe { 0x17 } else { 0x27 }, 0x01, 0x00, 0x00, 0x00];
+        payload.extend_from_slice(&400u32.to_be_bytes());
+        payload.push(nal_type);
+        payload.extend(std::iter::repeat_n(0xAB, 399));
+        make_video_packet(payload, timestamp, 1)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rtmp-hls-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_pes_split_into_ts_packets() {
+        let mut muxer = TsMuxer::new();
+        let mut out = Vec::new();
+        muxer.write_tables(&mut out);
+        muxer.write_video(&mut out, &[0x42; 1000], 80, 40, true);
+        muxer.write_audio(&mut out, &[0x21; 10], 40);
+
+        assert_eq!(out.len() % TS_PACKET_SIZE, 0);
+        assert!(out.chunks(TS_PACKET_SIZE).all(|packet| packet[0] == 0x47));
+
+        // PAT section checks out against its own CRC
+        let section_len = (((out[6] & 0x0F) as usize) << 8) | out[7] as usize;
+        assert_eq!(crc32_mpeg2(&out[5..8 + section_len]), 0);
+    }
+
+    #[test]
+    fn test_adts_header_length() {
+        let header = adts_header(2, 4, 2, 100);
+        assert_eq!(&header[..2], &[0xFF, 0xF1]);
+        let frame_len = ((header[3] as usize & 0x03) << 11) | ((header[4] as usize) << 3) | (header[5] as usize >> 5);
+        assert_eq!(frame_len, 107);
+    }
+
+    #[tokio::test]
+    async fn test_publishing_gops_writes_segments_and_playlist() {
+        let dir = temp_dir("gops");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+        let options = HlsOptions::default()
+            .segment_duration(Duration::from_secs(1))
+            .window_size(2);
+        let output = publisher.add_hls_output(&dir, options).await.unwrap();
+
+        publisher.process_video(avc_sequence_header()).await.unwrap();
+        publisher.process_audio(make_audio_packet(vec![0xAF, 0x00, 0x12, 0x10], 0, 1)).await.unwrap();
+
+        // Four one-second GOPs at 25 fps, with audio every 40 ms
+        for i in 0..100u32 {
+            let timestamp = i * 40;
+            publisher.process_video(frame(i % 25 == 0, timestamp)).await.unwrap();
+            publisher.process_audio(make_audio_packet(vec![0xAF, 0x01, 0x21, 0x00], timestamp, 1)).await.unwrap();
+        }
+
+        // Dropping the publisher ends the output, closing the last segment
+        drop(publisher);
+        output.await.unwrap().unwrap();
+
+        let playlist = tokio::fs::read_to_string(dir.join(HLS_PLAYLIST_NAME)).await.unwrap();
+        let segments: Vec<&str> = playlist.lines().filter(|line| line.ends_with(".ts")).collect();
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        assert!(playlist.starts_with("#EXTM3U\n"));
+        assert!(playlist.contains("#EXT-X-TARGETDURATION:1\n"));
+        assert!(playlist.contains("#EXT-X-MEDIA-SEQUENCE:2\n"));
+        assert!(playlist.ends_with("#EXT-X-ENDLIST\n"));
+        assert_eq!(segments, vec!["segment2.ts", "segment3.ts"]);
+    }
+
+    #[tokio::test]
+    async fn test_segments_leaving_window_are_deleted() {
+        let dir = temp_dir("window");
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+
+        let options = HlsOptions::default()
+            .segment_duration(Duration::from_secs(1))
+            .window_size(2);
+        let mut segmenter = HlsSegmenter::new(&dir, options);
+        segmenter.push(&avc_sequence_header(), 0).await.unwrap();
+        for i in 0..100u32 {
+            segmenter.push(&frame(i % 25 == 0, i * 40), i as u64 * 40).await.unwrap();
+        }
+
+        let mut files = Vec::new();
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            files.push(entry.file_name().into_string().unwrap());
+        }
+        files.sort();
+
+        let segment = tokio::fs::read(dir.join("segment2.ts")).await.unwrap();
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        // segment3 is still open; segment0 fell out of the window
+        assert_eq!(files, vec!["index.m3u8", "segment1.ts", "segment2.ts"]);
+        assert_eq!(segment.len() % TS_PACKET_SIZE, 0);
+        assert_eq!(&segment[..3], &[0x47, 0x40, 0x00]);
+    }
+}
@@ -4,8 +4,14 @@ mod stream;
 mod publisher;
 mod player;
 mod gop_cache;
+mod hls;
 
+pub use publisher::{JoinMode, Publisher, SendPacer, SUBSCRIBER_QUEUE_SIZE, DEFAULT_SUBSCRIBER_SEND_TIMEOUT, DEFAULT_AUDIO_ONLY_BUFFER};
+pub use hls::{HlsOptions, HlsSegmenter, HLS_PLAYLIST_NAME};
+pub use stream::{BitrateWindow, Stream, StreamState, StreamStats, BITRATE_WINDOW_MS};
+pub(crate) use publisher::{is_aac_sequence_header, is_avc_sequence_header};
+pub(crate) use stream::StreamType;
 
 pub async fn find_publisher(name: &str, registry: &PublisherRegistry) -> Option<PublisherInfo> {
     registry.get(name).await
-}
\ No newline at end of file
+}
@@ -1,10 +1,43 @@
-use crate::protocol::RtmpPacket;
+use bytes::Bytes;
+use crate::protocol::{make_play_stop_packet, make_stream_eof_packet, RtmpPacket};
 use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use crate::{RtmpData, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+use crate::{NetStatus, OverflowPolicy, RtmpCommand, RtmpData, RtmpHeader, Result, MSG_TYPE_AUDIO, MSG_TYPE_DATA_AMF0, MSG_TYPE_VIDEO};
+use crate::message::{is_droppable_video, is_inter_frame};
+use crate::processing::{detect_frame_type, TimestampNormalizer, VideoProcessor};
 use crate::stream::gop_cache::GopCache;
-use crate::stream::stream::{Stream, StreamMetadata};
+use crate::stream::hls::{HlsOptions, HlsSegmenter};
+use crate::stream::stream::{Stream, StreamMetadata, StreamState, StreamStats, StreamType};
+
+/// Packets a subscriber may fall behind before sends start to wait
+pub const SUBSCRIBER_QUEUE_SIZE: usize = 100;
+
+/// How long a full subscriber channel may block before it is dropped
+pub const DEFAULT_SUBSCRIBER_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Recent audio kept for subscribers joining a stream without video
+pub const DEFAULT_AUDIO_ONLY_BUFFER: Duration = Duration::from_secs(1);
+
+/// How much of the GOP cache a new subscriber is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum JoinMode {
+    /// Every cached GOP; playback starts further behind live but can
+    /// ride out an early stall
+    #[default]
+    Reliable,
+
+    /// Only the latest GOP, starting at its keyframe, for the shortest
+    /// delay behind live
+    LowLatency,
+}
 
 pub struct Publisher {
     /// Base stream
@@ -17,13 +50,31 @@ pub struct Publisher {
     subscribers: Arc<RwLock<Vec<SubscriberHandle>>>,
 
     /// Audio codec config
-    audio_codec_config: Arc<RwLock<Option<Vec<u8>>>>,
+    audio_codec_config: Arc<RwLock<Option<Bytes>>>,
 
     /// Video codec config
-    video_codec_config: Arc<RwLock<Option<Vec<u8>>>>,
+    video_codec_config: Arc<RwLock<Option<Bytes>>>,
 
     /// Metadata packet
     metadata_packet: Arc<RwLock<Option<RtmpPacket>>>,
+
+    /// Time a slow subscriber gets to drain its channel
+    send_timeout: Duration,
+
+    /// What a full subscriber channel does with live video
+    overflow_policy: OverflowPolicy,
+
+    /// Set once the source sends any video
+    video_seen: AtomicBool,
+
+    /// Recent audio, kept only while no video has been seen
+    recent_audio: Arc<RwLock<VecDeque<RtmpPacket>>>,
+
+    /// Span of audio kept in `recent_audio`
+    audio_only_buffer: Duration,
+
+    /// Woken by each audio or video packet, for idle watchdogs
+    media_arrived: Notify,
 }
 
 pub struct SubscriberHandle {
@@ -35,6 +86,39 @@ pub struct SubscriberHandle {
 
     /// Stream ID for subscriber
     stream_id: u32,
+
+    /// Delivery is suspended while paused
+    paused: bool,
+
+    /// Audio requested (receiveAudio)
+    want_audio: bool,
+
+    /// Video requested (receiveVideo)
+    want_video: bool,
+
+    /// Inter-frames are skipped until the next keyframe
+    awaiting_keyframe: AtomicBool,
+
+    /// Cached media replayed on join and resume
+    join_mode: JoinMode,
+}
+
+impl SubscriberHandle {
+    /// Check if the subscriber should get a live packet
+    fn wants(&self, packet: &RtmpPacket) -> bool {
+        !self.paused && self.accepts(packet)
+    }
+
+    /// Check if the subscriber asked for this kind of media
+    fn accepts(&self, packet: &RtmpPacket) -> bool {
+        if packet.is_audio() {
+            self.want_audio
+        } else if packet.is_video() {
+            self.want_video
+        } else {
+            true
+        }
+    }
 }
 
 impl Publisher {
@@ -47,15 +131,107 @@ impl Publisher {
             audio_codec_config: Arc::new(RwLock::new(None)),
             video_codec_config: Arc::new(RwLock::new(None)),
             metadata_packet: Arc::new(RwLock::new(None)),
+            send_timeout: DEFAULT_SUBSCRIBER_SEND_TIMEOUT,
+            overflow_policy: OverflowPolicy::default(),
+            video_seen: AtomicBool::new(false),
+            recent_audio: Arc::new(RwLock::new(VecDeque::new())),
+            audio_only_buffer: DEFAULT_AUDIO_ONLY_BUFFER,
+            media_arrived: Notify::new(),
+        }
+    }
+
+    /// Create publisher for a live stream
+    pub fn live(stream_id: u32, stream_name: String, gop_cache_size: usize) -> Self {
+        let stream = Stream::new(stream_id, stream_name, StreamType::Live);
+        Publisher::new(Arc::new(stream), gop_cache_size)
+    }
+
+    /// The stream this publisher feeds
+    pub fn stream(&self) -> &Arc<Stream> {
+        &self.stream
+    }
+
+    /// Mark the stream live once its source starts publishing
+    pub async fn start(&self) {
+        let subscribers = self.subscribers.read().await.len();
+        self.stream.set_state(StreamState::Publishing);
+        self.update_viewer_state(subscribers);
+    }
+
+    /// Mark the stream ended and tell every subscriber playback stopped
+    ///
+    /// Subscriptions are dropped, so each receiver finishes after the
+    /// `NetStream.Play.Stop` status and Stream EOF.
+    pub async fn end(&self) {
+        self.stream.set_state(StreamState::Ended);
+
+        let name = self.stream.info().await.name;
+        let subscribers = std::mem::take(&mut *self.subscribers.write().await);
+        for subscriber in subscribers {
+            send_play_stop(&subscriber.sender, &name, subscriber.stream_id, self.send_timeout).await;
+        }
+    }
+
+    /// Wait until no audio or video has arrived for `timeout`
+    ///
+    /// Returns early, without waiting out the timeout, once the stream ends.
+    pub async fn wait_for_silence(&self, timeout: Duration) {
+        let mut state = self.stream.watch_state();
+        loop {
+            tokio::select! {
+                arrived = tokio::time::timeout(timeout, self.media_arrived.notified()) => {
+                    if arrived.is_err() {
+                        return;
+                    }
+                }
+                _ = state.wait_for(|state| *state == StreamState::Ended) => return,
+            }
         }
     }
 
+    /// Switch between `Publishing` and `Playing` as subscribers come and go
+    fn update_viewer_state(&self, subscribers: usize) {
+        if matches!(self.stream.state(), StreamState::Publishing | StreamState::Playing) {
+            let state = if subscribers == 0 { StreamState::Publishing } else { StreamState::Playing };
+            self.stream.set_state(state);
+        }
+    }
+
+    /// Set how long a full subscriber channel may block before it is dropped
+    pub fn with_send_timeout(mut self, timeout: Duration) -> Self {
+        self.send_timeout = timeout;
+        self
+    }
+
+    /// Set how much recent audio a subscriber joining an audio-only stream
+    /// starts with; zero sends only the codec config
+    pub fn with_audio_only_buffer(mut self, span: Duration) -> Self {
+        self.audio_only_buffer = span;
+        self
+    }
+
+    /// Set how a full subscriber channel sheds live video
+    ///
+    /// Packets already in a channel cannot be recalled, so instead of
+    /// evicting buffered frames the incoming video frame is dropped. With
+    /// `DropToKeyframe` the subscriber then skips inter-frames until the next
+    /// keyframe. Audio, metadata and sequence headers still wait up to the
+    /// send timeout.
+    pub fn with_overflow_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow_policy = policy;
+        self
+    }
+
     /// Process audio packet
     pub async fn process_audio(&self, packet: RtmpPacket) -> Result<()> {
+        self.media_arrived.notify_one();
+
         // Check for AAC sequence header
         if is_aac_sequence_header(&packet.payload) {
             let mut config = self.audio_codec_config.write().await;
             *config = Some(packet.payload.clone());
+        } else if !self.video_seen.load(Ordering::Relaxed) {
+            self.buffer_audio(&packet).await;
         }
 
         // Update stats
@@ -63,6 +239,7 @@ impl Publisher {
             stats.audio_packets += 1;
             stats.bytes_in += packet.payload.len() as u64;
             stats.last_audio_timestamp = packet.timestamp();
+            stats.audio_window.record(packet.timestamp(), packet.payload.len());
         }).await;
 
         // Distribute to subscribers
@@ -73,10 +250,20 @@ impl Publisher {
 
     /// Process video packet
     pub async fn process_video(&self, mut packet: RtmpPacket) -> Result<()> {
+        self.media_arrived.notify_one();
+
+        // Video joins go through the GOP cache from now on
+        if !self.video_seen.swap(true, Ordering::Relaxed) {
+            self.recent_audio.write().await.clear();
+        }
+
         // Check for AVC sequence header
         if is_avc_sequence_header(&packet.payload) {
             let mut config = self.video_codec_config.write().await;
             *config = Some(packet.payload.clone());
+            drop(config);
+
+            self.fill_resolution(&packet).await;
         }
 
         // Add to GOP cache if keyframe
@@ -84,8 +271,9 @@ impl Publisher {
             let mut cache = self.gop_cache.write().await;
             cache.add_keyframe(packet.clone());
         } else {
+            let frame_type = detect_frame_type(&packet.payload);
             let mut cache = self.gop_cache.write().await;
-            cache.add_frame(packet.clone());
+            cache.add_frame(packet.clone(), frame_type);
         }
 
         // Update stats
@@ -93,6 +281,7 @@ impl Publisher {
             stats.video_packets += 1;
             stats.bytes_in += packet.payload.len() as u64;
             stats.last_video_timestamp = packet.timestamp();
+            stats.video_window.record(packet.timestamp(), packet.payload.len());
         }).await;
 
         // Distribute to subscribers
@@ -101,6 +290,41 @@ impl Publisher {
         Ok(())
     }
 
+    /// Take a resolution the metadata lacks from the sequence header's SPS
+    async fn fill_resolution(&self, packet: &RtmpPacket) {
+        let mut processor = VideoProcessor::new();
+        if processor.process(packet).is_err() {
+            return;
+        }
+        let Some((Some(width), Some(height))) = processor.avc_config().map(|c| (c.width, c.height)) else {
+            return;
+        };
+
+        let mut metadata = self.stream.info().await.metadata
+            .unwrap_or_else(|| StreamMetadata::from_amf(&Default::default()));
+        if metadata.width.is_none() || metadata.height.is_none() {
+            metadata.width = Some(width as f64);
+            metadata.height = Some(height as f64);
+            self.stream.set_metadata(metadata).await;
+        }
+    }
+
+    /// Keep the last `audio_only_buffer` of audio for joining subscribers
+    async fn buffer_audio(&self, packet: &RtmpPacket) {
+        let span = self.audio_only_buffer.as_millis() as u32;
+        if span == 0 {
+            return;
+        }
+
+        let mut recent = self.recent_audio.write().await;
+        recent.push_back(packet.clone());
+        while let Some(oldest) = recent.front()
+            && packet.timestamp().wrapping_sub(oldest.timestamp()) > span
+        {
+            recent.pop_front();
+        }
+    }
+
     /// Process metadata
     pub async fn process_metadata(&self, packet: RtmpPacket) -> Result<()> {
         // Parse metadata
@@ -126,102 +350,466 @@ impl Publisher {
         Ok(())
     }
 
+    /// Feed in a message from the stream's source, whatever carries it:
+    /// a publishing connection or an upstream server being relayed
+    ///
+    /// `onMetaData` replaces the stream's metadata, other data messages
+    /// are passed on as they are, and non-media messages are ignored.
+    pub async fn ingest(&self, packet: RtmpPacket) -> Result<()> {
+        match packet.message_type() {
+            MSG_TYPE_AUDIO => self.process_audio(packet).await,
+            MSG_TYPE_VIDEO => self.process_video(packet).await,
+            MSG_TYPE_DATA_AMF0 if RtmpData::decode(&packet.payload)?.data_type == "onMetaData" => {
+                self.process_metadata(packet).await
+            }
+            MSG_TYPE_DATA_AMF0 => self.distribute_packet(packet).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Send a data message, such as an onCuePoint, to every subscriber
+    ///
+    /// The packet is stamped with the latest media timestamp, so it plays
+    /// out in order with the audio and video around it. Unlike onMetaData it
+    /// is not kept for subscribers joining later.
+    pub async fn inject_data(&self, mut packet: RtmpPacket) -> Result<()> {
+        let stats = self.stream.stats().await;
+        packet.header.timestamp = stats.last_audio_timestamp.max(stats.last_video_timestamp);
+
+        self.distribute_packet(packet).await
+    }
+
     /// Add subscriber
+    ///
+    /// The returned receiver starts with the codec configs, metadata and the
+    /// cached GOP, followed by live packets. Re-adding an existing ID replaces
+    /// the previous subscription.
     pub async fn add_subscriber(
         &self,
         id: String,
         stream_id: u32,
     ) -> mpsc::Receiver<RtmpPacket> {
-        let (tx, rx) = mpsc::channel(100);
+        self.add_subscriber_with_mode(id, stream_id, JoinMode::default()).await
+    }
 
-        // Send initial packets
-        self.send_initial_packets(&tx, stream_id).await;
+    /// Add subscriber, choosing how much cached media it starts with
+    pub async fn add_subscriber_with_mode(
+        &self,
+        id: String,
+        stream_id: u32,
+        join_mode: JoinMode,
+    ) -> mpsc::Receiver<RtmpPacket> {
+        let initial = self.initial_packets(stream_id, join_mode).await;
+
+        // Room for the whole start-up burst, so queueing it never blocks
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_QUEUE_SIZE + initial.len());
+        for packet in initial {
+            let _ = tx.try_send(packet);
+        }
 
         // Add to subscribers
         let mut subscribers = self.subscribers.write().await;
+        subscribers.retain(|s| s.id != id);
         subscribers.push(SubscriberHandle {
             id,
             sender: tx,
             stream_id,
+            paused: false,
+            want_audio: true,
+            want_video: true,
+            awaiting_keyframe: AtomicBool::new(false),
+            join_mode,
         });
+        self.update_viewer_state(subscribers.len());
 
         rx
     }
 
+    /// Write the stream as HLS into `dir`, creating it if needed
+    ///
+    /// The output subscribes like a player, so it starts from the cached
+    /// GOP. It ends when the publisher goes away or the subscriber is
+    /// removed, closing the last segment and ending the playlist.
+    pub async fn add_hls_output(
+        &self,
+        dir: impl Into<PathBuf>,
+        options: HlsOptions,
+    ) -> Result<JoinHandle<Result<()>>> {
+        options.validate()?;
+        let dir = dir.into();
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let id = format!("hls:{}", dir.display());
+        let mut receiver = self.add_subscriber(id, self.stream.info().await.id).await;
+        let mut segmenter = HlsSegmenter::new(dir, options);
+
+        Ok(tokio::spawn(async move {
+            // Segment timing must survive the 32 bit wire timestamps wrapping
+            let mut timelines: HashMap<u32, TimestampNormalizer> = HashMap::new();
+            while let Some(packet) = receiver.recv().await {
+                let timestamp = timelines.entry(packet.header.chunk_stream_id)
+                    .or_default()
+                    .normalize(packet.timestamp());
+                segmenter.push(&packet, timestamp).await?;
+            }
+            segmenter.finish().await
+        }))
+    }
+
     /// Remove subscriber
     pub async fn remove_subscriber(&self, id: &str) {
         let mut subscribers = self.subscribers.write().await;
         subscribers.retain(|s| s.id != id);
+        self.update_viewer_state(subscribers.len());
+    }
+
+    /// Forget cached media from the previous source
+    ///
+    /// Used when a new publisher takes over the stream, so subscribers that
+    /// join or resume are not handed the old source's codec configs or GOP.
+    pub async fn reset_source(&self) {
+        self.gop_cache.write().await.clear();
+        self.recent_audio.write().await.clear();
+        self.video_seen.store(false, Ordering::Relaxed);
+        *self.audio_codec_config.write().await = None;
+        *self.video_codec_config.write().await = None;
+        *self.metadata_packet.write().await = None;
+    }
+
+    /// Pause or resume delivery to a subscriber
+    ///
+    /// Resuming replays the codec configs and cached GOP so playback restarts
+    /// on a keyframe. Returns false if there is no such subscriber.
+    pub async fn set_paused(&self, id: &str, paused: bool) -> bool {
+        // Hold the lock through the replay so no live packet overtakes it
+        let mut subscribers = self.subscribers.write().await;
+        let Some(subscriber) = subscribers.iter_mut().find(|s| s.id == id) else {
+            return false;
+        };
+
+        let resuming = subscriber.paused && !paused;
+        subscriber.paused = paused;
+        if !resuming {
+            return true;
+        }
+
+        let mut packets = self.initial_packets(subscriber.stream_id, subscriber.join_mode).await;
+        packets.retain(|p| subscriber.accepts(p));
+        if !queue_replay(subscriber, packets) {
+            subscribers.retain(|s| s.id != id);
+        }
+
+        true
+    }
+
+    /// Enable or disable audio delivery to a subscriber
+    ///
+    /// Re-enabling sends the audio sequence header first so the decoder can
+    /// pick the stream back up. Returns false if there is no such subscriber.
+    pub async fn set_receive_audio(&self, id: &str, enabled: bool) -> bool {
+        let config = self.audio_codec_config.read().await.clone()
+            .map(|config| crate::protocol::make_audio_packet(config, 0, 0));
+        self.set_receive(id, enabled, config, |s| &mut s.want_audio).await
+    }
+
+    /// Enable or disable video delivery to a subscriber
+    ///
+    /// Re-enabling sends the video sequence header first so the decoder can
+    /// pick the stream back up. Returns false if there is no such subscriber.
+    pub async fn set_receive_video(&self, id: &str, enabled: bool) -> bool {
+        let config = self.video_codec_config.read().await.clone()
+            .map(|config| crate::protocol::make_video_packet(config, 0, 0));
+        self.set_receive(id, enabled, config, |s| &mut s.want_video).await
     }
 
-    /// Send initial packets to new subscriber
-    async fn send_initial_packets(&self, sender: &mpsc::Sender<RtmpPacket>, stream_id: u32) {
+    async fn set_receive<F>(
+        &self,
+        id: &str,
+        enabled: bool,
+        sequence_header: Option<RtmpPacket>,
+        flag: F,
+    ) -> bool
+    where
+        F: FnOnce(&mut SubscriberHandle) -> &mut bool,
+    {
+        let mut subscribers = self.subscribers.write().await;
+        let Some(subscriber) = subscribers.iter_mut().find(|s| s.id == id) else {
+            return false;
+        };
+
+        let wanted = flag(subscriber);
+        let reenabled = enabled && !*wanted;
+        *wanted = enabled;
+
+        if reenabled && let Some(mut packet) = sequence_header {
+            packet.header.message_stream_id = subscriber.stream_id;
+            if !queue_replay(subscriber, vec![packet]) {
+                subscribers.retain(|s| s.id != id);
+            }
+        }
+
+        true
+    }
+
+    /// Restart delivery to a subscriber from the cached keyframe nearest
+    /// `position` (ms). Returns the timestamp delivery resumed from, or
+    /// `None` if there is no such subscriber.
+    pub async fn seek(&self, id: &str, position: u32) -> Option<u32> {
+        let mut subscribers = self.subscribers.write().await;
+        let subscriber = subscribers.iter_mut().find(|s| s.id == id)?;
+
+        let mut packets = self.packets_from(subscriber.stream_id, position).await;
+        let resumed_at = packets.iter()
+            .find(|p| p.is_video() && is_keyframe(&p.payload) && !is_avc_sequence_header(&p.payload))
+            .map(|p| p.timestamp())
+            .unwrap_or(position);
+        packets.retain(|p| subscriber.accepts(p));
+        if !queue_replay(subscriber, packets) {
+            subscribers.retain(|s| s.id != id);
+        }
+
+        Some(resumed_at)
+    }
+
+    /// Packets a new subscriber needs before live data
+    async fn initial_packets(&self, stream_id: u32, join_mode: JoinMode) -> Vec<RtmpPacket> {
+        match join_mode {
+            JoinMode::Reliable => self.packets_from(stream_id, 0).await,
+            // The latest GOP is the last one starting at or before any position
+            JoinMode::LowLatency => self.packets_from(stream_id, u32::MAX).await,
+        }
+    }
+
+    /// Codec configs and metadata, then cached media from the keyframe
+    /// nearest `position`
+    async fn packets_from(&self, stream_id: u32, position: u32) -> Vec<RtmpPacket> {
+        let mut packets = Vec::new();
+
         // Send metadata
         if let Some(metadata) = self.metadata_packet.read().await.as_ref() {
             let mut packet = metadata.clone();
             packet.header.message_stream_id = stream_id;
-            let _ = sender.send(packet).await;
+            packets.push(packet);
         }
 
         // Send audio codec config
         if let Some(config) = self.audio_codec_config.read().await.as_ref() {
-            let packet = crate::protocol::make_audio_packet(
+            packets.push(crate::protocol::make_audio_packet(
                 config.clone(),
                 0,
                 stream_id,
-            );
-            let _ = sender.send(packet).await;
+            ));
         }
 
         // Send video codec config
         if let Some(config) = self.video_codec_config.read().await.as_ref() {
-            let packet = crate::protocol::make_video_packet(
+            packets.push(crate::protocol::make_video_packet(
                 config.clone(),
                 0,
                 stream_id,
-            );
-            let _ = sender.send(packet).await;
+            ));
+        }
+
+        // Without video there is no keyframe to wait for, so start with
+        // the recent audio instead
+        if !self.video_seen.load(Ordering::Relaxed) {
+            for packet in self.recent_audio.read().await.iter() {
+                let mut packet = packet.clone();
+                packet.header.message_stream_id = stream_id;
+                packets.push(packet);
+            }
+            return packets;
         }
 
         // Send GOP cache
         let cache = self.gop_cache.read().await;
-        for packet in cache.get_gop() {
-            let mut p = packet.clone();
-            p.header.message_stream_id = stream_id;
-            let _ = sender.send(p).await;
+        for mut packet in cache.get_gop_from(position) {
+            packet.header.message_stream_id = stream_id;
+            packets.push(packet);
         }
+
+        packets
     }
 
     /// Distribute packet to all subscribers
+    ///
+    /// A subscriber whose channel stays full for longer than the send timeout,
+    /// or whose receiver is gone, is removed; dropping its sender ends the
+    /// subscriber's forwarding task.
     async fn distribute_packet(&self, packet: RtmpPacket) -> Result<()> {
         let mut failed = Vec::new();
-        let subscribers = self.subscribers.read().await;
+        let mut backlogged = Vec::new();
 
-        for subscriber in subscribers.iter() {
-            let mut p = packet.clone();
-            p.header.message_stream_id = subscriber.stream_id;
+        // Clones share the payload; only the header is copied per subscriber
+        {
+            let subscribers = self.subscribers.read().await;
+            for subscriber in subscribers.iter().filter(|s| s.wants(&packet)) {
+                let mut p = packet.clone();
+                p.header.message_stream_id = subscriber.stream_id;
 
-            if subscriber.sender.send(p).await.is_err() {
-                failed.push(subscriber.id.clone());
+                match self.try_deliver(subscriber, p) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(p)) => {
+                        backlogged.push((subscriber.id.clone(), subscriber.sender.clone(), p));
+                    }
+                    Err(TrySendError::Closed(_)) => failed.push(subscriber.id.clone()),
+                }
             }
         }
 
-        // Remove failed subscribers
-        if !failed.is_empty() {
-            drop(subscribers);
-            let mut subscribers = self.subscribers.write().await;
-            for id in failed {
-                subscribers.retain(|s| s.id != id);
+        // Subscribers that fell behind get the send timeout to catch up, all
+        // at once and without the lock, so one stalled viewer holds up
+        // neither the others nor changes to the subscriber list
+        let mut waits = JoinSet::new();
+        for (id, sender, p) in backlogged {
+            let timeout = self.send_timeout;
+            waits.spawn(async move { sender.send_timeout(p, timeout).await.is_err().then_some(id) });
+        }
+        while let Some(result) = waits.join_next().await {
+            if let Ok(Some(id)) = result {
+                failed.push(id);
             }
         }
 
+        // Remove failed subscribers
+        for id in failed {
+            self.remove_subscriber(&id).await;
+        }
+
         Ok(())
     }
 
+    /// Queue a live packet without waiting, shedding video per the
+    /// overflow policy
+    ///
+    /// A packet the policy does not let go of comes back in `Full`.
+    fn try_deliver(
+        &self,
+        subscriber: &SubscriberHandle,
+        packet: RtmpPacket,
+    ) -> std::result::Result<(), TrySendError<RtmpPacket>> {
+        // Set by the overflow policy or a cut short replay
+        if packet.is_video() {
+            if is_inter_frame(&packet) {
+                if subscriber.awaiting_keyframe.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+            } else if is_droppable_video(&packet) {
+                subscriber.awaiting_keyframe.store(false, Ordering::Relaxed);
+            }
+        }
+
+        if self.overflow_policy == OverflowPolicy::Reject || !packet.is_video() {
+            return subscriber.sender.try_send(packet);
+        }
+
+        match subscriber.sender.try_send(packet) {
+            Err(TrySendError::Full(packet)) if is_droppable_video(&packet) => {
+                if self.overflow_policy == OverflowPolicy::DropToKeyframe {
+                    subscriber.awaiting_keyframe.store(true, Ordering::Relaxed);
+                }
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Send a status command to every subscriber on its own stream
+    ///
+    /// Each subscriber may take up to the send timeout to make room; the
+    /// subscriber list is not locked meanwhile.
+    pub async fn notify_subscribers(&self, status: &RtmpCommand) {
+        let Ok(bytes) = status.encode() else {
+            return;
+        };
+        let bytes = Bytes::from(bytes);
+
+        let subscribers: Vec<_> = self.subscribers.read().await.iter()
+            .map(|s| (s.sender.clone(), s.stream_id))
+            .collect();
+        for (sender, stream_id) in subscribers {
+            let header = RtmpHeader::command(0, bytes.len() as u32, stream_id);
+            // A subscriber whose connection already closed needs no notification
+            let _ = sender.send_timeout(RtmpPacket::new(header, bytes.clone()), self.send_timeout).await;
+        }
+    }
+
     /// Get subscriber count
     pub async fn subscriber_count(&self) -> usize {
         self.subscribers.read().await.len()
     }
+
+    /// Get stream statistics
+    pub async fn stats(&self) -> StreamStats {
+        self.stream.stats().await
+    }
+
+    /// Current `(audio_bps, video_bps)` of the published stream
+    pub async fn bitrate(&self) -> (u64, u64) {
+        self.stream.bitrate().await
+    }
+}
+
+/// Subscribers still attached when a publisher is dropped without `end`
+/// get `NetStream.Play.Stop`, then their channels close
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        let Ok(mut subscribers) = self.subscribers.try_write() else {
+            return;
+        };
+        let Ok(bytes) = NetStatus::PlayStop.to_command().encode() else {
+            return;
+        };
+
+        // The same pair as `end()`, without waiting for room
+        for subscriber in subscribers.drain(..) {
+            let header = RtmpHeader::command(0, bytes.len() as u32, subscriber.stream_id);
+            if subscriber.sender.try_send(RtmpPacket::new(header, bytes.clone())).is_ok() {
+                let _ = subscriber.sender.try_send(make_stream_eof_packet(subscriber.stream_id));
+            }
+        }
+    }
+}
+
+/// Caps the rate packets are handed to one subscriber's connection
+///
+/// Each packet waits until the bytes sent before it fit within the rate, so
+/// a cached GOP is spread out instead of written in one burst. Budget left
+/// unused while idle is not saved up for a later burst.
+pub struct SendPacer {
+    /// Bits per second
+    max_bitrate: u64,
+
+    /// When the current run of paced packets started
+    started: Instant,
+
+    /// Bits sent since `started`
+    sent_bits: u64,
+}
+
+impl SendPacer {
+    /// Pace to at most `max_bitrate` bits per second
+    pub fn new(max_bitrate: u64) -> Self {
+        SendPacer {
+            max_bitrate: max_bitrate.max(1),
+            started: Instant::now(),
+            sent_bits: 0,
+        }
+    }
+
+    /// Wait until `packet` may be sent, then count it as sent
+    pub async fn wait(&mut self, packet: &RtmpPacket) {
+        let due = self.started + Duration::from_secs_f64(self.sent_bits as f64 / self.max_bitrate as f64);
+        let now = Instant::now();
+        if due > now {
+            tokio::time::sleep_until(due).await;
+        } else {
+            self.started = now;
+            self.sent_bits = 0;
+        }
+
+        self.sent_bits += packet.payload.len() as u64 * 8;
+    }
 }
 
 // Helper functions
@@ -235,7 +823,7 @@ fn is_keyframe(data: &[u8]) -> bool {
     frame_type == 1 // Keyframe
 }
 
-fn is_aac_sequence_header(data: &[u8]) -> bool {
+pub(crate) fn is_aac_sequence_header(data: &[u8]) -> bool {
     if data.len() < 2 {
         return false;
     }
@@ -245,7 +833,7 @@ fn is_aac_sequence_header(data: &[u8]) -> bool {
     sound_format == 10 && aac_packet_type == 0
 }
 
-fn is_avc_sequence_header(data: &[u8]) -> bool {
+pub(crate) fn is_avc_sequence_header(data: &[u8]) -> bool {
     if data.len() < 2 {
         return false;
     }
@@ -254,4 +842,348 @@ fn is_avc_sequence_header(data: &[u8]) -> bool {
     let codec_id = video_tag_header & 0x0F;
     let avc_packet_type = data[1];
     codec_id == 7 && avc_packet_type == 0
-}
\ No newline at end of file
+}
+
+/// Tell a subscriber its stream stopped: `NetStream.Play.Stop`, then Stream EOF
+///
+/// Each message may wait up to `timeout` for room in the channel.
+async fn send_play_stop(sender: &mpsc::Sender<RtmpPacket>, stream_name: &str, stream_id: u32, timeout: Duration) {
+    let Ok(stop) = make_play_stop_packet(stream_name, stream_id) else {
+        return;
+    };
+
+    if sender.send_timeout(stop, timeout).await.is_ok() {
+        let _ = sender.send_timeout(make_stream_eof_packet(stream_id), timeout).await;
+    }
+}
+
+/// Queue replayed packets ahead of live delivery without waiting
+///
+/// Callers hold the subscribers lock so no live packet overtakes the
+/// replay. If the channel fills up, the rest is skipped and video picks up
+/// again at the next live keyframe. Returns false if the subscriber is gone.
+fn queue_replay(subscriber: &SubscriberHandle, packets: Vec<RtmpPacket>) -> bool {
+    for packet in packets {
+        match subscriber.sender.try_send(packet) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                subscriber.awaiting_keyframe.store(true, Ordering::Relaxed);
+                break;
+            }
+            Err(TrySendError::Closed(_)) => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_slow_subscriber_dropped_after_timeout() {
+        let publisher = Publisher::live(1, "live".to_string(), 1)
+            .with_send_timeout(Duration::from_millis(10));
+
+        let _stalled = publisher.add_subscriber("slow".to_string(), 1).await;
+        let mut active = publisher.add_subscriber("fast".to_string(), 1).await;
+
+        // Nobody drains the stalled subscriber, so its channel fills up
+        for i in 0..=SUBSCRIBER_QUEUE_SIZE as u32 {
+            let audio = crate::protocol::make_audio_packet(vec![0xAF, 0x01], i, 1);
+            publisher.process_audio(audio).await.unwrap();
+            while active.try_recv().is_ok() {}
+        }
+
+        assert_eq!(publisher.subscriber_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_subscriber_does_not_block_others() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+
+        let mut stalled = publisher.add_subscriber("slow".to_string(), 1).await;
+        let mut active = publisher.add_subscriber("fast".to_string(), 1).await;
+
+        for i in 0..SUBSCRIBER_QUEUE_SIZE as u32 {
+            let audio = crate::protocol::make_audio_packet(vec![0xAF, 0x01], i, 1);
+            publisher.process_audio(audio).await.unwrap();
+            while active.try_recv().is_ok() {}
+        }
+
+        // While the publisher waits on the stalled subscriber, the others
+        // already have the packet and the subscriber list stays writable
+        let audio = crate::protocol::make_audio_packet(vec![0xAF, 0x01], 1000, 1);
+        let (result, ()) = tokio::join!(publisher.process_audio(audio), async {
+            assert!(active.recv().await.is_some());
+            let late = publisher.add_subscriber("late".to_string(), 2);
+            tokio::time::timeout(Duration::from_secs(1), late).await.expect("subscriber list is locked");
+            stalled.recv().await;
+        });
+        result.unwrap();
+
+        assert_eq!(publisher.subscriber_count().await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_publisher_closes_subscribers() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+        let mut first = publisher.add_subscriber("viewer1".to_string(), 1).await;
+        let mut second = publisher.add_subscriber("viewer2".to_string(), 2).await;
+
+        drop(publisher);
+
+        for (rx, stream_id) in [(&mut first, 1), (&mut second, 2)] {
+            let stop = rx.recv().await.expect("Play.Stop should arrive before the close");
+            let status = crate::RtmpCommand::decode(&stop.payload).unwrap();
+            let code = status.arguments[0].get_property("code").and_then(|v| v.as_string());
+            assert_eq!(code, Some("NetStream.Play.Stop"));
+
+            let eof = crate::UserControlEvent::decode(&rx.recv().await.unwrap().payload).unwrap();
+            assert_eq!(eof, crate::UserControlEvent::StreamEof(stream_id));
+            assert!(rx.recv().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_end_sends_play_stop_and_stream_eof() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+        let mut viewer = publisher.add_subscriber("viewer".to_string(), 3).await;
+
+        publisher.end().await;
+
+        let stop = viewer.recv().await.unwrap();
+        let status = crate::RtmpCommand::decode(&stop.payload).unwrap();
+        let code = status.arguments[0].get_property("code").and_then(|v| v.as_string());
+        assert_eq!(code, Some("NetStream.Play.Stop"));
+        assert_eq!(stop.message_stream_id(), 3);
+
+        let eof = viewer.recv().await.unwrap();
+        let event = crate::UserControlEvent::decode(&eof.payload).unwrap();
+        assert_eq!(event, crate::UserControlEvent::StreamEof(3));
+        assert!(viewer.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_new_subscriber_gets_codec_config() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+
+        let config = crate::protocol::make_video_packet(vec![0x17, 0x00, 0x01], 0, 1);
+        publisher.process_video(config).await.unwrap();
+
+        let mut rx = publisher.add_subscriber("viewer".to_string(), 5).await;
+        let first = rx.try_recv().unwrap();
+        assert_eq!(first.payload, vec![0x17, 0x00, 0x01]);
+        assert_eq!(first.message_stream_id(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_distribution_shares_payload() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+        let mut receivers = Vec::new();
+        for i in 0..50 {
+            receivers.push(publisher.add_subscriber(format!("viewer{}", i), i + 1).await);
+        }
+
+        let frame = crate::protocol::make_video_packet(vec![0x27; 64 * 1024], 40, 1);
+        let payload = frame.payload.as_ptr();
+        publisher.process_video(frame).await.unwrap();
+
+        // Every subscriber gets its own header over the same payload bytes
+        for (i, rx) in receivers.iter_mut().enumerate() {
+            let packet = rx.try_recv().unwrap();
+            assert_eq!(packet.payload.as_ptr(), payload);
+            assert_eq!(packet.message_stream_id(), i as u32 + 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_injected_cue_point_follows_media() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+        let mut rx = publisher.add_subscriber("viewer".to_string(), 1).await;
+
+        publisher.process_video(crate::protocol::make_video_packet(vec![0x27, 0x01], 40, 1)).await.unwrap();
+
+        let bytes = RtmpData::on_cue_point("ad-break", 0.04, Default::default()).encode().unwrap();
+        let cue_point = RtmpPacket::new(RtmpHeader::data(0, bytes.len() as u32, 1), bytes);
+        publisher.inject_data(cue_point).await.unwrap();
+
+        publisher.process_video(crate::protocol::make_video_packet(vec![0x27, 0x01], 80, 1)).await.unwrap();
+
+        let received: Vec<_> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(received.len(), 3);
+        assert!(received[1].is_data());
+        assert_eq!(RtmpData::decode(&received[1].payload).unwrap().data_type, "onCuePoint");
+
+        let timestamps: Vec<_> = received.iter().map(|p| p.timestamp()).collect();
+        assert_eq!(timestamps, [40, 40, 80]);
+    }
+
+    #[tokio::test]
+    async fn test_resolution_from_sequence_header() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+
+        // 720p Baseline SPS, no onMetaData
+        let sps = [
+            0x67, 0x42, 0xC0, 0x1F, 0xDA, 0x01, 0x40, 0x16, 0xEC, 0x04, 0x40, 0x00, 0x00, 0x03,
+            0x00, 0x40, 0x00, 0x00, 0x0C, 0x83, 0xC6, 0x0C, 0xA8,
+        ];
+        let mut payload = vec![0x17, 0x00, 0x00, 0x00, 0x00, 0x01, 0x42, 0xC0, 0x1F, 0xFF, 0xE1, 0x00, sps.len() as u8];
+        payload.extend_from_slice(&sps);
+        payload.extend_from_slice(&[0x01, 0x00, 0x04, 0x68, 0xCE, 0x3C, 0x80]);
+        publisher.process_video(crate::protocol::make_video_packet(payload, 0, 1)).await.unwrap();
+
+        let metadata = publisher.stream.info().await.metadata.unwrap();
+        assert_eq!((metadata.width, metadata.height), (Some(1280.0), Some(720.0)));
+    }
+
+    #[tokio::test]
+    async fn test_drop_to_keyframe_keeps_slow_subscriber() {
+        let publisher = Publisher::live(1, "live".to_string(), 1)
+            .with_send_timeout(Duration::from_millis(10))
+            .with_overflow_policy(OverflowPolicy::DropToKeyframe);
+
+        let mut rx = publisher.add_subscriber("slow".to_string(), 1).await;
+        for i in 0..=SUBSCRIBER_QUEUE_SIZE as u32 {
+            let frame = crate::protocol::make_video_packet(vec![0x27, 0x01], i, 1);
+            publisher.process_video(frame).await.unwrap();
+        }
+        assert_eq!(publisher.subscriber_count().await, 1);
+
+        // Once drained, inter-frames stay skipped until the next keyframe
+        while rx.try_recv().is_ok() {}
+        let frame = crate::protocol::make_video_packet(vec![0x27, 0x01], 1000, 1);
+        publisher.process_video(frame).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        let keyframe = crate::protocol::make_video_packet(vec![0x17, 0x01], 1040, 1);
+        publisher.process_video(keyframe).await.unwrap();
+        assert_eq!(rx.try_recv().unwrap().timestamp(), 1040);
+    }
+
+    #[tokio::test]
+    async fn test_resume_replay_larger_than_channel_skips_to_keyframe() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+        let mut rx = publisher.add_subscriber("viewer".to_string(), 1).await;
+        assert!(publisher.set_paused("viewer", true).await);
+
+        // The cached GOP no longer fits the channel, so resuming cannot
+        // replay all of it and must not wait for room either
+        let keyframe = crate::protocol::make_video_packet(vec![0x17, 0x01], 0, 1);
+        publisher.process_video(keyframe).await.unwrap();
+        for i in 1..=SUBSCRIBER_QUEUE_SIZE as u32 * 2 {
+            let frame = crate::protocol::make_video_packet(vec![0x27, 0x01], i, 1);
+            publisher.process_video(frame).await.unwrap();
+        }
+        let resume = publisher.set_paused("viewer", false);
+        assert!(tokio::time::timeout(Duration::from_secs(1), resume).await.unwrap());
+        assert_eq!(publisher.subscriber_count().await, 1);
+
+        // Live video picks up again at the next keyframe
+        while rx.try_recv().is_ok() {}
+        let frame = crate::protocol::make_video_packet(vec![0x27, 0x01], 1000, 1);
+        publisher.process_video(frame).await.unwrap();
+        assert!(rx.try_recv().is_err());
+
+        let keyframe = crate::protocol::make_video_packet(vec![0x17, 0x01], 1040, 1);
+        publisher.process_video(keyframe).await.unwrap();
+        assert_eq!(rx.try_recv().unwrap().timestamp(), 1040);
+    }
+
+    #[tokio::test]
+    async fn test_bitrate_tracks_audio_and_video() {
+        let publisher = Publisher::live(1, "live".to_string(), 1);
+
+        // 2 s of 25 fps video at 4000 bytes and 50 pps audio at 250 bytes
+        for i in 0..100u32 {
+            let audio = crate::protocol::make_audio_packet(vec![0xAF; 250], i * 20, 1);
+            publisher.process_audio(audio).await.unwrap();
+            if i % 2 == 0 {
+                let video = crate::protocol::make_video_packet(vec![0x27; 4000], i * 20, 1);
+                publisher.process_video(video).await.unwrap();
+            }
+        }
+
+        let (audio_bps, video_bps) = publisher.bitrate().await;
+        assert!((99_000..=101_000).contains(&audio_bps), "audio {} bps", audio_bps);
+        assert!((792_000..=808_000).contains(&video_bps), "video {} bps", video_bps);
+    }
+
+    #[tokio::test]
+    async fn test_low_latency_join_starts_at_latest_keyframe() {
+        let publisher = Publisher::live(1, "live".to_string(), 3);
+        let config = crate::protocol::make_video_packet(vec![0x17, 0x00, 0x01], 0, 1);
+        publisher.process_video(config).await.unwrap();
+        for base in [0, 1000, 2000] {
+            let keyframe = crate::protocol::make_video_packet(vec![0x17, 0x01], base, 1);
+            publisher.process_video(keyframe).await.unwrap();
+            for i in 1..25 {
+                let frame = crate::protocol::make_video_packet(vec![0x27, 0x01], base + i * 40, 1);
+                publisher.process_video(frame).await.unwrap();
+            }
+        }
+
+        let drain = |mut rx: mpsc::Receiver<RtmpPacket>| {
+            let mut packets = Vec::new();
+            while let Ok(packet) = rx.try_recv() {
+                packets.push(packet);
+            }
+            packets
+        };
+        let reliable = drain(publisher.add_subscriber("reliable".to_string(), 1).await);
+        let fast = drain(publisher.add_subscriber_with_mode("fast".to_string(), 1, JoinMode::LowLatency).await);
+
+        assert_eq!(reliable.len(), 76);
+        assert_eq!(fast.len(), 26);
+
+        // Sequence header first, then media from the newest keyframe
+        assert_eq!(fast[0].payload, vec![0x17, 0x00, 0x01]);
+        assert_eq!(fast[1].payload, vec![0x17, 0x01]);
+        assert_eq!(fast[1].timestamp(), 2000);
+    }
+
+    #[tokio::test]
+    async fn test_audio_only_join_gets_recent_audio() {
+        let publisher = Publisher::live(1, "radio".to_string(), 1);
+
+        let config = crate::protocol::make_audio_packet(vec![0xAF, 0x00, 0x12, 0x10], 0, 1);
+        publisher.process_audio(config).await.unwrap();
+        for i in 0..=20 {
+            let audio = crate::protocol::make_audio_packet(vec![0xAF, 0x01, i as u8], i * 100, 1);
+            publisher.process_audio(audio).await.unwrap();
+        }
+
+        let mut rx = publisher.add_subscriber("listener".to_string(), 3).await;
+        let mut packets = Vec::new();
+        while let Ok(packet) = rx.try_recv() {
+            packets.push(packet);
+        }
+
+        // Sequence header, then the last second of audio
+        assert_eq!(packets[0].payload, vec![0xAF, 0x00, 0x12, 0x10]);
+        let timestamps: Vec<_> = packets[1..].iter().map(|p| p.timestamp()).collect();
+        assert_eq!(timestamps, (10..=20).map(|i| i * 100).collect::<Vec<_>>());
+        assert!(packets.iter().all(|p| p.is_audio() && p.message_stream_id() == 3));
+    }
+
+    #[tokio::test]
+    async fn test_send_pacer_spreads_burst() {
+        // 80 kbit/s lets one 1000 byte packet through every 100ms
+        let max_bitrate = 80_000;
+        let mut pacer = SendPacer::new(max_bitrate);
+        let packets: Vec<_> = (0..5)
+            .map(|i| crate::protocol::make_video_packet(vec![0x27; 1000], i * 40, 1))
+            .collect();
+
+        let started = std::time::Instant::now();
+        for packet in &packets {
+            pacer.wait(packet).await;
+        }
+        let elapsed = started.elapsed();
+
+        // Everything but the last packet must have drained at the cap
+        let paced_bits = 4 * 1000 * 8;
+        assert!(elapsed >= Duration::from_secs_f64(paced_bits as f64 / max_bitrate as f64));
+        assert!(elapsed < Duration::from_secs(2));
+    }
+}
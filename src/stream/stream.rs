@@ -2,8 +2,8 @@ use crate::{Error, Result};
 use crate::protocol::{RtmpPacket, RtmpData};
 use crate::amf::Amf0Value;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use std::collections::HashMap;
+use tokio::sync::{watch, RwLock};
+use std::collections::{HashMap, VecDeque};
 
 #[derive(Debug, Clone)]
 pub struct StreamInfo {
@@ -31,6 +31,34 @@ pub enum StreamType {
     PlayOnly,
 }
 
+impl StreamType {
+    /// Type named by a `publish` command, if it is one of live, record or append
+    pub fn from_publish_type(publish_type: &str) -> Option<StreamType> {
+        match publish_type {
+            "live" => Some(StreamType::Live),
+            "record" => Some(StreamType::Record),
+            "append" => Some(StreamType::Append),
+            _ => None,
+        }
+    }
+}
+
+/// Lifecycle of a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamState {
+    /// Created, no source yet
+    Idle,
+
+    /// A source is publishing and nobody is watching
+    Publishing,
+
+    /// A source is publishing to at least one subscriber
+    Playing,
+
+    /// The source went away; the stream will not resume
+    Ended,
+}
+
 #[derive(Debug, Clone)]
 pub struct StreamMetadata {
     /// Video codec
@@ -84,12 +112,13 @@ impl StreamMetadata {
             custom: HashMap::new(),
         };
 
-        // Store other properties as custom
-        for (key, value) in data {
-            if !is_standard_metadata_key(key) {
-                metadata.custom.insert(key.clone(), value.clone());
-            }
-        }
+        // Keep whatever the typed fields would not reproduce, such as
+        // dates or a numeric codec ID, as custom
+        let typed = metadata.to_amf();
+        metadata.custom = data.iter()
+            .filter(|(key, value)| typed.get(*key) != Some(*value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
 
         metadata
     }
@@ -138,22 +167,19 @@ impl StreamMetadata {
     }
 }
 
-fn is_standard_metadata_key(key: &str) -> bool {
-    matches!(key,
-        "videocodecid" | "audiocodecid" | "width" | "height" | "framerate" |
-        "videodatarate" | "audiodatarate" | "audiosamplerate" | "audiochannels" | "duration"
-    )
-}
-
 pub struct Stream {
     /// Stream info
     info: Arc<RwLock<StreamInfo>>,
 
     /// Stream statistics
     stats: Arc<RwLock<StreamStats>>,
+
+    /// Lifecycle state
+    state: watch::Sender<StreamState>,
 }
 
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct StreamStats {
     /// Bytes received
     pub bytes_in: u64,
@@ -175,6 +201,65 @@ pub struct StreamStats {
 
     /// Last video timestamp
     pub last_video_timestamp: u32,
+
+    /// Recent audio samples for bitrate estimation
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub audio_window: BitrateWindow,
+
+    /// Recent video samples for bitrate estimation
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub video_window: BitrateWindow,
+}
+
+/// Span of media time the bitrate is averaged over
+pub const BITRATE_WINDOW_MS: u32 = 5000;
+
+/// Samples kept per window, bounding memory at high packet rates
+const BITRATE_WINDOW_SAMPLES: usize = 1024;
+
+/// Rolling window of (timestamp, bytes) samples
+#[derive(Debug, Default, Clone)]
+pub struct BitrateWindow {
+    samples: VecDeque<(u32, usize)>,
+
+    /// Sum of bytes in `samples`
+    bytes: usize,
+}
+
+impl BitrateWindow {
+    /// Record a packet of `bytes` at `timestamp` (ms)
+    pub fn record(&mut self, timestamp: u32, bytes: usize) {
+        // A timestamp going backwards means the publisher restarted its clock
+        if self.samples.back().is_some_and(|&(last, _)| timestamp < last) {
+            self.samples.clear();
+            self.bytes = 0;
+        }
+
+        self.samples.push_back((timestamp, bytes));
+        self.bytes += bytes;
+
+        while let Some(&(oldest, size)) = self.samples.front() {
+            if timestamp - oldest <= BITRATE_WINDOW_MS && self.samples.len() <= BITRATE_WINDOW_SAMPLES {
+                break;
+            }
+            self.samples.pop_front();
+            self.bytes -= size;
+        }
+    }
+
+    /// Bits per second over the window, 0 until two samples span some time
+    pub fn current_bitrate_bps(&self) -> u64 {
+        let (Some(&(first, first_bytes)), Some(&(last, _))) = (self.samples.front(), self.samples.back()) else {
+            return 0;
+        };
+
+        // The first sample's bytes arrived before the measured span
+        let span_ms = (last - first) as u64;
+        if span_ms == 0 {
+            return 0;
+        }
+        (self.bytes - first_bytes) as u64 * 8 * 1000 / span_ms
+    }
 }
 
 impl Stream {
@@ -191,14 +276,41 @@ impl Stream {
         Stream {
             info: Arc::new(RwLock::new(info)),
             stats: Arc::new(RwLock::new(StreamStats::default())),
+            state: watch::Sender::new(StreamState::Idle),
         }
     }
 
+    /// Current lifecycle state
+    pub fn state(&self) -> StreamState {
+        *self.state.borrow()
+    }
+
+    /// Subscribe to lifecycle changes, starting from the current state
+    pub fn watch_state(&self) -> watch::Receiver<StreamState> {
+        self.state.subscribe()
+    }
+
+    /// Move to `state`; `Ended` is final
+    pub(crate) fn set_state(&self, state: StreamState) {
+        self.state.send_if_modified(|current| {
+            if *current == state || *current == StreamState::Ended {
+                return false;
+            }
+            *current = state;
+            true
+        });
+    }
+
     /// Get stream info
     pub async fn info(&self) -> StreamInfo {
         self.info.read().await.clone()
     }
 
+    /// Change how the stream is published
+    pub async fn set_stream_type(&self, stream_type: StreamType) {
+        self.info.write().await.stream_type = stream_type;
+    }
+
     /// Update metadata
     pub async fn set_metadata(&self, metadata: StreamMetadata) {
         let mut info = self.info.write().await;
@@ -218,4 +330,68 @@ impl Stream {
     pub async fn stats(&self) -> StreamStats {
         (*self.stats.read().await).clone()
     }
+
+    /// Current `(audio_bps, video_bps)` over the last few seconds of media
+    pub async fn bitrate(&self) -> (u64, u64) {
+        let stats = self.stats.read().await;
+        (stats.audio_window.current_bitrate_bps(), stats.video_window.current_bitrate_bps())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_round_trip_keeps_dates() {
+        let mut data = HashMap::new();
+        data.insert("width".to_string(), Amf0Value::Number(1280.0));
+        data.insert("videocodecid".to_string(), Amf0Value::Number(7.0));
+        data.insert("creationdate".to_string(), Amf0Value::Date(1_700_000_000_000.0, 60));
+        data.insert("encoder".to_string(), Amf0Value::String("obs".to_string()));
+
+        let metadata = StreamMetadata::from_amf(&data);
+        assert_eq!(metadata.width, Some(1280.0));
+        assert_eq!(metadata.custom.len(), 3);
+        assert_eq!(metadata.to_amf(), data);
+    }
+
+    #[test]
+    fn test_bitrate_window_at_known_rate() {
+        // 25 fps of 5000 byte frames is 1 Mbit/s
+        let mut window = BitrateWindow::default();
+        for i in 0..250 {
+            window.record(i * 40, 5000);
+        }
+
+        let bps = window.current_bitrate_bps();
+        assert!((990_000..=1_010_000).contains(&bps), "estimated {} bps", bps);
+        assert!(window.samples.len() <= (BITRATE_WINDOW_MS / 40 + 1) as usize);
+    }
+
+    #[test]
+    fn test_bitrate_window_follows_rate_change() {
+        let mut window = BitrateWindow::default();
+        for i in 0..250 {
+            window.record(i * 40, 5000);
+        }
+
+        // Halve the frame size; after a full window only the new rate counts
+        for i in 250..400 {
+            window.record(i * 40, 2500);
+        }
+        let bps = window.current_bitrate_bps();
+        assert!((495_000..=505_000).contains(&bps), "estimated {} bps", bps);
+    }
+
+    #[test]
+    fn test_bitrate_window_resets_on_timestamp_rewind() {
+        let mut window = BitrateWindow::default();
+        window.record(10_000, 1000);
+        window.record(10_040, 1000);
+        assert!(window.current_bitrate_bps() > 0);
+
+        window.record(0, 1000);
+        assert_eq!(window.current_bitrate_bps(), 0);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,149 @@
+use std::net::SocketAddr;
+use log::warn;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use crate::{Error, Result};
+
+/// Source of incoming byte streams for `RtmpServer::listen_on`
+///
+/// Anything that yields ordered, reliable streams can carry RTMP: TCP,
+/// Unix sockets, or a QUIC connection's bidirectional streams.
+#[async_trait::async_trait]
+pub trait Transport: Send + Sync + 'static {
+    /// Stream handed to the connection pipeline
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Wait for the next incoming stream and the address it came from
+    ///
+    /// Transports without network addresses report a placeholder; the
+    /// server applies its per-IP limits to whatever is returned.
+    async fn accept(&self) -> Result<(Self::Stream, SocketAddr)>;
+}
+
+/// Opens outgoing byte streams for `RtmpClient::connect_with`
+#[async_trait::async_trait]
+pub trait Connector: Send + Sync {
+    /// Stream handed to the connection pipeline
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    /// Open a stream to the server named in the RTMP URL
+    async fn connect(&self, host: &str, port: u16) -> Result<Self::Stream>;
+}
+
+/// TCP listener, with Nagle's algorithm disabled on accepted sockets
+pub struct TcpTransport {
+    listener: TcpListener,
+}
+
+impl TcpTransport {
+    /// Listen on `host`:`port`
+    pub async fn bind(host: &str, port: u16) -> Result<Self> {
+        Self::bind_with_reuse_port(host, port, false).await
+    }
+
+    /// Listen on `host`:`port`, optionally sharing it via `SO_REUSEPORT`
+    pub async fn bind_with_reuse_port(host: &str, port: u16, reuse_port: bool) -> Result<Self> {
+        let listener = crate::server::bind_address(host, port, reuse_port).await
+            .map_err(|e| Error::connection(format!("Failed to bind {}:{}: {}", host, port, e)))?;
+        Ok(TcpTransport { listener })
+    }
+
+    /// Address actually bound, useful after binding port 0
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+}
+
+impl From<TcpListener> for TcpTransport {
+    fn from(listener: TcpListener) -> Self {
+        TcpTransport { listener }
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for TcpTransport {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> Result<(TcpStream, SocketAddr)> {
+        let (stream, peer_addr) = self.listener.accept().await?;
+        if let Err(e) = stream.set_nodelay(true) {
+            warn!(peer:% = peer_addr; "Failed to set TCP_NODELAY: {}", e);
+        }
+        Ok((stream, peer_addr))
+    }
+}
+
+/// Plain TCP connections, with Nagle's algorithm disabled
+pub struct TcpConnector;
+
+#[async_trait::async_trait]
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+
+    async fn connect(&self, host: &str, port: u16) -> Result<TcpStream> {
+        let addr = format!("{}:{}", host, port);
+        let stream = TcpStream::connect(&addr).await
+            .map_err(|e| Error::connection(format!("Failed to connect to {}: {}", addr, e)))?;
+        stream.set_nodelay(true)?;
+        Ok(stream)
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{UnixConnector, UnixTransport};
+
+#[cfg(unix)]
+mod unix {
+    use std::net::{Ipv4Addr, SocketAddr};
+    use std::path::{Path, PathBuf};
+    use tokio::net::{UnixListener, UnixStream};
+    use super::{Connector, Transport};
+    use crate::{Error, Result};
+
+    /// Unix domain socket listener; every peer is reported as `127.0.0.1:0`
+    pub struct UnixTransport {
+        listener: UnixListener,
+    }
+
+    impl UnixTransport {
+        /// Listen on the socket file at `path`
+        pub fn bind(path: impl AsRef<Path>) -> Result<Self> {
+            let path = path.as_ref();
+            let listener = UnixListener::bind(path)
+                .map_err(|e| Error::connection(format!("Failed to bind {}: {}", path.display(), e)))?;
+            Ok(UnixTransport { listener })
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Transport for UnixTransport {
+        type Stream = UnixStream;
+
+        async fn accept(&self) -> Result<(UnixStream, SocketAddr)> {
+            let (stream, _) = self.listener.accept().await?;
+            Ok((stream, SocketAddr::from((Ipv4Addr::LOCALHOST, 0))))
+        }
+    }
+
+    /// Connects to a Unix domain socket, whatever host the URL names
+    pub struct UnixConnector {
+        path: PathBuf,
+    }
+
+    impl UnixConnector {
+        /// Connect to the socket file at `path`
+        pub fn new(path: impl Into<PathBuf>) -> Self {
+            UnixConnector { path: path.into() }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Connector for UnixConnector {
+        type Stream = UnixStream;
+
+        async fn connect(&self, _host: &str, _port: u16) -> Result<UnixStream> {
+            UnixStream::connect(&self.path).await
+                .map_err(|e| Error::connection(format!("Failed to connect to {}: {}", self.path.display(), e)))
+        }
+    }
+}
@@ -57,6 +57,14 @@ impl ByteBuffer {
         Ok(bytes)
     }
 
+    /// Look at the next `len` bytes without advancing the cursor
+    pub fn peek_bytes(&self, len: usize) -> IoResult<&[u8]> {
+        if !self.has_remaining(len) {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "Not enough bytes"));
+        }
+        Ok(&self.buffer[self.cursor..self.cursor + len])
+    }
+
     /// Write bytes to buffer
     pub fn write_bytes(&mut self, data: &[u8]) -> IoResult<()> {
         self.buffer.extend_from_slice(data);
@@ -73,6 +81,14 @@ impl ByteBuffer {
         Ok(value)
     }
 
+    /// Look at the next u8 without advancing the cursor
+    pub fn peek_u8(&self) -> IoResult<u8> {
+        if !self.has_remaining(1) {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "Not enough bytes"));
+        }
+        Ok(self.buffer[self.cursor])
+    }
+
     /// Write u8
     pub fn write_u8(&mut self, value: u8) -> IoResult<()> {
         self.buffer.push(value);
@@ -117,6 +133,28 @@ impl ByteBuffer {
         Ok(())
     }
 
+    /// Read u24 (big endian)
+    pub fn read_u24_be(&mut self) -> IoResult<u32> {
+        if !self.has_remaining(3) {
+            return Err(IoError::new(ErrorKind::UnexpectedEof, "Not enough bytes"));
+        }
+        let mut cursor = Cursor::new(&self.buffer[self.cursor..]);
+        let value = cursor.read_u24::<BigEndian>()?;
+        self.cursor += 3;
+        Ok(value)
+    }
+
+    /// Write u24 (big endian), failing for values above 0xFFFFFF
+    pub fn write_u24_be(&mut self, value: u32) -> IoResult<()> {
+        if value > 0xFFFFFF {
+            return Err(IoError::new(ErrorKind::InvalidInput, "Value exceeds 24 bits"));
+        }
+        let mut bytes = vec![];
+        bytes.write_u24::<BigEndian>(value)?;
+        self.buffer.extend_from_slice(&bytes);
+        Ok(())
+    }
+
     /// Read u32 (big endian)
     pub fn read_u32_be(&mut self) -> IoResult<u32> {
         if !self.has_remaining(4) {
@@ -206,6 +244,54 @@ mod tests {
         assert_eq!(buffer.read_u16_be().unwrap(), 0x1234);
     }
 
+    #[test]
+    fn test_read_write_u24() {
+        let mut buffer = ByteBuffer::with_capacity(10);
+        buffer.write_u24_be(0x123456).unwrap();
+        buffer.write_u24_be(0xFFFFFF).unwrap();
+        assert_eq!(buffer.as_slice(), &[0x12, 0x34, 0x56, 0xFF, 0xFF, 0xFF]);
+
+        assert_eq!(buffer.read_u24_be().unwrap(), 0x123456);
+        assert_eq!(buffer.read_u24_be().unwrap(), 0xFFFFFF);
+        assert!(buffer.read_u24_be().is_err());
+    }
+
+    #[test]
+    fn test_write_u24_rejects_wide_values() {
+        let mut buffer = ByteBuffer::with_capacity(4);
+        assert_eq!(buffer.write_u24_be(0x1000000).unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_read_u24_at_end_of_buffer() {
+        let mut buffer = ByteBuffer::new(vec![0x01, 0x02, 0x03, 0x04]);
+        buffer.read_u8().unwrap();
+        assert_eq!(buffer.read_u24_be().unwrap(), 0x020304);
+
+        let mut buffer = ByteBuffer::new(vec![0x01, 0x02]);
+        assert!(buffer.read_u24_be().is_err());
+        assert_eq!(buffer.position(), 0);
+    }
+
+    #[test]
+    fn test_peek_does_not_advance() {
+        let mut buffer = ByteBuffer::new(vec![1, 2, 3]);
+
+        assert_eq!(buffer.peek_u8().unwrap(), 1);
+        assert_eq!(buffer.peek_bytes(3).unwrap(), &[1, 2, 3]);
+        assert_eq!(buffer.position(), 0);
+
+        buffer.read_bytes(2).unwrap();
+        assert_eq!(buffer.peek_u8().unwrap(), 3);
+        assert!(buffer.peek_bytes(2).is_err());
+        assert_eq!(buffer.peek_bytes(0).unwrap(), &[] as &[u8]);
+
+        buffer.read_u8().unwrap();
+        assert!(buffer.peek_u8().is_err());
+        assert_eq!(buffer.remaining(), 0);
+    }
+
     #[test]
     fn test_remaining_bytes() {
         let data = vec![1, 2, 3, 4, 5];
@@ -20,6 +20,9 @@ pub enum Error {
     #[error("AMF encode error: {0}")]
     AmfEncode(String),
 
+    #[error("Malformed command: {0}")]
+    MalformedCommand(String),
+
     #[error("Chunk error: {0}")]
     Chunk(String),
 
@@ -73,6 +76,11 @@ impl Error {
         Error::AmfEncode(msg.into())
     }
 
+    /// Create a malformed command error
+    pub fn malformed_command(msg: impl Into<String>) -> Self {
+        Error::MalformedCommand(msg.into())
+    }
+
     /// Create a chunk error
     pub fn chunk(msg: impl Into<String>) -> Self {
         Error::Chunk(msg.into())
@@ -2,7 +2,75 @@
 //
 // This module provides reusable test utilities for integration and unit tests
 
-use rtmp::{RtmpPacket, RtmpHeader};
+use rtmp::{EventListener, RtmpClient, RtmpPacket, RtmpHeader, RtmpServer};
+use std::net::SocketAddr;
+use tokio::sync::mpsc;
+
+/// Buffer size of each direction of an in-memory connection
+const MEMORY_PIPE_SIZE: usize = 64 * 1024;
+
+/// Address in-memory clients appear to connect from (TEST-NET-1)
+pub const MEMORY_PEER_ADDR: &str = "192.0.2.1:50000";
+
+/// Connect a client to `server` over an in-memory pipe instead of TCP
+///
+/// Both ends run the real handshake and connection loops, so tests need
+/// neither free ports nor sleeps waiting for a listener.
+pub async fn connect_in_memory(server: &RtmpServer, url: &str) -> RtmpClient {
+    connect_in_memory_from(server, url, MEMORY_PEER_ADDR.parse().unwrap()).await
+}
+
+/// Connect in memory, appearing to the server to come from `peer_addr`
+pub async fn connect_in_memory_from(server: &RtmpServer, url: &str, peer_addr: SocketAddr) -> RtmpClient {
+    let (client_side, server_side) = tokio::io::duplex(MEMORY_PIPE_SIZE);
+    server.serve_stream(server_side, peer_addr).await;
+
+    let mut client = RtmpClient::new();
+    client.connect_stream(client_side, url).await
+        .expect("In-memory connect should succeed");
+    client
+}
+
+/// Server event, as recorded by `EventRecorder`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    Connect(String),
+    Publish(String),
+    Play(String),
+    Disconnect,
+}
+
+/// Listener forwarding server events to a channel, so tests can wait for
+/// them instead of sleeping
+pub struct EventRecorder {
+    tx: mpsc::UnboundedSender<ServerEvent>,
+}
+
+impl EventRecorder {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<ServerEvent>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (EventRecorder { tx }, rx)
+    }
+}
+
+#[async_trait::async_trait]
+impl EventListener for EventRecorder {
+    async fn on_connect(&self, _conn_id: &str, app: &str, _peer_addr: Option<SocketAddr>) {
+        let _ = self.tx.send(ServerEvent::Connect(app.to_string()));
+    }
+
+    async fn on_publish(&self, stream_name: &str, _conn_id: &str) {
+        let _ = self.tx.send(ServerEvent::Publish(stream_name.to_string()));
+    }
+
+    async fn on_play(&self, stream_name: &str, _conn_id: &str) {
+        let _ = self.tx.send(ServerEvent::Play(stream_name.to_string()));
+    }
+
+    async fn on_disconnect(&self, _conn_id: &str) {
+        let _ = self.tx.send(ServerEvent::Disconnect);
+    }
+}
 
 /// Create a test video packet with specified timestamp
 pub fn create_test_video_packet(timestamp: u32, is_keyframe: bool) -> RtmpPacket {
@@ -80,10 +148,10 @@ pub fn create_test_metadata_packet() -> RtmpPacket {
 
 /// Compare two RTMP packets for equality
 pub fn assert_packet_equal(a: &RtmpPacket, b: &RtmpPacket) {
-    assert_eq!(a.header().timestamp, b.header().timestamp, "Timestamps don't match");
-    assert_eq!(a.header().message_type, b.header().message_type, "Message types don't match");
-    assert_eq!(a.header().message_stream_id, b.header().message_stream_id, "Stream IDs don't match");
-    assert_eq!(a.payload(), b.payload(), "Payloads don't match");
+    assert_eq!(a.header.timestamp, b.header.timestamp, "Timestamps don't match");
+    assert_eq!(a.header.message_type, b.header.message_type, "Message types don't match");
+    assert_eq!(a.header.message_stream_id, b.header.message_stream_id, "Stream IDs don't match");
+    assert_eq!(a.payload, b.payload, "Payloads don't match");
 }
 
 /// Generate test video frame data
@@ -147,19 +215,19 @@ mod tests {
     #[test]
     fn test_create_video_packet() {
         let packet = create_test_video_packet(1000, true);
-        assert_eq!(packet.header().timestamp, 1000);
-        assert_eq!(packet.header().message_type, 9);
-        assert!(packet.payload().len() > 0);
-        assert_eq!(packet.payload()[0], 0x17); // Keyframe marker
+        assert_eq!(packet.header.timestamp, 1000);
+        assert_eq!(packet.header.message_type, 9);
+        assert!(!packet.payload.is_empty());
+        assert_eq!(packet.payload[0], 0x17); // Keyframe marker
     }
 
     #[test]
     fn test_create_audio_packet() {
         let packet = create_test_audio_packet(2000);
-        assert_eq!(packet.header().timestamp, 2000);
-        assert_eq!(packet.header().message_type, 8);
-        assert!(packet.payload().len() > 0);
-        assert_eq!(packet.payload()[0], 0xAF); // AAC marker
+        assert_eq!(packet.header.timestamp, 2000);
+        assert_eq!(packet.header.message_type, 8);
+        assert!(!packet.payload.is_empty());
+        assert_eq!(packet.payload[0], 0xAF); // AAC marker
     }
 
     #[test]
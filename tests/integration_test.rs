@@ -2,9 +2,14 @@
 // 
 // These tests verify end-to-end functionality of the RTMP server and client
 
-use rtmp::{RtmpServer, RtmpClient, ServerConfig};
+#[allow(dead_code)]
+mod common;
+
+use common::{connect_in_memory, connect_in_memory_from, EventRecorder, ServerEvent};
+use rtmp::{ConnectionState, ListenSpec, RtmpClient, RtmpServer, ServerConfig};
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::AsyncReadExt;
 
 /// Helper function to create a test server on a unique port
 async fn create_test_server(port: u16) -> Arc<RtmpServer> {
@@ -56,31 +61,197 @@ async fn test_server_starts_and_accepts_connections() {
 
 #[tokio::test]
 async fn test_client_can_connect() {
-    let port = 19351;
-    let server = create_test_server(port).await;
-    
-    // Start server
-    let server_handle = tokio::spawn(async move {
-        server.listen().await
+    let server = RtmpServer::new(ServerConfig::default());
+    let (recorder, mut events) = EventRecorder::new();
+    server.add_event_listener(Arc::new(recorder)).await;
+
+    // Handshake and connect over an in-memory pipe
+    let client = connect_in_memory(&server, "rtmp://localhost/live").await;
+
+    // createStream waits for the server's _result
+    let stream_id = client.create_stream().await.expect("createStream should succeed");
+    assert!(stream_id > 0);
+    assert_eq!(events.recv().await, Some(ServerEvent::Connect("live".to_string())));
+}
+
+#[tokio::test]
+async fn test_publish_and_play_in_memory() {
+    let server = RtmpServer::new(ServerConfig::default());
+    let (recorder, mut events) = EventRecorder::new();
+    server.add_event_listener(Arc::new(recorder)).await;
+
+    let mut publisher = connect_in_memory(&server, "rtmp://localhost/live").await;
+    assert_eq!(events.recv().await, Some(ServerEvent::Connect("live".to_string())));
+    publisher.publish("test", "live").await.expect("publish should succeed");
+    assert_eq!(events.recv().await, Some(ServerEvent::Publish("test".to_string())));
+    assert!(server.context().publishers().is_publishing("test").await);
+
+    publisher.send_video(vec![0x17, 0x00, 0x00, 0x00, 0x00], 0).await.unwrap();
+    publisher.send_video(vec![0x17, 0x01, 0x00, 0x00, 0x00], 0).await.unwrap();
+
+    let mut player = connect_in_memory(&server, "rtmp://localhost/live").await;
+    assert_eq!(events.recv().await, Some(ServerEvent::Connect("live".to_string())));
+    player.play("test", -2.0, -1.0, true).await.expect("play should succeed");
+    assert_eq!(events.recv().await, Some(ServerEvent::Play("test".to_string())));
+
+    let info = server.context().publishers().get("test").await.unwrap();
+    assert_eq!(info.publisher.subscriber_count().await, 1);
+
+    // Closing the publisher releases the stream
+    publisher.disconnect().await.unwrap();
+    assert_eq!(events.recv().await, Some(ServerEvent::Disconnect));
+    assert!(!server.context().publishers().is_publishing("test").await);
+}
+
+#[tokio::test]
+async fn test_connections_info_shows_publisher() {
+    let server = RtmpServer::new(ServerConfig::default());
+    let (recorder, mut events) = EventRecorder::new();
+    server.add_event_listener(Arc::new(recorder)).await;
+
+    let mut publisher = connect_in_memory(&server, "rtmp://localhost/live").await;
+    publisher.publish("test", "live").await.expect("publish should succeed");
+    assert_eq!(events.recv().await, Some(ServerEvent::Connect("live".to_string())));
+    assert_eq!(events.recv().await, Some(ServerEvent::Publish("test".to_string())));
+
+    let connections = server.connections_info().await;
+    assert_eq!(connections.len(), 1);
+    let info = &connections[0];
+    assert_eq!(info.state, ConnectionState::Publishing);
+    assert_eq!(info.app.as_deref(), Some("live"));
+    assert_eq!(info.stream_name.as_deref(), Some("test"));
+    assert!(info.bytes_received > 0);
+    assert!(info.bytes_sent > 0);
+}
+
+#[tokio::test]
+async fn test_played_media_reaches_receiver() {
+    let server = RtmpServer::new(ServerConfig::default());
+    let (recorder, mut events) = EventRecorder::new();
+    server.add_event_listener(Arc::new(recorder)).await;
+
+    let mut publisher = connect_in_memory(&server, "rtmp://localhost/live").await;
+    publisher.publish("media", "live").await.expect("publish should succeed");
+    assert_eq!(events.recv().await, Some(ServerEvent::Connect("live".to_string())));
+    assert_eq!(events.recv().await, Some(ServerEvent::Publish("media".to_string())));
+    let keyframe = vec![0x17, 0x01, 0x00, 0x00, 0x00, 0xAA];
+    publisher.send_video(keyframe.clone(), 0).await.unwrap();
+
+    let mut player = connect_in_memory(&server, "rtmp://localhost/live").await;
+    let mut media = player.media_receiver().await;
+    player.play("media", -2.0, -1.0, true).await.expect("play should succeed");
+    assert_eq!(events.recv().await, Some(ServerEvent::Connect("live".to_string())));
+    assert_eq!(events.recv().await, Some(ServerEvent::Play("media".to_string())));
+
+    // The cached keyframe arrives first, then live audio
+    let audio = vec![0xAF, 0x01, 0xBB];
+    publisher.send_audio(audio.clone(), 40).await.unwrap();
+
+    let mut video = None;
+    let mut live_audio = None;
+    while video.is_none() || live_audio.is_none() {
+        let packet = tokio::time::timeout(Duration::from_secs(2), media.recv()).await
+            .expect("Media should reach the player")
+            .expect("Receiver should stay open");
+        match packet.message_type() {
+            rtmp::MSG_TYPE_VIDEO => video = Some(packet),
+            rtmp::MSG_TYPE_AUDIO => live_audio = Some(packet),
+            _ => {}
+        }
+    }
+
+    assert_eq!(video.unwrap().payload, keyframe);
+    let live_audio = live_audio.unwrap();
+    assert_eq!(live_audio.payload, audio);
+    assert_eq!(live_audio.timestamp(), 40);
+}
+
+#[tokio::test]
+async fn test_relay_republishes_upstream_stream() {
+    let upstream = RtmpServer::new(ServerConfig::default());
+    let relay = RtmpServer::new(ServerConfig::default());
+
+    let mut publisher = connect_in_memory(&upstream, "rtmp://origin/live").await;
+    publisher.publish("cam", "live").await.expect("publish should succeed");
+    let keyframe = vec![0x17, 0x01, 0x00, 0x00, 0x00, 0xAA];
+    publisher.send_video(keyframe.clone(), 0).await.unwrap();
+
+    // Pull the stream from upstream and feed it into a local publisher
+    let mut puller = connect_in_memory(&upstream, "rtmp://origin/live").await;
+    let mut pulled = puller.media_receiver().await;
+    puller.play("cam", -2.0, -1.0, true).await.expect("play upstream should succeed");
+
+    let registry = relay.context().publishers();
+    registry.register("cam".to_string(), "relay-cam".to_string(), 1, None).await.unwrap();
+    let local = registry.get("cam").await.unwrap().publisher;
+    tokio::spawn(async move {
+        while let Some(packet) = pulled.recv().await {
+            local.ingest(packet).await.unwrap();
+        }
     });
-    
-    // Wait for server to start
-    tokio::time::sleep(Duration::from_millis(200)).await;
-    
-    // Create client
-    let mut client = RtmpClient::new();
-    
-    // Connect (this will likely fail with current implementation, but demonstrates the test)
-    let url = format!("rtmp://127.0.0.1:{}/live", port);
-    let result = client.connect(&url).await;
-    
-    // Note: This test may fail until full handshake is implemented
-    // For now, we just ensure the connection attempt doesn't panic
-    drop(result);
-    
-    // Cleanup
-    drop(client);
-    server_handle.abort();
+
+    let mut viewer = connect_in_memory(&relay, "rtmp://edge/live").await;
+    let mut media = viewer.media_receiver().await;
+    viewer.play("cam", -2.0, -1.0, true).await.expect("play from relay should succeed");
+
+    let info = registry.get("cam").await.unwrap();
+    for _ in 0..20 {
+        if info.publisher.subscriber_count().await == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(info.publisher.subscriber_count().await, 1);
+
+    // The cached keyframe came through the relay, live audio follows it
+    let audio = vec![0xAF, 0x01, 0xBB];
+    publisher.send_audio(audio.clone(), 40).await.unwrap();
+
+    let mut video = None;
+    let mut live_audio = None;
+    while video.is_none() || live_audio.is_none() {
+        let packet = tokio::time::timeout(Duration::from_secs(2), media.recv()).await
+            .expect("Relayed media should reach the viewer")
+            .expect("Receiver should stay open");
+        match packet.message_type() {
+            rtmp::MSG_TYPE_VIDEO => video = Some(packet),
+            rtmp::MSG_TYPE_AUDIO => live_audio = Some(packet),
+            _ => {}
+        }
+    }
+
+    assert_eq!(video.unwrap().payload, keyframe);
+    let live_audio = live_audio.unwrap();
+    assert_eq!(live_audio.payload, audio);
+    assert_eq!(live_audio.timestamp(), 40);
+}
+
+#[tokio::test]
+async fn test_auth_hooks_see_peer_addr() {
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (on_connect, on_publish) = (seen.clone(), seen.clone());
+    let config = ServerConfig::builder()
+        .connect_auth(move |params| {
+            on_connect.lock().unwrap().push(params.peer_addr);
+            Box::pin(async { true })
+        })
+        .publish_auth(move |params| {
+            on_publish.lock().unwrap().push(params.peer_addr);
+            Box::pin(async { true })
+        })
+        .build()
+        .unwrap();
+    let server = RtmpServer::new(config);
+    let (recorder, mut events) = EventRecorder::new();
+    server.add_event_listener(Arc::new(recorder)).await;
+
+    let peer_addr = "203.0.113.7:40000".parse().unwrap();
+    let mut client = connect_in_memory_from(&server, "rtmp://localhost/live", peer_addr).await;
+    client.publish("test", "live").await.expect("publish should succeed");
+    assert_eq!(events.recv().await, Some(ServerEvent::Connect("live".to_string())));
+    assert_eq!(events.recv().await, Some(ServerEvent::Publish("test".to_string())));
+
+    assert_eq!(*seen.lock().unwrap(), vec![Some(peer_addr), Some(peer_addr)]);
 }
 
 #[tokio::test]
@@ -152,6 +323,195 @@ async fn test_server_respects_connection_limit() {
     server_handle.abort();
 }
 
+#[tokio::test]
+async fn test_listens_on_multiple_addresses() {
+    let (first_port, second_port) = (19356, 19357);
+    let config = ServerConfig::builder()
+        .listen_on(ListenSpec::new("127.0.0.1", first_port))
+        .listen_on(ListenSpec::new("127.0.0.1", second_port))
+        .build()
+        .expect("Failed to build server config");
+    let server = Arc::new(RtmpServer::new(config));
+    let (recorder, mut events) = EventRecorder::new();
+    server.add_event_listener(Arc::new(recorder)).await;
+
+    let listener = server.clone();
+    let server_handle = tokio::spawn(async move {
+        listener.listen().await
+    });
+    assert!(wait_for_server(first_port, 20).await, "First address should accept");
+    assert!(wait_for_server(second_port, 20).await, "Second address should accept");
+
+    // Publish through one address and play through the other
+    let mut publisher = RtmpClient::new();
+    publisher.connect(&format!("rtmp://127.0.0.1:{}/live", first_port)).await
+        .expect("connect on first address should succeed");
+    publisher.publish("shared", "live").await.expect("publish should succeed");
+
+    let mut player = RtmpClient::new();
+    player.connect(&format!("rtmp://127.0.0.1:{}/live", second_port)).await
+        .expect("connect on second address should succeed");
+    player.play("shared", -2.0, -1.0, true).await.expect("play should succeed");
+
+    let mut publishes = 0;
+    let mut plays = 0;
+    while publishes == 0 || plays == 0 {
+        match tokio::time::timeout(Duration::from_secs(2), events.recv()).await {
+            Ok(Some(ServerEvent::Publish(_))) => publishes += 1,
+            Ok(Some(ServerEvent::Play(_))) => plays += 1,
+            Ok(Some(_)) => {}
+            _ => panic!("Expected publish and play events"),
+        }
+    }
+
+    let info = server.context().publishers().get("shared").await
+        .expect("Stream should be registered");
+    assert_eq!(info.publisher.subscriber_count().await, 1);
+
+    server_handle.abort();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_unix_socket_transport_runs_full_session() {
+    use rtmp::{UnixConnector, UnixTransport};
+
+    let path = std::env::temp_dir().join(format!("rtmp-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let server = Arc::new(RtmpServer::new(ServerConfig::default()));
+    let transport = UnixTransport::bind(&path).expect("Failed to bind Unix socket");
+    let listener = server.clone();
+    let server_handle = tokio::spawn(async move {
+        listener.listen_on(transport).await
+    });
+
+    // Handshake, connect and publish all run over the socket
+    let mut client = RtmpClient::new();
+    client.connect_with(&UnixConnector::new(&path), "rtmp://localhost/live").await
+        .expect("connect over Unix socket should succeed");
+    client.publish("unix", "live").await.expect("publish should succeed");
+
+    let mut published = false;
+    for _ in 0..20 {
+        if server.context().publishers().is_publishing("unix").await {
+            published = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(published, "Stream should be registered");
+    let connections = server.connections_info().await;
+    assert_eq!(connections.len(), 1);
+    assert_eq!(connections[0].app.as_deref(), Some("live"));
+
+    server.shutdown().await;
+    let _ = tokio::time::timeout(Duration::from_secs(2), server_handle).await;
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_connect_rate_limit_rejects_past_burst() {
+    let port = 19358;
+    let config = ServerConfig::builder()
+        .host("127.0.0.1")
+        .port(port)
+        .connect_rate_limit(0.1, 3)
+        .build()
+        .expect("Failed to build config");
+    let server = Arc::new(RtmpServer::new(config));
+
+    let listener = server.clone();
+    let server_handle = tokio::spawn(async move {
+        listener.listen().await
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Accepted sockets wait for the handshake; rejected ones are closed
+    let mut accepted = 0;
+    let mut rejected = 0;
+    let mut open = Vec::new();
+    for _ in 0..6 {
+        let mut socket = tokio::net::TcpStream::connect(format!("127.0.0.1:{}", port)).await
+            .expect("TCP connect should succeed");
+        let mut buf = [0u8; 1];
+        match tokio::time::timeout(Duration::from_millis(200), socket.read(&mut buf)).await {
+            Ok(Ok(0)) | Ok(Err(_)) => rejected += 1,
+            Ok(Ok(_)) => panic!("Server should wait for the client handshake"),
+            Err(_) => accepted += 1,
+        }
+        open.push(socket);
+    }
+
+    assert_eq!(accepted, 3);
+    assert_eq!(rejected, 3);
+
+    server_handle.abort();
+}
+
+#[tokio::test]
+async fn test_shutdown_stops_idle_listener() {
+    let port = 19354;
+    let server = create_test_server(port).await;
+
+    // Start server with no clients
+    let listener = server.clone();
+    let server_handle = tokio::spawn(async move {
+        listener.listen().await
+    });
+    assert!(wait_for_server(port, 20).await, "Server should start");
+
+    // Wait for the probe connection to be cleaned up
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // listen must return without another connection arriving
+    assert!(server.shutdown_graceful(Duration::from_secs(1)).await);
+    let result = tokio::time::timeout(Duration::from_secs(1), server_handle).await;
+    assert!(result.is_ok(), "listen should return promptly after shutdown");
+    assert!(result.unwrap().unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_silent_peer_is_reaped_after_read_timeout() {
+    use tokio::io::AsyncWriteExt;
+
+    let port = 19355;
+    let config = ServerConfig::builder()
+        .host("127.0.0.1")
+        .port(port)
+        .read_timeout(Duration::from_millis(300))
+        .build()
+        .expect("Failed to build server config");
+    let server = Arc::new(RtmpServer::new(config));
+
+    let listener = server.clone();
+    let server_handle = tokio::spawn(async move {
+        listener.listen().await
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Send part of C0+C1, then go quiet without closing the socket
+    let mut stream = tokio::net::TcpStream::connect(format!("127.0.0.1:{}", port))
+        .await
+        .expect("Should connect");
+    stream.write_all(&[0x03; 100]).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(server.connection_count().await, 1);
+
+    let mut reaped = false;
+    for _ in 0..20 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        if server.connection_count().await == 0 {
+            reaped = true;
+            break;
+        }
+    }
+    assert!(reaped, "Silent connection should be dropped after the read timeout");
+
+    drop(stream);
+    server_handle.abort();
+}
+
 #[tokio::test]
 async fn test_server_config_validation() {
     // Test invalid port
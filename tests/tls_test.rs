@@ -0,0 +1,89 @@
+// TLS (rtmps) tests for RustRTMP
+//
+// These tests need the `tls` feature: cargo test --features tls
+
+#![cfg(feature = "tls")]
+
+use rtmp::{ClientConfig, RtmpClient, RtmpServer, ServerConfig};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Write a self-signed certificate for localhost and its key to temp files
+fn write_self_signed_cert(name: &str) -> (PathBuf, PathBuf) {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
+        .expect("Failed to generate certificate");
+
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join(format!("{}-{}-cert.pem", name, std::process::id()));
+    let key_path = dir.join(format!("{}-{}-key.pem", name, std::process::id()));
+    std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+    std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+
+    (cert_path, key_path)
+}
+
+#[tokio::test]
+async fn test_tls_server_accepts_rtmps_client() {
+    let port = 19360;
+    let (cert_path, key_path) = write_self_signed_cert("rtmp-tls-server");
+
+    let config = ServerConfig::builder()
+        .host("127.0.0.1")
+        .port(port)
+        .tls_cert_path(&cert_path)
+        .tls_key_path(&key_path)
+        .build()
+        .expect("Failed to build server config");
+    let server = Arc::new(RtmpServer::new(config));
+
+    let server_handle = tokio::spawn(async move {
+        server.listen().await
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // TLS handshake, then RTMP handshake and connect over the encrypted stream
+    let client_config = ClientConfig::builder()
+        .tls_root_cert(&cert_path)
+        .build()
+        .expect("Failed to build client config");
+    let mut client = RtmpClient::with_config(client_config);
+    client.connect(&format!("rtmps://localhost:{}/live", port)).await
+        .expect("rtmps connect should succeed");
+
+    // A plain RTMP client cannot complete the handshake with a TLS server
+    let mut plain = RtmpClient::with_config(
+        ClientConfig::builder()
+            .connect_timeout(Duration::from_secs(1))
+            .build()
+            .unwrap(),
+    );
+    let plain_result = tokio::time::timeout(
+        Duration::from_secs(2),
+        plain.connect(&format!("rtmp://127.0.0.1:{}/live", port)),
+    ).await;
+    assert!(!matches!(plain_result, Ok(Ok(()))));
+
+    server_handle.abort();
+    let _ = std::fs::remove_file(&cert_path);
+    let _ = std::fs::remove_file(&key_path);
+}
+
+#[tokio::test]
+async fn test_tls_config_requires_cert_and_key() {
+    let result = ServerConfig::builder()
+        .tls_cert_path("cert.pem")
+        .build();
+    assert!(result.is_err(), "Should reject a certificate without a key");
+
+    let result = ServerConfig::builder()
+        .tls_key_path("key.pem")
+        .build();
+    assert!(result.is_err(), "Should reject a key without a certificate");
+
+    let result = ServerConfig::builder()
+        .tls_cert_path("cert.pem")
+        .tls_key_path("key.pem")
+        .build();
+    assert!(result.is_ok(), "Should accept a certificate with its key");
+}